@@ -0,0 +1,257 @@
+//! Accessibility checks
+//!
+//! These catch common mistakes that make pages harder to use with assistive
+//! technology. They are no substitute for testing with a screen reader.
+
+use std::{cell::RefCell, collections::HashSet, rc::Rc};
+
+use lol_html::{element, rewrite_str, EndTagHandler, RewriteStrSettings};
+use serde_json::Value;
+
+use crate::{index::SiteMetadata, renderer::RenderedSite};
+
+use super::{sort_findings, AuditPage, Finding, Priority};
+
+/// The minimum contrast ratio for normal text under WCAG level AA
+const MIN_CONTRAST: f64 = 4.5;
+
+/// Input types that don't need a label
+const UNLABELED_INPUT_TYPES: &[&str] = &["hidden", "submit", "reset", "button", "image"];
+
+/// Runs the accessibility checks on the generated pages.
+pub fn audit_a11y(site: &RenderedSite<'_>, pages: &[AuditPage<'_>]) -> Vec<Finding> {
+    let mut findings = check_theme_colors(&site.config().theme_opts);
+    for page in pages {
+        findings.extend(
+            check_page(&page.html)
+                .into_iter()
+                .map(|(priority, message)| Finding {
+                    priority,
+                    page: page.page.source_path().into(),
+                    message,
+                }),
+        );
+    }
+    sort_findings(&mut findings);
+    findings
+}
+
+#[derive(Default)]
+struct PageState {
+    last_heading: Option<u8>,
+    has_main: bool,
+    /// The `id`s of form controls that need a label
+    unlabeled: Vec<String>,
+    /// Form controls with no `id`, so no `<label for>` can refer to them
+    unlabeled_without_id: usize,
+    label_targets: HashSet<String>,
+    label_depth: usize,
+    problems: Vec<(Priority, String)>,
+}
+
+/// Checks the HTML of a single page.
+pub(crate) fn check_page(html: &str) -> Vec<(Priority, String)> {
+    let state = Rc::new(RefCell::new(PageState::default()));
+
+    let headings = {
+        let state = state.clone();
+        element!("h1, h2, h3, h4, h5, h6", move |el| {
+            let level = el.tag_name().as_bytes()[1] - b'0';
+            let mut state = state.borrow_mut();
+            if let Some(last) = state.last_heading {
+                if level > last + 1 {
+                    state.problems.push((
+                        Priority::Medium,
+                        format!("heading levels skip from h{last} to h{level}"),
+                    ));
+                }
+            }
+            state.last_heading = Some(level);
+            Ok(())
+        })
+    };
+    let main = {
+        let state = state.clone();
+        element!("main, [role=main]", move |_| {
+            state.borrow_mut().has_main = true;
+            Ok(())
+        })
+    };
+    let labels = {
+        let state = state.clone();
+        element!("label", move |el| {
+            let mut s = state.borrow_mut();
+            if let Some(target) = el.get_attribute("for") {
+                s.label_targets.insert(target);
+            }
+            s.label_depth += 1;
+            drop(s);
+            let state = state.clone();
+            if let Some(handlers) = el.end_tag_handlers() {
+                let handler: EndTagHandler<'static> = Box::new(move |_| {
+                    state.borrow_mut().label_depth -= 1;
+                    Ok(())
+                });
+                handlers.push(handler);
+            }
+            Ok(())
+        })
+    };
+    let controls = {
+        let state = state.clone();
+        element!("input, select, textarea", move |el| {
+            let input_type = el.get_attribute("type").unwrap_or_default();
+            let mut state = state.borrow_mut();
+            if UNLABELED_INPUT_TYPES.contains(&input_type.to_ascii_lowercase().as_str())
+                || state.label_depth > 0
+                || el.has_attribute("aria-label")
+                || el.has_attribute("aria-labelledby")
+                || el.has_attribute("title")
+            {
+                return Ok(());
+            }
+            match el.get_attribute("id") {
+                Some(id) => state.unlabeled.push(id),
+                None => state.unlabeled_without_id += 1,
+            }
+            Ok(())
+        })
+    };
+
+    // Errors only mean the rest of the page couldn't be checked
+    let _ = rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![headings, main, labels, controls],
+            ..RewriteStrSettings::new()
+        },
+    );
+
+    let mut state = state.take();
+    if !state.has_main {
+        state
+            .problems
+            .push((Priority::Medium, "there is no `<main>` landmark".into()));
+    }
+    let missing_labels = state.unlabeled_without_id
+        + state
+            .unlabeled
+            .iter()
+            .filter(|id| !state.label_targets.contains(*id))
+            .count();
+    if missing_labels > 0 {
+        state.problems.push((
+            Priority::High,
+            format!("{missing_labels} form control(s) have no label"),
+        ));
+    }
+    state.problems
+}
+
+/// Checks the contrast of the colors in the theme's `colors` option against
+/// its background color.
+pub(crate) fn check_theme_colors(theme_opts: &Value) -> Vec<Finding> {
+    let Some(colors) = theme_opts.get("colors").and_then(Value::as_object) else {
+        return vec![];
+    };
+    let Some(background) = colors
+        .get("background")
+        .and_then(Value::as_str)
+        .and_then(parse_color)
+    else {
+        return vec![];
+    };
+    colors
+        .iter()
+        .filter(|(name, _)| *name != "background")
+        .filter_map(|(name, color)| {
+            let ratio = contrast(parse_color(color.as_str()?)?, background);
+            (ratio < MIN_CONTRAST).then(|| Finding {
+                priority: Priority::High,
+                page: "Site.toml".into(),
+                message: format!(
+                    "`theme_opts.colors.{name}` has a contrast ratio of {ratio:.2} against the \
+                     background, which is less than {MIN_CONTRAST}"
+                ),
+            })
+        })
+        .collect()
+}
+
+/// Parses a color written as `#rgb` or `#rrggbb`.
+fn parse_color(color: &str) -> Option<[u8; 3]> {
+    let hex = color.trim().strip_prefix('#')?;
+    let channel = |s: &str| u8::from_str_radix(s, 16).ok();
+    match hex.len() {
+        3 => {
+            let mut rgb = [0; 3];
+            for (i, c) in hex.chars().enumerate() {
+                rgb[i] = channel(&c.to_string())? * 17;
+            }
+            Some(rgb)
+        }
+        6 => Some([
+            channel(&hex[0..2])?,
+            channel(&hex[2..4])?,
+            channel(&hex[4..6])?,
+        ]),
+        _ => None,
+    }
+}
+
+/// Computes the WCAG contrast ratio between two colors.
+fn contrast(a: [u8; 3], b: [u8; 3]) -> f64 {
+    let (a, b) = (luminance(a), luminance(b));
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+fn luminance(rgb: [u8; 3]) -> f64 {
+    let [r, g, b] = rgb.map(|c| {
+        let c = f64::from(c) / 255.0;
+        if c <= 0.03928 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    });
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use crate::audit::Priority;
+
+    use super::{check_page, check_theme_colors, contrast};
+
+    #[test]
+    fn page_problems() {
+        let html = r#"<h1>Title</h1><h3>Skipped</h3>
+            <form>
+                <label>Name <input name="name"></label>
+                <label for="email">Email</label><input id="email">
+                <input id="phone"><textarea></textarea>
+                <input type="submit">
+            </form>"#;
+        assert_eq!(
+            check_page(html),
+            [
+                (Priority::Medium, "heading levels skip from h1 to h3".into()),
+                (Priority::Medium, "there is no `<main>` landmark".into()),
+                (Priority::High, "2 form control(s) have no label".into()),
+            ]
+        );
+        assert!(check_page("<main><h2>Fine</h2><h3>Fine</h3></main>").is_empty());
+    }
+
+    #[test]
+    fn color_contrast() {
+        assert_eq!(contrast([0, 0, 0], [255, 255, 255]), 21.0);
+        let findings = check_theme_colors(&json!({
+            "colors": { "background": "#fff", "text": "#222222", "muted": "#aaa" }
+        }));
+        assert_eq!(findings.len(), 1);
+        assert!(findings[0].message.starts_with("`theme_opts.colors.muted`"));
+    }
+}
@@ -0,0 +1,78 @@
+//! Checking a generated site for common problems
+//!
+//! Audits look at the final HTML of each page, so they see what readers and
+//! search engines see, including everything the theme adds.
+
+use std::{fmt, path::PathBuf};
+
+use serde::Deserialize;
+
+use crate::{
+    generator::{GeneratorContext, GeneratorError},
+    renderer::{RenderedPageRef, RenderedSite},
+};
+
+pub mod a11y;
+pub mod seo;
+
+/// The `[audit]` section of `Site.toml`
+#[derive(Deserialize, Debug, Default)]
+pub struct AuditConfig {
+    /// Whether to check every page for accessibility problems during builds
+    #[serde(default)]
+    pub accessibility: bool,
+}
+
+/// How urgently a finding should be addressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    High,
+    Medium,
+    Low,
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Priority::High => "High",
+            Priority::Medium => "Medium",
+            Priority::Low => "Low",
+        })
+    }
+}
+
+/// A problem found by an audit
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub priority: Priority,
+    /// The source path of the page with the problem
+    pub page: PathBuf,
+    pub message: String,
+}
+
+/// A page along with the HTML generated for it
+pub struct AuditPage<'a> {
+    pub page: RenderedPageRef<'a>,
+    pub html: String,
+}
+
+/// Generates the final HTML for every page on the site.
+pub fn generate_pages<'a>(
+    site: &'a RenderedSite<'_>,
+    gcx: &GeneratorContext<'_>,
+) -> Result<Vec<AuditPage<'a>>, GeneratorError> {
+    site.all_pages()
+        .map(|page| {
+            Ok(AuditPage {
+                page,
+                html: gcx.page_html(page, site)?,
+            })
+        })
+        .collect()
+}
+
+/// Orders findings with the most important first.
+pub fn sort_findings(findings: &mut [Finding]) {
+    findings
+        .sort_by(|a, b| (a.priority, &a.page, &a.message).cmp(&(b.priority, &b.page, &b.message)));
+}
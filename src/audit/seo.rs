@@ -0,0 +1,218 @@
+//! Checks that help pages show up well in search results
+
+use std::collections::{HashMap, HashSet};
+
+use url::Url;
+
+use crate::{
+    html_links::{LinkKind, PageLinks},
+    index::{PageMetadata, SiteMetadata},
+    renderer::RenderedSite,
+};
+
+use super::{sort_findings, AuditPage, Finding, Priority};
+
+/// Titles longer than this are likely to be cut off in search results
+const MAX_TITLE_LENGTH: usize = 60;
+
+/// Runs the SEO checks on the generated pages.
+pub fn audit_seo(site: &RenderedSite<'_>, pages: &[AuditPage<'_>]) -> Vec<Finding> {
+    let mut findings = vec![];
+    let mut add = |priority, page: &AuditPage<'_>, message: String| {
+        findings.push(Finding {
+            priority,
+            page: page.page.source_path().into(),
+            message,
+        })
+    };
+
+    let mut titles: HashMap<&str, Vec<&AuditPage<'_>>> = HashMap::new();
+    for page in pages {
+        // Pages without a title have nothing in common
+        if !page.page.title().trim().is_empty() {
+            titles.entry(page.page.title()).or_default().push(page);
+        }
+    }
+
+    let mut linked = HashSet::new();
+    for page in pages {
+        let links = PageLinks::scan(&page.html);
+        for href in links.targets(&[LinkKind::Anchor]) {
+            if let Some(url) = local_url(site, &page.page.url(), href) {
+                if url != page.page.url().trim_matches('/') {
+                    linked.insert(url);
+                }
+            }
+        }
+        if links.images_without_alt > 0 {
+            add(
+                Priority::High,
+                page,
+                format!("{} image(s) have no alt text", links.images_without_alt),
+            );
+        }
+
+        let title = page.page.title();
+        if let Some(others) = titles.get(title).filter(|others| others.len() > 1) {
+            let others = others
+                .iter()
+                .filter(|other| other.page.source_path() != page.page.source_path())
+                .map(|other| format!("`{}`", other.page.source_path().display()))
+                .collect::<Vec<_>>();
+            add(
+                Priority::High,
+                page,
+                format!("the title `{title}` is also used by {}", others.join(", ")),
+            );
+        }
+        if title.chars().count() > MAX_TITLE_LENGTH {
+            add(
+                Priority::Low,
+                page,
+                format!(
+                    "the title is {} characters long, which is more than {MAX_TITLE_LENGTH}",
+                    title.chars().count()
+                ),
+            );
+        }
+        if page
+            .page
+            .source()
+            .description()
+            .is_none_or(|description| description.trim().is_empty())
+        {
+            add(Priority::Medium, page, "there is no description".into());
+        }
+    }
+
+    for page in pages {
+        let url = page.page.url();
+        let url = url.trim_matches('/');
+        // The home page is where readers start, so it doesn't need links
+        if !url.is_empty() && !linked.contains(url) {
+            add(Priority::Medium, page, "no other page links here".into());
+        }
+    }
+
+    sort_findings(&mut findings);
+    findings
+}
+
+/// Converts a link on the page at `page_url` to the URL of a page on this
+/// site, without leading or trailing slashes, or returns `None` if it points
+/// somewhere else.
+fn local_url(site: &RenderedSite<'_>, page_url: &str, href: &str) -> Option<String> {
+    let href = href.split(['#', '?']).next().unwrap_or_default();
+    let path = match href.strip_prefix(site.base_url()) {
+        Some(path) if !site.base_url().is_empty() => path,
+        // Links to the same page, and to other sites or schemes like `mailto:`
+        _ if href.is_empty() || href.starts_with("//") || Url::parse(href).is_ok() => return None,
+        _ => href,
+    };
+    // Pages are written to `index.html` in a directory named by their URL, so
+    // relative links start from there
+    let dir = page_url.trim_matches('/');
+    let base = Url::parse("http://localhost/")
+        .ok()?
+        .join(&format!("{dir}/"))
+        .ok()?;
+    let url = base.join(path).ok()?;
+    let path = url.path().trim_matches('/');
+    Some(
+        path.strip_suffix("index.html")
+            .unwrap_or(path)
+            .trim_matches('/')
+            .to_string(),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        audit::{AuditPage, Priority},
+        index::{PageSource, SiteIndex, SourceFormat},
+    };
+
+    use super::audit_seo;
+
+    #[test]
+    fn find_problems() {
+        let mut site = SiteIndex::default();
+        for (path, contents) in [
+            (
+                "index.md",
+                "---\nlayout: page\ntitle: Home\ndescription: Hi\n---\n",
+            ),
+            (
+                "about.md",
+                "---\nlayout: page\ntitle: Same\ndescription: About\n---\n",
+            ),
+            ("other.md", "---\nlayout: page\ntitle: Same\n---\n"),
+            (
+                "untitled.md",
+                "---\nlayout: page\ntitle: \"\"\ndescription: None\n---\n",
+            ),
+            (
+                "untitled-too.md",
+                "---\nlayout: page\ntitle: \"\"\ndescription: None\n---\n",
+            ),
+        ] {
+            site.add_page(PageSource::from_string(
+                path,
+                SourceFormat::Markdown,
+                contents,
+            ));
+        }
+        let site = site.render().unwrap();
+        let html = [
+            r#"<a href="/about">About</a><img src="a.png">"#,
+            r#"<a href="/">Home</a><img src="b.png" alt=""><a href="../untitled/">Untitled</a>"#,
+            "",
+            r#"<a href="../untitled-too/index.html">Untitled</a>"#,
+            r#"<a href="untitled/">Not a page</a>"#,
+        ];
+        let pages = site
+            .all_pages()
+            .zip(html)
+            .map(|(page, html)| AuditPage {
+                page,
+                html: html.into(),
+            })
+            .collect::<Vec<_>>();
+
+        let findings = audit_seo(&site, &pages)
+            .into_iter()
+            .map(|f| (f.priority, f.page.to_string_lossy().into_owned(), f.message))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            findings,
+            [
+                (
+                    Priority::High,
+                    "about.md".into(),
+                    "the title `Same` is also used by `other.md`".into()
+                ),
+                (
+                    Priority::High,
+                    "index.md".into(),
+                    "1 image(s) have no alt text".into()
+                ),
+                (
+                    Priority::High,
+                    "other.md".into(),
+                    "the title `Same` is also used by `about.md`".into()
+                ),
+                (
+                    Priority::Medium,
+                    "other.md".into(),
+                    "no other page links here".into()
+                ),
+                (
+                    Priority::Medium,
+                    "other.md".into(),
+                    "there is no description".into()
+                ),
+            ]
+        );
+    }
+}
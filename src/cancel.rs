@@ -0,0 +1,74 @@
+//! Stopping a build early, such as when the user presses Ctrl-C
+//!
+//! A [`CancellationToken`] is attached to the [`SiteIndex`](crate::index::SiteIndex)
+//! and checked between pages while rendering and generating, so cancelling
+//! stops the build at the next page rather than partway through writing one.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use tokio::sync::Notify;
+
+/// A flag shared between the code running a build and whoever wants to stop it
+///
+/// Clones share the same flag, so cancelling any of them cancels them all.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<Inner>);
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks the build to stop. This is safe to call from a signal handler
+    /// thread.
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+        self.0.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Waits until the token is cancelled
+    pub async fn cancelled(&self) {
+        loop {
+            // Register before checking the flag so a cancellation in between
+            // isn't missed
+            let notified = self.0.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CancellationToken;
+
+    #[tokio::test]
+    async fn clones_share_cancellation() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(!clone.is_cancelled());
+
+        let waiter = tokio::spawn(async move { clone.cancelled().await });
+        token.cancel();
+        waiter.await.unwrap();
+        assert!(token.is_cancelled());
+
+        // Waiting on a token that is already cancelled returns right away
+        token.cancelled().await;
+    }
+}
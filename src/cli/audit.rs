@@ -0,0 +1,65 @@
+use std::path::PathBuf;
+
+use clap::{Args, Subcommand};
+use ebg::{
+    audit::{a11y::audit_a11y, generate_pages, seo::audit_seo, Finding},
+    generator::GeneratorContext,
+    index::SiteIndex,
+};
+use miette::IntoDiagnostic;
+use tokio::runtime::Runtime;
+
+use super::{build::find_site_root, render::preview_options, Command};
+
+#[derive(Args)]
+pub struct AuditOptions {
+    #[command(subcommand)]
+    audit: Audit,
+
+    /// The site directory
+    #[arg(long, global = true)]
+    site: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Audit {
+    /// Check for duplicate or overly long titles, missing descriptions and
+    /// alt text, and pages nothing links to
+    Seo,
+    /// Check heading order, landmarks, form labels, and the contrast of the
+    /// theme's colors
+    A11y,
+}
+
+impl Command for AuditOptions {
+    fn run(self) -> miette::Result<()> {
+        Runtime::new().into_diagnostic()?.block_on(async move {
+            let root = find_site_root(self.site.as_deref())?;
+            let site = SiteIndex::from_directory(&root, false).await?;
+            let site = site.render()?;
+            let options = preview_options(root);
+            let gcx = GeneratorContext::new(&site, &options)?;
+            let pages = generate_pages(&site, &gcx)?;
+
+            let findings = match self.audit {
+                Audit::Seo => audit_seo(&site, &pages),
+                Audit::A11y => audit_a11y(&site, &pages),
+            };
+            print_report(&findings);
+            Ok(())
+        })
+    }
+}
+
+/// Prints findings grouped by priority, most important first.
+pub(crate) fn print_report(findings: &[Finding]) {
+    let mut priority = None;
+    for finding in findings {
+        if priority != Some(finding.priority) {
+            priority = Some(finding.priority);
+            println!("{} priority:", finding.priority);
+        }
+        println!("  {}: {}", finding.page.display(), finding.message);
+    }
+    println!("{} issue(s) found", findings.len());
+}
@@ -5,10 +5,12 @@ use std::{
 };
 
 use ebg::{
+    cancel::CancellationToken,
     generator::{self, GeneratorContext, Observer},
-    index::{PageMetadata, SiteIndex, SiteMetadata},
+    index::{PageFilter, PageMetadata, SiteIndex, SiteMetadata},
+    lock::DirLock,
 };
-use indicatif::{MultiProgress, ProgressBar};
+use indicatif::{HumanBytes, MultiProgress, ProgressBar};
 use miette::{Context, IntoDiagnostic};
 use tokio::runtime::Runtime;
 use tracing::info;
@@ -86,24 +88,107 @@ impl super::Command for generator::Options {
         let path = find_site_root(self.path.as_deref()).context("finding Site.toml")?;
         info!("building blog from {}", path.display());
 
+        // Held until the build is done
+        let _lock = DirLock::acquire(&self.destination, self.lock_mode(), |holder| {
+            eprintln!(
+                "Waiting for {holder} to finish with `{}`",
+                self.destination.display()
+            )
+        })?;
+
         let start_time = Instant::now();
         let progress = BuildStatusViewer::new();
 
+        // The first Ctrl-C stops the build at the next page and tidies up; a
+        // second one exits right away
+        let cancellation = CancellationToken::new();
+        ctrlc::set_handler({
+            let cancellation = cancellation.clone();
+            move || {
+                if cancellation.is_cancelled() {
+                    std::process::exit(130);
+                }
+                eprintln!("Cancelling build; press Ctrl-C again to stop immediately");
+                cancellation.cancel();
+            }
+        })
+        .into_diagnostic()?;
+
         Runtime::new().into_diagnostic()?.block_on(async move {
             progress.begin_load_site();
-            let site = SiteIndex::from_directory(&path, self.unpublished).await?;
+            // Nothing has been written while loading, so it can stop anywhere
+            let mut site = tokio::select! {
+                site = SiteIndex::from_directory(&path, self.unpublished) => site?,
+                () = cancellation.cancelled() => {
+                    miette::bail!(
+                        help = "nothing was written to the destination directory",
+                        "build cancelled while loading the site"
+                    )
+                }
+            };
+            site.set_cancellation(cancellation);
             progress.end_load_site(&site);
 
-            let site = site.render()?;
+            let site = match PageFilter::new(&self.only, &site)? {
+                Some(filter) => site.render_only(filter)?,
+                None => site.render()?,
+            };
 
             let gcx = GeneratorContext::new(&site, &self)?;
 
-            gcx.generate_site(&site).await?;
+            let summary = gcx.generate_site(&site).await?;
             progress.site_complete(&site);
 
             let elapsed = start_time.elapsed();
 
-            println!("Built site in {:.2?}", elapsed);
+            println!(
+                "Built site in {:.2?}: {} pages, {} files, {} bytes",
+                elapsed, summary.pages, summary.files, summary.total_bytes
+            );
+            if !summary.budget_violations.is_empty() {
+                println!("Over budget:");
+                for violation in &summary.budget_violations {
+                    println!("  {violation}");
+                }
+            }
+            if let Some(memory) = &summary.memory {
+                let peak = memory
+                    .peak_rss
+                    .map_or("unknown".to_string(), |peak| HumanBytes(peak).to_string());
+                println!(
+                    "Memory: {peak} peak, about {} of site data, {} of rendered content",
+                    HumanBytes(memory.site_value),
+                    HumanBytes(memory.rendered_content)
+                );
+                if memory.sequential {
+                    println!("  Pages were generated one at a time to stay under the memory limit");
+                }
+            }
+            if !summary.unreferenced_feeds.is_empty() {
+                println!(
+                    "No page links to these feeds; themes can list every feed with `site.feeds`:"
+                );
+                for feed in &summary.unreferenced_feeds {
+                    println!("  {feed}");
+                }
+            }
+            if !summary.nondeterministic.is_empty() {
+                println!("Output may differ between builds of the same sources:");
+                for reason in &summary.nondeterministic {
+                    println!("  {reason}");
+                }
+            }
+            let accessibility = summary.accessibility_by_page();
+            if !accessibility.is_empty() {
+                println!(
+                    "Accessibility: {} issue(s) on {} page(s); run `ebg audit a11y` for details",
+                    summary.accessibility.len(),
+                    accessibility.len()
+                );
+                for (page, count) in accessibility {
+                    println!("  {}: {count}", page.display());
+                }
+            }
 
             Ok(())
         })
@@ -111,7 +196,10 @@ impl super::Command for generator::Options {
 }
 
 pub(crate) fn find_site_root(path: Option<&Path>) -> miette::Result<PathBuf> {
-    let mut path = path.unwrap_or(Path::new(".")).to_path_buf();
+    let mut path = path
+        .filter(|path| !path.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+        .to_path_buf();
     loop {
         if path.join("Site.toml").exists() {
             return Ok(path.clone());
@@ -123,3 +211,20 @@ pub(crate) fn find_site_root(path: Option<&Path>) -> miette::Result<PathBuf> {
         };
     }
 }
+
+/// Converts a path given on the command line into a source path relative to
+/// the site root.
+pub(crate) fn source_path_in_site(root: &Path, path: &Path) -> miette::Result<PathBuf> {
+    let path = std::fs::canonicalize(path)
+        .into_diagnostic()
+        .with_context(|| format!("finding `{}`", path.display()))?;
+    let root = std::fs::canonicalize(root).into_diagnostic()?;
+    match path.strip_prefix(&root) {
+        Ok(relative) => Ok(relative.to_path_buf()),
+        Err(_) => miette::bail!(
+            "`{}` is not inside the site at `{}`",
+            path.display(),
+            root.display()
+        ),
+    }
+}
@@ -0,0 +1,63 @@
+use std::path::PathBuf;
+
+use clap::Args;
+use ebg::{
+    http::HttpClient,
+    index::{SiteIndex, SiteMetadata},
+    link_check::{check_external_links, DEFAULT_CONCURRENCY},
+    lock::{DirLock, LockMode},
+    renderer::check_links,
+};
+use miette::{miette, IntoDiagnostic};
+use tokio::runtime::Runtime;
+
+use super::{build::find_site_root, Command};
+
+#[derive(Args)]
+pub struct CheckLinksOptions {
+    /// The site directory
+    path: Option<PathBuf>,
+
+    /// Also request every link to another site to make sure it still works
+    #[arg(long)]
+    external: bool,
+
+    /// How many external links to check at once
+    #[arg(long, default_value_t = DEFAULT_CONCURRENCY)]
+    concurrency: usize,
+}
+
+impl Command for CheckLinksOptions {
+    fn run(self) -> miette::Result<()> {
+        Runtime::new().into_diagnostic()?.block_on(async move {
+            let root = find_site_root(self.path.as_deref())?;
+            let site = SiteIndex::from_directory(&root, false).await?;
+
+            let broken = check_links(&site);
+            let mut count = broken.len();
+            for link in broken {
+                eprintln!("{:?}", miette::Report::new(link));
+            }
+
+            if self.external {
+                // Another command may be filling in the same cache
+                let cache = root.join(&site.config().http.cache_dir);
+                let _lock = DirLock::acquire(&cache, LockMode::Wait, |holder| {
+                    eprintln!("Waiting for {holder} to finish with `{}`", cache.display())
+                })?;
+                let client = HttpClient::new(&site.config().http, &root);
+                let dead = check_external_links(&site, &client, self.concurrency).await;
+                count += dead.len();
+                for link in dead {
+                    eprintln!("{:?}", miette::Report::new(link));
+                }
+            }
+
+            if count == 0 {
+                println!("No broken links found");
+                return Ok(());
+            }
+            Err(miette!("found {count} broken link(s)"))
+        })
+    }
+}
@@ -0,0 +1,140 @@
+use std::{
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use clap::Args;
+use ebg::{
+    generator::GeneratorContext,
+    index::{PageMetadata, SiteIndex, SiteMetadata},
+    renderer::{RenderedPageRef, RenderedSite},
+};
+use miette::IntoDiagnostic;
+use tokio::runtime::Runtime;
+
+use super::{build::find_site_root, render::preview_options, Command};
+
+const HELP: &str = "\
+Commands:
+  pages                  list every page
+  posts                  list posts, newest first
+  tag <tag>              list pages with a tag
+  show <source-path>     print a page's frontmatter and metadata
+  eval <source-path> <expression>
+                         evaluate a Tera expression against a page, e.g.
+                         `eval about.md page.title | upper`
+  help                   print this message
+  quit                   leave the console";
+
+/// Interactively query the pages in a site
+#[derive(Args)]
+pub struct ConsoleOptions {
+    /// The site directory
+    path: Option<PathBuf>,
+}
+
+impl Command for ConsoleOptions {
+    fn run(self) -> miette::Result<()> {
+        Runtime::new().into_diagnostic()?.block_on(async move {
+            let root = find_site_root(self.path.as_deref())?;
+            let site = SiteIndex::from_directory(&root, true).await?;
+            let site = site.render()?;
+            let options = preview_options(root);
+            let gcx = GeneratorContext::new(&site, &options)?;
+
+            println!(
+                "Loaded {} pages from {}. Type `help` for a list of commands.",
+                site.num_pages(),
+                site.title()
+            );
+
+            let mut stdout = std::io::stdout();
+            let mut lines = std::io::stdin().lock().lines();
+            loop {
+                print!("ebg> ");
+                stdout.flush().into_diagnostic()?;
+                let Some(line) = lines.next() else {
+                    println!();
+                    break;
+                };
+                let line = line.into_diagnostic()?;
+                let (command, args) = line
+                    .trim()
+                    .split_once(char::is_whitespace)
+                    .unwrap_or((line.trim(), ""));
+                let args = args.trim();
+                match command {
+                    "" => {}
+                    "quit" | "exit" => break,
+                    "help" => println!("{HELP}"),
+                    "pages" => list(site.all_pages()),
+                    "posts" => list(site.posts()),
+                    "tag" => list(
+                        site.all_pages()
+                            .filter(|page| page.source().tags().iter().any(|tag| tag == args)),
+                    ),
+                    "show" => match find_page(&site, args) {
+                        Some(page) => show(page),
+                        None => println!("no page with source path `{args}`"),
+                    },
+                    "eval" => {
+                        let (path, expression) =
+                            args.split_once(char::is_whitespace).unwrap_or((args, ""));
+                        let Some(page) = find_page(&site, path) else {
+                            println!("no page with source path `{path}`");
+                            continue;
+                        };
+                        let context = gcx.template_context(page, &site);
+                        match tera::Tera::one_off(
+                            &format!("{{{{ {expression} }}}}"),
+                            &context,
+                            false,
+                        ) {
+                            Ok(value) => println!("{value}"),
+                            Err(e) => println!("error: {}", error_chain(&e)),
+                        }
+                    }
+                    _ => {
+                        println!("unknown command `{command}`; type `help` for a list of commands")
+                    }
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+fn find_page<'a>(site: &'a RenderedSite<'_>, path: &str) -> Option<RenderedPageRef<'a>> {
+    site.find_page_by_source_path(Path::new(path))
+}
+
+fn list<'a>(pages: impl Iterator<Item = RenderedPageRef<'a>>) {
+    for page in pages {
+        println!("{}\t{}", page.source_path().display(), page.title());
+    }
+}
+
+fn show(page: RenderedPageRef<'_>) {
+    println!("title: {}", page.title());
+    println!("url: {}", page.url());
+    println!("kind: {:?}", page.source().kind());
+    if let Some(date) = page.publish_date() {
+        println!("date: {}", date.to_rfc3339());
+    }
+    if let Some(frontmatter) = page.source().raw_frontmatter() {
+        println!("---\n{}\n---", frontmatter.trim());
+    }
+}
+
+/// Tera errors put the useful details in their sources, so this joins them
+/// into one message.
+fn error_chain(e: &dyn std::error::Error) -> String {
+    let mut message = e.to_string();
+    let mut source = e.source();
+    while let Some(e) = source {
+        message.push_str(": ");
+        message.push_str(&e.to_string());
+        source = e.source();
+    }
+    message
+}
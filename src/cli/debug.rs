@@ -0,0 +1,92 @@
+use std::path::PathBuf;
+
+use clap::{Args, Subcommand};
+use ebg::{generator::GeneratorContext, index::SiteIndex, renderer::planned_link_rewrites};
+use miette::IntoDiagnostic;
+use tokio::runtime::Runtime;
+
+use super::{
+    build::{find_site_root, source_path_in_site},
+    render::preview_options,
+    Command,
+};
+
+#[derive(Args)]
+pub struct DebugOptions {
+    #[command(subcommand)]
+    command: DebugCommand,
+}
+
+#[derive(Subcommand)]
+enum DebugCommand {
+    /// Print the variables that templates see when rendering a page
+    Context(ContextOptions),
+    /// Show how the rewrite rules in Site.toml would change external links
+    RewriteLinks(RewriteLinksOptions),
+}
+
+impl Command for DebugOptions {
+    fn run(self) -> miette::Result<()> {
+        match self.command {
+            DebugCommand::Context(options) => options.run(),
+            DebugCommand::RewriteLinks(options) => options.run(),
+        }
+    }
+}
+
+#[derive(Args)]
+struct ContextOptions {
+    /// The source file of the page
+    page: PathBuf,
+}
+
+impl Command for ContextOptions {
+    fn run(self) -> miette::Result<()> {
+        Runtime::new().into_diagnostic()?.block_on(async move {
+            let root = find_site_root(self.page.parent())?;
+            let source_path = source_path_in_site(&root, &self.page)?;
+
+            let site = SiteIndex::from_directory(&root, true).await?;
+            let site = site.render()?;
+            let page = site.find_page_by_source_path(&source_path).ok_or_else(|| {
+                miette::miette!("no page with source path `{}`", source_path.display())
+            })?;
+
+            let options = preview_options(root);
+            let gcx = GeneratorContext::new(&site, &options)?;
+            let context = gcx.template_context(page, &site).into_json();
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&context).into_diagnostic()?
+            );
+            Ok(())
+        })
+    }
+}
+
+#[derive(Args)]
+struct RewriteLinksOptions {
+    /// The site directory
+    path: Option<PathBuf>,
+}
+
+impl Command for RewriteLinksOptions {
+    fn run(self) -> miette::Result<()> {
+        Runtime::new().into_diagnostic()?.block_on(async move {
+            let root = find_site_root(self.path.as_deref())?;
+            let site = SiteIndex::from_directory(&root, true).await?;
+
+            let rewrites = planned_link_rewrites(&site);
+            for rewrite in &rewrites {
+                println!(
+                    "{}: {} -> {}",
+                    rewrite.page.display(),
+                    rewrite.from,
+                    rewrite.to
+                );
+            }
+            println!("{} link(s) would change", rewrites.len());
+            Ok(())
+        })
+    }
+}
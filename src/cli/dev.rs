@@ -0,0 +1,282 @@
+//! Commands that help with developing EBG itself.
+
+use std::{
+    fmt::Write as _,
+    path::{Path, PathBuf},
+};
+
+use chrono::{Duration, NaiveDate};
+use clap::{Args, Subcommand};
+use image::{Rgb, RgbImage};
+use miette::{bail, Context, IntoDiagnostic};
+
+use super::Command;
+
+#[derive(Args)]
+pub struct DevOptions {
+    #[command(subcommand)]
+    command: DevCommand,
+}
+
+#[derive(Subcommand)]
+enum DevCommand {
+    /// Write a large made-up site, for profiling builds
+    GenSite(GenSiteOptions),
+}
+
+impl Command for DevOptions {
+    fn run(self) -> miette::Result<()> {
+        match self.command {
+            DevCommand::GenSite(options) => options.run(),
+        }
+    }
+}
+
+#[derive(Args)]
+struct GenSiteOptions {
+    /// Where to write the site, which must not exist yet or be empty
+    destination: PathBuf,
+    /// How many posts to write
+    #[arg(long, default_value_t = 1000)]
+    posts: usize,
+    /// How many images to write, which posts show at random
+    #[arg(long, default_value_t = 100)]
+    images: usize,
+    /// Seeds the random choices, so the same seed always gives the same site
+    #[arg(long, default_value_t = 1)]
+    seed: u64,
+}
+
+const WORDS: &[&str] = &[
+    "async", "borrow", "cargo", "closure", "compiler", "crate", "data", "enum", "error", "future",
+    "generic", "heap", "iterator", "lifetime", "macro", "memory", "module", "pattern", "pointer",
+    "runtime", "safety", "stack", "string", "thread", "trait", "type", "unsafe", "value", "vector",
+    "the", "a", "of", "and", "to", "in", "is", "with", "for", "that", "when", "it", "we",
+];
+
+const TAGS: &[&str] = &[
+    "rust",
+    "async",
+    "compilers",
+    "performance",
+    "testing",
+    "web",
+    "travel",
+    "music",
+    "books",
+    "tools",
+    "linux",
+    "math",
+];
+
+const CATEGORIES: &[&str] = &["Programming", "Life", "Reviews", "Notes"];
+
+/// A small, fast random number generator, so the same seed gives the same site
+/// everywhere
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // xorshift gets stuck at zero
+        Self(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Returns a number in `range`
+    fn range(&mut self, range: std::ops::Range<usize>) -> usize {
+        range.start + (self.next() % (range.end - range.start) as u64) as usize
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.range(0..items.len())]
+    }
+
+    fn chance(&mut self, percent: usize) -> bool {
+        self.range(0..100) < percent
+    }
+}
+
+impl Command for GenSiteOptions {
+    fn run(self) -> miette::Result<()> {
+        let dest = &self.destination;
+        if dest.exists() && std::fs::read_dir(dest).into_diagnostic()?.next().is_some() {
+            bail!("`{}` is not empty", dest.display());
+        }
+
+        let mut rng = Rng::new(self.seed);
+        write(
+            dest.join("Site.toml"),
+            "title = \"Generated Site\"\n\
+             url = \"https://example.com\"\n\
+             author = \"Generated Author\"\n\
+             content = [\"index.md\", \"about.md\", \"images\"]\n",
+        )?;
+        write(dest.join("index.md"), "---\nlayout: index\n---\n")?;
+        write(
+            dest.join("about.md"),
+            &format!(
+                "---\ntitle: About\nlayout: page\n---\n{}",
+                paragraphs(&mut rng, 3)
+            ),
+        )?;
+        write_theme(dest)?;
+
+        std::fs::create_dir_all(dest.join("images")).into_diagnostic()?;
+        for i in 0..self.images {
+            let (width, height) = (rng.range(200..1600) as u32, rng.range(200..1200) as u32);
+            let color = [rng.next() as u8, rng.next() as u8, rng.next() as u8];
+            let image = RgbImage::from_fn(width, height, |x, y| {
+                Rgb([
+                    color[0].wrapping_add((x * 255 / width) as u8),
+                    color[1].wrapping_add((y * 255 / height) as u8),
+                    color[2],
+                ])
+            });
+            let path = dest.join(image_path(i));
+            image
+                .save(&path)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("writing `{}`", path.display()))?;
+        }
+
+        let start = NaiveDate::from_ymd_opt(2010, 1, 1).unwrap();
+        let names: Vec<String> = (0..self.posts)
+            .map(|i| {
+                // Spread the posts out, sometimes several on one day
+                let date = start + Duration::days((i * 2 / 3) as i64);
+                format!("{}-post-{i}.md", date.format("%Y-%m-%d"))
+            })
+            .collect();
+        for (i, name) in names.iter().enumerate() {
+            let post = post(&mut rng, i, &names[..i], self.images);
+            write(dest.join("_posts").join(name), &post)?;
+        }
+
+        println!(
+            "Wrote {} posts and {} images to {}",
+            self.posts,
+            self.images,
+            dest.display()
+        );
+        Ok(())
+    }
+}
+
+fn image_path(i: usize) -> String {
+    format!("images/image-{i}.png")
+}
+
+/// Writes a post, which links to some of the posts before it
+fn post(rng: &mut Rng, i: usize, earlier: &[String], images: usize) -> String {
+    let mut tags = vec![];
+    for _ in 0..rng.range(0..4) {
+        let tag = rng.pick(TAGS);
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    let mut post = format!(
+        "---\ntitle: \"Post {i}: {}\"\nlayout: post\ntags: [{}]\ncategories: [{}]\n---\n",
+        sentence(rng, 4).trim_end_matches('.'),
+        tags.join(", "),
+        rng.pick(CATEGORIES),
+    );
+    post += &paragraphs(rng, 2);
+    post += "<!-- MORE -->\n\n";
+    for section in 0..rng.range(1..5) {
+        writeln!(post, "## Section {section}\n").unwrap();
+        let count = rng.range(1..4);
+        post += &paragraphs(rng, count);
+        if !earlier.is_empty() && rng.chance(50) {
+            let target = &earlier[rng.range(0..earlier.len())];
+            writeln!(post, "See also [an earlier post](./{target}).\n").unwrap();
+        }
+        if images > 0 && rng.chance(30) {
+            writeln!(post, "![An image](/{})\n", image_path(rng.range(0..images))).unwrap();
+        }
+        if rng.chance(30) {
+            writeln!(
+                post,
+                "```rust\nfn {}() -> usize {{\n    {}\n}}\n```\n",
+                rng.pick(WORDS),
+                rng.range(0..1000)
+            )
+            .unwrap();
+        }
+        if rng.chance(10) {
+            writeln!(
+                post,
+                "A footnote.[^{section}]\n\n[^{section}]: {}\n",
+                sentence(rng, 6)
+            )
+            .unwrap();
+        }
+    }
+    post
+}
+
+fn paragraphs(rng: &mut Rng, count: usize) -> String {
+    let mut text = String::new();
+    for _ in 0..count {
+        for _ in 0..rng.range(2..6) {
+            let length = rng.range(5..15);
+            text += &sentence(rng, length);
+            text.push(' ');
+        }
+        text += "\n\n";
+    }
+    text
+}
+
+fn sentence(rng: &mut Rng, words: usize) -> String {
+    let mut sentence = (0..words)
+        .map(|_| rng.pick(WORDS))
+        .collect::<Vec<_>>()
+        .join(" ");
+    sentence[..1].make_ascii_uppercase();
+    sentence + "."
+}
+
+fn write_theme(dest: &Path) -> miette::Result<()> {
+    let theme = dest.join("theme");
+    write(
+        theme.join("base.html"),
+        "<!DOCTYPE html>\n<html><head><title>{{ page.title }} - {{ site.title }}</title></head>\n\
+         <body><main>{% block content %}{{ page.content }}{% endblock content %}</main></body></html>\n",
+    )?;
+    write(theme.join("page.html"), "{% extends \"base.html\" %}\n")?;
+    write(
+        theme.join("post.html"),
+        "{% extends \"base.html\" %}\n{% block content %}<article><h1>{{ page.title }}</h1>\
+         {{ page.content }}</article>\n<ul>{% for tag in page.tags %}<li>{{ tag }}</li>{% endfor %}</ul>\n\
+         {% endblock content %}\n",
+    )?;
+    write(
+        theme.join("index.html"),
+        "{% extends \"base.html\" %}\n{% block content %}<ul>{% for post in site.posts | slice(end=20) %}\
+         <li><a href=\"{{ post.url }}\">{{ post.title }}</a>{{ post.excerpt }}</li>{% endfor %}</ul>\n\
+         {% endblock content %}\n",
+    )?;
+    write(
+        theme.join("tag.html"),
+        "{% extends \"base.html\" %}\n{% block content %}<ul>{% for post in page.posts %}\
+         <li><a href=\"{{ post.url }}\">{{ post.title }}</a></li>{% endfor %}</ul>\n\
+         {% endblock content %}\n",
+    )
+}
+
+fn write(path: impl AsRef<Path>, contents: &str) -> miette::Result<()> {
+    let path = path.as_ref();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).into_diagnostic()?;
+    }
+    std::fs::write(path, contents)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("writing `{}`", path.display()))
+}
@@ -0,0 +1,182 @@
+use std::path::PathBuf;
+
+use clap::{Args, Subcommand};
+use ebg::{
+    export::{
+        archive::{export_archive, ArchiveFormat},
+        epub::{write_epub, Book},
+        pdf::{default_output, export_pdf},
+        ExportError,
+    },
+    index::{SiteIndex, SiteMetadata},
+};
+use miette::IntoDiagnostic;
+use tokio::runtime::Runtime;
+
+use super::{
+    build::{find_site_root, source_path_in_site},
+    Command,
+};
+
+#[derive(Args)]
+pub struct ExportOptions {
+    #[command(subcommand)]
+    format: ExportFormat,
+}
+
+#[derive(Subcommand)]
+enum ExportFormat {
+    /// Export a page as a PDF using the theme's print styling
+    Pdf(PdfOptions),
+    /// Bundle a series or a list of posts into an EPUB book
+    Epub(EpubOptions),
+    /// Export a snapshot of the whole site that can be browsed offline
+    Archive(ArchiveOptions),
+}
+
+#[derive(Args)]
+struct PdfOptions {
+    /// The source file of the page to export
+    page: PathBuf,
+
+    /// Where to write the PDF [default: the page's slug with a .pdf extension]
+    #[arg(long, short = 'o')]
+    output: Option<PathBuf>,
+
+    /// The command used to convert HTML to PDF, overriding Site.toml
+    #[arg(long, num_args = 1.., allow_hyphen_values = true)]
+    command: Option<Vec<String>>,
+}
+
+impl Command for ExportOptions {
+    fn run(self) -> miette::Result<()> {
+        match self.format {
+            ExportFormat::Pdf(options) => options.run(),
+            ExportFormat::Epub(options) => options.run(),
+            ExportFormat::Archive(options) => options.run(),
+        }
+    }
+}
+
+#[derive(Args)]
+struct EpubOptions {
+    /// Include all posts in this series, in order of publication
+    #[arg(long)]
+    series: Option<String>,
+
+    /// Source files of pages to include as chapters, in order
+    pages: Vec<PathBuf>,
+
+    /// The title of the book [default: the series name]
+    #[arg(long)]
+    title: Option<String>,
+
+    /// Where to write the book
+    #[arg(long, short = 'o', default_value = "book.epub")]
+    output: PathBuf,
+
+    /// The site directory
+    #[arg(long)]
+    site: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct ArchiveOptions {
+    #[arg(long, value_enum, default_value = "zip")]
+    format: ArchiveFormat,
+
+    /// Where to write the archive [default: `site.zip` or `site-archive/`
+    /// depending on the format]
+    #[arg(long, short = 'o')]
+    output: Option<PathBuf>,
+
+    /// The site directory
+    path: Option<PathBuf>,
+}
+
+impl Command for PdfOptions {
+    fn run(self) -> miette::Result<()> {
+        Runtime::new().into_diagnostic()?.block_on(async move {
+            let root = find_site_root(self.page.parent())?;
+            let source_path = source_path_in_site(&root, &self.page)?;
+
+            let site = SiteIndex::from_directory(&root, true).await?;
+            let site = site.render()?;
+
+            let output = self
+                .output
+                .unwrap_or_else(|| default_output(&site, &source_path));
+            let command = self
+                .command
+                .unwrap_or_else(|| site.config().export.pdf.command.clone());
+            export_pdf(&site, &source_path, &output, &command).await?;
+
+            println!("Wrote {}", output.display());
+            Ok(())
+        })
+    }
+}
+
+impl Command for EpubOptions {
+    fn run(self) -> miette::Result<()> {
+        Runtime::new().into_diagnostic()?.block_on(async move {
+            let root = find_site_root(self.site.as_deref())?;
+            let site = SiteIndex::from_directory(&root, false).await?;
+            let site = site.render()?;
+
+            let mut book = match &self.series {
+                Some(series) => Book::from_series(&site, series),
+                None => Book {
+                    title: String::new(),
+                    chapters: vec![],
+                },
+            };
+            for page in &self.pages {
+                let source_path = source_path_in_site(&root, page)?;
+                let page = site
+                    .find_page_by_source_path(&source_path)
+                    .ok_or(ExportError::PageNotFound(source_path))?;
+                book.chapters.push(page);
+            }
+            if let Some(title) = self.title {
+                book.title = title;
+            }
+            if book.title.is_empty() {
+                book.title = site.title().to_string();
+            }
+            if book.chapters.is_empty() {
+                return Err(ExportError::Empty.into());
+            }
+
+            let file = std::fs::File::create(&self.output)
+                .map_err(|e| ExportError::Write(self.output.clone(), e))?;
+            write_epub(&site, &book, file)?;
+
+            println!(
+                "Wrote {} chapters to {}",
+                book.chapters.len(),
+                self.output.display()
+            );
+            Ok(())
+        })
+    }
+}
+
+impl Command for ArchiveOptions {
+    fn run(self) -> miette::Result<()> {
+        Runtime::new().into_diagnostic()?.block_on(async move {
+            let root = find_site_root(self.path.as_deref())?;
+            let site = SiteIndex::from_directory(&root, false).await?;
+            let site = site.render()?;
+
+            let output = self.output.unwrap_or_else(|| match self.format {
+                ArchiveFormat::Zip => "site.zip".into(),
+                ArchiveFormat::SingleFile => "site-archive".into(),
+            });
+            export_archive(&site, self.format, &output).await?;
+
+            println!("Wrote {}", output.display());
+            Ok(())
+        })
+    }
+}
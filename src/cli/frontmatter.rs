@@ -0,0 +1,147 @@
+use std::path::PathBuf;
+
+use clap::{Args, Subcommand};
+use ebg::index::{FrontMatterEdit, SiteIndex, SiteMetadata};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use miette::{Context, IntoDiagnostic};
+use tokio::runtime::Runtime;
+
+use super::{build::find_site_root, Command};
+
+#[derive(Args)]
+pub struct FrontmatterOptions {
+    #[command(subcommand)]
+    command: EditCommand,
+
+    /// Only change pages whose source path matches this glob
+    ///
+    /// Can be given more than once. By default, every page is changed.
+    #[arg(long, global = true, value_name = "GLOB")]
+    only: Vec<String>,
+
+    /// Print the pages that would change without changing them
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    /// The site directory
+    #[arg(long, global = true)]
+    site: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum EditCommand {
+    /// Set a key to a value, which is parsed as YAML
+    Set {
+        key: String,
+        value: String,
+        /// Leave pages that already have the key alone
+        #[arg(long)]
+        if_missing: bool,
+    },
+    /// Rename a key, or with `--in`, replace a value within a key
+    Rename {
+        from: String,
+        to: String,
+        /// The key whose value, or list entries, should be renamed
+        #[arg(long = "in", value_name = "KEY")]
+        key: Option<String>,
+    },
+    /// Remove a key, or with `--value`, remove an entry from a list
+    Remove {
+        key: String,
+        #[arg(long)]
+        value: Option<String>,
+    },
+}
+
+impl EditCommand {
+    fn into_edit(self) -> miette::Result<FrontMatterEdit> {
+        Ok(match self {
+            EditCommand::Set {
+                key,
+                value,
+                if_missing,
+            } => FrontMatterEdit::Set {
+                key,
+                value: serde_yaml::from_str(&value)
+                    .into_diagnostic()
+                    .with_context(|| format!("parsing `{value}` as YAML"))?,
+                if_missing,
+            },
+            EditCommand::Rename {
+                from,
+                to,
+                key: None,
+            } => FrontMatterEdit::RenameKey { from, to },
+            EditCommand::Rename {
+                from,
+                to,
+                key: Some(key),
+            } => FrontMatterEdit::RenameValue { key, from, to },
+            EditCommand::Remove { key, value: None } => FrontMatterEdit::Remove { key },
+            EditCommand::Remove {
+                key,
+                value: Some(value),
+            } => FrontMatterEdit::RemoveValue { key, value },
+        })
+    }
+}
+
+impl Command for FrontmatterOptions {
+    fn run(self) -> miette::Result<()> {
+        let edit = self.command.into_edit()?;
+        let filter = build_filter(&self.only)?;
+
+        Runtime::new().into_diagnostic()?.block_on(async move {
+            let root = find_site_root(self.site.as_deref())?;
+            let site = SiteIndex::from_directory(&root, true).await?;
+
+            let mut changed = 0;
+            for page in site.all_pages() {
+                if !filter.is_match(page.source_path()) {
+                    continue;
+                }
+                let Some(contents) = page
+                    .edit_frontmatter(&edit)
+                    .into_diagnostic()
+                    .with_context(|| {
+                        format!("parsing frontmatter of `{}`", page.source_path().display())
+                    })?
+                else {
+                    continue;
+                };
+
+                println!("{}", page.source_path().display());
+                changed += 1;
+                if !self.dry_run {
+                    let path = site.root_dir().join(page.source_path());
+                    std::fs::write(&path, contents)
+                        .into_diagnostic()
+                        .with_context(|| format!("writing `{}`", path.display()))?;
+                }
+            }
+
+            if self.dry_run {
+                println!("{changed} page(s) would change");
+            } else {
+                println!("Changed {changed} page(s)");
+            }
+            Ok(())
+        })
+    }
+}
+
+fn build_filter(patterns: &[String]) -> miette::Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    if patterns.is_empty() {
+        builder.add(Glob::new("**").into_diagnostic()?);
+    }
+    for pattern in patterns {
+        builder.add(
+            Glob::new(pattern)
+                .into_diagnostic()
+                .with_context(|| format!("parsing `{pattern}`"))?,
+        );
+    }
+    builder.build().into_diagnostic()
+}
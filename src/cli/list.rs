@@ -1,8 +1,11 @@
 use std::path::PathBuf;
 
 use clap::{Args, ValueEnum};
-use ebg::index::{PageKind, SiteIndex};
-use miette::IntoDiagnostic;
+use ebg::{
+    generator::Taxonomy,
+    index::{PageKind, SiteIndex},
+};
+use miette::{bail, IntoDiagnostic};
 use tokio::runtime::Runtime;
 
 use super::{build::find_site_root, Command};
@@ -11,6 +14,9 @@ use super::{build::find_site_root, Command};
 pub struct ListOptions {
     scope: Scope,
     path: Option<PathBuf>,
+    /// List the tags or categories in `Site.toml` that no post uses
+    #[arg(long)]
+    unused: bool,
 }
 
 #[derive(ValueEnum, Clone, PartialEq)]
@@ -19,6 +25,10 @@ pub enum Scope {
     Posts,
     Pages,
     Drafts,
+    /// Tags with their post counts and slugs
+    Tags,
+    /// Categories with their post counts and slugs
+    Categories,
 }
 
 impl Command for ListOptions {
@@ -26,12 +36,34 @@ impl Command for ListOptions {
         Runtime::new().into_diagnostic()?.block_on(async move {
             let path = find_site_root(self.path.as_deref())?;
 
+            let taxonomy = match self.scope {
+                Scope::Tags => Some(Taxonomy::Tags),
+                Scope::Categories => Some(Taxonomy::Categories),
+                _ => None,
+            };
+            if self.unused && taxonomy.is_none() {
+                bail!("`--unused` only applies to tags and categories");
+            }
+
             let site = SiteIndex::from_directory(
                 &path,
                 self.scope == Scope::Drafts || self.scope == Scope::All,
             )
             .await?;
 
+            if let Some(taxonomy) = taxonomy {
+                if self.unused {
+                    for term in taxonomy.unused(&site) {
+                        println!("{term}");
+                    }
+                } else {
+                    for (term, count) in taxonomy.counts(&site) {
+                        println!("{term}\t{count}\t{}", Taxonomy::slug(term));
+                    }
+                }
+                return Ok(());
+            }
+
             let items: Vec<_> = match self.scope {
                 Scope::All => site.all_pages().collect(),
                 Scope::Posts => site.posts().collect(),
@@ -40,6 +72,7 @@ impl Command for ListOptions {
                     .filter(|page| page.kind() == PageKind::Page)
                     .collect(),
                 Scope::Drafts => site.all_pages().filter(|page| !page.published()).collect(),
+                Scope::Tags | Scope::Categories => unreachable!(),
             };
 
             for item in items {
@@ -1,9 +1,19 @@
 //! Code for implementing the command line interface to EBG.
 
 pub mod about;
+pub mod audit;
 pub mod build;
+pub mod check_links;
+pub mod console;
+pub mod debug;
+pub mod dev;
+pub mod export;
+pub mod frontmatter;
 pub mod list;
 pub mod new_post;
+pub mod posts;
+pub mod render;
+pub mod syndicate;
 
 /// Describes a command that can be run from the command line.
 ///
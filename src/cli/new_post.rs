@@ -4,7 +4,8 @@ use std::{
 };
 
 use clap::Parser;
-use miette::IntoDiagnostic;
+use ebg::index::DRAFTS_DIR;
+use miette::{Context, IntoDiagnostic};
 use tracing::debug;
 
 #[derive(Parser)]
@@ -13,6 +14,10 @@ pub struct NewPostOptions {
     /// Open the new post in the default editor
     #[clap(long)]
     open: bool,
+    /// Start the post in `_drafts`, without a date, until `ebg posts publish`
+    /// moves it into `_posts`
+    #[clap(long)]
+    draft: bool,
 }
 
 impl super::Command for NewPostOptions {
@@ -27,26 +32,33 @@ impl super::Command for NewPostOptions {
             ));
         }
 
-        let posts_dir = root.join("_posts");
+        let posts_dir = root.join(if self.draft { DRAFTS_DIR } else { "_posts" });
 
         if !posts_dir.exists() {
             create_dir(&posts_dir).into_diagnostic()?;
         }
 
-        let post_filename = posts_dir.join(format!(
-            "{}-{}.md",
-            chrono::Local::now().format("%Y-%m-%d"),
-            slug::slugify(&self.title)
-        ));
+        let slug = slug::slugify(&self.title);
+        let post_filename = if self.draft {
+            posts_dir.join(format!("{slug}.md"))
+        } else {
+            posts_dir.join(format!(
+                "{}-{slug}.md",
+                chrono::Local::now().format("%Y-%m-%d"),
+            ))
+        };
         debug!("creating new post at {}", post_filename.display());
 
-        let mut file = File::create(&post_filename).into_diagnostic()?;
+        // Drafts are already left out of the site until they are published
+        let published = if self.draft { "" } else { "published: false\n" };
+        let mut file = File::create_new(&post_filename)
+            .into_diagnostic()
+            .with_context(|| format!("creating `{}`", post_filename.display()))?;
         file.write_all(
             format!(
                 r#"---
 layout: post
-published: false
----
+{published}---
 
 # {title}
 "#,
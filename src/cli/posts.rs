@@ -0,0 +1,207 @@
+use std::path::{Path, PathBuf};
+
+use clap::{Args, Subcommand};
+use ebg::{
+    index::{
+        plan_post_renames, plan_publish, rewrite_source_links, DraftPublish, PostLayout,
+        PostRename, RenameOptions, SiteIndex, SiteMetadata, DRAFTS_DIR,
+    },
+    syndication::SyndicationState,
+};
+use miette::{bail, Context, IntoDiagnostic};
+use tokio::runtime::Runtime;
+
+use super::{
+    build::{find_site_root, source_path_in_site},
+    Command,
+};
+
+#[derive(Args)]
+pub struct PostsOptions {
+    #[command(subcommand)]
+    command: PostsCommand,
+}
+
+#[derive(Subcommand)]
+enum PostsCommand {
+    /// Give post source files consistent names
+    ///
+    /// The date in each filename is updated to match the date in the
+    /// frontmatter, and source links in other pages and the syndication state
+    /// are updated to follow the renamed posts.
+    Rename(RenameArgs),
+    /// Move a draft into the posts directory, dated today
+    ///
+    /// Source links in other pages are updated to follow it.
+    Publish(PublishArgs),
+}
+
+impl Command for PostsOptions {
+    fn run(self) -> miette::Result<()> {
+        match self.command {
+            PostsCommand::Rename(args) => args.run(),
+            PostsCommand::Publish(args) => args.run(),
+        }
+    }
+}
+
+#[derive(Args)]
+struct RenameArgs {
+    /// Convert single-file posts into bundle directories
+    #[arg(long, conflicts_with = "flat")]
+    bundle: bool,
+
+    /// Convert bundle directories into single-file posts
+    #[arg(long)]
+    flat: bool,
+
+    /// Derive each post's slug from its title. This changes the post's URL.
+    #[arg(long)]
+    slugify: bool,
+
+    /// Print the changes without making them
+    #[arg(long)]
+    dry_run: bool,
+
+    /// The site directory
+    path: Option<PathBuf>,
+}
+
+impl Command for RenameArgs {
+    fn run(self) -> miette::Result<()> {
+        let options = RenameOptions {
+            layout: match (self.bundle, self.flat) {
+                (true, _) => Some(PostLayout::Bundle),
+                (_, true) => Some(PostLayout::Flat),
+                _ => None,
+            },
+            slugify: self.slugify,
+        };
+
+        Runtime::new().into_diagnostic()?.block_on(async move {
+            let root = find_site_root(self.path.as_deref())?;
+            let site = SiteIndex::from_directory(&root, true).await?;
+            let renames = plan_post_renames(&site, &options);
+            apply_renames(&site, &renames, self.dry_run)?;
+            println!("Renamed {} post(s)", renames.len());
+            Ok(())
+        })
+    }
+}
+
+#[derive(Args)]
+struct PublishArgs {
+    /// The draft to publish, such as `_drafts/hello.md` or a bundle directory
+    draft: PathBuf,
+
+    /// Print the changes without making them
+    #[arg(long)]
+    dry_run: bool,
+}
+
+impl Command for PublishArgs {
+    fn run(self) -> miette::Result<()> {
+        let draft = if self.draft.is_dir() {
+            self.draft.join("index.md")
+        } else {
+            self.draft.clone()
+        };
+        let draft = std::fs::canonicalize(&draft)
+            .into_diagnostic()
+            .with_context(|| format!("finding `{}`", draft.display()))?;
+        Runtime::new().into_diagnostic()?.block_on(async move {
+            let root = find_site_root(draft.parent())?;
+            let source_path = source_path_in_site(&root, &draft)?;
+            let site = SiteIndex::from_directory(&root, true).await?;
+            let Some(page) = site.find_page_by_source_path(&source_path) else {
+                bail!("`{}` is not a post", source_path.display());
+            };
+            let Some(DraftPublish { rename, contents }) = plan_publish(&site, page) else {
+                bail!(
+                    help = format!("only posts in `{DRAFTS_DIR}` can be published"),
+                    "`{}` is not a draft",
+                    source_path.display()
+                );
+            };
+            let to = site.root_dir().join(&rename.to);
+            if to.exists() {
+                bail!("`{}` already exists", rename.to.display());
+            }
+            apply_renames(&site, std::slice::from_ref(&rename), self.dry_run)?;
+            // Written after the move, and includes the link updates
+            // `apply_renames` made to the draft
+            if let Some(contents) = contents {
+                println!("updating {}", rename.to.display());
+                if !self.dry_run {
+                    std::fs::write(&to, contents)
+                        .into_diagnostic()
+                        .with_context(|| format!("writing `{}`", to.display()))?;
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Moves posts and updates the source links that point to them, or just
+/// prints what would change with `dry_run`.
+fn apply_renames(site: &SiteIndex, renames: &[PostRename], dry_run: bool) -> miette::Result<()> {
+    for page in site.all_pages() {
+        let Some(contents) = rewrite_source_links(site, page, renames) else {
+            continue;
+        };
+        println!("updating links in {}", page.source_path().display());
+        if !dry_run {
+            let path = site.root_dir().join(page.source_path());
+            std::fs::write(&path, contents)
+                .into_diagnostic()
+                .with_context(|| format!("writing `{}`", path.display()))?;
+        }
+    }
+
+    for rename in renames {
+        println!("{} → {}", rename.from.display(), rename.to.display());
+        if !dry_run {
+            move_post(site.root_dir(), rename)
+                .into_diagnostic()
+                .with_context(|| format!("moving `{}`", rename.from.display()))?;
+        }
+    }
+
+    // The syndication state is keyed by source path, so it has to follow the
+    // posts too
+    let state_path = site.root_dir().join(&site.config().syndication.state);
+    let mut state = SyndicationState::load(&state_path)?;
+    let mut changed = false;
+    for rename in renames {
+        changed |= state.rename(&rename.from, &rename.to);
+    }
+    if changed {
+        println!("updating {}", site.config().syndication.state.display());
+        if !dry_run {
+            state.save(&state_path)?;
+        }
+    }
+    Ok(())
+}
+
+fn move_post(root: &Path, rename: &PostRename) -> std::io::Result<()> {
+    let from = root.join(&rename.from);
+    let to = root.join(&rename.to);
+    let is_bundle = |path: &Path| path.file_stem().is_some_and(|stem| stem == "index");
+    let from_dir = from.parent().unwrap();
+    let to_dir = to.parent().unwrap();
+
+    match (is_bundle(&from), is_bundle(&to)) {
+        // Move the whole directory so the other files come along
+        (true, true) => std::fs::rename(from_dir, to_dir),
+        (true, false) => {
+            std::fs::rename(&from, &to)?;
+            std::fs::remove_dir(from_dir)
+        }
+        (false, _) => {
+            std::fs::create_dir_all(to_dir)?;
+            std::fs::rename(&from, &to)
+        }
+    }
+}
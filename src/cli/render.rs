@@ -0,0 +1,67 @@
+use std::{io::Write, path::PathBuf};
+
+use clap::Args;
+use ebg::{
+    generator::{GeneratorContext, Options},
+    index::SiteIndex,
+};
+use miette::IntoDiagnostic;
+use tokio::runtime::Runtime;
+
+use super::{
+    build::{find_site_root, source_path_in_site},
+    Command,
+};
+
+/// Render a single page and print the HTML
+#[derive(Args)]
+pub struct RenderOptions {
+    /// The source file of the page to render
+    page: PathBuf,
+
+    /// Print only the page's contents, without the theme's layout
+    #[arg(long)]
+    fragment: bool,
+}
+
+impl Command for RenderOptions {
+    fn run(self) -> miette::Result<()> {
+        Runtime::new().into_diagnostic()?.block_on(async move {
+            let root = find_site_root(self.page.parent())?;
+            let source_path = source_path_in_site(&root, &self.page)?;
+
+            let site = SiteIndex::from_directory(&root, true).await?;
+            let site = site.render()?;
+            let page = site.find_page_by_source_path(&source_path).ok_or_else(|| {
+                miette::miette!("no page with source path `{}`", source_path.display())
+            })?;
+
+            let html = if self.fragment {
+                page.rendered_contents().to_string()
+            } else {
+                let options = preview_options(root);
+                let gcx = GeneratorContext::new(&site, &options)?;
+                gcx.page_html(page, &site)?
+            };
+
+            std::io::stdout()
+                .write_all(html.as_bytes())
+                .into_diagnostic()?;
+            Ok(())
+        })
+    }
+}
+
+/// Generator options for commands that render pages without writing them
+pub(crate) fn preview_options(root: PathBuf) -> Options {
+    Options {
+        path: Some(root),
+        destination: "publish".into(),
+        unpublished: true,
+        fsync: false,
+        only: vec![],
+        wait: false,
+        force: false,
+        verify: false,
+    }
+}
@@ -0,0 +1,121 @@
+use std::path::PathBuf;
+
+use chrono::Utc;
+use clap::Args;
+use ebg::{
+    generator::canonical_url,
+    http::HttpClient,
+    index::{PageMetadata, SiteIndex, SiteMetadata},
+    lock::{DirLock, LockMode},
+    syndication::{compose_status, html_to_text, mastodon, SyndicatedCopy, SyndicationState},
+};
+use miette::{Context, IntoDiagnostic};
+use tokio::runtime::Runtime;
+use tracing::info;
+
+use super::{build::find_site_root, Command};
+
+#[derive(Args)]
+pub struct SyndicateOptions {
+    path: Option<PathBuf>,
+
+    /// Print what would be posted without contacting any services
+    #[arg(long)]
+    dry_run: bool,
+}
+
+impl Command for SyndicateOptions {
+    fn run(self) -> miette::Result<()> {
+        Runtime::new().into_diagnostic()?.block_on(async move {
+            let root = find_site_root(self.path.as_deref())?;
+            let site = SiteIndex::from_directory(&root, false).await?;
+            let config = &site.config().syndication;
+            let state_path = root.join(&config.state);
+
+            let Some(mastodon) = &config.mastodon else {
+                miette::bail!(
+                    help = "add a `[syndication.mastodon]` section to Site.toml",
+                    "no syndication targets are configured"
+                );
+            };
+
+            let mut state = SyndicationState::load(&state_path)?;
+            let first_run = config.since.is_none() && state.started().is_none();
+            let since = state.since(config.since);
+            if first_run {
+                println!(
+                    "Only posts published from now on will be syndicated; set `since` in \
+                     `[syndication]` to include older ones"
+                );
+                if !self.dry_run {
+                    state.save(&state_path)?;
+                }
+            }
+
+            let rendered = site.render()?;
+            let mut posts: Vec<_> = rendered
+                .posts()
+                .filter(|post| {
+                    !site
+                        .syndication()
+                        .is_syndicated(post.source_path(), mastodon::SERVICE)
+                })
+                .filter(|post| post.publish_date().is_some_and(|date| date >= since))
+                .collect();
+            posts.sort_by_key(|post| post.publish_date());
+
+            if posts.is_empty() {
+                println!("Nothing new to syndicate");
+                return Ok(());
+            }
+
+            let token = if self.dry_run {
+                None
+            } else {
+                Some(mastodon.token()?)
+            };
+            // Another command may be filling in the same cache
+            let cache = root.join(&site.config().http.cache_dir);
+            let _lock = DirLock::acquire(&cache, LockMode::Wait, |holder| {
+                eprintln!("Waiting for {holder} to finish with `{}`", cache.display())
+            })?;
+            let client = HttpClient::new(&site.config().http, &root);
+
+            for post in posts {
+                let url = canonical_url(&rendered, post);
+                let summary =
+                    html_to_text(post.rendered_excerpt().unwrap_or(post.rendered_contents()));
+                let status = compose_status(post.title(), &summary, &url, mastodon.max_length);
+
+                let Some(token) = &token else {
+                    println!("--- {} ---\n{status}\n", post.source_path().display());
+                    continue;
+                };
+
+                info!(
+                    "syndicating {} to {}",
+                    post.source_path().display(),
+                    mastodon::SERVICE
+                );
+                let remote_url = mastodon
+                    .post_status(&client, token, &status, &url)
+                    .await
+                    .with_context(|| format!("syndicating `{}`", post.source_path().display()))?;
+                println!("{} → {remote_url}", post.source_path().display());
+
+                state.record(
+                    post.source_path(),
+                    SyndicatedCopy {
+                        service: mastodon::SERVICE.into(),
+                        url: remote_url,
+                        date: Utc::now(),
+                    },
+                );
+                // Save after every post so a later failure doesn't cause duplicates
+                state.save(&state_path)?;
+            }
+
+            Ok(())
+        })
+    }
+}
@@ -57,7 +57,7 @@ impl DiagnosticContext {
 
     fn record_report(&mut self, report: miette::Report) {
         debug!("recording diagnostic: {}", report);
-        if <_ as AsRef<(dyn Diagnostic + 'static)>>::as_ref(&report)
+        if <_ as AsRef<dyn Diagnostic + 'static>>::as_ref(&report)
             .severity()
             .unwrap_or(miette::Severity::Error)
             >= miette::Severity::Error
@@ -0,0 +1,305 @@
+//! Self-contained snapshots of the site for offline browsing.
+//!
+//! Generated sites use root-relative links like `/blog/` that only work when
+//! served by a web server. The archive exporters rewrite these so the snapshot
+//! can be opened straight from disk.
+
+use std::{
+    collections::HashMap,
+    io::{Seek, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use clap::ValueEnum;
+use lol_html::{element, html_content::ContentType, rewrite_str, RewriteStrSettings};
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+use crate::{index::PageMetadata, renderer::RenderedSite};
+
+use super::{media_type, ExportError, Scratch};
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ArchiveFormat {
+    /// A zip file of the whole site with links rewritten for `file://` browsing
+    Zip,
+    /// A directory with one HTML file per page, with stylesheets, scripts, and
+    /// images inlined
+    SingleFile,
+}
+
+/// Exports the site in the given format.
+///
+/// For [`ArchiveFormat::Zip`], `output` is the zip file to create. For
+/// [`ArchiveFormat::SingleFile`], it is the directory to write pages into.
+pub async fn export_archive(
+    site: &RenderedSite<'_>,
+    format: ArchiveFormat,
+    output: &Path,
+) -> Result<(), ExportError> {
+    let scratch = Scratch::generate(site).await?;
+    match format {
+        ArchiveFormat::Zip => {
+            let file =
+                std::fs::File::create(output).map_err(|e| ExportError::Write(output.into(), e))?;
+            write_zip(scratch.root(), file)
+        }
+        ArchiveFormat::SingleFile => write_single_files(site, scratch.root(), output),
+    }
+}
+
+fn write_zip(root: &Path, out: impl Write + Seek) -> Result<(), ExportError> {
+    let mut zip = ZipWriter::new(out);
+    for entry in walkdir::WalkDir::new(root).sort_by_file_name() {
+        let entry = entry.map_err(|e| ExportError::Read(root.into(), e.into()))?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(root).unwrap();
+        let name = relative.to_string_lossy().replace('\\', "/");
+
+        let contents =
+            std::fs::read(entry.path()).map_err(|e| ExportError::Read(entry.path().into(), e))?;
+        let contents = if media_type(entry.path()) == "text/html" {
+            rewrite_links_for_files(root, relative, &String::from_utf8_lossy(&contents))?
+                .into_bytes()
+        } else {
+            contents
+        };
+
+        zip.start_file(name.as_str(), SimpleFileOptions::default())
+            .map_err(ExportError::Zip)?;
+        zip.write_all(&contents)
+            .map_err(|e| ExportError::Write(relative.into(), e))?;
+    }
+    zip.finish().map_err(ExportError::Zip)?;
+    Ok(())
+}
+
+/// Rewrites every local link in a page into a relative link to a file.
+///
+/// `page` is the path of the HTML file relative to `root`.
+fn rewrite_links_for_files(root: &Path, page: &Path, html: &str) -> Result<String, ExportError> {
+    let rewrite = |el: &mut lol_html::html_content::Element, attr: &str| {
+        if let Some(link) = el.get_attribute(attr) {
+            if let Some(new_link) = relative_file_link(root, page, &link) {
+                el.set_attribute(attr, &new_link)?;
+            }
+        }
+        Ok(())
+    };
+    let rewritten = rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![
+                element!("a[href], link[href]", |el| rewrite(el, "href")),
+                element!(
+                    "img[src], script[src], source[src], video[src], audio[src]",
+                    |el| { rewrite(el, "src") }
+                ),
+                element!("video[poster]", |el| rewrite(el, "poster")),
+            ],
+            ..RewriteStrSettings::new()
+        },
+    );
+    rewritten.map_err(|e| ExportError::Rewrite(page.into(), e))
+}
+
+/// Splits a link into the path and any query or fragment suffix.
+fn split_suffix(link: &str) -> (&str, &str) {
+    match link.find(['#', '?']) {
+        Some(i) => link.split_at(i),
+        None => (link, ""),
+    }
+}
+
+fn is_local(link: &str) -> bool {
+    !(link.is_empty()
+        || link.contains("://")
+        || link.starts_with("//")
+        || link.starts_with('#')
+        || link.starts_with("mailto:")
+        || link.starts_with("tel:")
+        || link.starts_with("data:")
+        || link.starts_with("javascript:"))
+}
+
+/// Resolves a link found in `page` to a path relative to the site root.
+fn resolve(page: &Path, link: &str) -> PathBuf {
+    let joined = match link.strip_prefix('/') {
+        Some(link) => PathBuf::from(link),
+        None => page.parent().unwrap_or(Path::new("")).join(link),
+    };
+    let mut normalized = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::Normal(part) => normalized.push(part),
+            _ => (),
+        }
+    }
+    normalized
+}
+
+fn relative_file_link(root: &Path, page: &Path, link: &str) -> Option<String> {
+    if !is_local(link) {
+        return None;
+    }
+    let (path, suffix) = split_suffix(link);
+    let mut target = resolve(page, path);
+    if path.ends_with('/') || path.is_empty() || root.join(&target).is_dir() {
+        target.push("index.html");
+    }
+    let relative = pathdiff::diff_paths(&target, page.parent().unwrap_or(Path::new("")))?;
+    Some(relative.to_string_lossy().replace('\\', "/") + suffix)
+}
+
+/// Chooses the file name a page is written to in single file mode.
+fn single_file_name(url: &str) -> String {
+    let name = url.trim_matches('/').replace('/', "-");
+    if name.is_empty() {
+        "index.html".into()
+    } else {
+        name + ".html"
+    }
+}
+
+fn write_single_files(
+    site: &RenderedSite<'_>,
+    root: &Path,
+    output: &Path,
+) -> Result<(), ExportError> {
+    std::fs::create_dir_all(output).map_err(|e| ExportError::Write(output.into(), e))?;
+
+    // Maps the generated file of each page to its single file name
+    let pages: HashMap<PathBuf, String> = site
+        .all_pages()
+        .map(|page| {
+            let url = page.url();
+            (
+                resolve(Path::new("index.html"), &format!("/{url}")).join("index.html"),
+                single_file_name(&url),
+            )
+        })
+        .collect();
+
+    for (page, name) in &pages {
+        let html = std::fs::read_to_string(root.join(page))
+            .map_err(|e| ExportError::Read(root.join(page), e))?;
+        let html = inline_assets(root, page, &pages, &html)?;
+        let dest = output.join(name);
+        std::fs::write(&dest, html).map_err(|e| ExportError::Write(dest, e))?;
+    }
+    Ok(())
+}
+
+/// Inlines stylesheets, scripts, and images and points links to other pages at
+/// their single file versions.
+fn inline_assets(
+    root: &Path,
+    page: &Path,
+    pages: &HashMap<PathBuf, String>,
+    html: &str,
+) -> Result<String, ExportError> {
+    let local_file = |link: &str| -> Option<PathBuf> {
+        if !is_local(link) {
+            return None;
+        }
+        let path = root.join(resolve(page, split_suffix(link).0));
+        path.is_file().then_some(path)
+    };
+
+    let rewritten = rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![
+                element!("a[href]", |el| {
+                    let href = el.get_attribute("href").unwrap();
+                    if is_local(&href) {
+                        let (path, suffix) = split_suffix(&href);
+                        let target = resolve(page, path).join("index.html");
+                        if let Some(name) = pages.get(&target) {
+                            el.set_attribute("href", &format!("{name}{suffix}"))?;
+                        }
+                    }
+                    Ok(())
+                }),
+                element!("link[rel=stylesheet][href]", |el| {
+                    if let Some(css) = local_file(&el.get_attribute("href").unwrap())
+                        .and_then(|path| std::fs::read_to_string(path).ok())
+                    {
+                        el.replace(&format!("<style>\n{css}\n</style>"), ContentType::Html);
+                    }
+                    Ok(())
+                }),
+                element!("script[src]", |el| {
+                    if let Some(js) = local_file(&el.get_attribute("src").unwrap())
+                        .and_then(|path| std::fs::read_to_string(path).ok())
+                    {
+                        el.remove_attribute("src");
+                        el.set_inner_content(&js, ContentType::Html);
+                    }
+                    Ok(())
+                }),
+                element!("img[src]", |el| {
+                    if let Some(path) = local_file(&el.get_attribute("src").unwrap()) {
+                        if let Ok(data) = std::fs::read(&path) {
+                            let uri = format!(
+                                "data:{};base64,{}",
+                                media_type(&path),
+                                STANDARD.encode(data)
+                            );
+                            el.set_attribute("src", &uri)?;
+                        }
+                    }
+                    Ok(())
+                }),
+            ],
+            ..RewriteStrSettings::new()
+        },
+    );
+    rewritten.map_err(|e| ExportError::Rewrite(page.into(), e))
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::{relative_file_link, single_file_name};
+
+    #[test]
+    fn relative_links() {
+        let root = Path::new("/nonexistent");
+        let page = Path::new("blog/2024/01/01/hello/index.html");
+        assert_eq!(
+            relative_file_link(root, page, "/about/").as_deref(),
+            Some("../../../../../about/index.html")
+        );
+        assert_eq!(
+            relative_file_link(root, page, "/style.css").as_deref(),
+            Some("../../../../../style.css")
+        );
+        assert_eq!(
+            relative_file_link(root, Path::new("index.html"), "/#top").as_deref(),
+            Some("index.html#top")
+        );
+        assert_eq!(
+            relative_file_link(root, page, "image.png").as_deref(),
+            Some("image.png")
+        );
+        assert_eq!(relative_file_link(root, page, "https://example.com/"), None);
+        assert_eq!(relative_file_link(root, page, "#section"), None);
+    }
+
+    #[test]
+    fn single_file_names() {
+        assert_eq!(single_file_name(""), "index.html");
+        assert_eq!(single_file_name("about"), "about.html");
+        assert_eq!(
+            single_file_name("blog/2024/01/01/hello/"),
+            "blog-2024-01-01-hello.html"
+        );
+    }
+}
@@ -0,0 +1,327 @@
+//! Bundling posts into an EPUB book.
+//!
+//! Each post becomes a chapter built from its rendered HTML. Local images
+//! referenced by the posts are embedded in the book so it can be read offline.
+
+use std::{
+    collections::HashMap,
+    io::{Seek, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::Utc;
+use quick_xml::escape::escape;
+use serde::Deserialize;
+use tracing::debug;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::{
+    index::{PageMetadata, SiteMetadata},
+    renderer::{RenderedPageRef, RenderedSite},
+};
+
+use super::{extension, media_type, ExportError};
+
+/// The `[export.epub]` section of `Site.toml`
+#[derive(Deserialize, Debug)]
+pub struct EpubConfig {
+    /// An image to use as the book's cover, relative to the site root
+    pub cover: Option<PathBuf>,
+    #[serde(default = "default_language")]
+    pub language: String,
+}
+
+impl Default for EpubConfig {
+    fn default() -> Self {
+        Self {
+            cover: None,
+            language: default_language(),
+        }
+    }
+}
+
+fn default_language() -> String {
+    "en".into()
+}
+
+/// Describes the book to generate
+pub struct Book<'a> {
+    pub title: String,
+    pub chapters: Vec<RenderedPageRef<'a>>,
+}
+
+impl<'a> Book<'a> {
+    /// Creates a book from all the posts in a series, ordered by publication
+    /// date.
+    pub fn from_series(site: &'a RenderedSite<'_>, series: &str) -> Self {
+        let mut chapters: Vec<_> = site
+            .posts()
+            .filter(|post| post.source().series() == Some(series))
+            .collect();
+        chapters.sort_by_key(|post| post.publish_date());
+        Self {
+            title: series.to_string(),
+            chapters,
+        }
+    }
+}
+
+/// A file that will be embedded into the book
+struct Resource {
+    /// Path within the EPUB, relative to the `OEBPS` directory
+    href: String,
+    media_type: &'static str,
+    contents: Vec<u8>,
+    properties: Option<&'static str>,
+}
+
+/// Writes `book` to `out` as an EPUB 3 file.
+pub fn write_epub(
+    site: &RenderedSite<'_>,
+    book: &Book<'_>,
+    out: impl Write + Seek,
+) -> Result<(), ExportError> {
+    let config = &site.config().export.epub;
+    let mut resources = vec![];
+
+    if let Some(cover) = &config.cover {
+        let path = site.root_dir().join(cover);
+        let contents = std::fs::read(&path).map_err(|e| ExportError::Read(path.clone(), e))?;
+        resources.push(Resource {
+            href: format!("images/cover.{}", extension(&path)),
+            media_type: media_type(&path),
+            contents,
+            properties: Some("cover-image"),
+        });
+    }
+
+    let mut images = HashMap::new();
+    let mut chapters = vec![];
+    for (i, page) in book.chapters.iter().enumerate() {
+        let content = embed_images(site, page, &mut images, &mut resources);
+        chapters.push(Resource {
+            href: format!("chapter-{}.xhtml", i + 1),
+            media_type: "application/xhtml+xml",
+            contents: xhtml_document(
+                page.title(),
+                &format!("<h1>{}</h1>\n{content}", escape(page.title())),
+            )
+            .into_bytes(),
+            properties: None,
+        });
+    }
+
+    let nav = nav_document(&book.title, book, &chapters);
+    let package = package_document(site, book, &config.language, &chapters, &resources);
+
+    let mut zip = ZipWriter::new(out);
+    let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = SimpleFileOptions::default();
+
+    // The mimetype file must come first and be uncompressed.
+    let mut write = |name: &str, contents: &[u8], options| -> Result<(), ExportError> {
+        zip.start_file(name, options).map_err(ExportError::Zip)?;
+        zip.write_all(contents)
+            .map_err(|e| ExportError::Write(name.into(), e))
+    };
+    write("mimetype", b"application/epub+zip", stored)?;
+    write("META-INF/container.xml", CONTAINER_XML.as_bytes(), deflated)?;
+    write("OEBPS/content.opf", package.as_bytes(), deflated)?;
+    write("OEBPS/nav.xhtml", nav.as_bytes(), deflated)?;
+    for resource in chapters.iter().chain(resources.iter()) {
+        write(
+            &format!("OEBPS/{}", resource.href),
+            &resource.contents,
+            deflated,
+        )?;
+    }
+    zip.finish().map_err(ExportError::Zip)?;
+
+    Ok(())
+}
+
+const CONTAINER_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#;
+
+fn xhtml_document(title: &str, body: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+<head><title>{}</title></head>
+<body>
+{body}
+</body>
+</html>
+"#,
+        escape(title)
+    )
+}
+
+fn nav_document(title: &str, book: &Book<'_>, chapters: &[Resource]) -> String {
+    let items = book
+        .chapters
+        .iter()
+        .zip(chapters)
+        .map(|(page, chapter)| {
+            format!(
+                "<li><a href=\"{}\">{}</a></li>",
+                chapter.href,
+                escape(page.title())
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    xhtml_document(
+        title,
+        &format!(
+            "<nav epub:type=\"toc\" id=\"toc\">\n<h1>{}</h1>\n<ol>\n{items}\n</ol>\n</nav>",
+            escape(title)
+        ),
+    )
+}
+
+fn package_document(
+    site: &RenderedSite<'_>,
+    book: &Book<'_>,
+    language: &str,
+    chapters: &[Resource],
+    resources: &[Resource],
+) -> String {
+    let identifier = format!("{}#{}", site.base_url(), slug::slugify(&book.title));
+    let author = site
+        .author()
+        .map(|author| format!("<dc:creator>{}</dc:creator>\n", escape(author)))
+        .unwrap_or_default();
+
+    let manifest = chapters
+        .iter()
+        .chain(resources)
+        .enumerate()
+        .map(|(i, resource)| {
+            format!(
+                "<item id=\"item-{i}\" href=\"{}\" media-type=\"{}\"{}/>",
+                resource.href,
+                resource.media_type,
+                resource
+                    .properties
+                    .map(|p| format!(" properties=\"{p}\""))
+                    .unwrap_or_default()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let spine = (0..chapters.len())
+        .map(|i| format!("<itemref idref=\"item-{i}\"/>"))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="book-id">
+<metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+<dc:identifier id="book-id">{}</dc:identifier>
+<dc:title>{}</dc:title>
+<dc:language>{}</dc:language>
+{author}<meta property="dcterms:modified">{}</meta>
+</metadata>
+<manifest>
+<item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+{manifest}
+</manifest>
+<spine>
+{spine}
+</spine>
+</package>
+"#,
+        escape(&identifier),
+        escape(&book.title),
+        escape(language),
+        Utc::now().format("%Y-%m-%dT%H:%M:%SZ"),
+    )
+}
+
+/// Finds local images in the page's content, adds them to `resources` and
+/// rewrites the references to point at the embedded copy.
+///
+/// `images` tracks images that were already embedded so they are only
+/// included once.
+fn embed_images(
+    site: &RenderedSite<'_>,
+    page: &RenderedPageRef<'_>,
+    images: &mut HashMap<PathBuf, String>,
+    resources: &mut Vec<Resource>,
+) -> String {
+    let mut content = page.rendered_contents().to_string();
+    for src in image_sources(page.rendered_contents()) {
+        let Some(path) = resolve_local(site, page, &src) else {
+            continue;
+        };
+        let href = match images.get(&path) {
+            Some(href) => href.clone(),
+            None => {
+                let Ok(contents) = std::fs::read(&path) else {
+                    debug!("could not read image `{}`, leaving it out", path.display());
+                    continue;
+                };
+                let href = format!("images/{}.{}", images.len() + 1, extension(&path));
+                resources.push(Resource {
+                    href: href.clone(),
+                    media_type: media_type(&path),
+                    contents,
+                    properties: None,
+                });
+                images.insert(path, href.clone());
+                href
+            }
+        };
+        content = content.replace(&format!("src=\"{src}\""), &format!("src=\"{href}\""));
+    }
+    content
+}
+
+/// Returns the `src` attribute of every `<img>` tag in `html`.
+fn image_sources(html: &str) -> Vec<String> {
+    html.match_indices("<img")
+        .filter_map(|(start, _)| {
+            let tag = &html[start..start + html[start..].find('>')?];
+            let (_, rest) = tag.split_once("src=\"")?;
+            let (src, _) = rest.split_once('"')?;
+            Some(src.to_string())
+        })
+        .collect()
+}
+
+fn resolve_local(
+    site: &RenderedSite<'_>,
+    page: &RenderedPageRef<'_>,
+    src: &str,
+) -> Option<PathBuf> {
+    if src.contains("://") || src.starts_with("data:") || src.starts_with("//") {
+        return None;
+    }
+    let path = match src.strip_prefix('/') {
+        Some(path) => site.root_dir().join(path),
+        None => site
+            .root_dir()
+            .join(page.source_path().parent().unwrap_or(Path::new("")))
+            .join(src),
+    };
+    path.is_file().then_some(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::image_sources;
+
+    #[test]
+    fn find_image_sources() {
+        let html = r#"<p><img src="/images/a.png" alt="a" /> and <img alt="b" src="b.jpg"></p>"#;
+        assert_eq!(image_sources(html), vec!["/images/a.png", "b.jpg"]);
+    }
+}
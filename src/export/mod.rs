@@ -0,0 +1,116 @@
+//! Exporting parts of the site into other formats.
+
+use std::path::{Path, PathBuf};
+
+use miette::Diagnostic;
+use serde::Deserialize;
+use tempfile::TempDir;
+use thiserror::Error;
+
+use crate::{
+    generator::{GeneratorContext, GeneratorError, Options},
+    renderer::RenderedSite,
+};
+
+pub mod archive;
+pub mod epub;
+pub mod pdf;
+
+/// The `[export]` section of `Site.toml`
+#[derive(Deserialize, Debug, Default)]
+pub struct ExportConfig {
+    #[serde(default)]
+    pub pdf: pdf::PdfConfig,
+    #[serde(default)]
+    pub epub: epub::EpubConfig,
+}
+
+#[non_exhaustive]
+#[derive(Diagnostic, Error, Debug)]
+pub enum ExportError {
+    #[error("no page with source path `{}`", .0.display())]
+    PageNotFound(PathBuf),
+    #[error("rendering page for export")]
+    Generator(#[source] GeneratorError),
+    #[error("generating site for export")]
+    Build(#[source] crate::Error),
+    #[error("rewriting links in `{}`", .0.display())]
+    Rewrite(PathBuf, #[source] lol_html::errors::RewritingError),
+    #[error("creating temporary directory")]
+    TempDir(#[source] std::io::Error),
+    #[error("reading `{}`", .0.display())]
+    Read(PathBuf, #[source] std::io::Error),
+    #[error("writing `{}`", .0.display())]
+    Write(PathBuf, #[source] std::io::Error),
+    #[error("writing zip archive")]
+    Zip(#[source] zip::result::ZipError),
+    #[error("nothing to export")]
+    #[diagnostic(help("check the series name or list some pages to include"))]
+    Empty,
+    #[error("no export command configured")]
+    #[diagnostic(help("set `command` in the `[export.pdf]` section of Site.toml"))]
+    MissingCommand,
+    #[error("running `{0}`")]
+    RunCommand(String, #[source] std::io::Error),
+    #[error("`{0}` exited with {1}")]
+    CommandFailed(String, std::process::ExitStatus),
+}
+
+impl From<GeneratorError> for ExportError {
+    fn from(value: GeneratorError) -> Self {
+        Self::Generator(value)
+    }
+}
+
+/// A complete copy of the generated site in a temporary directory.
+///
+/// Exporters use this as the starting point so they see exactly what a
+/// regular build would produce.
+pub(crate) struct Scratch {
+    _dir: TempDir,
+    options: Options,
+}
+
+impl Scratch {
+    pub(crate) async fn generate(site: &RenderedSite<'_>) -> Result<Self, ExportError> {
+        let dir = tempfile::tempdir().map_err(ExportError::TempDir)?;
+        let options = Options {
+            path: None,
+            destination: dir.path().join("site"),
+            unpublished: true,
+            fsync: false,
+            only: vec![],
+            wait: false,
+            force: false,
+            verify: false,
+        };
+        let gcx = GeneratorContext::new(site, &options)?;
+        gcx.generate_site(site).await.map_err(ExportError::Build)?;
+        Ok(Self { _dir: dir, options })
+    }
+
+    /// The directory the site was generated into
+    pub(crate) fn root(&self) -> &Path {
+        &self.options.destination
+    }
+}
+
+pub(crate) fn extension(path: &Path) -> String {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+pub(crate) fn media_type(path: &Path) -> &'static str {
+    match extension(path).as_str() {
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "js" => "text/javascript",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        _ => "application/octet-stream",
+    }
+}
@@ -0,0 +1,142 @@
+//! Exporting pages as PDFs.
+//!
+//! EBG does not typeset PDFs itself. Instead, the page is rendered with the
+//! theme's print template into a scratch copy of the site and then handed to
+//! an external tool, such as headless Chromium or WeasyPrint.
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::{generator::GeneratorContext, index::PageMetadata, renderer::RenderedSite};
+
+use super::{ExportError, Scratch};
+
+/// The `[export.pdf]` section of `Site.toml`
+#[derive(Deserialize, Debug)]
+pub struct PdfConfig {
+    /// The command used to convert HTML to PDF
+    ///
+    /// `{input}` and `{output}` are replaced by the path of the HTML file and
+    /// the PDF file to create.
+    #[serde(default = "default_command")]
+    pub command: Vec<String>,
+}
+
+impl Default for PdfConfig {
+    fn default() -> Self {
+        Self {
+            command: default_command(),
+        }
+    }
+}
+
+fn default_command() -> Vec<String> {
+    [
+        "chromium",
+        "--headless",
+        "--no-pdf-header-footer",
+        "--print-to-pdf={output}",
+        "{input}",
+    ]
+    .map(String::from)
+    .to_vec()
+}
+
+/// Renders the page at `source_path` and converts it to a PDF at `output`.
+pub async fn export_pdf(
+    site: &RenderedSite<'_>,
+    source_path: &Path,
+    output: &Path,
+    command: &[String],
+) -> Result<(), ExportError> {
+    let page = site
+        .find_page_by_source_path(source_path)
+        .ok_or_else(|| ExportError::PageNotFound(source_path.into()))?;
+
+    // Generate the whole site so stylesheets and images are available to the
+    // PDF converter.
+    let scratch = Scratch::generate(site).await?;
+    let gcx = GeneratorContext::new(site, &scratch.options)?;
+
+    let html = gcx.render_page_for_print(page, site)?;
+    let html = make_links_local(&html, scratch.root());
+    let input = scratch.root().join(page.url()).join("print.html");
+    std::fs::write(&input, html).map_err(|e| ExportError::Write(input.clone(), e))?;
+
+    let output = std::path::absolute(output).map_err(|e| ExportError::Write(output.into(), e))?;
+    run_converter(command, &input, &output)
+}
+
+/// Rewrites root-relative links so they point into `root` on the local file
+/// system, since the converter loads the page through a `file://` URL.
+fn make_links_local(html: &str, root: &Path) -> String {
+    let root = root.to_string_lossy().replace('\\', "/");
+    let root = root.trim_end_matches('/');
+    let mut html = html.to_string();
+    for attr in ["href", "src"] {
+        for quote in ['"', '\''] {
+            html = html.replace(
+                &format!("{attr}={quote}/"),
+                &format!("{attr}={quote}file://{root}/"),
+            );
+            // undo the damage to protocol-relative URLs
+            html = html.replace(
+                &format!("{attr}={quote}file://{root}//"),
+                &format!("{attr}={quote}//"),
+            );
+        }
+    }
+    html
+}
+
+fn run_converter(command: &[String], input: &Path, output: &Path) -> Result<(), ExportError> {
+    let substitute = |arg: &String| {
+        arg.replace("{input}", &input.to_string_lossy())
+            .replace("{output}", &output.to_string_lossy())
+    };
+    let Some((program, args)) = command.split_first() else {
+        return Err(ExportError::MissingCommand);
+    };
+    let args: Vec<_> = args.iter().map(substitute).collect();
+    debug!("running {program} {args:?}");
+
+    let status = Command::new(program)
+        .args(&args)
+        .status()
+        .map_err(|e| ExportError::RunCommand(program.clone(), e))?;
+    if !status.success() {
+        return Err(ExportError::CommandFailed(program.clone(), status));
+    }
+    Ok(())
+}
+
+/// Picks a default file name for the PDF based on the page's slug.
+pub fn default_output(site: &RenderedSite<'_>, source_path: &Path) -> PathBuf {
+    let name = site
+        .index()
+        .find_page_by_source_path(source_path)
+        .map(|page| page.title_slug().to_string())
+        .unwrap_or_else(|| "page".to_string());
+    PathBuf::from(name + ".pdf")
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::make_links_local;
+
+    #[test]
+    fn rewrite_root_relative_links() {
+        let html = r#"<link href="/style.css"><img src='/a.png'><a href="//example.com/">x</a><a href="https://example.com/">y</a>"#;
+        assert_eq!(
+            make_links_local(html, Path::new("/tmp/site")),
+            r#"<link href="file:///tmp/site/style.css"><img src='file:///tmp/site/a.png'><a href="//example.com/">x</a><a href="https://example.com/">y</a>"#
+        );
+    }
+}
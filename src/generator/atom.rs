@@ -1,19 +1,60 @@
 //! Rendering sites into atom.xml files
 
-use std::io::Write;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    io::Write,
+    path::PathBuf,
+};
 
-use chrono::Utc;
 use quick_xml::{
     events::{BytesCData, BytesDecl, BytesText, Event::*},
     Writer,
 };
+use serde::Deserialize;
 use thiserror::Error;
 
 use crate::{
     index::{PageMetadata, SiteMetadata},
-    renderer::RenderedSite,
+    renderer::{RenderedPageRef, RenderedSite},
 };
 
+use super::{build_info::build_time, content_hashes::ContentHashes, short_urls::ShortUrls};
+
+/// The `[feed]` section of `Site.toml`
+#[derive(Deserialize, Debug, Default)]
+pub struct FeedConfig {
+    /// Whether feeds include only a summary of each post rather than its full
+    /// contents
+    #[serde(default)]
+    pub summary_only: bool,
+    /// Overrides for the feeds of individual series, keyed by series name
+    #[serde(default)]
+    pub series: HashMap<String, SeriesFeedConfig>,
+    /// A file, relative to the site root, that records a hash of each post so
+    /// feeds only show posts as updated when their contents change
+    pub manifest: Option<PathBuf>,
+    /// Whether to also publish the site's feed as a JSON Feed in `feed.json`
+    #[serde(default)]
+    pub json: bool,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct SeriesFeedConfig {
+    pub summary_only: Option<bool>,
+}
+
+impl FeedConfig {
+    /// Returns whether the feed for `series`, or the site feed if `None`, is
+    /// summary only.
+    pub(super) fn summary_only(&self, series: Option<&str>) -> bool {
+        series
+            .and_then(|series| self.series.get(series))
+            .and_then(|config| config.summary_only)
+            .unwrap_or(self.summary_only)
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum AtomError {
     #[error("xml generation")]
@@ -26,8 +67,79 @@ pub enum AtomError {
 
 pub(crate) fn generate_atom(
     site: &RenderedSite,
+    short_urls: &ShortUrls,
+    hashes: &ContentHashes,
+    out: impl Write,
+) -> std::result::Result<(), AtomError> {
+    let feed = Feed {
+        title: site.title(),
+        path: "atom.xml",
+        summary_only: site.config().feed.summary_only(None),
+        posts: site.posts().collect(),
+    };
+    write_feed(site, short_urls, hashes, feed, out)
+}
+
+/// Returns the path of the feed for a series, relative to the site root.
+pub(crate) fn series_feed_path(series: &str) -> String {
+    format!("series/{}/atom.xml", slug::slugify(series))
+}
+
+/// Writes a feed for each series of posts, returning the path of each feed
+/// and its contents.
+pub(crate) fn generate_series_feeds(
+    site: &RenderedSite,
+    short_urls: &ShortUrls,
+    hashes: &ContentHashes,
+) -> Result<Vec<(String, Vec<u8>)>, AtomError> {
+    let mut series = BTreeMap::<_, Vec<_>>::new();
+    for post in site.posts() {
+        if let Some(name) = post.source().series() {
+            series.entry(name).or_default().push(post);
+        }
+    }
+    series
+        .into_iter()
+        .map(|(name, posts)| {
+            let path = series_feed_path(name);
+            let title = format!("{}: {name}", site.title());
+            let feed = Feed {
+                title: &title,
+                path: &path,
+                summary_only: site.config().feed.summary_only(Some(name)),
+                posts,
+            };
+            let mut contents = vec![];
+            write_feed(site, short_urls, hashes, feed, &mut contents)?;
+            Ok((path, contents))
+        })
+        .collect()
+}
+
+/// A feed of some of the site's posts
+struct Feed<'a> {
+    title: &'a str,
+    /// Where the feed is published, relative to the site root
+    path: &'a str,
+    /// Whether to leave out the full contents of each post
+    summary_only: bool,
+    posts: Vec<RenderedPageRef<'a>>,
+}
+
+/// Writes an Atom feed of the ten most recent posts in `feed`.
+fn write_feed(
+    site: &RenderedSite,
+    short_urls: &ShortUrls,
+    hashes: &ContentHashes,
+    feed: Feed<'_>,
     out: impl Write,
 ) -> std::result::Result<(), AtomError> {
+    let Feed {
+        title,
+        path,
+        summary_only,
+        mut posts,
+    } = feed;
     let mut writer = Writer::new(out);
 
     writer.write_event(Decl(BytesDecl::new("1.0", Some("utf-8"), None)))?;
@@ -36,7 +148,7 @@ pub(crate) fn generate_atom(
         .create_element("feed")
         .with_attribute(("xmlns", "http://www.w3.org/2005/Atom"))
         .write_inner_content(|writer| -> Result<(), AtomError> {
-            let atom_url = format!("{}/atom.xml", site.base_url());
+            let atom_url = site.full_url(path);
 
             writer
                 .create_element("link")
@@ -56,9 +168,19 @@ pub(crate) fn generate_atom(
                 ])
                 .write_empty()?;
 
+            posts.sort_by_key(|b| std::cmp::Reverse(b.publish_date()));
+            posts.truncate(10);
+            let updated = |post: RenderedPageRef<'_>| hashes.updated(post).or(post.publish_date());
+
+            // The feed changes when its newest entry does
+            let feed_updated = posts
+                .iter()
+                .filter_map(|post| updated(*post))
+                .max()
+                .unwrap_or_else(build_time);
             writer
                 .create_element("updated")
-                .write_text_content(BytesText::new(&Utc::now().to_rfc3339()))?;
+                .write_text_content(BytesText::new(&feed_updated.to_rfc3339()))?;
 
             writer
                 .create_element("id")
@@ -66,13 +188,12 @@ pub(crate) fn generate_atom(
 
             writer
                 .create_element("title")
-                .with_attribute(("type", "html"))
-                .write_text_content(BytesText::new(site.title()))?;
+                .write_text_content(text(title))?;
 
             if let Some(subtitle) = site.subtitle() {
                 writer
                     .create_element("subtitle")
-                    .write_text_content(BytesText::new(subtitle))?;
+                    .write_text_content(text(subtitle))?;
             }
 
             if let Some(author) = site.author() {
@@ -80,70 +201,96 @@ pub(crate) fn generate_atom(
                     |writer| -> Result<(), AtomError> {
                         writer
                             .create_element("name")
-                            .write_text_content(BytesText::new(author))?;
+                            .write_text_content(text(author))?;
                         Ok(())
                     },
                 )?;
             }
 
-            let mut posts: Vec<_> = site.posts().collect();
-            posts.sort_by_key(|b| std::cmp::Reverse(b.publish_date()));
-
-            for post in posts.into_iter().take(10) {
-                let post_url = format!("{}/{}", site.base_url(), post.url());
+            for post in posts {
+                let post_url = site.full_url(&post.url());
                 writer.create_element("entry").write_inner_content(
                     |writer| -> Result<(), AtomError> {
                         writer
                             .create_element("title")
-                            .with_attribute(("type", "html"))
-                            .write_text_content(BytesText::new(post.title()))?;
+                            .write_text_content(text(post.title()))?;
                         writer
                             .create_element("link")
                             .with_attributes([
                                 ("href", post_url.as_str()),
                                 ("rel", "alternate"),
                                 ("type", "text/html"),
-                                ("title", site.title()),
+                                ("title", xml_chars(site.title()).as_ref()),
                             ])
                             .write_empty()?;
+                        if let Some(short_url) = short_urls.short_url(site, post) {
+                            writer
+                                .create_element("link")
+                                .with_attributes([
+                                    ("href", short_url.as_str()),
+                                    ("rel", "shortlink"),
+                                    ("type", "text/html"),
+                                ])
+                                .write_empty()?;
+                        }
                         if let Some(published) = post.publish_date() {
                             writer.create_element("published").write_text_content(
                                 BytesText::new(published.to_rfc3339().as_str()),
                             )?;
-                            // TODO: find a more accurate way to do last updated.
+                        }
+                        if let Some(updated) = updated(post) {
                             writer
                                 .create_element("updated")
                                 .write_text_content(BytesText::new(
-                                    published.to_rfc3339().as_str(),
+                                    updated.to_rfc3339().as_str(),
                                 ))?;
                         }
                         writer
                             .create_element("id")
                             .write_text_content(BytesText::new(post_url.as_str()))?;
 
-                        writer
-                            .create_element("content")
-                            .with_attributes([("type", "html"), ("xml:base", post_url.as_str())])
-                            .write_cdata_content(BytesCData::new(post.rendered_contents()))?;
+                        if !summary_only {
+                            writer
+                                .create_element("content")
+                                .with_attributes([
+                                    ("type", "html"),
+                                    ("xml:base", post_url.as_str()),
+                                ])
+                                .write_inner_content(|writer| {
+                                    write_cdata(writer, post.rendered_contents())
+                                })?;
+                        }
 
                         if let Some(author) = site.author() {
                             writer.create_element("author").write_inner_content(
                                 |writer| -> Result<(), AtomError> {
                                     writer
                                         .create_element("name")
-                                        .write_text_content(BytesText::new(author))?;
+                                        .write_text_content(text(author))?;
                                     Ok(())
                                 },
                             )?;
                         }
 
-                        // FIXME: Add categories for posts that have them
+                        let source = post.source();
+                        for term in source.categories().iter().chain(source.tags()) {
+                            writer
+                                .create_element("category")
+                                .with_attribute(("term", xml_chars(term).as_ref()))
+                                .write_empty()?;
+                        }
 
                         if let Some(excerpt) = post.rendered_excerpt() {
                             writer
                                 .create_element("summary")
                                 .with_attribute(("type", "html"))
-                                .write_cdata_content(BytesCData::new(excerpt))?;
+                                .write_inner_content(|writer| write_cdata(writer, excerpt))?;
+                        } else if let Some(description) =
+                            post.description().filter(|_| summary_only)
+                        {
+                            writer
+                                .create_element("summary")
+                                .write_text_content(text(description))?;
                         }
 
                         Ok(())
@@ -156,3 +303,125 @@ pub(crate) fn generate_atom(
 
     Ok(())
 }
+
+/// Text for an element, with any characters XML can't hold removed.
+///
+/// Titles are plain text, so markup in them is escaped and shown as written.
+fn text(text: &str) -> BytesText<'static> {
+    BytesText::new(&xml_chars(text)).into_owned()
+}
+
+/// Removes the characters that XML doesn't allow anywhere, not even escaped,
+/// which are mostly control characters.
+fn xml_chars(text: &str) -> Cow<'_, str> {
+    let allowed = |c: char| {
+        matches!(c, '\t' | '\n' | '\r') || (c >= ' ' && !matches!(c, '\u{fffe}' | '\u{ffff}'))
+    };
+    if text.chars().all(allowed) {
+        Cow::Borrowed(text)
+    } else {
+        Cow::Owned(text.chars().filter(|c| allowed(*c)).collect())
+    }
+}
+
+/// Writes `text` as CDATA, which is how HTML is embedded in the feed.
+fn write_cdata<W: Write>(writer: &mut Writer<W>, text: &str) -> Result<(), AtomError> {
+    let text = xml_chars(text);
+    for section in cdata_sections(&text) {
+        writer.write_event(CData(BytesCData::new(section)))?;
+    }
+    Ok(())
+}
+
+/// Splits `text` into pieces that can each be a CDATA section.
+///
+/// A CDATA section ends at the first `]]>`, so that can't appear inside one.
+/// Each occurrence is split between two sections, with `]]` ending one and
+/// `>` starting the next, which readers join back together.
+fn cdata_sections(text: &str) -> Vec<&str> {
+    let mut sections = vec![];
+    let mut rest = text;
+    while let Some(end) = rest.find("]]>") {
+        sections.push(&rest[..end + 2]);
+        rest = &rest[end + 2..];
+    }
+    sections.push(rest);
+    sections
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        generator::{content_hashes::ContentHashes, short_urls::ShortUrls},
+        index::{PageSource, SiteIndex, SourceFormat},
+    };
+
+    use super::{cdata_sections, generate_atom, xml_chars, FeedConfig};
+
+    #[test]
+    fn cdata_splitting() {
+        assert_eq!(cdata_sections("<p>Hello</p>"), ["<p>Hello</p>"]);
+        assert_eq!(cdata_sections("a]]>b]]>"), ["a]]", ">b]]", ">"]);
+        assert_eq!(cdata_sections("]]]>"), ["]]]", ">"]);
+    }
+
+    #[test]
+    fn invalid_xml_chars() {
+        assert_eq!(xml_chars("tab\tnewline\n"), "tab\tnewline\n");
+        assert_eq!(xml_chars("null\0 bell\u{7} \u{fffe}"), "null bell ");
+    }
+
+    /// Generates a feed with awkward contents and reads it back with a feed
+    /// parser to check nothing was lost or mangled.
+    #[test]
+    fn feed_round_trip() -> miette::Result<()> {
+        let mut site = SiteIndex::default();
+        site.add_page(PageSource::from_string(
+            "_posts/2024-01-02-cdata.md",
+            SourceFormat::Markdown,
+            "---\nlayout: post\ntitle: Using <Vec<T>> & \"friends\"\ntags: [C&C]\n---\n\
+             <div>Ends with ]]> and ]]]]>, with a form feed\u{c}.</div>\n",
+        ));
+        let site = site.render()?;
+
+        let mut atom = vec![];
+        generate_atom(
+            &site,
+            &ShortUrls::assign(&site),
+            &ContentHashes::default(),
+            &mut atom,
+        )
+        .unwrap();
+
+        let feed = feed_rs::parser::parse(atom.as_slice()).unwrap();
+        let entry = &feed.entries[0];
+        assert_eq!(
+            entry.title.as_ref().unwrap().content,
+            r#"Using <Vec<T>> & "friends""#
+        );
+        assert_eq!(entry.categories[0].term, "C&C");
+        let content = entry.content.as_ref().unwrap().body.as_ref().unwrap();
+        assert!(
+            content.contains("<div>Ends with ]]> and ]]]]>, with a form feed.</div>"),
+            "{content}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn series_override_summary_only() {
+        let config: FeedConfig = toml::from_str(
+            r#"
+summary_only = true
+[series."Async Rust"]
+summary_only = false
+[series.Other]
+"#,
+        )
+        .unwrap();
+        assert!(config.summary_only(None));
+        assert!(!config.summary_only(Some("Async Rust")));
+        assert!(config.summary_only(Some("Other")));
+        assert!(config.summary_only(Some("Unlisted")));
+    }
+}
@@ -0,0 +1,104 @@
+//! Breadcrumb trails that lead from the home page to the current page
+
+use serde_json::{json, Value};
+
+use crate::{
+    index::{PageMetadata, SiteMetadata},
+    renderer::{RenderedPageRef, RenderedSite},
+};
+
+pub(crate) struct Breadcrumb {
+    pub title: String,
+    /// The URL of the page, relative to the site root
+    pub url: String,
+}
+
+/// Finds the pages above `page` in the URL hierarchy, starting with the home
+/// page and ending with `page` itself.
+///
+/// Each parent directory of the page's URL is included if there is a page at
+/// that URL, so `docs/guide/themes` might have the trail home → `docs` →
+/// `docs/guide` → `docs/guide/themes`.
+pub(crate) fn breadcrumbs(site: &RenderedSite<'_>, page: RenderedPageRef<'_>) -> Vec<Breadcrumb> {
+    let url = page.url();
+    let url = url.trim_end_matches('/');
+    let find = |prefix: &str| {
+        site.all_pages()
+            .find(|page| page.url().trim_end_matches('/') == prefix)
+    };
+
+    let mut trail = vec![Breadcrumb {
+        title: find("").map_or(site.title().to_string(), |home| home.title().to_string()),
+        url: String::new(),
+    }];
+    if url.is_empty() {
+        return trail;
+    }
+    let mut prefix = String::new();
+    let segments: Vec<_> = url.split('/').collect();
+    for segment in &segments[..segments.len() - 1] {
+        prefix = if prefix.is_empty() {
+            segment.to_string()
+        } else {
+            format!("{prefix}/{segment}")
+        };
+        if let Some(parent) = find(&prefix) {
+            trail.push(Breadcrumb {
+                title: parent.title().to_string(),
+                url: parent.url(),
+            });
+        }
+    }
+    trail.push(Breadcrumb {
+        title: page.title().to_string(),
+        url: page.url(),
+    });
+    trail
+}
+
+/// Builds `page.breadcrumbs`.
+pub(crate) fn breadcrumbs_value(site: &RenderedSite<'_>, page: RenderedPageRef<'_>) -> Value {
+    breadcrumbs(site, page)
+        .into_iter()
+        .map(|crumb| json!({ "title": crumb.title, "url": format!("/{}", crumb.url) }))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::index::{PageSource, SiteIndex, SourceFormat};
+
+    use super::breadcrumbs;
+
+    #[test]
+    fn nested_trail() {
+        let mut site = SiteIndex::default();
+        for (path, title) in [
+            ("index.md", "Home"),
+            ("docs/index.md", "Docs"),
+            ("docs/guide/themes.md", "Themes"),
+            ("_posts/2024-01-01-hello.md", "Hello"),
+        ] {
+            site.add_page(PageSource::from_string(
+                path,
+                SourceFormat::Markdown,
+                format!("---\nlayout: page\ntitle: {title}\n---\n"),
+            ));
+        }
+        let site = site.render().unwrap();
+        let trail = |title: &str| {
+            let page = site.all_pages().find(|page| page.title() == title).unwrap();
+            breadcrumbs(&site, page)
+                .into_iter()
+                .map(|crumb| format!("{} {}", crumb.title, crumb.url))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            trail("Themes"),
+            ["Home ", "Docs docs", "Themes docs/guide/themes"]
+        );
+        assert_eq!(trail("Hello"), ["Home ", "Hello blog/2024/01/01/hello/"]);
+        assert_eq!(trail("Home"), ["Home "]);
+    }
+}
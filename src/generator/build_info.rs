@@ -0,0 +1,133 @@
+//! Information about the current build that is exposed to templates as `build`
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+#[derive(Serialize, Debug)]
+pub(crate) struct BuildInfo {
+    pub timestamp: DateTime<Utc>,
+    pub ebg_version: &'static str,
+    /// The commit checked out in the repository containing the site, if any
+    pub git_rev: Option<String>,
+    /// Whether `timestamp` came from `SOURCE_DATE_EPOCH` rather than the clock
+    #[serde(skip)]
+    pub fixed: bool,
+}
+
+impl BuildInfo {
+    pub fn new(root_dir: &Path) -> Self {
+        let fixed = fixed_build_time();
+        Self {
+            timestamp: fixed.unwrap_or_else(Utc::now),
+            ebg_version: env!("CARGO_PKG_VERSION"),
+            git_rev: git_rev(root_dir),
+            fixed: fixed.is_some(),
+        }
+    }
+}
+
+/// Returns the time to record as when the site was built.
+///
+/// Following the [reproducible builds convention][sde], this is the time in
+/// the `SOURCE_DATE_EPOCH` environment variable if it is set, so that building
+/// the same sources gives the same output.
+///
+/// [sde]: https://reproducible-builds.org/specs/source-date-epoch/
+pub(crate) fn build_time() -> DateTime<Utc> {
+    fixed_build_time().unwrap_or_else(Utc::now)
+}
+
+/// Returns the time in `SOURCE_DATE_EPOCH`, if it is set.
+fn fixed_build_time() -> Option<DateTime<Utc>> {
+    source_date_epoch(std::env::var("SOURCE_DATE_EPOCH").ok().as_deref())
+}
+
+fn source_date_epoch(value: Option<&str>) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp(value?.trim().parse().ok()?, 0)
+}
+
+/// Finds the commit hash of `HEAD` in the repository containing `path`.
+///
+/// This reads the repository files directly rather than running `git` so
+/// builds don't depend on git being installed.
+fn git_rev(path: &Path) -> Option<String> {
+    let git_dir = find_git_dir(path)?;
+    let head = std::fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let head = head.trim();
+    let Some(reference) = head.strip_prefix("ref: ") else {
+        // detached HEAD
+        return Some(head.to_string());
+    };
+
+    // Linked worktrees keep shared refs in the main repository
+    let common_dir = std::fs::read_to_string(git_dir.join("commondir"))
+        .map(|common| git_dir.join(common.trim()))
+        .unwrap_or_else(|_| git_dir.clone());
+
+    for dir in [&git_dir, &common_dir] {
+        if let Ok(rev) = std::fs::read_to_string(dir.join(reference)) {
+            return Some(rev.trim().to_string());
+        }
+    }
+    let packed = std::fs::read_to_string(common_dir.join("packed-refs")).ok()?;
+    packed.lines().find_map(|line| {
+        let (rev, name) = line.split_once(' ')?;
+        (name == reference).then(|| rev.to_string())
+    })
+}
+
+fn find_git_dir(path: &Path) -> Option<PathBuf> {
+    let path = path.canonicalize().ok()?;
+    for dir in path.ancestors() {
+        let git = dir.join(".git");
+        if git.is_dir() {
+            return Some(git);
+        }
+        // Worktrees and submodules use a file pointing at the real directory
+        if let Ok(contents) = std::fs::read_to_string(&git) {
+            let target = contents.trim().strip_prefix("gitdir: ")?;
+            return Some(dir.join(target));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+
+    use super::{git_rev, source_date_epoch};
+
+    #[test]
+    fn parse_source_date_epoch() {
+        assert_eq!(
+            source_date_epoch(Some("1704067200")),
+            Some(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap())
+        );
+        assert_eq!(source_date_epoch(Some("yesterday")), None);
+        assert_eq!(source_date_epoch(None), None);
+    }
+
+    #[test]
+    fn read_packed_and_loose_refs() {
+        let dir = tempfile::tempdir().unwrap();
+        let git = dir.path().join(".git");
+        std::fs::create_dir_all(git.join("refs/heads")).unwrap();
+
+        std::fs::write(git.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        std::fs::write(
+            git.join("packed-refs"),
+            "# pack-refs with: peeled fully-peeled sorted\n1111 refs/heads/main\n",
+        )
+        .unwrap();
+        assert_eq!(git_rev(dir.path()).as_deref(), Some("1111"));
+
+        std::fs::write(git.join("refs/heads/main"), "2222\n").unwrap();
+        assert_eq!(git_rev(dir.path()).as_deref(), Some("2222"));
+
+        std::fs::write(git.join("HEAD"), "3333\n").unwrap();
+        assert_eq!(git_rev(dir.path()).as_deref(), Some("3333"));
+    }
+}
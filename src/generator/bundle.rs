@@ -0,0 +1,153 @@
+//! Running an external bundler, such as esbuild, on script and style entry
+//! points
+//!
+//! Each bundle is written to `assets/` with a hash of its contents in the file
+//! name. Templates find the final URL with the `asset` function, e.g.
+//! `{{ asset(path="js/main.ts") }}`.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    process::Command,
+};
+
+use serde::Deserialize;
+use serde_json::Value;
+use tempfile::TempDir;
+use tracing::debug;
+
+use crate::index::SiteMetadata;
+
+use super::{page_assets::fingerprinted_url, writer::OutputWriter, GeneratorError};
+
+/// The `[bundle]` section of `Site.toml`
+#[derive(Deserialize, Debug)]
+pub struct BundleConfig {
+    /// The bundler command
+    ///
+    /// `{input}` and `{output}` are replaced by the entry point and the file
+    /// the bundler should write.
+    pub command: Vec<String>,
+    /// Entry points, relative to the site root
+    pub entries: Vec<String>,
+}
+
+/// The bundles built for a site
+pub(crate) struct Bundles {
+    /// Keeps the bundler output around until it is copied to the destination
+    dir: Option<TempDir>,
+    /// Maps each entry point to the URL of its bundle
+    urls: BTreeMap<String, String>,
+}
+
+impl Bundles {
+    pub fn build(site: &dyn SiteMetadata) -> Result<Self, GeneratorError> {
+        let Some(config) = &site.config().bundle else {
+            return Ok(Self {
+                dir: None,
+                urls: BTreeMap::new(),
+            });
+        };
+
+        let dir = tempfile::tempdir().map_err(GeneratorError::BundleDir)?;
+        let mut urls = BTreeMap::new();
+        for entry in &config.entries {
+            let input = site.root_dir().join(entry);
+            let output = dir.path().join(output_name(Path::new(entry)));
+            run_bundler(&config.command, &input, &output)?;
+
+            let contents = std::fs::read(&output)
+                .map_err(|e| GeneratorError::BundleOutput(entry.clone(), e))?;
+            let url = fingerprinted_url(&output, &contents);
+            let staged = dir.path().join(url.trim_start_matches('/'));
+            std::fs::create_dir_all(staged.parent().unwrap())
+                .map_err(|e| GeneratorError::BundleOutput(entry.clone(), e))?;
+            std::fs::rename(&output, &staged)
+                .map_err(|e| GeneratorError::BundleOutput(entry.clone(), e))?;
+            urls.insert(entry.clone(), url);
+        }
+
+        Ok(Self {
+            dir: Some(dir),
+            urls,
+        })
+    }
+
+    /// Copies the bundles into the destination along with
+    /// `assets/manifest.json`, which maps entry points to their bundles.
+    pub fn copy(&self, writer: &OutputWriter) {
+        let Some(dir) = &self.dir else {
+            return;
+        };
+        for url in self.urls.values() {
+            let url = url.trim_start_matches('/');
+            writer.copy(&dir.path().join(url), url);
+        }
+        writer.write(
+            "assets/manifest.json",
+            serde_json::to_string_pretty(&self.urls).unwrap(),
+        );
+    }
+
+    /// Creates the `asset` template function.
+    pub fn template_function(&self) -> impl tera::Function {
+        let urls = self.urls.clone();
+        move |args: &HashMap<String, Value>| {
+            let path = args
+                .get("path")
+                .and_then(Value::as_str)
+                .ok_or("asset() requires a `path` argument")?;
+            urls.get(path)
+                .map(|url| Value::String(url.clone()))
+                .ok_or_else(|| format!("`{path}` is not a bundle entry in Site.toml").into())
+        }
+    }
+}
+
+/// Chooses the name of the bundler output for an entry point. Scripts are
+/// compiled to JavaScript, while other files keep their extension.
+fn output_name(entry: &Path) -> String {
+    let stem = entry.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = match entry.extension().and_then(|ext| ext.to_str()) {
+        Some("ts" | "tsx" | "jsx" | "mjs" | "mts") | None => "js",
+        Some(ext) => ext,
+    };
+    format!("{stem}.{ext}")
+}
+
+fn run_bundler(command: &[String], input: &Path, output: &Path) -> Result<(), GeneratorError> {
+    let Some((program, args)) = command.split_first() else {
+        return Err(GeneratorError::MissingBundler);
+    };
+    let args: Vec<_> = args
+        .iter()
+        .map(|arg| {
+            arg.replace("{input}", &input.to_string_lossy())
+                .replace("{output}", &output.to_string_lossy())
+        })
+        .collect();
+    debug!("running {program} {args:?}");
+
+    let status = Command::new(program)
+        .args(&args)
+        .status()
+        .map_err(|e| GeneratorError::RunBundler(program.clone(), e))?;
+    if !status.success() {
+        return Err(GeneratorError::BundlerFailed(input.into(), status));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::output_name;
+
+    #[test]
+    fn output_names() {
+        assert_eq!(output_name(Path::new("js/main.ts")), "main.js");
+        assert_eq!(output_name(Path::new("js/app.jsx")), "app.js");
+        assert_eq!(output_name(Path::new("css/site.css")), "site.css");
+    }
+}
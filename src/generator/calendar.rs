@@ -0,0 +1,119 @@
+//! Rendering the publication schedule into an iCalendar (`.ics`) file
+//!
+//! Each post becomes an all-day event on its publication date, including posts
+//! that are dated in the future, so the schedule can be viewed in a calendar
+//! app.
+
+use crate::{
+    index::{PageMetadata, SiteMetadata},
+    renderer::RenderedSite,
+};
+
+pub(crate) fn generate_calendar(site: &RenderedSite) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        format!("PRODID:-//ebg//{}//EN", env!("CARGO_PKG_VERSION")),
+        "CALSCALE:GREGORIAN".to_string(),
+        format!("X-WR-CALNAME:{}", escape_text(site.title())),
+    ];
+
+    let scheduled = site.index().scheduled_posts().map(|post| {
+        (
+            post.title().unwrap_or(post.title_slug()),
+            post.url(),
+            post.publish_date(),
+        )
+    });
+    let published: Vec<_> = site.posts().collect();
+    let mut posts: Vec<_> = published
+        .iter()
+        .map(|post| (post.title(), post.url(), post.publish_date()))
+        .chain(scheduled)
+        .collect();
+    posts.sort_by_key(|(_, _, date)| *date);
+
+    let host = site
+        .base_url()
+        .split("://")
+        .nth(1)
+        .unwrap_or(site.base_url())
+        .trim_end_matches('/');
+    for (title, path, date) in posts {
+        let Some(date) = date else {
+            continue;
+        };
+        let url = site.full_url(&path);
+        lines.extend([
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:{}@{host}", path.trim_end_matches('/')),
+            // Stamping events with the post's date rather than the build time
+            // keeps the file the same until the posts change
+            format!("DTSTAMP:{}", date.format("%Y%m%dT%H%M%SZ")),
+            format!("DTSTART;VALUE=DATE:{}", date.format("%Y%m%d")),
+            format!("SUMMARY:{}", escape_text(title)),
+            format!("URL:{url}"),
+            format!("DESCRIPTION:{}", escape_text(&url)),
+            "END:VEVENT".to_string(),
+        ]);
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    lines.iter().map(|line| fold_line(line) + "\r\n").collect()
+}
+
+/// Escapes characters that have special meaning in iCalendar text values.
+fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\r' => (),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Splits lines longer than 75 bytes into continuation lines, as required by
+/// RFC 5545.
+fn fold_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len());
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            // the leading space counts towards the length of the next line
+            width = 1;
+        }
+        width += c.len_utf8();
+        folded.push(c);
+    }
+    folded
+}
+
+#[cfg(test)]
+mod test {
+    use super::{escape_text, fold_line};
+
+    #[test]
+    fn escape() {
+        assert_eq!(
+            escape_text("Hello, World; a\\b\nc"),
+            "Hello\\, World\\; a\\\\b\\nc"
+        );
+    }
+
+    #[test]
+    fn fold_long_lines() {
+        let line = format!("SUMMARY:{}", "é".repeat(40));
+        let folded = fold_line(&line);
+        assert!(folded.split("\r\n").all(|l| l.len() <= 75));
+        assert_eq!(folded.replace("\r\n ", ""), line);
+        assert_eq!(fold_line("SUMMARY:short"), "SUMMARY:short");
+    }
+}
@@ -0,0 +1,270 @@
+//! Tracking when each post's contents last changed
+//!
+//! A hash of each post is recorded in a manifest file that is kept with the
+//! site's source. When a post's hash differs from the one in the manifest, the
+//! post is considered updated as of the current build. This lets feeds give an
+//! accurate `<updated>` time, rather than marking every post as updated on
+//! every rebuild.
+//!
+//! The manifest also records a hash of the HTML written for each post. Output
+//! is meant to be byte-for-byte identical when nothing a page depends on has
+//! changed, so deploy tools can compare these hashes to find the pages that
+//! need uploading.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    index::{PageMetadata, SiteMetadata},
+    renderer::{RenderedPageRef, RenderedSite},
+};
+
+use super::GeneratorError;
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Manifest {
+    /// Posts keyed by their source path relative to the site root
+    #[serde(default)]
+    posts: BTreeMap<String, PostEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct PostEntry {
+    hash: String,
+    updated: DateTime<Utc>,
+    /// A hash of the HTML generated for the post
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    output: Option<String>,
+}
+
+#[derive(Default)]
+pub(crate) struct ContentHashes {
+    /// Where the manifest is saved, if the site keeps one
+    path: Option<PathBuf>,
+    manifest: Manifest,
+    /// Hashes of the HTML generated in this build, by manifest key
+    outputs: Mutex<BTreeMap<String, String>>,
+    /// The posts whose source is the same as in the last build
+    unchanged: BTreeSet<String>,
+    /// Unchanged posts whose HTML differs from the last build
+    changed_outputs: Mutex<BTreeSet<PathBuf>>,
+}
+
+impl ContentHashes {
+    /// Hashes every post and compares them with the manifest from the previous
+    /// build, if the site keeps one.
+    pub fn load(site: &RenderedSite<'_>, now: DateTime<Utc>) -> Result<Self, GeneratorError> {
+        let Some(path) = &site.config().feed.manifest else {
+            return Ok(Self::default());
+        };
+        let path = site.root_dir().join(path);
+        let previous: Manifest = match std::fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|e| GeneratorError::ParseManifest(path.clone(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Manifest::default(),
+            Err(e) => return Err(GeneratorError::ReadManifest(path, e)),
+        };
+
+        let mut unchanged = BTreeSet::new();
+        let posts = site
+            .posts()
+            .map(|post| {
+                let key = manifest_key(post.source_path());
+                let hash = content_hash(post);
+                let previous = previous.posts.get(&key);
+                if previous.is_some_and(|previous| previous.hash == hash) {
+                    unchanged.insert(key.clone());
+                }
+                let entry = update_entry(previous, hash, post.publish_date(), now);
+                (key, entry)
+            })
+            .collect();
+        Ok(Self {
+            path: Some(path),
+            manifest: Manifest { posts },
+            outputs: Mutex::default(),
+            unchanged,
+            changed_outputs: Mutex::default(),
+        })
+    }
+
+    /// Returns when `post` was last updated, if the site keeps a manifest.
+    pub fn updated(&self, post: RenderedPageRef<'_>) -> Option<DateTime<Utc>> {
+        self.manifest
+            .posts
+            .get(&manifest_key(post.source_path()))
+            .map(|entry| entry.updated)
+    }
+
+    /// Records the HTML generated for `post`.
+    pub fn record_output(&self, post: RenderedPageRef<'_>, html: &str) {
+        if self.path.is_none() {
+            return;
+        }
+        let key = manifest_key(post.source_path());
+        let hash = format!("{:x}", Sha256::digest(html));
+        let entry = self.manifest.posts.get(&key);
+        if self.unchanged.contains(&key)
+            && entry.is_some_and(|entry| entry.output.as_ref().is_some_and(|out| *out != hash))
+        {
+            self.changed_outputs
+                .lock()
+                .unwrap()
+                .insert(post.source_path().to_path_buf());
+        }
+        self.outputs.lock().unwrap().insert(key, hash);
+    }
+
+    /// Describes the posts whose HTML changed even though their source
+    /// didn't.
+    ///
+    /// This is expected when the theme or the other posts change, but
+    /// otherwise means something in the build isn't deterministic.
+    pub fn changed_outputs(&self) -> Vec<String> {
+        self.changed_outputs
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|path| {
+                format!(
+                    "`{}` generated different HTML than the last build, though its source is \
+                     unchanged",
+                    path.display()
+                )
+            })
+            .collect()
+    }
+
+    /// Writes the manifest for the next build to compare against.
+    pub fn save(&self) -> Result<(), GeneratorError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let outputs = self.outputs.lock().unwrap();
+        let manifest = Manifest {
+            posts: self
+                .manifest
+                .posts
+                .iter()
+                .map(|(key, entry)| {
+                    let output = outputs.get(key).or(entry.output.as_ref()).cloned();
+                    (
+                        key.clone(),
+                        PostEntry {
+                            output,
+                            ..entry.clone()
+                        },
+                    )
+                })
+                .collect(),
+        };
+        let contents =
+            toml::to_string_pretty(&manifest).map_err(GeneratorError::SerializeManifest)?;
+        // Leave the file alone if nothing changed, so `ebg serve` doesn't see
+        // a change and rebuild again
+        if std::fs::read_to_string(path).is_ok_and(|old| old == contents) {
+            return Ok(());
+        }
+        std::fs::write(path, contents).map_err(|e| GeneratorError::WriteManifest(path.clone(), e))
+    }
+}
+
+fn manifest_key(source_path: &Path) -> String {
+    source_path.to_string_lossy().replace('\\', "/")
+}
+
+/// Hashes the parts of a post that readers see in a feed.
+fn content_hash(post: RenderedPageRef<'_>) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(post.title());
+    hasher.update([0]);
+    hasher.update(post.rendered_contents());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Decides when a post was last updated, given its entry from the previous
+/// manifest.
+///
+/// Posts that are new to the manifest are treated as unchanged since they were
+/// published.
+fn update_entry(
+    previous: Option<&PostEntry>,
+    hash: String,
+    published: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> PostEntry {
+    let updated = match previous {
+        Some(previous) if previous.hash == hash => previous.updated,
+        Some(_) => now,
+        None => published.unwrap_or(now),
+    };
+    PostEntry {
+        hash,
+        updated,
+        output: previous.and_then(|previous| previous.output.clone()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+
+    use crate::index::{PageSource, SiteIndex, SourceFormat};
+
+    use super::{update_entry, ContentHashes, PostEntry};
+
+    #[test]
+    fn only_changed_posts_are_updated() {
+        let published = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let edited = Utc.with_ymd_and_hms(2024, 2, 1, 0, 0, 0).unwrap();
+        let now = Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap();
+
+        let new = update_entry(None, "a".into(), Some(published), now);
+        assert_eq!(new.updated, published);
+
+        let previous = PostEntry {
+            hash: "a".into(),
+            updated: edited,
+            output: Some("html".into()),
+        };
+        let unchanged = update_entry(Some(&previous), "a".into(), Some(published), now);
+        assert_eq!(unchanged, previous);
+
+        let changed = update_entry(Some(&previous), "b".into(), Some(published), now);
+        assert_eq!(changed.updated, now);
+        assert_eq!(changed.hash, "b");
+    }
+
+    #[test]
+    fn report_changed_output() -> miette::Result<()> {
+        let dir = tempfile::tempdir().unwrap();
+        let mut site = SiteIndex::default();
+        site.config_mut().feed.manifest = Some(dir.path().join("manifest.toml"));
+        site.add_page(PageSource::from_string(
+            "_posts/2024-01-01-one.md",
+            SourceFormat::Markdown,
+            "---\nlayout: post\n---\nHello\n",
+        ));
+        let site = site.render()?;
+        let now = Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap();
+        let post = site.posts().next().unwrap();
+
+        let hashes = ContentHashes::load(&site, now)?;
+        hashes.record_output(post, "<p>Hello</p>");
+        hashes.save()?;
+
+        let hashes = ContentHashes::load(&site, now)?;
+        hashes.record_output(post, "<p>Hello</p>");
+        assert!(hashes.changed_outputs().is_empty());
+        hashes.record_output(post, "<p>Hello again</p>");
+        assert_eq!(hashes.changed_outputs().len(), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,327 @@
+//! Inlining the CSS a page needs for its first paint
+//!
+//! EBG doesn't lay pages out, so it can't know what is above the fold. Instead,
+//! it keeps the rules whose selectors refer only to elements, classes, and ids
+//! that appear in the page. These are inlined into a `<style>` element, and the
+//! full stylesheet is loaded without blocking rendering.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use lol_html::{element, html_content::ContentType, rewrite_str, RewriteStrSettings};
+
+use super::GeneratorError;
+
+/// The tags, classes, and ids used in a page
+#[derive(Default, Debug)]
+struct UsedNames {
+    tags: HashSet<String>,
+    classes: HashSet<String>,
+    ids: HashSet<String>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Rule {
+    Style {
+        selectors: String,
+        body: String,
+    },
+    /// A conditional group like `@media` or `@supports`
+    Group {
+        prelude: String,
+        rules: Vec<Rule>,
+    },
+}
+
+/// Inlines critical CSS into pages, caching the parsed stylesheets
+#[derive(Default)]
+pub(crate) struct CriticalCss {
+    stylesheets: Mutex<HashMap<PathBuf, Option<Arc<Vec<Rule>>>>>,
+}
+
+impl CriticalCss {
+    /// Inlines the critical rules from each local stylesheet linked by `html`.
+    ///
+    /// `root` is the directory stylesheet URLs are resolved against, and `url`
+    /// is the URL of the page itself.
+    pub fn inline(&self, html: &str, root: &Path, url: &str) -> Result<String, GeneratorError> {
+        let used = used_names(html)?;
+        let rewritten = rewrite_str(
+            html,
+            RewriteStrSettings {
+                element_content_handlers: vec![element!("link[rel=stylesheet][href]", |el| {
+                    let href = el.get_attribute("href").unwrap();
+                    let Some(rules) = self.stylesheet(root, url, &href) else {
+                        return Ok(());
+                    };
+                    let critical = critical_rules(&rules, &used);
+                    let href = quick_xml::escape::escape(&href);
+                    el.before(&format!("<style>{critical}</style>"), ContentType::Html);
+                    el.replace(
+                        &format!(
+                            r#"<link rel="preload" href="{href}" as="style" onload="this.onload=null;this.rel='stylesheet'"><noscript><link rel="stylesheet" href="{href}"></noscript>"#
+                        ),
+                        ContentType::Html,
+                    );
+                    Ok(())
+                })],
+                ..RewriteStrSettings::new()
+            },
+        );
+        rewritten.map_err(|e| GeneratorError::CriticalCss(Box::new(e)))
+    }
+
+    /// Loads and parses a local stylesheet, or returns `None` if `href` does
+    /// not refer to a file in `root`.
+    fn stylesheet(&self, root: &Path, url: &str, href: &str) -> Option<Arc<Vec<Rule>>> {
+        if href.contains("://") || href.starts_with("//") {
+            return None;
+        }
+        let href = href.split(['?', '#']).next().unwrap_or(href);
+        let path = match href.strip_prefix('/') {
+            Some(href) => root.join(href),
+            None => root.join(url).join(href),
+        };
+        self.stylesheets
+            .lock()
+            .unwrap()
+            .entry(path)
+            .or_insert_with_key(|path| {
+                let css = std::fs::read_to_string(path).ok()?;
+                Some(Arc::new(parse_rules(&css)))
+            })
+            .clone()
+    }
+}
+
+fn used_names(html: &str) -> Result<UsedNames, GeneratorError> {
+    let used = Mutex::new(UsedNames::default());
+    rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![element!("*", |el| {
+                let mut used = used.lock().unwrap();
+                used.tags.insert(el.tag_name());
+                if let Some(class) = el.get_attribute("class") {
+                    used.classes
+                        .extend(class.split_whitespace().map(String::from));
+                }
+                if let Some(id) = el.get_attribute("id") {
+                    used.ids.insert(id);
+                }
+                Ok(())
+            })],
+            ..RewriteStrSettings::new()
+        },
+    )
+    .map_err(|e| GeneratorError::CriticalCss(Box::new(e)))?;
+    Ok(used.into_inner().unwrap())
+}
+
+/// Splits a stylesheet into rules.
+///
+/// At-rules other than `@media` and `@supports`, such as `@font-face` and
+/// `@import`, are left out since they are not needed for the first paint.
+fn parse_rules(css: &str) -> Vec<Rule> {
+    let css = strip_comments(css);
+    let mut rules = vec![];
+    let mut rest = css.as_str();
+    while let Some(open) = rest.find(['{', ';']) {
+        let prelude = rest[..open].trim();
+        if rest.as_bytes()[open] == b';' {
+            rest = &rest[open + 1..];
+            continue;
+        }
+        let close = matching_brace(rest, open);
+        let body = &rest[open + 1..close];
+        if prelude.starts_with("@media") || prelude.starts_with("@supports") {
+            rules.push(Rule::Group {
+                prelude: prelude.to_string(),
+                rules: parse_rules(body),
+            });
+        } else if !prelude.starts_with('@') {
+            rules.push(Rule::Style {
+                selectors: prelude.to_string(),
+                body: body.trim().to_string(),
+            });
+        }
+        rest = &rest[(close + 1).min(rest.len())..];
+    }
+    rules
+}
+
+fn strip_comments(css: &str) -> String {
+    let mut out = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(start) = rest.find("/*") {
+        out.push_str(&rest[..start]);
+        rest = match rest[start + 2..].find("*/") {
+            Some(end) => &rest[start + 2 + end + 2..],
+            None => "",
+        };
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Finds the `}` that closes the `{` at `open`, skipping over strings.
+fn matching_brace(css: &str, open: usize) -> usize {
+    let mut depth = 0;
+    let mut quote = None;
+    for (i, c) in css[open..].char_indices() {
+        match (c, quote) {
+            (c, Some(q)) if c == q => quote = None,
+            (_, Some(_)) => (),
+            ('"' | '\'', None) => quote = Some(c),
+            ('{', None) => depth += 1,
+            ('}', None) => {
+                depth -= 1;
+                if depth == 0 {
+                    return open + i;
+                }
+            }
+            _ => (),
+        }
+    }
+    css.len()
+}
+
+fn critical_rules(rules: &[Rule], used: &UsedNames) -> String {
+    let mut css = String::new();
+    for rule in rules {
+        match rule {
+            Rule::Style { selectors, body } => {
+                let selectors: Vec<_> = split_selectors(selectors)
+                    .into_iter()
+                    .filter(|selector| selector_is_used(selector, used))
+                    .collect();
+                if !selectors.is_empty() {
+                    css += &format!("{}{{{body}}}", selectors.join(","));
+                }
+            }
+            Rule::Group { prelude, rules } => {
+                let inner = critical_rules(rules, used);
+                if !inner.is_empty() {
+                    css += &format!("{prelude}{{{inner}}}");
+                }
+            }
+        }
+    }
+    css
+}
+
+/// Splits a selector list on commas that are not nested inside parentheses.
+fn split_selectors(selectors: &str) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in selectors.char_indices() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(selectors[start..i].trim());
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    parts.push(selectors[start..].trim());
+    parts
+}
+
+/// Checks whether every simple selector in `selector` names something used in
+/// the page.
+///
+/// Pseudo-classes, pseudo-elements, and attribute selectors are ignored, so
+/// this may keep some rules that don't apply but should not drop any that do.
+fn selector_is_used(selector: &str, used: &UsedNames) -> bool {
+    let mut chars = selector.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '.' | '#' => {
+                let name = take_ident(&mut chars);
+                let set = if c == '.' { &used.classes } else { &used.ids };
+                if !set.contains(&name) {
+                    return false;
+                }
+            }
+            ':' => {
+                chars.next_if_eq(&':');
+                take_ident(&mut chars);
+                skip_group(&mut chars, '(', ')');
+            }
+            '[' => skip_group_from_inside(&mut chars, '[', ']'),
+            c if c.is_alphabetic() => {
+                let name = c.to_string() + &take_ident(&mut chars);
+                if !used.tags.contains(&name.to_lowercase()) {
+                    return false;
+                }
+            }
+            _ => (),
+        }
+    }
+    true
+}
+
+fn take_ident(chars: &mut std::iter::Peekable<impl Iterator<Item = char>>) -> String {
+    let mut ident = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || *c == '-' || *c == '_') {
+        ident.push(c);
+    }
+    ident
+}
+
+fn skip_group(
+    chars: &mut std::iter::Peekable<impl Iterator<Item = char>>,
+    open: char,
+    close: char,
+) {
+    if chars.next_if_eq(&open).is_some() {
+        skip_group_from_inside(chars, open, close);
+    }
+}
+
+fn skip_group_from_inside(chars: &mut impl Iterator<Item = char>, open: char, close: char) {
+    let mut depth = 1;
+    for c in chars {
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            depth -= 1;
+            if depth == 0 {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{critical_rules, parse_rules, used_names};
+
+    #[test]
+    fn keep_used_rules() {
+        let css = r#"
+/* comment { } */
+@import url("fonts.css");
+@font-face { font-family: X; src: url("x.woff2"); }
+body { margin: 0 }
+.unused, h1.title { color: red }
+#missing > p { color: blue }
+a:hover::after { content: "}" }
+@media (max-width: 600px) { .title { font-size: 1em } .nope { display: none } }
+@media print { .nope { display: none } }
+"#;
+        let html = r#"<html><body><h1 class="title">Hi</h1><a href="/">home</a></body></html>"#;
+
+        let used = used_names(html).unwrap();
+        assert_eq!(
+            critical_rules(&parse_rules(css), &used),
+            r#"body{margin: 0}h1.title{color: red}a:hover::after{content: "}"}@media (max-width: 600px){.title{font-size: 1em}}"#
+        );
+    }
+}
@@ -0,0 +1,233 @@
+//! Redirect and header rules in the format each static host expects
+//!
+//! Rules are written once in `Site.toml` and translated for the host named by
+//! `deploy.provider`.
+
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::warn;
+use url::Url;
+
+/// The `[deploy]` section of `Site.toml`
+#[derive(Deserialize, Debug, Default)]
+pub struct DeployConfig {
+    pub provider: Option<DeployProvider>,
+    #[serde(default)]
+    pub redirects: Vec<Redirect>,
+    /// Headers to send, keyed by path pattern and then by header name
+    #[serde(default)]
+    pub headers: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeployProvider {
+    Netlify,
+    Cloudflare,
+    Vercel,
+    S3,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Redirect {
+    /// The path to redirect from. A trailing `*` matches any path with this
+    /// prefix.
+    pub from: String,
+    /// The path or URL to redirect to
+    pub to: String,
+    #[serde(default = "default_status")]
+    pub status: u16,
+}
+
+fn default_status() -> u16 {
+    301
+}
+
+/// Generates the files that tell the host about redirects and headers, as
+/// pairs of file names and contents.
+pub(crate) fn deploy_files(config: &DeployConfig) -> Vec<(&'static str, String)> {
+    let Some(provider) = config.provider else {
+        return vec![];
+    };
+    if config.redirects.is_empty() && config.headers.is_empty() {
+        return vec![];
+    }
+    match provider {
+        DeployProvider::Netlify | DeployProvider::Cloudflare => {
+            let mut files = vec![];
+            if !config.redirects.is_empty() {
+                files.push(("_redirects", redirects_file(config)));
+            }
+            if !config.headers.is_empty() {
+                files.push(("_headers", headers_file(config)));
+            }
+            files
+        }
+        DeployProvider::Vercel => vec![("vercel.json", vercel_json(config))],
+        DeployProvider::S3 => {
+            if !config.headers.is_empty() {
+                warn!("S3 website hosting can't set headers, so `deploy.headers` is ignored");
+            }
+            if config.redirects.is_empty() {
+                return vec![];
+            }
+            vec![("s3-routing-rules.json", s3_routing_rules(config))]
+        }
+    }
+}
+
+fn redirects_file(config: &DeployConfig) -> String {
+    config
+        .redirects
+        .iter()
+        .map(|redirect| {
+            // Netlify and Cloudflare put the matched part of the path in `:splat`
+            let to = if redirect.from.ends_with('*') && redirect.to.ends_with('*') {
+                format!("{}:splat", redirect.to.trim_end_matches('*'))
+            } else {
+                redirect.to.clone()
+            };
+            format!("{} {to} {}\n", redirect.from, redirect.status)
+        })
+        .collect()
+}
+
+fn headers_file(config: &DeployConfig) -> String {
+    config
+        .headers
+        .iter()
+        .map(|(path, headers)| {
+            let mut block = format!("{path}\n");
+            for (name, value) in headers {
+                block += &format!("  {name}: {value}\n");
+            }
+            block
+        })
+        .collect()
+}
+
+/// Translates a path with an optional trailing `*` into Vercel's pattern
+/// syntax.
+fn vercel_pattern(path: &str) -> String {
+    match path.strip_suffix('*') {
+        Some(prefix) => format!("{prefix}:path*"),
+        None => path.to_string(),
+    }
+}
+
+fn vercel_json(config: &DeployConfig) -> String {
+    let redirects: Vec<_> = config
+        .redirects
+        .iter()
+        .map(|redirect| {
+            json!({
+                "source": vercel_pattern(&redirect.from),
+                "destination": vercel_pattern(&redirect.to),
+                "statusCode": redirect.status,
+            })
+        })
+        .collect();
+    let headers: Vec<_> = config
+        .headers
+        .iter()
+        .map(|(path, headers)| {
+            json!({
+                "source": vercel_pattern(path),
+                "headers": headers
+                    .iter()
+                    .map(|(key, value)| json!({ "key": key, "value": value }))
+                    .collect::<Vec<_>>(),
+            })
+        })
+        .collect();
+    serde_json::to_string_pretty(&json!({ "redirects": redirects, "headers": headers })).unwrap()
+}
+
+/// Builds routing rules for an S3 website. S3 only matches key prefixes, so
+/// every rule applies to all paths that start with `from`.
+fn s3_routing_rules(config: &DeployConfig) -> String {
+    let rules: Vec<Value> = config
+        .redirects
+        .iter()
+        .map(|redirect| {
+            let prefix = redirect.from.trim_start_matches('/').trim_end_matches('*');
+            let mut target = json!({ "HttpRedirectCode": redirect.status.to_string() });
+            let path = match Url::parse(&redirect.to) {
+                Ok(url) => {
+                    target["Protocol"] = json!(url.scheme());
+                    if let Some(host) = url.host_str() {
+                        target["HostName"] = json!(host);
+                    }
+                    url.path().to_string()
+                }
+                Err(_) => redirect.to.clone(),
+            };
+            let path = path.trim_start_matches('/');
+            match path.strip_suffix('*') {
+                Some(path) => target["ReplaceKeyPrefixWith"] = json!(path),
+                None => target["ReplaceKeyWith"] = json!(path),
+            }
+            json!({
+                "Condition": { "KeyPrefixEquals": prefix },
+                "Redirect": target,
+            })
+        })
+        .collect();
+    serde_json::to_string_pretty(&rules).unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{deploy_files, DeployConfig};
+
+    const CONFIG: &str = r#"
+        redirects = [
+            { from = "/old/", to = "/new/" },
+            { from = "/blog/*", to = "https://blog.example.com/*", status = 302 },
+        ]
+
+        [headers."/assets/*"]
+        Cache-Control = "max-age=31536000"
+    "#;
+
+    fn files(provider: &str) -> Vec<(&'static str, String)> {
+        let config: DeployConfig =
+            toml::from_str(&format!("provider = \"{provider}\"\n{CONFIG}")).unwrap();
+        deploy_files(&config)
+    }
+
+    #[test]
+    fn netlify_files() {
+        assert_eq!(
+            files("netlify"),
+            [
+                (
+                    "_redirects",
+                    "/old/ /new/ 301\n/blog/* https://blog.example.com/:splat 302\n".to_string()
+                ),
+                (
+                    "_headers",
+                    "/assets/*\n  Cache-Control: max-age=31536000\n".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn vercel_and_s3_files() {
+        let vercel = &files("vercel")[0];
+        assert_eq!(vercel.0, "vercel.json");
+        let vercel: serde_json::Value = serde_json::from_str(&vercel.1).unwrap();
+        assert_eq!(vercel["redirects"][1]["source"], "/blog/:path*");
+        assert_eq!(vercel["headers"][0]["source"], "/assets/:path*");
+
+        let s3 = &files("s3")[0];
+        let s3: serde_json::Value = serde_json::from_str(&s3.1).unwrap();
+        assert_eq!(s3[0]["Condition"]["KeyPrefixEquals"], "old/");
+        assert_eq!(s3[0]["Redirect"]["ReplaceKeyWith"], "new/");
+        assert_eq!(s3[1]["Redirect"]["HostName"], "blog.example.com");
+        assert_eq!(s3[1]["Redirect"]["ReplaceKeyPrefixWith"], "");
+    }
+}
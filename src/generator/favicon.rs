@@ -0,0 +1,100 @@
+//! Generating the usual set of favicons from a single source image
+
+use std::{io::Cursor, path::Path};
+
+use image::{
+    codecs::ico::{IcoEncoder, IcoFrame},
+    imageops::FilterType,
+    DynamicImage, ExtendedColorType, ImageFormat,
+};
+
+use super::{writer::OutputWriter, GeneratorError};
+
+/// The PNG icons that are generated, along with their size in pixels
+pub(crate) const PNG_ICONS: &[(&str, u32)] = &[
+    ("favicon-16x16.png", 16),
+    ("favicon-32x32.png", 32),
+    ("apple-touch-icon.png", 180),
+    ("android-chrome-192x192.png", 192),
+    ("android-chrome-512x512.png", 512),
+];
+
+/// The sizes that are bundled into `favicon.ico`
+const ICO_SIZES: &[u32] = &[16, 32, 48];
+
+/// Writes all the favicons derived from `source`.
+pub(crate) fn generate_favicons(
+    source: &Path,
+    writer: &OutputWriter,
+) -> Result<(), GeneratorError> {
+    let image_error = |e| GeneratorError::Favicon(source.into(), e);
+    let image = image::open(source).map_err(image_error)?;
+
+    for (name, size) in PNG_ICONS {
+        let mut png = Cursor::new(vec![]);
+        resize(&image, *size)
+            .write_to(&mut png, ImageFormat::Png)
+            .map_err(image_error)?;
+        writer.write(name, png.into_inner());
+    }
+
+    let frames = ICO_SIZES
+        .iter()
+        .map(|size| {
+            let icon = resize(&image, *size).into_rgba8();
+            IcoFrame::as_png(icon.as_raw(), *size, *size, ExtendedColorType::Rgba8)
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(image_error)?;
+    let mut ico = vec![];
+    IcoEncoder::new(&mut ico)
+        .encode_images(&frames)
+        .map_err(image_error)?;
+    writer.write("favicon.ico", ico);
+
+    Ok(())
+}
+
+/// Scales the image to a square of the given size, cropping it if it is not
+/// already square.
+fn resize(image: &DynamicImage, size: u32) -> DynamicImage {
+    image.resize_to_fill(size, size, FilterType::Lanczos3)
+}
+
+/// The `<link>` tags that reference the generated icons
+pub(crate) fn favicon_markup() -> String {
+    [
+        r#"<link rel="icon" href="/favicon.ico" sizes="48x48">"#,
+        r#"<link rel="icon" type="image/png" sizes="32x32" href="/favicon-32x32.png">"#,
+        r#"<link rel="icon" type="image/png" sizes="16x16" href="/favicon-16x16.png">"#,
+        r#"<link rel="apple-touch-icon" sizes="180x180" href="/apple-touch-icon.png">"#,
+    ]
+    .join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use image::{GenericImageView, RgbaImage};
+
+    use crate::generator::writer::OutputWriter;
+
+    use super::{generate_favicons, PNG_ICONS};
+
+    #[test]
+    fn generate_all_sizes() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("logo.png");
+        RgbaImage::new(600, 400).save(&source).unwrap();
+
+        let writer = OutputWriter::new(dir.path(), false);
+        generate_favicons(&source, &writer).unwrap();
+        writer.finish().unwrap();
+
+        for (name, size) in PNG_ICONS {
+            let icon = image::open(dir.path().join(name)).unwrap();
+            assert_eq!(icon.dimensions(), (*size, *size));
+        }
+        let ico = image::open(dir.path().join("favicon.ico")).unwrap();
+        assert_eq!(ico.dimensions(), (48, 48));
+    }
+}
@@ -0,0 +1,203 @@
+//! The list of feeds a site publishes, exposed to templates as `site.feeds`
+//!
+//! Feed readers find a site's feeds through `<link rel="alternate">` tags in
+//! its pages. Themes can write these by looping over the list, or include
+//! `page.feed_links`, which has tags for the site's feeds and the page's
+//! series. A feed that no page links to is hard for readers to find, so the
+//! build summary lists any that are left out.
+
+use std::{
+    collections::BTreeSet,
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    html_links::{LinkKind, PageLinks},
+    index::SiteMetadata,
+    renderer::RenderedSite,
+};
+
+use super::{atom::series_feed_path, health::resolve, JSON_FEED_PATH};
+
+/// A feed that the site publishes
+#[derive(Serialize, Debug, PartialEq)]
+pub(crate) struct FeedLink {
+    /// The feed's URL, relative to the site root and starting with `/`
+    pub url: String,
+    /// The feed's MIME type, for the `type` attribute of a `<link>`
+    #[serde(rename = "type")]
+    mime: &'static str,
+    title: String,
+    /// The series the feed is for, if it isn't the site's main feed
+    series: Option<String>,
+}
+
+/// Lists the feeds that a build of `site` generates, with the site-wide ones
+/// first.
+pub(crate) fn site_feeds(site: &RenderedSite<'_>) -> Vec<FeedLink> {
+    let mut feeds = vec![FeedLink {
+        url: "/atom.xml".to_string(),
+        mime: "application/atom+xml",
+        title: site.title().to_string(),
+        series: None,
+    }];
+    if site.config().feed.json {
+        feeds.push(FeedLink {
+            url: format!("/{JSON_FEED_PATH}"),
+            mime: "application/feed+json",
+            title: site.title().to_string(),
+            series: None,
+        });
+    }
+    let series = site
+        .posts()
+        .filter_map(|post| post.source().series())
+        .collect::<BTreeSet<_>>();
+    feeds.extend(series.into_iter().map(|series| FeedLink {
+        url: format!("/{}", series_feed_path(series)),
+        mime: "application/atom+xml",
+        title: format!("{}: {series}", site.title()),
+        series: Some(series.to_string()),
+    }));
+    feeds
+}
+
+pub(crate) fn feeds_value(site: &RenderedSite<'_>) -> Value {
+    serde_json::to_value(site_feeds(site)).unwrap()
+}
+
+/// The site's feeds, along with which ones the generated pages link to
+pub(crate) struct Feeds {
+    feeds: Vec<(FeedLink, AtomicBool)>,
+    base_url: String,
+}
+
+impl Feeds {
+    pub fn new(site: &RenderedSite<'_>) -> Self {
+        Self {
+            feeds: site_feeds(site)
+                .into_iter()
+                .map(|feed| (feed, AtomicBool::new(false)))
+                .collect(),
+            base_url: site.base_url().to_string(),
+        }
+    }
+
+    /// Returns `<link>` tags for the site-wide feeds and the feed for
+    /// `series`, if there is one, for the `<head>` of a page.
+    pub fn links(&self, series: Option<&str>) -> String {
+        self.feeds
+            .iter()
+            .map(|(feed, _)| feed)
+            .filter(|feed| feed.series.is_none() || feed.series.as_deref() == series)
+            .map(|feed| {
+                format!(
+                    r#"<link rel="alternate" type="{}" title="{}" href="{}">"#,
+                    feed.mime,
+                    tera::escape_html(&feed.title),
+                    feed.url
+                )
+            })
+            .collect()
+    }
+
+    /// Notes the feeds linked from `html`, the page written to `path`.
+    pub fn record(&self, path: &Path, html: &str) {
+        // Most pages link to the same feeds, so there's no need to look once
+        // they have all been seen
+        if self
+            .feeds
+            .iter()
+            .all(|(_, referenced)| referenced.load(Ordering::Relaxed))
+        {
+            return;
+        }
+        let links = PageLinks::scan(html);
+        for href in links.targets(&[LinkKind::Anchor, LinkKind::Link]) {
+            let Some(target) = resolve(path, href, &self.base_url) else {
+                continue;
+            };
+            for (feed, referenced) in &self.feeds {
+                if Path::new(&feed.url[1..]) == target {
+                    referenced.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+    }
+
+    /// Returns the URLs of the feeds that no page linked to.
+    pub fn unreferenced(&self) -> Vec<String> {
+        self.feeds
+            .iter()
+            .filter(|(_, referenced)| !referenced.load(Ordering::Relaxed))
+            .map(|(feed, _)| feed.url.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use crate::index::{PageSource, SiteIndex, SiteMetadata, SourceFormat};
+
+    use super::{site_feeds, FeedLink, Feeds};
+
+    #[test]
+    fn feeds() -> miette::Result<()> {
+        let mut site = SiteIndex::default();
+        site.config_mut().feed.json = true;
+        site.add_page(PageSource::from_string(
+            "_posts/2024-01-01-one.md",
+            SourceFormat::Markdown,
+            "---\nlayout: post\nseries: Async Rust\n---\n",
+        ));
+        site.add_page(PageSource::from_string(
+            "_posts/2024-01-02-two.md",
+            SourceFormat::Markdown,
+            "---\nlayout: post\n---\n",
+        ));
+        let site = site.render()?;
+
+        let feeds = site_feeds(&site);
+        assert_eq!(
+            feeds
+                .iter()
+                .map(|feed| feed.url.as_str())
+                .collect::<Vec<_>>(),
+            ["/atom.xml", "/feed.json", "/series/async-rust/atom.xml"]
+        );
+        assert_eq!(
+            feeds[2],
+            FeedLink {
+                url: "/series/async-rust/atom.xml".to_string(),
+                mime: "application/atom+xml",
+                title: format!("{}: Async Rust", site.title()),
+                series: Some("Async Rust".to_string()),
+            }
+        );
+
+        let feeds = Feeds::new(&site);
+        assert_eq!(
+            feeds.links(None),
+            r#"<link rel="alternate" type="application/atom+xml" title="" href="/atom.xml">"#
+                .to_string()
+                + r#"<link rel="alternate" type="application/feed+json" title="" href="/feed.json">"#
+        );
+        assert!(feeds
+            .links(Some("Async Rust"))
+            .ends_with(r#"title=": Async Rust" href="/series/async-rust/atom.xml">"#));
+
+        feeds.record(
+            Path::new("blog/2024/01/01/one/index.html"),
+            r#"<link rel="alternate" href="/atom.xml"><a href="../../../../../feed.json">JSON</a>
+               <a href="/atom.xml.bak">Not a feed</a>"#,
+        );
+        assert_eq!(feeds.unreferenced(), ["/series/async-rust/atom.xml"]);
+        Ok(())
+    }
+}
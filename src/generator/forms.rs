@@ -0,0 +1,156 @@
+//! Making simple forms, such as contact forms, work on static hosts
+//!
+//! Hosts handle form submissions in different ways. Netlify looks for
+//! attributes on the `<form>` element, while Cloudflare Pages needs a worker to
+//! receive the submission. Templates use `site.forms` to get the right markup
+//! for the configured host, and EBG writes any files the host needs.
+
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// The name of the hidden field that catches spam bots
+const HONEYPOT: &str = "bot-field";
+
+/// The `[forms]` section of `Site.toml`
+#[derive(Deserialize, Debug)]
+pub struct FormsConfig {
+    pub provider: FormProvider,
+    /// The forms on the site, by name
+    #[serde(flatten)]
+    pub forms: BTreeMap<String, FormConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FormProvider {
+    Netlify,
+    Cloudflare,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct FormConfig {
+    /// The page to show after the form is submitted
+    pub redirect: Option<String>,
+}
+
+/// Builds `site.forms`, which has the attributes and hidden fields for each
+/// form.
+pub(crate) fn forms_value(config: &FormsConfig) -> Value {
+    config
+        .forms
+        .iter()
+        .map(|(name, form)| {
+            let value = json!({
+                "attributes": form_attributes(config.provider, name, form),
+                "fields": hidden_fields(config.provider, name),
+            });
+            (name.clone(), value)
+        })
+        .collect::<serde_json::Map<_, _>>()
+        .into()
+}
+
+fn form_attributes(provider: FormProvider, name: &str, form: &FormConfig) -> String {
+    match provider {
+        FormProvider::Netlify => {
+            let mut attributes = format!(
+                r#"name="{name}" method="POST" data-netlify="true" netlify-honeypot="{HONEYPOT}""#
+            );
+            if let Some(redirect) = &form.redirect {
+                attributes += &format!(r#" action="{redirect}""#);
+            }
+            attributes
+        }
+        FormProvider::Cloudflare => {
+            format!(r#"name="{name}" method="POST" action="/_forms/{name}""#)
+        }
+    }
+}
+
+fn hidden_fields(provider: FormProvider, name: &str) -> String {
+    let honeypot = format!(
+        r#"<p hidden><label>Leave this empty: <input name="{HONEYPOT}" tabindex="-1" autocomplete="off"></label></p>"#
+    );
+    match provider {
+        FormProvider::Netlify => {
+            format!(r#"<input type="hidden" name="form-name" value="{name}">{honeypot}"#)
+        }
+        FormProvider::Cloudflare => honeypot,
+    }
+}
+
+/// Generates `_worker.js` for Cloudflare Pages, which accepts submissions to
+/// `/_forms/<name>` and serves everything else from the site's files.
+///
+/// What to do with a submission depends on the site, so the worker only logs
+/// it. Sites are expected to replace the marked section, for example to send
+/// an email.
+pub(crate) fn generate_worker(config: &FormsConfig) -> Option<String> {
+    if config.provider != FormProvider::Cloudflare {
+        return None;
+    }
+    let forms: BTreeMap<_, _> = config
+        .forms
+        .iter()
+        .map(|(name, form)| (name, json!({ "redirect": form.redirect })))
+        .collect();
+    Some(format!(
+        r#"// Generated by EBG from the [forms] section of Site.toml
+const FORMS = {forms};
+
+export default {{
+  async fetch(request, env) {{
+    const url = new URL(request.url);
+    const match = url.pathname.match(/^\/_forms\/([^/]+)\/?$/);
+    if (request.method === "POST" && match && Object.hasOwn(FORMS, match[1])) {{
+      const form = FORMS[match[1]];
+      const data = await request.formData();
+      if (!data.get("{HONEYPOT}")) {{
+        data.delete("{HONEYPOT}");
+        // Deliver the submission here, for example by email or to a KV namespace.
+        console.log(JSON.stringify({{ form: match[1], fields: Object.fromEntries(data) }}));
+      }}
+      return Response.redirect(new URL(form.redirect ?? "/", url), 303);
+    }}
+    return env.ASSETS.fetch(request);
+  }},
+}};
+"#,
+        forms = serde_json::to_string(&forms).unwrap(),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{forms_value, generate_worker, FormsConfig};
+
+    #[test]
+    fn netlify_forms() {
+        let config: FormsConfig =
+            toml::from_str("provider = \"netlify\"\n[contact]\nredirect = \"/thanks/\"\n").unwrap();
+        let value = forms_value(&config);
+        assert_eq!(
+            value["contact"]["attributes"],
+            r#"name="contact" method="POST" data-netlify="true" netlify-honeypot="bot-field" action="/thanks/""#
+        );
+        assert!(value["contact"]["fields"]
+            .as_str()
+            .unwrap()
+            .starts_with(r#"<input type="hidden" name="form-name" value="contact">"#));
+        assert!(generate_worker(&config).is_none());
+    }
+
+    #[test]
+    fn cloudflare_forms() {
+        let config: FormsConfig = toml::from_str("provider = \"cloudflare\"\n[contact]\n").unwrap();
+        let value = forms_value(&config);
+        assert_eq!(
+            value["contact"]["attributes"],
+            r#"name="contact" method="POST" action="/_forms/contact""#
+        );
+        let worker = generate_worker(&config).unwrap();
+        assert!(worker.contains(r#"const FORMS = {"contact":{"redirect":null}};"#));
+    }
+}
@@ -0,0 +1,287 @@
+//! An internal page summarizing the health of the generated site
+//!
+//! The page is written to `/_health/` after everything else, so it can look at
+//! the final output. It is not part of the site's pages, so it never shows up
+//! in feeds or listings.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    html_links::{LinkKind, PageLinks},
+    index::SiteMetadata,
+};
+
+use super::{build_info::build_time, BuildSummary};
+
+/// Where the health page is written, relative to the destination directory
+pub(crate) const HEALTH_PATH: &str = "_health/index.html";
+
+/// Problems with the links between the generated pages
+#[derive(Debug, Default, PartialEq)]
+struct LinkReport {
+    /// Links to files that don't exist, by the page that has them
+    broken: BTreeMap<PathBuf, Vec<String>>,
+    /// Pages that no other page links to
+    orphans: Vec<PathBuf>,
+}
+
+impl LinkReport {
+    /// Checks the links on every HTML page in `dest`.
+    fn check(dest: &Path, base_url: &str) -> Self {
+        let pages: BTreeSet<PathBuf> = walkdir::WalkDir::new(dest)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_file())
+            .filter_map(|entry| entry.path().strip_prefix(dest).ok().map(PathBuf::from))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "html"))
+            .filter(|path| path != Path::new(HEALTH_PATH))
+            .collect();
+
+        let mut report = Self::default();
+        let mut linked = BTreeSet::new();
+        let mut redirects = BTreeSet::new();
+        for page in &pages {
+            let Ok(html) = std::fs::read_to_string(dest.join(page)) else {
+                continue;
+            };
+            let links = PageLinks::scan(&html);
+            if links.redirect {
+                redirects.insert(page.clone());
+            }
+            for href in links.targets(&[LinkKind::Anchor]) {
+                let Some(target) = resolve(page, href, base_url) else {
+                    continue;
+                };
+                match find_target(dest, &target) {
+                    Some(target) => {
+                        if &target != page {
+                            linked.insert(target);
+                        }
+                    }
+                    None => report
+                        .broken
+                        .entry(page.clone())
+                        .or_default()
+                        .push(href.to_string()),
+                }
+            }
+        }
+
+        report.orphans = pages
+            .into_iter()
+            .filter(|page| {
+                // Readers start at the home page and are sent to the 404 page
+                // by the server, so neither needs links. Redirects such as
+                // short URLs are meant to be shared rather than linked.
+                page != Path::new("index.html")
+                    && page != Path::new("404.html")
+                    && !redirects.contains(page)
+            })
+            .filter(|page| !linked.contains(page))
+            .collect();
+        report
+    }
+}
+
+/// Resolves a link on `page` to a path relative to the destination directory,
+/// or returns `None` if it points somewhere else.
+pub(super) fn resolve(page: &Path, href: &str, base_url: &str) -> Option<PathBuf> {
+    let href = href.split(['#', '?']).next().unwrap_or_default();
+    let href = match href.strip_prefix(base_url.trim_end_matches('/')) {
+        Some(path) if !base_url.is_empty() && (path.is_empty() || path.starts_with('/')) => {
+            if path.is_empty() {
+                "/"
+            } else {
+                path
+            }
+        }
+        _ => href,
+    };
+    if href.is_empty() || href.contains(':') || href.starts_with("//") {
+        return None;
+    }
+
+    let mut path = match href.strip_prefix('/') {
+        Some(href) => PathBuf::from(href),
+        None => page.parent().unwrap_or(Path::new("")).join(href),
+    };
+    // Normalize `..` and `.` without touching the filesystem
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::ParentDir => {
+                normalized.pop();
+            }
+            std::path::Component::Normal(part) => normalized.push(part),
+            _ => {}
+        }
+    }
+    path = normalized;
+    if href.ends_with('/') {
+        path.push("index.html");
+    }
+    Some(path)
+}
+
+/// Finds the file a link points to, allowing links to directories to mean
+/// their `index.html`.
+pub(super) fn find_target(dest: &Path, target: &Path) -> Option<PathBuf> {
+    let full = dest.join(target);
+    if full.is_file() {
+        Some(target.into())
+    } else if full.join("index.html").is_file() {
+        Some(target.join("index.html"))
+    } else {
+        None
+    }
+}
+
+/// Generates the health page for the site in `dest`.
+pub(crate) fn health_page(site: &impl SiteMetadata, dest: &Path, summary: &BuildSummary) -> String {
+    let links = LinkReport::check(dest, site.base_url());
+
+    let stats = format!(
+        "<dl><dt>Built</dt><dd>{}</dd><dt>Pages</dt><dd>{}</dd><dt>Files</dt><dd>{}</dd>\
+         <dt>Total size</dt><dd>{} bytes</dd></dl>",
+        build_time().format("%Y-%m-%d %H:%M:%S UTC"),
+        summary.pages,
+        summary.files,
+        summary.total_bytes,
+    );
+    let broken = links
+        .broken
+        .iter()
+        .flat_map(|(page, hrefs)| {
+            hrefs.iter().map(move |href| {
+                format!(
+                    "<li><code>{}</code> links to <code>{}</code></li>",
+                    escape(&page.display().to_string()),
+                    escape(href)
+                )
+            })
+        })
+        .collect::<Vec<_>>();
+    let orphans = links
+        .orphans
+        .iter()
+        .map(|page| {
+            let page = escape(&page.display().to_string());
+            format!(r#"<li><a href="/{page}">{page}</a></li>"#)
+        })
+        .collect::<Vec<_>>();
+    let budgets = summary
+        .budget_violations
+        .iter()
+        .map(|violation| format!("<li>{}</li>", escape(&violation.to_string())))
+        .collect::<Vec<_>>();
+    let accessibility = summary
+        .accessibility
+        .iter()
+        .map(|finding| {
+            format!(
+                "<li>{} <code>{}</code>: {}</li>",
+                finding.priority,
+                escape(&finding.page.display().to_string()),
+                escape(&finding.message)
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let mut sections = vec![format!("<h2>Build</h2>{stats}")];
+    for (title, items) in [
+        ("Broken links", broken),
+        ("Orphan pages", orphans),
+        ("Size budgets", budgets),
+        ("Accessibility", accessibility),
+    ] {
+        let body = if items.is_empty() {
+            "<p>No problems found.</p>".to_string()
+        } else {
+            format!("<ul>{}</ul>", items.join(""))
+        };
+        sections.push(format!("<h2>{title} ({})</h2>{body}", items.len()));
+    }
+
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\"><head><meta charset=\"utf-8\">\
+         <meta name=\"robots\" content=\"noindex\">\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\
+         <title>Site health: {}</title></head>\
+         <body><h1>Site health</h1>{}</body></html>\n",
+        escape(site.title()),
+        sections.join("")
+    )
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::{Path, PathBuf};
+
+    use super::{resolve, LinkReport};
+
+    #[test]
+    fn resolve_links() {
+        let page = Path::new("blog/post/index.html");
+        let base = "https://example.com";
+        assert_eq!(
+            resolve(page, "/about/", base),
+            Some(PathBuf::from("about/index.html"))
+        );
+        assert_eq!(
+            resolve(page, "../other/#top", base),
+            Some(PathBuf::from("blog/other/index.html"))
+        );
+        assert_eq!(
+            resolve(page, "image.png", base),
+            Some(PathBuf::from("blog/post/image.png"))
+        );
+        assert_eq!(
+            resolve(page, "https://example.com/about", base),
+            Some(PathBuf::from("about"))
+        );
+        assert_eq!(resolve(page, "https://example.org/", base), None);
+        assert_eq!(resolve(page, "mailto:me@example.com", base), None);
+        assert_eq!(resolve(page, "#top", base), None);
+    }
+
+    #[test]
+    fn broken_links_and_orphans() {
+        let dest = tempfile::tempdir().unwrap();
+        let write = |path: &str, contents: &str| {
+            let path = dest.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        };
+        write(
+            "index.html",
+            r#"<a href="/about/">About</a><a href="/missing/">?</a>"#,
+        );
+        write(
+            "about/index.html",
+            r#"<a href="/">Home</a><a href="/about/">Self</a>"#,
+        );
+        write("lonely/index.html", r#"<a href="/about">About</a>"#);
+        write(
+            "s/abc/index.html",
+            r#"<meta http-equiv="refresh" content="0; url=/about/">"#,
+        );
+
+        let report = LinkReport::check(dest.path(), "");
+        assert_eq!(
+            report.broken.get(Path::new("index.html")),
+            Some(&vec!["/missing/".to_string()])
+        );
+        assert_eq!(report.broken.len(), 1);
+        assert_eq!(report.orphans, [PathBuf::from("lonely/index.html")]);
+    }
+}
@@ -0,0 +1,148 @@
+//! Reusing resized images between builds
+//!
+//! Resizing is the slowest part of building a site with many photos, so each
+//! resized copy is kept in `.ebg-cache/images`, named by a hash of the source
+//! image and how it was resized. Later builds reuse it as long as the source
+//! is unchanged.
+//!
+//! Images are turned upright according to their EXIF orientation before they
+//! are resized, since the copies don't keep the original's metadata.
+
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader, ImageResult};
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+/// Where resized images are kept, relative to the site root
+const IMAGE_CACHE_DIR: &str = ".ebg-cache/images";
+
+pub(crate) struct ImageCache {
+    dir: PathBuf,
+}
+
+impl ImageCache {
+    pub fn new(root_dir: &Path) -> Self {
+        Self {
+            dir: root_dir.join(IMAGE_CACHE_DIR),
+        }
+    }
+
+    /// Resizes the image at `source` once for each of `variants`, returning
+    /// the encoded copies in the same order.
+    ///
+    /// Each variant is named by a key, such as `480w`, that describes how
+    /// `resize` changes the image. The image is only decoded if some variant
+    /// isn't cached yet.
+    pub fn resize<V>(
+        &self,
+        source: &Path,
+        variants: &[(String, V)],
+        resize: impl Fn(&DynamicImage, &V) -> DynamicImage,
+    ) -> ImageResult<Vec<Vec<u8>>> {
+        let format = ImageFormat::from_path(source)?;
+        let contents = std::fs::read(source)?;
+        let hash = format!("{:x}", Sha256::digest(&contents));
+        let extension = format.extensions_str().first().unwrap_or(&"img");
+
+        let mut image = None;
+        let mut copies = vec![];
+        for (key, variant) in variants {
+            let path = self.dir.join(format!("{hash}-{key}.{extension}"));
+            if let Ok(copy) = std::fs::read(&path) {
+                copies.push(copy);
+                continue;
+            }
+            let image = match &mut image {
+                Some(image) => image,
+                None => image.insert(open_upright(&contents, format)?),
+            };
+            let mut copy = Cursor::new(vec![]);
+            resize(image, variant).write_to(&mut copy, format)?;
+            let copy = copy.into_inner();
+            // The cache only saves time, so the build goes on without it
+            if let Err(e) =
+                std::fs::create_dir_all(&self.dir).and_then(|()| std::fs::write(&path, &copy))
+            {
+                debug!("could not cache `{}`: {e}", path.display());
+            }
+            copies.push(copy);
+        }
+        Ok(copies)
+    }
+}
+
+/// Decodes an image, turning it the way its EXIF orientation says to.
+fn open_upright(contents: &[u8], format: ImageFormat) -> ImageResult<DynamicImage> {
+    let mut decoder = ImageReader::with_format(Cursor::new(contents), format).into_decoder()?;
+    let orientation = decoder.orientation()?;
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use image::{GenericImageView, ImageFormat, RgbImage};
+
+    use super::{open_upright, ImageCache};
+
+    #[test]
+    fn orientation() {
+        let mut jpeg = Cursor::new(vec![]);
+        RgbImage::new(4, 2)
+            .write_to(&mut jpeg, ImageFormat::Jpeg)
+            .unwrap();
+        let jpeg = jpeg.into_inner();
+
+        // An APP1 segment saying the image is turned 90 degrees
+        let mut exif = b"Exif\0\0II\x2a\x00\x08\x00\x00\x00\x01\x00".to_vec();
+        exif.extend_from_slice(&[0x12, 0x01, 0x03, 0x00, 1, 0, 0, 0, 6, 0, 0, 0, 0, 0, 0, 0]);
+        let mut rotated = jpeg[..2].to_vec();
+        rotated.extend_from_slice(&[0xff, 0xe1]);
+        rotated.extend_from_slice(&((exif.len() + 2) as u16).to_be_bytes());
+        rotated.extend(exif);
+        rotated.extend_from_slice(&jpeg[2..]);
+
+        let image = |contents: &[u8]| open_upright(contents, ImageFormat::Jpeg).unwrap();
+        assert_eq!(image(&jpeg).dimensions(), (4, 2));
+        assert_eq!(image(&rotated).dimensions(), (2, 4));
+    }
+
+    #[test]
+    fn reuse_resized() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("photo.png");
+        RgbImage::new(40, 20)
+            .save_with_format(&source, ImageFormat::Png)
+            .unwrap();
+
+        let cache = ImageCache::new(dir.path());
+        let resize = |image: &image::DynamicImage, width: &u32| image.thumbnail(*width, u32::MAX);
+        let copies = cache
+            .resize(&source, &[("10w".into(), 10)], resize)
+            .unwrap();
+        let copy = image::load_from_memory(&copies[0]).unwrap();
+        assert_eq!(copy.dimensions(), (10, 5));
+
+        // A cached copy is used without decoding the image again
+        let copies = cache
+            .resize(&source, &[("10w".into(), 10)], |_, _| unreachable!())
+            .unwrap();
+        assert_eq!(image::load_from_memory(&copies[0]).unwrap().width(), 10);
+
+        // Changing the source makes new copies
+        RgbImage::new(20, 20)
+            .save_with_format(&source, ImageFormat::Png)
+            .unwrap();
+        let copies = cache
+            .resize(&source, &[("10w".into(), 10)], resize)
+            .unwrap();
+        assert_eq!(image::load_from_memory(&copies[0]).unwrap().height(), 10);
+    }
+}
@@ -0,0 +1,396 @@
+//! Removing private metadata from published images
+//!
+//! Photos straight from a phone usually record where they were taken. When
+//! stripping is enabled, JPEG and PNG files are copied without their EXIF, XMP,
+//! and other metadata, except for the fields on the keep list, such as the
+//! copyright notice.
+
+use std::path::Path;
+
+use serde::Deserialize;
+
+use super::{writer::OutputWriter, GeneratorError};
+
+/// The `[image_metadata]` section of `Site.toml`
+#[derive(Deserialize, Debug)]
+pub struct ImageMetadataConfig {
+    /// Whether to remove metadata from images
+    #[serde(default)]
+    pub strip: bool,
+    /// Fields that are kept when metadata is removed
+    #[serde(default = "default_keep")]
+    pub keep: Vec<MetadataField>,
+}
+
+impl Default for ImageMetadataConfig {
+    fn default() -> Self {
+        Self {
+            strip: false,
+            keep: default_keep(),
+        }
+    }
+}
+
+fn default_keep() -> Vec<MetadataField> {
+    vec![
+        MetadataField::Orientation,
+        MetadataField::Artist,
+        MetadataField::Copyright,
+    ]
+}
+
+/// Metadata fields that can be kept
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum MetadataField {
+    /// Which way up the image is, which viewers need to display it correctly
+    Orientation,
+    Artist,
+    Copyright,
+    ImageDescription,
+}
+
+impl MetadataField {
+    /// The EXIF tag for this field
+    fn exif_tag(self) -> u16 {
+        match self {
+            MetadataField::Orientation => 0x0112,
+            MetadataField::Artist => 0x013b,
+            MetadataField::Copyright => 0x8298,
+            MetadataField::ImageDescription => 0x010e,
+        }
+    }
+
+    /// The keyword of the PNG text chunk for this field
+    fn png_keyword(self) -> Option<&'static [u8]> {
+        match self {
+            MetadataField::Orientation => None,
+            MetadataField::Artist => Some(b"Author"),
+            MetadataField::Copyright => Some(b"Copyright"),
+            MetadataField::ImageDescription => Some(b"Description"),
+        }
+    }
+}
+
+/// Copies `from` to `dest`, relative to the destination directory, removing
+/// its metadata if it is an image and the site asks for that.
+///
+/// An image whose metadata can't be removed is an error rather than being
+/// copied as is, since it could reveal where the photo was taken.
+pub(crate) fn copy_file(
+    config: &ImageMetadataConfig,
+    from: &Path,
+    dest: impl AsRef<Path>,
+    writer: &OutputWriter,
+) -> Result<(), GeneratorError> {
+    let is_image = from
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            ["jpg", "jpeg", "png"]
+                .iter()
+                .any(|image| ext.eq_ignore_ascii_case(image))
+        });
+    if config.strip && is_image {
+        if let Ok(contents) = std::fs::read(from) {
+            let stripped = strip_metadata(&contents, &config.keep)
+                .ok_or_else(|| GeneratorError::StripMetadata(from.to_path_buf()))?;
+            writer.write(dest, stripped);
+            return Ok(());
+        }
+    }
+    writer.copy(from, dest);
+    Ok(())
+}
+
+/// Removes the metadata from a JPEG or PNG image, returning `None` if the
+/// image is in another format or could not be understood.
+fn strip_metadata(contents: &[u8], keep: &[MetadataField]) -> Option<Vec<u8>> {
+    if contents.starts_with(&[0xff, 0xd8]) {
+        strip_jpeg(contents, keep)
+    } else if contents.starts_with(PNG_SIGNATURE) {
+        strip_png(contents, keep)
+    } else {
+        None
+    }
+}
+
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+
+fn strip_jpeg(contents: &[u8], keep: &[MetadataField]) -> Option<Vec<u8>> {
+    let mut output = contents.get(..2)?.to_vec();
+    let mut pos = 2;
+    loop {
+        if *contents.get(pos)? != 0xff {
+            return None;
+        }
+        let marker = *contents.get(pos + 1)?;
+        // Markers without a length
+        if marker == 0x01 || (0xd0..=0xd8).contains(&marker) {
+            output.extend_from_slice(&contents[pos..pos + 2]);
+            pos += 2;
+            continue;
+        }
+        if marker == 0xd9 {
+            output.extend_from_slice(&contents[pos..]);
+            return Some(output);
+        }
+        let len = u16::from_be_bytes([*contents.get(pos + 2)?, *contents.get(pos + 3)?]) as usize;
+        let segment = contents.get(pos..pos + 2 + len)?;
+        // The length includes its own two bytes, so anything less is broken
+        let data = segment.get(4..)?;
+        match marker {
+            // The image data follows the start of scan, so copy the rest as is
+            0xda => {
+                output.extend_from_slice(&contents[pos..]);
+                return Some(output);
+            }
+            0xe1 if data.starts_with(EXIF_HEADER) => {
+                let tiff = filter_exif(&data[EXIF_HEADER.len()..], keep)?;
+                if let Some(tiff) = tiff {
+                    let len = u16::try_from(2 + EXIF_HEADER.len() + tiff.len()).ok()?;
+                    output.extend_from_slice(&[0xff, 0xe1]);
+                    output.extend_from_slice(&len.to_be_bytes());
+                    output.extend_from_slice(EXIF_HEADER);
+                    output.extend_from_slice(&tiff);
+                }
+            }
+            // XMP and other APP1 data, Photoshop's IPTC records, and comments
+            0xe1 | 0xed | 0xfe => {}
+            _ => output.extend_from_slice(segment),
+        }
+        pos += 2 + len;
+    }
+}
+
+/// Builds a new EXIF block containing only the kept fields from the first
+/// image directory of `tiff`.
+///
+/// Returns `None` if the block could not be understood, and `Some(None)` if
+/// none of the kept fields are present.
+fn filter_exif(tiff: &[u8], keep: &[MetadataField]) -> Option<Option<Vec<u8>>> {
+    let big_endian = match tiff.get(..2)? {
+        b"II" => false,
+        b"MM" => true,
+        _ => return None,
+    };
+    let u16_at = |pos: usize| -> Option<u16> {
+        let bytes = [*tiff.get(pos)?, *tiff.get(pos + 1)?];
+        Some(if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    };
+    let u32_at = |pos: usize| -> Option<u32> {
+        let bytes: [u8; 4] = tiff.get(pos..pos + 4)?.try_into().ok()?;
+        Some(if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    };
+    let put_u16 = |out: &mut Vec<u8>, value: u16| {
+        out.extend_from_slice(&if big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        })
+    };
+    let put_u32 = |out: &mut Vec<u8>, value: u32| {
+        out.extend_from_slice(&if big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        })
+    };
+
+    let ifd = u32_at(4)? as usize;
+    let count = u16_at(ifd)? as usize;
+    // The tag, type, count, and value of each kept entry
+    let mut entries = vec![];
+    for i in 0..count {
+        let entry = ifd + 2 + i * 12;
+        let tag = u16_at(entry)?;
+        if !keep.iter().any(|field| field.exif_tag() == tag) {
+            continue;
+        }
+        let kind = u16_at(entry + 2)?;
+        let count = u32_at(entry + 4)?;
+        let size = match kind {
+            1 | 2 | 6 | 7 => 1,
+            3 | 8 => 2,
+            4 | 9 | 11 => 4,
+            5 | 10 | 12 => 8,
+            _ => return None,
+        } * count as usize;
+        let value = if size <= 4 {
+            tiff.get(entry + 8..entry + 8 + size)?
+        } else {
+            let offset = u32_at(entry + 8)? as usize;
+            tiff.get(offset..offset + size)?
+        };
+        entries.push((tag, kind, count, value));
+    }
+    if entries.is_empty() {
+        return Some(None);
+    }
+    entries.sort_by_key(|(tag, ..)| *tag);
+
+    let mut out = tiff[..4].to_vec();
+    put_u32(&mut out, 8);
+    put_u16(&mut out, entries.len() as u16);
+    let mut data_offset = 8 + 2 + entries.len() * 12 + 4;
+    let mut data = vec![];
+    for (tag, kind, count, value) in &entries {
+        put_u16(&mut out, *tag);
+        put_u16(&mut out, *kind);
+        put_u32(&mut out, *count);
+        if value.len() <= 4 {
+            let mut inline = value.to_vec();
+            inline.resize(4, 0);
+            out.extend_from_slice(&inline);
+        } else {
+            put_u32(&mut out, data_offset as u32);
+            data.extend_from_slice(value);
+            // Values start on a word boundary
+            if value.len() % 2 == 1 {
+                data.push(0);
+            }
+            data_offset = 8 + 2 + entries.len() * 12 + 4 + data.len();
+        }
+    }
+    put_u32(&mut out, 0);
+    out.extend_from_slice(&data);
+    Some(Some(out))
+}
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+fn strip_png(contents: &[u8], keep: &[MetadataField]) -> Option<Vec<u8>> {
+    let mut output = PNG_SIGNATURE.to_vec();
+    let mut pos = PNG_SIGNATURE.len();
+    while pos < contents.len() {
+        let len = u32::from_be_bytes(contents.get(pos..pos + 4)?.try_into().ok()?) as usize;
+        let chunk = contents.get(pos..pos + 12 + len)?;
+        let kind = &chunk[4..8];
+        let data = &chunk[8..8 + len];
+        let keep_chunk = match kind {
+            b"eXIf" => false,
+            b"tEXt" | b"zTXt" | b"iTXt" => {
+                let keyword = data.split(|b| *b == 0).next().unwrap_or_default();
+                keep.iter()
+                    .any(|field| field.png_keyword() == Some(keyword))
+            }
+            _ => true,
+        };
+        if keep_chunk {
+            output.extend_from_slice(chunk);
+        }
+        pos += 12 + len;
+    }
+    Some(output)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{strip_metadata, MetadataField, PNG_SIGNATURE};
+
+    /// Builds a little-endian EXIF block with an artist, a GPS pointer, and an
+    /// orientation.
+    fn exif() -> Vec<u8> {
+        let mut tiff = b"II\x2a\x00\x08\x00\x00\x00".to_vec();
+        tiff.extend_from_slice(&3u16.to_le_bytes());
+        // Artist, an ASCII string stored after the directory
+        let artist = b"Ana Lovelace\0";
+        tiff.extend_from_slice(&0x013bu16.to_le_bytes());
+        tiff.extend_from_slice(&2u16.to_le_bytes());
+        tiff.extend_from_slice(&(artist.len() as u32).to_le_bytes());
+        tiff.extend_from_slice(&(8 + 2 + 3 * 12 + 4u32).to_le_bytes());
+        // Orientation, a short stored inline
+        tiff.extend_from_slice(&0x0112u16.to_le_bytes());
+        tiff.extend_from_slice(&3u16.to_le_bytes());
+        tiff.extend_from_slice(&1u32.to_le_bytes());
+        tiff.extend_from_slice(&[6, 0, 0, 0]);
+        // GPS directory pointer
+        tiff.extend_from_slice(&0x8825u16.to_le_bytes());
+        tiff.extend_from_slice(&4u16.to_le_bytes());
+        tiff.extend_from_slice(&1u32.to_le_bytes());
+        tiff.extend_from_slice(&100u32.to_le_bytes());
+        tiff.extend_from_slice(&0u32.to_le_bytes());
+        tiff.extend_from_slice(artist);
+        tiff
+    }
+
+    /// Builds a JPEG with the given APP1 segment and comment.
+    fn jpeg(app1: Option<&[u8]>, comment: bool) -> Vec<u8> {
+        let mut jpeg = vec![0xff, 0xd8];
+        jpeg.extend_from_slice(&[0xff, 0xe0, 0x00, 0x04, b'J', b'F']);
+        if let Some(app1) = app1 {
+            jpeg.extend_from_slice(&[0xff, 0xe1]);
+            jpeg.extend_from_slice(&((app1.len() + 2) as u16).to_be_bytes());
+            jpeg.extend_from_slice(app1);
+        }
+        if comment {
+            jpeg.extend_from_slice(&[0xff, 0xfe, 0x00, 0x05, b'h', b'i', b'!']);
+        }
+        jpeg.extend_from_slice(&[0xff, 0xda, 0x00, 0x02, 0x12, 0x34, 0xff, 0xd9]);
+        jpeg
+    }
+
+    #[test]
+    fn strip_jpeg() {
+        let mut app1 = b"Exif\0\0".to_vec();
+        app1.extend(exif());
+        let original = jpeg(Some(&app1), true);
+
+        assert_eq!(strip_metadata(&original, &[]).unwrap(), jpeg(None, false));
+
+        let mut orientation = b"Exif\0\0II\x2a\x00\x08\x00\x00\x00\x01\x00".to_vec();
+        orientation.extend_from_slice(&[0x12, 0x01, 0x03, 0x00, 1, 0, 0, 0, 6, 0, 0, 0]);
+        orientation.extend_from_slice(&[0, 0, 0, 0]);
+        assert_eq!(
+            strip_metadata(&original, &[MetadataField::Orientation]).unwrap(),
+            jpeg(Some(&orientation), false)
+        );
+
+        let artist = strip_metadata(&original, &[MetadataField::Artist]).unwrap();
+        assert!(artist
+            .windows(b"Ana Lovelace".len())
+            .any(|w| w == b"Ana Lovelace"));
+    }
+
+    #[test]
+    fn damaged_jpeg() {
+        assert!(strip_metadata(&[0xff, 0xd8], &[]).is_none());
+        assert!(strip_metadata(&[0xff, 0xd8, 0xff, 0xe1, 0x00, 0x01, 0x00], &[]).is_none());
+        let mut truncated = jpeg(Some(b"Exif\0\0"), false);
+        truncated.truncate(8);
+        assert!(strip_metadata(&truncated, &[]).is_none());
+    }
+
+    #[test]
+    fn strip_png() {
+        let chunk = |kind: &[u8], data: &[u8]| {
+            let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+            chunk.extend_from_slice(kind);
+            chunk.extend_from_slice(data);
+            chunk.extend_from_slice(&[0; 4]);
+            chunk
+        };
+        let mut png = PNG_SIGNATURE.to_vec();
+        png.extend(chunk(b"IHDR", &[0; 13]));
+        png.extend(chunk(b"eXIf", &exif()));
+        png.extend(chunk(b"tEXt", b"Copyright\0Ana"));
+        png.extend(chunk(b"tEXt", b"Location\0Home"));
+        png.extend(chunk(b"IEND", &[]));
+
+        let mut expected = PNG_SIGNATURE.to_vec();
+        expected.extend(chunk(b"IHDR", &[0; 13]));
+        expected.extend(chunk(b"tEXt", b"Copyright\0Ana"));
+        expected.extend(chunk(b"IEND", &[]));
+        assert_eq!(
+            strip_metadata(&png, &[MetadataField::Copyright]).unwrap(),
+            expected
+        );
+    }
+}
@@ -0,0 +1,128 @@
+//! Rendering sites into `feed.json` files, following [JSON Feed 1.1]
+//!
+//! [JSON Feed 1.1]: https://www.jsonfeed.org/version/1.1/
+
+use serde_json::{json, Map, Value};
+
+use crate::{
+    index::{PageMetadata, SiteMetadata},
+    renderer::{RenderedPageRef, RenderedSite},
+};
+
+use super::content_hashes::ContentHashes;
+
+/// Where the JSON feed is published, relative to the site root
+pub(crate) const JSON_FEED_PATH: &str = "feed.json";
+
+/// Builds a JSON feed of the ten most recent posts, matching `atom.xml`.
+pub(crate) fn generate_json_feed(site: &RenderedSite<'_>, hashes: &ContentHashes) -> Value {
+    let summary_only = site.config().feed.summary_only(None);
+    let mut posts = site.posts().collect::<Vec<_>>();
+    posts.sort_by_key(|post| std::cmp::Reverse(post.publish_date()));
+    posts.truncate(10);
+
+    let mut feed = Map::new();
+    feed.insert("version".into(), json!("https://jsonfeed.org/version/1.1"));
+    feed.insert("title".into(), json!(site.title()));
+    feed.insert("home_page_url".into(), json!(site.base_url()));
+    feed.insert("feed_url".into(), json!(site.full_url(JSON_FEED_PATH)));
+    if let Some(subtitle) = site.subtitle() {
+        feed.insert("description".into(), json!(subtitle));
+    }
+    if let Some(author) = site.author() {
+        feed.insert("authors".into(), json!([{ "name": author }]));
+    }
+    feed.insert(
+        "items".into(),
+        posts
+            .into_iter()
+            .map(|post| item(site, hashes, post, summary_only))
+            .collect(),
+    );
+    feed.into()
+}
+
+fn item(
+    site: &RenderedSite<'_>,
+    hashes: &ContentHashes,
+    post: RenderedPageRef<'_>,
+    summary_only: bool,
+) -> Value {
+    let url = site.full_url(&post.url());
+    let mut item = Map::new();
+    item.insert("id".into(), json!(url));
+    item.insert("url".into(), json!(url));
+    item.insert("title".into(), json!(post.title()));
+
+    // Every item needs some content, so summary-only feeds use the summary
+    match (summary_only, post.rendered_excerpt(), post.description()) {
+        (false, ..) => {
+            item.insert("content_html".into(), json!(post.rendered_contents()));
+        }
+        (true, Some(excerpt), _) => {
+            item.insert("content_html".into(), json!(excerpt));
+        }
+        (true, None, Some(description)) => {
+            item.insert("content_text".into(), json!(description));
+        }
+        (true, None, None) => {
+            item.insert("content_text".into(), json!(""));
+        }
+    }
+    if let Some(description) = post.description() {
+        item.insert("summary".into(), json!(description));
+    }
+    if let Some(published) = post.publish_date() {
+        item.insert("date_published".into(), json!(published.to_rfc3339()));
+    }
+    if let Some(updated) = hashes.updated(post).or(post.publish_date()) {
+        item.insert("date_modified".into(), json!(updated.to_rfc3339()));
+    }
+    if let Some(author) = site.author() {
+        item.insert("authors".into(), json!([{ "name": author }]));
+    }
+    let source = post.source();
+    let tags: Vec<_> = source.categories().iter().chain(source.tags()).collect();
+    if !tags.is_empty() {
+        item.insert("tags".into(), json!(tags));
+    }
+    item.into()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        index::{PageSource, SiteIndex, SourceFormat},
+        renderer::{CodeFormatter, RenderContext, RenderSource, RenderedPageRef},
+    };
+
+    use super::{item, ContentHashes};
+
+    #[test]
+    fn feed_item() -> miette::Result<()> {
+        let source = PageSource::from_string(
+            "_posts/2024-01-02-hello.md",
+            SourceFormat::Markdown,
+            "---\nlayout: post\ntitle: Hello\ndescription: A greeting\ntags: rust\n---\nintro\n<!-- MORE -->\nrest\n",
+        );
+        let index = SiteIndex::default();
+        let fmt = CodeFormatter::new();
+        let page = RenderContext::run_dcx(&index, &fmt, |rcx| source.render(rcx))?;
+        let site = index.render()?;
+        let post = RenderedPageRef::new(&source, &page);
+
+        let full = item(&site, &ContentHashes::default(), post, false);
+        assert_eq!(full["title"], "Hello");
+        assert!(full["content_html"].as_str().unwrap().contains("rest"));
+        assert_eq!(full["summary"], "A greeting");
+        assert_eq!(full["tags"][0], "rust");
+        assert!(full["date_published"]
+            .as_str()
+            .unwrap()
+            .starts_with("2024-01-0"));
+
+        let summary = item(&site, &ContentHashes::default(), post, true);
+        assert_eq!(summary["content_html"], "<p>intro</p>\n");
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+//! Structured data that tells search engines what a post is about
+//!
+//! Posts get schema.org `BlogPosting` and `BreadcrumbList` items in a JSON-LD
+//! `<script>` element.
+
+use serde_json::{json, Value};
+
+use crate::{
+    index::{PageMetadata, SiteMetadata},
+    renderer::{RenderedPageRef, RenderedSite},
+};
+
+use super::{breadcrumbs::breadcrumbs, canonical_url};
+
+/// Builds the JSON-LD for a post.
+pub(crate) fn post_jsonld(site: &RenderedSite<'_>, post: RenderedPageRef<'_>) -> Value {
+    let url = site.full_url(&post.url());
+
+    // Republished posts point search engines at the original
+    let mut posting = json!({
+        "@type": "BlogPosting",
+        "headline": post.title(),
+        "url": url,
+        "mainEntityOfPage": canonical_url(site, post),
+    });
+    if let Some(date) = post.publish_date() {
+        posting["datePublished"] = json!(date.to_rfc3339());
+    }
+    if let Some(author) = site.author() {
+        posting["author"] = json!({ "@type": "Person", "name": author });
+    }
+    if let Some(description) = post.description() {
+        posting["description"] = json!(description);
+    }
+    if let Some(image) = post.source().image() {
+        posting["image"] = json!(absolute_url(site, &post.url(), image));
+    }
+
+    let breadcrumbs = json!({
+        "@type": "BreadcrumbList",
+        "itemListElement": breadcrumbs(site, post)
+            .into_iter()
+            .enumerate()
+            .map(|(i, crumb)| {
+                json!({
+                    "@type": "ListItem",
+                    "position": i + 1,
+                    "name": crumb.title,
+                    "item": site.full_url(&crumb.url),
+                })
+            })
+            .collect::<Vec<_>>(),
+    });
+
+    json!({
+        "@context": "https://schema.org",
+        "@graph": [posting, breadcrumbs],
+    })
+}
+
+/// Wraps JSON-LD in a `<script>` element.
+pub(crate) fn jsonld_markup(jsonld: &Value) -> String {
+    // `</script>` inside a string would end the element early
+    let json = jsonld.to_string().replace("</", "<\\/");
+    format!(r#"<script type="application/ld+json">{json}</script>"#)
+}
+
+/// Resolves a link from a page's frontmatter into an absolute URL.
+fn absolute_url(site: &RenderedSite<'_>, page_url: &str, link: &str) -> String {
+    if link.contains("://") {
+        link.to_string()
+    } else if link.starts_with('/') {
+        site.full_url(link)
+    } else {
+        site.full_url(&format!("{}/{link}", page_url.trim_end_matches('/')))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::index::{PageSource, SiteIndex, SourceFormat};
+
+    use super::{jsonld_markup, post_jsonld};
+
+    #[test]
+    fn blog_posting() {
+        let mut site = SiteIndex::default();
+        site.add_page(PageSource::from_string(
+            "_posts/2024-03-01-hello.md",
+            SourceFormat::Markdown,
+            "---\nlayout: post\ntitle: Hello </script>\nimage: cover.png\ncanonical_url: https://elsewhere.example/hello\n---\n",
+        ));
+        let site = site.render().unwrap();
+        let post = site.posts().next().unwrap();
+
+        let jsonld = post_jsonld(&site, post);
+        let posting = &jsonld["@graph"][0];
+        assert_eq!(posting["headline"], "Hello </script>");
+        assert_eq!(
+            posting["datePublished"].as_str().unwrap().get(..10),
+            Some("2024-03-01")
+        );
+        assert_eq!(posting["image"], "/blog/2024/03/01/hello/cover.png");
+        assert_eq!(
+            posting["mainEntityOfPage"],
+            "https://elsewhere.example/hello"
+        );
+        assert_eq!(
+            jsonld["@graph"][1]["itemListElement"][1]["item"],
+            "/blog/2024/03/01/hello/"
+        );
+        assert!(!jsonld_markup(&jsonld).contains("Hello </script>"));
+    }
+}
@@ -0,0 +1,138 @@
+//! Measuring how much memory a build uses
+//!
+//! The whole rendered site stays in memory until every page is written, since
+//! listings, feeds, and hashes all need it. On top of that, each page's
+//! template context includes the whole `site` value, and pages are generated
+//! in parallel, so large sites can hold copies of it on every thread at once.
+//! When the `[memory]` section of `Site.toml` sets a limit that this would
+//! exceed, pages are generated one at a time instead. There is no streaming
+//! mode to fall back on.
+
+use serde::Deserialize;
+
+/// The `[memory]` section of `Site.toml`
+///
+/// All sizes are in bytes.
+#[derive(Deserialize, Debug, Default)]
+pub struct MemoryConfig {
+    /// Whether to include memory use in the build summary
+    #[serde(default)]
+    pub report: bool,
+    /// Generate pages one at a time when generating them in parallel would
+    /// probably use more memory than this
+    pub limit: Option<u64>,
+}
+
+impl MemoryConfig {
+    fn enabled(&self) -> bool {
+        self.report || self.limit.is_some()
+    }
+}
+
+/// How much memory a build used, and what used it
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryReport {
+    /// The most memory the process had resident, if the platform reports it
+    pub peak_rss: Option<u64>,
+    /// An estimate of the size of the `site` template value, from the text of
+    /// the posts in it
+    pub site_value: u64,
+    /// The total size of every page's rendered HTML
+    pub rendered_content: u64,
+    /// Whether pages were generated one at a time to stay under the limit
+    pub sequential: bool,
+}
+
+/// What is known about memory use before pages are generated
+pub(crate) struct MemoryEstimate {
+    site_value: u64,
+    rendered_content: u64,
+    /// The memory in use before generating pages
+    current_rss: Option<u64>,
+}
+
+impl MemoryEstimate {
+    /// Measures the site if `config` asks for memory use to be reported or
+    /// limited.
+    ///
+    /// `site_value` is an estimate of the size of the `site` template value.
+    pub(crate) fn measure(
+        config: &MemoryConfig,
+        site_value: u64,
+        rendered_content: u64,
+    ) -> Option<Self> {
+        config.enabled().then(|| Self {
+            site_value,
+            rendered_content,
+            current_rss: process_status("VmRSS"),
+        })
+    }
+
+    /// Estimates the peak memory use if `threads` pages are generated at once.
+    fn peak(&self, threads: usize) -> u64 {
+        // The rendered pages are part of the memory already in use. Each
+        // thread holds the `site` value it builds as well as the template
+        // context's copy of it.
+        self.current_rss.unwrap_or(self.rendered_content) + self.site_value * 2 * threads as u64
+    }
+
+    /// Whether pages should be generated one at a time to stay under the
+    /// limit in `config`
+    pub(crate) fn sequential(&self, config: &MemoryConfig, threads: usize) -> bool {
+        threads > 1 && config.limit.is_some_and(|limit| self.peak(threads) > limit)
+    }
+
+    pub(crate) fn report(&self, sequential: bool) -> MemoryReport {
+        MemoryReport {
+            peak_rss: process_status("VmHWM"),
+            site_value: self.site_value,
+            rendered_content: self.rendered_content,
+            sequential,
+        }
+    }
+}
+
+/// Reads a size in bytes from `/proc/self/status`, which only exists on Linux
+fn process_status(field: &str) -> Option<u64> {
+    parse_status(&std::fs::read_to_string("/proc/self/status").ok()?, field)
+}
+
+fn parse_status(status: &str, field: &str) -> Option<u64> {
+    let line = status
+        .lines()
+        .find_map(|line| line.strip_prefix(field)?.strip_prefix(':'))?;
+    let kilobytes = line.trim().strip_suffix("kB")?.trim().parse::<u64>().ok()?;
+    Some(kilobytes * 1024)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_status, MemoryConfig, MemoryEstimate};
+
+    #[test]
+    fn status() {
+        let status = "Name:\tebg\nVmHWM:\t  204800 kB\nVmRSS:\t  102400 kB\n";
+        assert_eq!(parse_status(status, "VmHWM"), Some(200 * 1024 * 1024));
+        assert_eq!(parse_status(status, "VmRSS"), Some(100 * 1024 * 1024));
+        assert_eq!(parse_status(status, "VmSwap"), None);
+    }
+
+    #[test]
+    fn limit() {
+        let estimate = MemoryEstimate {
+            site_value: 10,
+            rendered_content: 50,
+            current_rss: Some(100),
+        };
+        let config = |limit| MemoryConfig {
+            report: false,
+            limit,
+        };
+        assert!(!estimate.sequential(&config(None), 8));
+        assert!(!estimate.sequential(&config(Some(260)), 8));
+        assert!(estimate.sequential(&config(Some(259)), 8));
+        assert!(!estimate.sequential(&config(Some(0)), 1));
+
+        assert!(MemoryEstimate::measure(&config(None), 0, 0).is_none());
+    }
+}
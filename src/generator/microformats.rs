@@ -0,0 +1,229 @@
+//! Support for themes that mark up posts with microformats2
+//!
+//! IndieWeb tools read `h-entry` and `h-card` classes to understand posts and
+//! their authors. The generator provides ready-made values for the properties
+//! and checks that themes include the ones readers expect.
+
+use std::{cell::RefCell, collections::HashSet, rc::Rc};
+
+use lol_html::{element, rewrite_str, EndTagHandler, RewriteStrSettings};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{
+    index::{PageMetadata, SiteMetadata},
+    renderer::{RenderedPageRef, RenderedSite},
+};
+
+/// The `[microformats]` section of `Site.toml`
+#[derive(Deserialize, Debug, Default)]
+pub struct MicroformatsConfig {
+    /// Adds `page.mf2` to the template context and checks pages that use
+    /// microformats for missing properties
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+/// The properties each root class should have
+const REQUIRED_PROPERTIES: &[(&str, &[&str])] = &[
+    (
+        "h-entry",
+        &["e-content", "dt-published", "u-url", "p-author"],
+    ),
+    ("h-card", &["p-name", "u-url"]),
+];
+
+/// The root classes in the microformats2 vocabulary
+///
+/// Other classes starting with `h-`, such as `h-100` from CSS frameworks, are
+/// not microformats.
+const ROOT_CLASSES: &[&str] = &[
+    "h-adr",
+    "h-app",
+    "h-card",
+    "h-cite",
+    "h-entry",
+    "h-event",
+    "h-feed",
+    "h-geo",
+    "h-item",
+    "h-listing",
+    "h-product",
+    "h-recipe",
+    "h-resume",
+    "h-review",
+    "h-review-aggregate",
+];
+
+/// Builds the values for a page's microformats properties.
+pub(crate) fn mf2_value(site: &RenderedSite<'_>, page: RenderedPageRef<'_>) -> Value {
+    let mut value = json!({
+        "name": page.title(),
+        "url": site.full_url(&page.url()),
+        "category": page.source().tags(),
+        "author": {
+            "name": site.author(),
+            "url": site.full_url(""),
+            "email": site.author_email().map(|email| format!("mailto:{email}")),
+        },
+    });
+    if let Some(date) = page.publish_date() {
+        value["published"] = json!(date.to_rfc3339());
+    }
+    if let Some(description) = page.description() {
+        value["summary"] = json!(description);
+    }
+    value
+}
+
+/// A microformats root, such as `h-entry`, and the properties found inside it
+struct Root {
+    class: String,
+    properties: HashSet<String>,
+}
+
+/// Finds the properties that are missing from the microformats on a page.
+///
+/// Properties only count toward the nearest root that contains them, so an
+/// `h-entry`'s `p-name` does not satisfy its author's `h-card`.
+pub(crate) fn missing_properties(html: &str) -> Vec<(String, &'static str)> {
+    let stack: Rc<RefCell<Vec<Root>>> = Rc::default();
+    let missing: Rc<RefCell<Vec<(String, &'static str)>>> = Rc::default();
+    let handler = {
+        let stack = stack.clone();
+        let missing = missing.clone();
+        element!("[class]", move |el| {
+            let class = el.get_attribute("class").unwrap();
+            let classes: Vec<_> = class.split_whitespace().collect();
+            if let Some(root) = stack.borrow_mut().last_mut() {
+                root.properties.extend(
+                    classes
+                        .iter()
+                        .filter(|class| is_property(class))
+                        .map(|class| class.to_string()),
+                );
+            }
+
+            let roots: Vec<_> = classes
+                .iter()
+                .filter(|class| ROOT_CLASSES.contains(class))
+                .map(|class| class.to_string())
+                .collect();
+            if roots.is_empty() {
+                return Ok(());
+            }
+            // Links have an implied `u-url`
+            let is_link = el.tag_name() == "a" && el.has_attribute("href");
+            let Some(handlers) = el.end_tag_handlers() else {
+                return Ok(());
+            };
+            let count = roots.len();
+            stack
+                .borrow_mut()
+                .extend(roots.into_iter().map(|class| Root {
+                    class,
+                    properties: HashSet::new(),
+                }));
+            let stack = stack.clone();
+            let missing = missing.clone();
+            let handler: EndTagHandler<'static> = Box::new(move |_| {
+                let mut stack = stack.borrow_mut();
+                let mut properties = HashSet::new();
+                let roots: Vec<_> = (0..count).filter_map(|_| stack.pop()).collect();
+                for root in &roots {
+                    properties.extend(root.properties.iter().cloned());
+                }
+                add_implied_properties(&mut properties, is_link);
+                for root in roots.into_iter().rev() {
+                    missing
+                        .borrow_mut()
+                        .extend(check_root(&root.class, &properties));
+                }
+                Ok(())
+            });
+            handlers.push(handler);
+            Ok(())
+        })
+    };
+    // Errors only mean the rest of the page couldn't be checked
+    let _ = rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![handler],
+            ..RewriteStrSettings::new()
+        },
+    );
+
+    let missing = missing.borrow().clone();
+    missing
+}
+
+fn is_property(class: &str) -> bool {
+    ["p-", "u-", "dt-", "e-"]
+        .iter()
+        .any(|prefix| class.starts_with(prefix))
+}
+
+/// Adds the properties a microformats parser would infer for a root with
+/// nothing more specific.
+fn add_implied_properties(properties: &mut HashSet<String>, is_link: bool) {
+    let has_prefix = |prefixes: &[&str]| {
+        properties
+            .iter()
+            .any(|property| prefixes.iter().any(|prefix| property.starts_with(prefix)))
+    };
+    let implied_name = !has_prefix(&["p-", "e-"]);
+    let implied_url = is_link && !has_prefix(&["u-"]);
+    if implied_name {
+        properties.insert("p-name".to_string());
+    }
+    if implied_url {
+        properties.insert("u-url".to_string());
+    }
+}
+
+fn check_root(class: &str, properties: &HashSet<String>) -> Vec<(String, &'static str)> {
+    REQUIRED_PROPERTIES
+        .iter()
+        .filter(|(root, _)| *root == class)
+        .flat_map(|(_, required)| required.iter())
+        .filter(|property| !properties.contains(**property))
+        .map(|property| (class.to_string(), *property))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::missing_properties;
+
+    #[test]
+    fn check_properties() {
+        let html = r#"<article class="h-entry">
+            <h1 class="p-name">Title</h1>
+            <a class="u-url" href="/post/"><time class="dt-published">Today</time></a>
+            <div class="e-content">Hi</div>
+            <a class="p-author h-card" href="/">Me</a>
+        </article>"#;
+        assert!(missing_properties(html).is_empty());
+
+        let html = r#"<article class="h-entry">
+            <div class="e-content">Hi</div>
+            <div class="p-author h-card"><span class="p-org">Me</span></div>
+        </article>"#;
+        let missing: Vec<_> = missing_properties(html)
+            .into_iter()
+            .map(|(root, property)| format!("{root} {property}"))
+            .collect();
+        assert_eq!(
+            missing,
+            [
+                "h-card p-name",
+                "h-card u-url",
+                "h-entry dt-published",
+                "h-entry u-url"
+            ]
+        );
+        assert!(missing_properties("<p>No microformats</p>").is_empty());
+        assert!(missing_properties(r#"<div class="h-100 w-50">Utility classes</div>"#).is_empty());
+    }
+}
@@ -1,6 +1,10 @@
 use std::{
     io,
     path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
 };
 
 use miette::Diagnostic;
@@ -9,21 +13,113 @@ use serde_json::{json, Map, Value};
 use std::fs;
 use tera::Tera;
 use thiserror::Error;
-use tracing::{debug, warn};
+use tracing::{debug, info, instrument, warn};
 
 use crate::{
+    audit::{
+        a11y::{check_page, check_theme_colors},
+        sort_findings, Finding,
+    },
     index::{PageMetadata, SiteMetadata},
-    renderer::{RenderedPageRef, RenderedSite},
+    lock::LockMode,
+    renderer::{
+        is_safe_url, ColorScheme, RenderedPageRef, RenderedSite, DECODE_SCRIPT, HIDDEN_HREF,
+    },
 };
 use clap::Args;
 use clap::ValueHint::DirPath;
 
-use self::{atom::generate_atom, theme::create_template_engine};
+use self::{
+    atom::{generate_atom, generate_series_feeds, series_feed_path},
+    breadcrumbs::breadcrumbs_value,
+    build_info::BuildInfo,
+    bundle::Bundles,
+    calendar::generate_calendar,
+    content_hashes::ContentHashes,
+    critical_css::CriticalCss,
+    deploy::deploy_files,
+    favicon::{favicon_markup, generate_favicons},
+    feeds::{feeds_value, Feeds},
+    forms::{forms_value, generate_worker},
+    health::{health_page, HEALTH_PATH},
+    image_metadata::copy_file,
+    jsonfeed::{generate_json_feed, JSON_FEED_PATH},
+    jsonld::{jsonld_markup, post_jsonld},
+    memory::MemoryEstimate,
+    microformats::{mf2_value, missing_properties},
+    on_this_day::{on_this_day, posts_by_day},
+    page_assets::PageAssets,
+    qr_code::{qr_code_svg, QR_CODE_FILE},
+    random::register_random_filters,
+    responsive_images::generate_resized_images,
+    revisions::{changelog_markup, Revisions},
+    sections::Sections,
+    short_urls::ShortUrls,
+    sitemap::generate_sitemap,
+    text_files::{generate_humans, generate_robots, generate_security},
+    theme::create_template_engine,
+    thumbnail::{generate_gallery_thumbnails, Thumbnails},
+    verify::verify_output,
+    video::generate_posters,
+    web_app::{generate_manifest, generate_service_worker, web_app_markup},
+    writer::OutputWriter,
+};
 
 use rayon::prelude::*;
 
 mod atom;
+mod breadcrumbs;
+mod build_info;
+mod bundle;
+mod calendar;
+mod content_hashes;
+mod critical_css;
+mod deploy;
+mod favicon;
+mod feeds;
+mod forms;
+mod health;
+mod image_cache;
+mod image_metadata;
+mod jsonfeed;
+mod jsonld;
+mod memory;
+mod microformats;
+mod on_this_day;
+mod page_assets;
+mod qr_code;
+mod random;
+mod responsive_images;
+mod revisions;
+mod sections;
+mod short_urls;
+mod sitemap;
+mod summary;
+mod taxonomies;
+mod text_files;
 mod theme;
+mod thumbnail;
+mod verify;
+mod video;
+mod web_app;
+mod writer;
+
+pub use atom::{FeedConfig, SeriesFeedConfig};
+pub use bundle::BundleConfig;
+pub use deploy::{DeployConfig, DeployProvider, Redirect};
+pub use forms::{FormConfig, FormProvider, FormsConfig};
+pub use image_metadata::{ImageMetadataConfig, MetadataField};
+pub use memory::{MemoryConfig, MemoryReport};
+pub use microformats::MicroformatsConfig;
+pub use on_this_day::OnThisDayConfig;
+pub use revisions::RevisionsConfig;
+pub use short_urls::ShortUrlConfig;
+pub use summary::{BudgetConfig, BudgetKind, BudgetViolation, BuildSummary};
+pub use taxonomies::{Taxonomy, TaxonomyConfig};
+pub use text_files::{Human, HumansConfig, RobotsConfig, RobotsRule, SecurityConfig};
+pub use thumbnail::ThumbnailConfig;
+pub use web_app::WebAppConfig;
+pub use writer::{WriteErrors, WriteFailure};
 
 #[derive(Args, Clone)]
 pub struct Options {
@@ -36,30 +132,123 @@ pub struct Options {
     /// Include posts marked with `published: false`
     #[arg(long, default_value_t = false)]
     pub unpublished: bool,
+
+    /// Flush every generated file to disk before finishing
+    #[arg(long, default_value_t = false)]
+    pub fsync: bool,
+
+    /// Only build pages and files whose path matches this glob
+    ///
+    /// Can be given more than once. The existing output is updated in place.
+    #[arg(long, value_name = "GLOB")]
+    pub only: Vec<String>,
+
+    /// Wait for another build into the same destination to finish instead of
+    /// stopping with an error
+    #[arg(long, conflicts_with = "force")]
+    pub wait: bool,
+
+    /// Build even if another build is writing to the same destination
+    #[arg(long)]
+    pub force: bool,
+
+    /// Check the output after building: that every URL in the sitemap leads
+    /// to a file, that every page was written, and that the feeds parse
+    #[arg(long)]
+    pub verify: bool,
+}
+
+impl Options {
+    /// What to do if another build has the destination locked
+    pub fn lock_mode(&self) -> LockMode {
+        if self.force {
+            LockMode::Force
+        } else if self.wait {
+            LockMode::Wait
+        } else {
+            LockMode::Fail
+        }
+    }
 }
 
 #[derive(Diagnostic, Debug, Error)]
 pub enum GeneratorError {
     #[error("generating atom feed")]
     AtomError(#[source] atom::AtomError),
+    #[error("generating taxonomy pages")]
+    Taxonomy(#[source] taxonomies::TaxonomyError),
+    #[error("generating sitemap")]
+    Sitemap(#[source] quick_xml::Error),
     #[error("could not compute relative path for {0}")]
     ComputeRelativePath(PathBuf),
+    #[error("build cancelled after generating {generated} of {total} pages; {outcome}")]
+    Cancelled {
+        generated: usize,
+        total: usize,
+        /// What became of the destination directory
+        outcome: String,
+    },
+    #[error("the output failed verification:{}", verify::list_problems(.0))]
+    Verify(Vec<String>),
     #[error("removing old destination directory: {}", .0.display())]
     CleanDestDir(PathBuf, #[source] io::Error),
     #[error("creating destination directory: {}", .0.display())]
     CreateDestDir(PathBuf, #[source] io::Error),
-    #[error("copying {} to {}", .0.display(), .1.display())]
-    Copy(PathBuf, PathBuf, #[source] io::Error),
-    #[error("creating file `{}`", .0.display())]
-    CreateFile(PathBuf, #[source] io::Error),
-    #[error("writing file contents to `{}`", .0.display())]
-    WriteFile(PathBuf, #[source] io::Error),
+    #[error("reading symbolic link `{}`", .0.display())]
+    ReadLink(PathBuf, #[source] io::Error),
     #[error("loading templates")]
     LoadTemplates(#[source] Box<dyn std::error::Error + Send + Sync>),
     #[error("importing site macros")]
     ImportSiteMacros(#[source] Box<dyn std::error::Error + Send + Sync>),
     #[error("rendering template")]
     RenderTemplate(#[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error("reading `{1}`, which is used by `{}`", .0.display())]
+    PageAsset(PathBuf, String, #[source] io::Error),
+    #[error("creating temporary directory for bundles")]
+    BundleDir(#[source] io::Error),
+    #[error("no bundler command is configured")]
+    MissingBundler,
+    #[error("running bundler `{0}`")]
+    RunBundler(String, #[source] io::Error),
+    #[error("bundling `{}` failed with {1}", .0.display())]
+    BundlerFailed(PathBuf, std::process::ExitStatus),
+    #[error("reading bundle for `{0}`")]
+    BundleOutput(String, #[source] io::Error),
+    #[error("inlining critical CSS")]
+    CriticalCss(#[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    Write(WriteErrors),
+    #[error("generating QR code for `{0}`")]
+    QrCode(String, #[source] qrcode::types::QrError),
+    #[error("generating favicon from `{}`", .0.display())]
+    Favicon(PathBuf, #[source] image::ImageError),
+    #[error("generating thumbnail from `{}`", .0.display())]
+    Thumbnail(PathBuf, #[source] image::ImageError),
+    #[error(
+        "could not remove the metadata from `{}`, which may be damaged; fix or replace it, or set \
+         `strip = false` in `[image_metadata]` to publish it as it is",
+        .0.display()
+    )]
+    StripMetadata(PathBuf),
+    #[error("resizing `{}`", .0.display())]
+    ResizeImage(PathBuf, #[source] image::ImageError),
+    #[error("creating temporary directory for video posters")]
+    PosterDir(#[source] io::Error),
+    #[error("no poster command is configured")]
+    MissingPosterCommand,
+    #[error("running poster command `{0}`")]
+    RunPosterCommand(String, #[source] io::Error),
+    #[error("generating poster for `{}` failed with {1}", .0.display())]
+    PosterCommandFailed(PathBuf, std::process::ExitStatus),
+    #[error("reading content manifest `{}`", .0.display())]
+    ReadManifest(PathBuf, #[source] io::Error),
+    #[error("parsing content manifest `{}`", .0.display())]
+    ParseManifest(PathBuf, #[source] toml::de::Error),
+    #[error("serializing content manifest")]
+    SerializeManifest(#[source] toml::ser::Error),
+    #[error("writing content manifest `{}`", .0.display())]
+    WriteManifest(PathBuf, #[source] io::Error),
 }
 
 pub trait Observer: Send + Sync {
@@ -75,15 +264,46 @@ pub struct GeneratorContext<'a> {
     templates: Tera,
     options: &'a Options,
     progress: Option<&'a dyn Observer>,
+    build: BuildInfo,
+    page_assets: PageAssets,
+    critical_css: Option<CriticalCss>,
+    bundles: Bundles,
+    short_urls: ShortUrls,
+    sections: Sections,
+    thumbnails: Thumbnails,
+    content_hashes: ContentHashes,
+    revisions: Revisions,
+    feeds: Feeds,
 }
 
 impl<'a> GeneratorContext<'a> {
     pub fn new(site: &RenderedSite, options: &'a Options) -> Result<Self, GeneratorError> {
-        let templates = create_template_engine(site.root_dir(), site.config())?;
+        let mut templates = create_template_engine(site.root_dir(), site.config())?;
+        let bundles = Bundles::build(site)?;
+        templates.register_function("asset", bundles.template_function());
+        let build = BuildInfo::new(site.root_dir());
+        // Seeding from the clock would make every build different
+        let seed = site
+            .config()
+            .random_seed
+            .or(build.fixed.then(|| build.timestamp.timestamp() as u64))
+            .unwrap_or_default();
+        register_random_filters(&mut templates, seed);
+        let content_hashes = ContentHashes::load(site, build.timestamp)?;
         Ok(Self {
             templates,
             options,
             progress: None,
+            build,
+            page_assets: PageAssets::collect(site)?,
+            critical_css: site.config().critical_css.then(CriticalCss::default),
+            bundles,
+            short_urls: ShortUrls::assign(site),
+            sections: Sections::build(site),
+            thumbnails: Thumbnails::collect(site),
+            content_hashes,
+            revisions: Revisions::load(site.root_dir(), site.config().revisions.as_ref()),
+            feeds: Feeds::new(site),
         })
     }
 
@@ -92,27 +312,33 @@ impl<'a> GeneratorContext<'a> {
         self
     }
 
-    pub async fn generate_site(&self, site: &RenderedSite<'_>) -> super::Result<()> {
-        // Clear the destination directory
-        let cleanup = if self.options.destination.exists() {
+    /// Generates the whole site into the destination directory and returns
+    /// a summary of what was written.
+    #[instrument(name = "generate", skip_all)]
+    pub async fn generate_site(&self, site: &RenderedSite<'_>) -> super::Result<BuildSummary> {
+        // Clear the destination directory, unless this is a partial build that
+        // updates the existing output. The old output is kept until the new
+        // output is complete so it can be put back if the build is cancelled.
+        let old = if site.filter().is_none() && self.options.destination.exists() {
             let old = tempfile::tempdir().unwrap();
             debug!(
                 "moving old destination directory out of the way: {} → {}",
                 self.options.destination.display(),
                 old.path().display()
             );
-            fs::rename(&self.options.destination, &old.path().join("publish"))
-                .or_else(|e| {
+            match fs::rename(&self.options.destination, old.path().join("publish")) {
+                Ok(()) => Some(old),
+                Err(e) => {
                     warn!(
                         "failed to move old destination directory, falling back on regular removal: {}",
                         e);
                     // If the rename fails, try to remove the destination directory
-                    fs::remove_dir_all(&self.options.destination)
-                })
-                .map_err(|e| GeneratorError::CleanDestDir(self.options.destination.clone(), e))?;
-            Some(tokio::spawn(async move {
-                drop(old);
-            }))
+                    fs::remove_dir_all(&self.options.destination).map_err(|e| {
+                        GeneratorError::CleanDestDir(self.options.destination.clone(), e)
+                    })?;
+                    None
+                }
+            }
         } else {
             None
         };
@@ -122,78 +348,621 @@ impl<'a> GeneratorContext<'a> {
             .await
             .map_err(|e| GeneratorError::CreateDestDir(self.options.destination.clone(), e))?;
 
+        let writer = OutputWriter::new(&self.options.destination, self.options.fsync);
+        let accessibility = site
+            .config()
+            .audit
+            .accessibility
+            .then(|| Mutex::new(check_theme_colors(&site.config().theme_opts)));
+
+        let memory_config = &site.config().memory;
+        // Each post's content and excerpt make up most of the `site` value
+        let memory = MemoryEstimate::measure(
+            memory_config,
+            site.posts()
+                .map(|post| {
+                    let content = post.rendered_contents().len();
+                    (content + post.rendered_excerpt().map_or(content, str::len)) as u64
+                })
+                .sum(),
+            site.all_pages()
+                .map(|page| page.rendered_contents().len() as u64)
+                .sum(),
+        );
+        let sequential = memory
+            .as_ref()
+            .is_some_and(|memory| memory.sequential(memory_config, rayon::current_num_threads()));
+        if sequential {
+            info!("generating pages one at a time to stay under the memory limit");
+        }
+
         // Generate pages
-        site.all_pages()
-            .collect::<Vec<_>>()
-            .par_iter()
-            .try_for_each(|post: &RenderedPageRef<'_>| {
-                if let Some(progress) = self.progress {
-                    progress.begin_page(post);
-                }
-                self.generate_page(*post, site)?;
-                if let Some(progress) = self.progress {
-                    progress.end_page(post);
-                }
-                Ok::<_, GeneratorError>(())
-            })?;
+        let pages = site
+            .all_pages()
+            .filter(|page| site.includes(page.source()))
+            .collect::<Vec<_>>();
+        let cancellation = site.index().cancellation();
+        let generated = AtomicUsize::new(0);
+        let generate = |post: &RenderedPageRef<'_>| {
+            // Skip the rest once the build is cancelled, which is handled
+            // below
+            if cancellation.is_cancelled() {
+                return Ok(());
+            }
+            if let Some(progress) = self.progress {
+                progress.begin_page(post);
+            }
+            self.generate_page(*post, site, &writer, accessibility.as_ref())?;
+            generated.fetch_add(1, Ordering::Relaxed);
+            if let Some(progress) = self.progress {
+                progress.end_page(post);
+            }
+            Ok::<_, GeneratorError>(())
+        };
+        if sequential {
+            pages.iter().try_for_each(generate)?;
+        } else {
+            pages.par_iter().try_for_each(generate)?;
+        }
+        if cancellation.is_cancelled() {
+            Err(self.cancelled(old.as_ref(), &generated, pages.len()))?;
+        }
+
+        // Copy the files from post bundles next to their posts
+        for page in site.all_pages().filter(|page| site.includes(page.source())) {
+            let source = page.source();
+            let Some(bundle_dir) = source.source_path().parent() else {
+                continue;
+            };
+            let bundle_dir = site.root_dir().join(bundle_dir);
+            for file in source.bundle_files() {
+                let Ok(relative) = file.strip_prefix(&bundle_dir) else {
+                    return Err(GeneratorError::ComputeRelativePath(file.clone()))?;
+                };
+                copy_file(
+                    &site.config().image_metadata,
+                    file,
+                    Path::new(&page.url()).join(relative),
+                    &writer,
+                )?;
+            }
+        }
 
         // Copy raw files (those that don't need processing or generation)
         for file in site.raw_files() {
-            debug!(
-                "copying from {}, root {}",
-                file.display(),
-                site.root_dir().display()
-            );
             let Some(relative_dest) = diff_paths(file, site.root_dir()) else {
                 return Err(GeneratorError::ComputeRelativePath(file.into()))?;
             };
-            let dest = self.options.destination.join(relative_dest);
+            if site
+                .filter()
+                .is_some_and(|filter| !filter.matches(&relative_dest))
+            {
+                continue;
+            }
+            if site.config().preserve_symlinks && file.is_symlink() {
+                let target = std::fs::read_link(file)
+                    .map_err(|e| GeneratorError::ReadLink(file.into(), e))?;
+                writer.symlink(&target, relative_dest);
+            } else {
+                copy_file(&site.config().image_metadata, file, relative_dest, &writer)?;
+            }
+        }
 
-            if let Some(parent) = dest.parent() {
-                fs::create_dir_all(parent)
-                    .map_err(|e| GeneratorError::CreateDestDir(parent.into(), e))?;
+        self.page_assets.copy(site.root_dir(), &writer);
+        self.bundles.copy(&writer);
+        self.thumbnails.write(site, &writer)?;
+        generate_posters(site, &writer)?;
+        generate_gallery_thumbnails(site, &writer)?;
+        generate_resized_images(site, &writer)?;
+
+        if cancellation.is_cancelled() {
+            Err(self.cancelled(old.as_ref(), &generated, pages.len()))?;
+        }
+
+        // Site-wide files depend on every page, so they are left alone in
+        // partial builds
+        if site.filter().is_none() {
+            self.generate_site_files(site, &writer)?;
+        }
+
+        writer.finish().map_err(GeneratorError::Write)?;
+
+        // The new output is complete, so finish the build even if it is
+        // cancelled from here on
+        if let Some(old) = old {
+            tokio::task::spawn_blocking(move || drop(old))
+                .await
+                .unwrap();
+        }
+
+        // Partial builds leave most of the output alone, so there's nothing
+        // to compare it with
+        if self.options.verify && site.filter().is_none() {
+            let problems = verify_output(site, &self.options.destination);
+            if !problems.is_empty() {
+                Err(GeneratorError::Verify(problems))?;
             }
+        }
 
-            fs::copy(file, &dest).map_err(|e| GeneratorError::Copy(file.into(), dest, e))?;
+        let mut summary = BuildSummary::measure(&self.options.destination, &site.config().budgets);
+        if let Some(accessibility) = accessibility {
+            summary.accessibility = accessibility.into_inner().unwrap();
+            sort_findings(&mut summary.accessibility);
+        }
+        summary.memory = memory.map(|memory| memory.report(sequential));
+        summary.nondeterministic = self.content_hashes.changed_outputs();
+        let security_expires = site.config().security_txt.as_ref().map(|s| s.expires);
+        if security_expires == Some(None) && !self.build.fixed {
+            summary.nondeterministic.push(
+                "security.txt expires a set time after the build; set `security_txt.expires` or \
+                 `SOURCE_DATE_EPOCH` to keep it the same"
+                    .to_string(),
+            );
+        }
+        // Only a full build sees every page that might link to a feed
+        if site.filter().is_none() {
+            summary.unreferenced_feeds = self.feeds.unreferenced();
         }
 
+        // The health page describes the rest of the output, so it has to come
+        // last
+        if site.config().health_page && site.filter().is_none() {
+            let writer = OutputWriter::new(&self.options.destination, self.options.fsync);
+            writer.write(
+                HEALTH_PATH,
+                health_page(site, &self.options.destination, &summary),
+            );
+            writer.finish().map_err(GeneratorError::Write)?;
+        }
+        Ok(summary)
+    }
+
+    /// Stops a cancelled build, putting back the old contents of the
+    /// destination directory if they were moved out of the way.
+    fn cancelled(
+        &self,
+        old: Option<&tempfile::TempDir>,
+        generated: &AtomicUsize,
+        total: usize,
+    ) -> GeneratorError {
+        let dest = &self.options.destination;
+        let restored = old.is_some_and(|old| {
+            debug!("restoring old destination directory: {}", dest.display());
+            fs::remove_dir_all(dest)
+                .and_then(|()| fs::rename(old.path().join("publish"), dest))
+                .inspect_err(|e| warn!("failed to restore old destination directory: {e}"))
+                .is_ok()
+        });
+        let outcome = if restored {
+            format!("{} was left as it was before the build", dest.display())
+        } else {
+            format!("{} may be incomplete", dest.display())
+        };
+        GeneratorError::Cancelled {
+            generated: generated.load(Ordering::Relaxed),
+            total,
+            outcome,
+        }
+    }
+
+    /// Generates the files that describe the site as a whole, such as feeds.
+    fn generate_site_files(
+        &self,
+        site: &RenderedSite<'_>,
+        writer: &OutputWriter,
+    ) -> Result<(), GeneratorError> {
         // Generate the atom feed
         //
         // FIXME: this is only relevant if we have posts. Maybe it should have an option to disable it
         // in the site config?
-        generate_atom(
-            site,
-            std::fs::File::create(self.options.destination.join("atom.xml"))
-                .map_err(|e| GeneratorError::CreateFile("atom.xml".into(), e))?,
-        )
-        .map_err(GeneratorError::AtomError)?;
+        let mut atom = vec![];
+        generate_atom(site, &self.short_urls, &self.content_hashes, &mut atom)
+            .map_err(GeneratorError::AtomError)?;
+        writer.write("atom.xml", atom);
+        for (path, feed) in generate_series_feeds(site, &self.short_urls, &self.content_hashes)
+            .map_err(GeneratorError::AtomError)?
+        {
+            writer.write(path, feed);
+        }
+        if site.config().feed.json {
+            let feed = generate_json_feed(site, &self.content_hashes);
+            writer.write(JSON_FEED_PATH, serde_json::to_vec_pretty(&feed).unwrap());
+        }
+        // Drafts shouldn't be recorded until they are published
+        if !self.options.unpublished {
+            self.content_hashes.save()?;
+        }
 
-        if let Some(cleanup) = cleanup {
-            cleanup.await.unwrap()
+        if site.config().calendar {
+            writer.write("calendar.ics", generate_calendar(site));
+        }
+
+        let highlight_scheme = site.config().color_scheme.or(site
+            .config()
+            .highlight_classes
+            .then_some(ColorScheme::Light));
+        if let Some(scheme) = highlight_scheme {
+            writer.write(
+                "highlight.css",
+                site.index().highlight_themes().stylesheet(scheme),
+            );
+        }
+
+        if let Some(favicon) = &site.config().favicon {
+            generate_favicons(&site.root_dir().join(favicon), writer)?;
+        }
+
+        if let Some(web_app) = &site.config().web_app {
+            writer.write("site.webmanifest", generate_manifest(site, web_app));
+            if web_app.service_worker {
+                writer.write(
+                    "sw.js",
+                    generate_service_worker(site, web_app, &self.options.destination),
+                );
+            }
+        }
+
+        for (name, contents) in deploy_files(&site.config().deploy) {
+            writer.write(name, contents);
+        }
+
+        if let Some(worker) = site.config().forms.as_ref().and_then(generate_worker) {
+            writer.write("_worker.js", worker);
+        }
+
+        // The URLs of pages that don't come from a source file
+        let mut generated = vec![];
+        if site.config().glossary.as_ref().is_some_and(|g| g.page) {
+            generated.extend(self.generate_glossary_page(site, writer)?);
+        }
+
+        for taxonomy in Taxonomy::ALL {
+            generated.extend(self.generate_taxonomy_pages(site, taxonomy, writer)?);
+        }
+
+        if site.config().sitemap {
+            if site.base_url().is_empty() {
+                warn!("`sitemap` needs `url` to be set, since sitemaps list full URLs");
+            } else {
+                let sitemap = generate_sitemap(site, &self.content_hashes, &generated)
+                    .map_err(GeneratorError::Sitemap)?;
+                writer.write("sitemap.xml", sitemap);
+            }
+        }
+
+        if let Some(humans) = &site.config().humans_txt {
+            writer.write("humans.txt", generate_humans(site, humans));
+        }
+
+        if let Some(robots) = &site.config().robots_txt {
+            writer.write("robots.txt", generate_robots(site, robots));
+        }
+
+        if let Some(security) = &site.config().security_txt {
+            writer.write(
+                ".well-known/security.txt",
+                generate_security(site, security, self.build.timestamp),
+            );
+        }
+
+        // Tell automation when the next rebuild is needed
+        if let Some((date, post)) = site
+            .index()
+            .scheduled_posts()
+            .filter_map(|post| Some((post.publish_date()?, post)))
+            .min_by_key(|(date, _)| *date)
+        {
+            let contents = json!({
+                "next_publish": date.to_rfc3339(),
+                "url": site.full_url(&post.url()),
+                "scheduled": site.index().scheduled_posts().count(),
+            });
+            writer.write("next-publish.json", contents.to_string());
+        }
+
+        self.short_urls.write(site, writer);
+
+        if site.config().on_this_day.json {
+            for (day, posts) in posts_by_day(site) {
+                writer.write(format!("on-this-day/{day}.json"), posts.to_string());
+            }
         }
 
         Ok(())
     }
 
+    /// Writes `glossary/index.html` using the theme's `glossary.html` template,
+    /// returning its URL if there is a template.
+    fn generate_glossary_page(
+        &self,
+        site: &RenderedSite<'_>,
+        writer: &OutputWriter,
+    ) -> Result<Option<String>, GeneratorError> {
+        if !self
+            .templates
+            .get_template_names()
+            .any(|t| t == "glossary.html")
+        {
+            warn!("the glossary page needs a `glossary.html` template in the theme");
+            return Ok(None);
+        }
+        let mut context = tera::Context::new();
+        let mut site_value = site.value();
+        site_value["sections"] = self.sections.value();
+        context.insert("site", &site_value);
+        context.insert(
+            "page",
+            &json!({
+                "title": "Glossary",
+                "url": "glossary/",
+                "feed_links": self.feeds.links(None),
+            }),
+        );
+        context.insert("theme", &theme_value(site));
+        context.insert("build", &self.build);
+        context.insert("print", &false);
+        let html = self
+            .templates
+            .render("glossary.html", &context)
+            .map_err(|e| GeneratorError::RenderTemplate(Box::new(e)))?;
+        self.feeds.record(Path::new("glossary/index.html"), &html);
+        writer.write("glossary/index.html", html);
+        Ok(Some("/glossary/".to_string()))
+    }
+
+    /// Writes a page listing the posts with each tag or category, if the theme
+    /// has a template for them, and returns their URLs.
+    fn generate_taxonomy_pages(
+        &self,
+        site: &RenderedSite<'_>,
+        taxonomy: Taxonomy,
+        writer: &OutputWriter,
+    ) -> Result<Vec<String>, GeneratorError> {
+        let template = taxonomy.template();
+        if !self.templates.get_template_names().any(|t| t == template) {
+            return Ok(vec![]);
+        }
+        let terms = taxonomy.posts(site);
+        taxonomy
+            .check_slugs(terms.keys().copied())
+            .map_err(GeneratorError::Taxonomy)?;
+        let mut site_value = site.value();
+        site_value["sections"] = self.sections.value();
+        let mut urls = vec![];
+        for (term, posts) in terms {
+            let path = taxonomy.path(term);
+            let mut context = tera::Context::new();
+            context.insert("site", &site_value);
+            context.insert(
+                "page",
+                &json!({
+                    "title": term,
+                    "url": format!("/{path}"),
+                    "taxonomy": taxonomy.name(),
+                    "posts": posts
+                        .into_iter()
+                        .map(|post| site.page_value(post))
+                        .collect::<Vec<_>>(),
+                    "feed_links": self.feeds.links(None),
+                }),
+            );
+            context.insert("theme", &theme_value(site));
+            context.insert("build", &self.build);
+            context.insert("print", &false);
+            let html = self
+                .templates
+                .render(template, &context)
+                .map_err(|e| GeneratorError::RenderTemplate(Box::new(e)))?;
+            let dest = Path::new(&path).join("index.html");
+            self.feeds.record(&dest, &html);
+            writer.write(dest, html);
+            urls.push(format!("/{path}"));
+        }
+        Ok(urls)
+    }
+
+    #[instrument(skip_all, fields(page = %page.source_path().display()))]
     fn generate_page(
         &self,
         page: RenderedPageRef<'_>,
         site: &RenderedSite<'_>,
+        writer: &OutputWriter,
+        accessibility: Option<&Mutex<Vec<Finding>>>,
     ) -> Result<(), GeneratorError> {
-        let dest = self.options.destination.join(page.url()).join("index.html");
+        let dest = Path::new(&page.url()).join("index.html");
 
         debug!("destination path: {}", dest.display());
 
+        let html = self.page_html(page, site)?;
+        if site.config().qr_codes && page.source().is_post() {
+            let url = self
+                .short_urls
+                .short_url(site, page)
+                .unwrap_or_else(|| site.full_url(&page.url()));
+            let svg = qr_code_svg(&url).map_err(|e| GeneratorError::QrCode(url, e))?;
+            writer.write(Path::new(&page.url()).join(QR_CODE_FILE), svg);
+        }
+        if site.config().microformats.enabled {
+            for (root, property) in missing_properties(&html) {
+                warn!(
+                    "`{}` uses `{root}` but has no `{property}`",
+                    page.source_path().display()
+                );
+            }
+        }
+        if let Some(accessibility) = accessibility {
+            let findings = check_page(&html)
+                .into_iter()
+                .map(|(priority, message)| Finding {
+                    priority,
+                    page: page.source_path().into(),
+                    message,
+                });
+            accessibility.lock().unwrap().extend(findings);
+        }
+        if page.source().is_post() {
+            self.content_hashes.record_output(page, &html);
+        }
+        self.feeds.record(&dest, &html);
+        writer.write(dest, html);
+
+        Ok(())
+    }
+
+    /// Produces exactly the HTML that a build writes for `page`.
+    ///
+    /// Unlike [`GeneratorContext::render_page`], this includes processing
+    /// that happens after the template is rendered, such as inlining critical
+    /// CSS.
+    pub fn page_html(
+        &self,
+        page: RenderedPageRef<'_>,
+        site: &RenderedSite<'_>,
+    ) -> Result<String, GeneratorError> {
+        let mut content = self.render_page(page, site)?;
+        if site.config().jsonld && page.source().is_post() {
+            if let Some(head_end) = content.find("</head>") {
+                let markup = jsonld_markup(&post_jsonld(site, page));
+                content.insert_str(head_end, &markup);
+            }
+        }
+        if content.contains(HIDDEN_HREF) {
+            if let Some(body_end) = content.rfind("</body>") {
+                content.insert_str(body_end, DECODE_SCRIPT);
+            }
+        }
+        match &self.critical_css {
+            Some(critical_css) => critical_css.inline(&content, site.root_dir(), &page.url()),
+            None => Ok(content),
+        }
+    }
+
+    /// Builds the variables that are available to templates while rendering
+    /// `page`.
+    pub fn template_context(
+        &self,
+        page: RenderedPageRef<'_>,
+        site: &RenderedSite<'_>,
+    ) -> tera::Context {
+        let mut context = tera::Context::new();
+        let mut site_value = site.value();
+        site_value["sections"] = self.sections.value();
+        context.insert("site", &site_value);
+        let mut page_value = site.page_value(page);
+        page_value["extra_css"] = json!(self
+            .page_assets
+            .urls(page.source(), page.source().extra_css()));
+        page_value["extra_js"] = json!(self
+            .page_assets
+            .urls(page.source(), page.source().extra_js()));
+        page_value["comments"] = site
+            .index()
+            .comments(page.source())
+            .iter()
+            .map(|comment| {
+                json!({
+                    "author": comment.author,
+                    "url": comment.url.as_deref().filter(|url| is_safe_url(url)),
+                    "date": comment.date.to_string(),
+                    "body": comment.body_html(),
+                })
+            })
+            .collect();
+        page_value["canonical_url"] = json!(canonical_url(site, page));
+        let series = page.source().series().filter(|_| page.source().is_post());
+        if let Some(series) = series {
+            page_value["series_feed"] = json!(format!("/{}", series_feed_path(series)));
+        }
+        page_value["feed_links"] = json!(self.feeds.links(series));
+        page_value["breadcrumbs"] = breadcrumbs_value(site, page);
+        if let Some(section) = self.sections.page_value(page) {
+            page_value["section"] = section;
+        }
+        if let Some(short_url) = self.short_urls.short_url(site, page) {
+            page_value["short_url"] = json!(short_url);
+        }
+        if let Some(thumbnail) = self.thumbnails.url(page) {
+            page_value["thumbnail"] = json!(thumbnail);
+        }
+        if site.config().revisions.is_some() {
+            let revisions = self.revisions.of(page.source_path());
+            page_value["revisions"] = json!(revisions);
+            if page.source().has_changelog() && !revisions.is_empty() {
+                page_value["changelog"] = json!(changelog_markup(revisions));
+            }
+        }
+        if site.config().qr_codes && page.source().is_post() {
+            page_value["qr_code"] = json!(format!("/{}{QR_CODE_FILE}", page.url()));
+        }
+        if site.config().microformats.enabled {
+            page_value["mf2"] = mf2_value(site, page);
+        }
+        if page.source().is_post() {
+            page_value["jsonld"] = json!(jsonld_markup(&post_jsonld(site, page)));
+        }
+        context.insert("page", &page_value);
+        context.insert("theme", &theme_value(site));
+        context.insert("build", &self.build);
+        context.insert("print", &false);
+        context
+    }
+
+    /// Renders a page to the final HTML that would be written to the
+    /// destination directory.
+    pub fn render_page(
+        &self,
+        page: RenderedPageRef<'_>,
+        site: &RenderedSite<'_>,
+    ) -> Result<String, GeneratorError> {
+        self.render_page_with(
+            page,
+            site,
+            page.template(),
+            self.template_context(page, site),
+        )
+    }
+
+    /// Renders a page for printing.
+    ///
+    /// This uses the theme's `print.html` template if there is one and
+    /// otherwise falls back on the page's usual layout. Either way, templates
+    /// can check the `print` variable to adjust their output.
+    pub fn render_page_for_print(
+        &self,
+        page: RenderedPageRef<'_>,
+        site: &RenderedSite<'_>,
+    ) -> Result<String, GeneratorError> {
+        let mut context = self.template_context(page, site);
+        context.insert("print", &true);
+        let template = if self
+            .templates
+            .get_template_names()
+            .any(|t| t == "print.html")
+        {
+            Some("print")
+        } else {
+            page.template()
+        };
+        self.render_page_with(page, site, template, context)
+    }
+
+    #[instrument(
+        name = "render_template",
+        skip_all,
+        fields(page = %page.source_path().display(), template = ?template)
+    )]
+    fn render_page_with(
+        &self,
+        page: RenderedPageRef<'_>,
+        site: &RenderedSite<'_>,
+        template: Option<&str>,
+        mut context: tera::Context,
+    ) -> Result<String, GeneratorError> {
         let content = page.rendered_contents();
 
-        debug!("post template: {:?}", page.template());
-        let content = match page.template() {
+        debug!("post template: {:?}", template);
+        Ok(match template {
             Some(template) => {
-                let mut context = tera::Context::new();
-                context.insert("site", &site.value());
-                context.insert("page", &page.value());
-                context.insert("theme", &site.config().theme_opts);
-
                 let content_template = site
                     .config()
                     .macros
@@ -213,15 +982,32 @@ impl<'a> GeneratorContext<'a> {
                     .map_err(|e| GeneratorError::RenderTemplate(Box::new(e)))?
             }
             None => content.to_string(),
-        };
-
-        std::fs::create_dir_all(dest.parent().unwrap())
-            .map_err(|e| GeneratorError::CreateDestDir(dest.parent().unwrap().to_path_buf(), e))?;
+        })
+    }
+}
 
-        std::fs::write(&dest, content).map_err(|e| GeneratorError::WriteFile(dest, e))?;
+/// The URL search engines should treat as the page's original, which is the
+/// page's own URL unless it was republished from elsewhere
+pub fn canonical_url(site: &RenderedSite<'_>, page: RenderedPageRef<'_>) -> String {
+    match page.source().canonical_url() {
+        Some(url) => url.to_string(),
+        None => site.full_url(&page.url()),
+    }
+}
 
-        Ok(())
+/// Builds the `theme` template variable from `theme_opts`, adding the site's
+/// color scheme unless the theme options already set one.
+fn theme_value(site: &RenderedSite<'_>) -> Value {
+    let mut theme = site.config().theme_opts.clone();
+    if let Some(scheme) = site.config().color_scheme {
+        if theme.is_null() {
+            theme = json!({});
+        }
+        if let Some(theme) = theme.as_object_mut() {
+            theme.entry("color_scheme").or_insert_with(|| json!(scheme));
+        }
     }
+    theme
 }
 
 /// Converts an object into a format that can be passed to a Tera template
@@ -233,19 +1019,56 @@ impl ToValue for RenderedPageRef<'_> {
     fn value(&self) -> Value {
         let mut page = Map::new();
         page.insert("title".to_string(), json!(self.title()));
+        if let Some(description) = self.description() {
+            page.insert("description".to_string(), json!(description));
+        }
+        if let Some(image) = self.source().image() {
+            page.insert("image".to_string(), json!(image));
+        }
         page.insert("url".to_string(), json!(Path::new("/").join(self.url())));
         if let Some(date) = self.publish_date() {
             page.insert("date".to_string(), json!(date));
         }
+        page.insert("tags".to_string(), json!(self.source().tags()));
+        page.insert("categories".to_string(), json!(self.source().categories()));
+        page.insert(
+            "comments_enabled".to_string(),
+            json!(self.source().comments_enabled()),
+        );
         page.insert(
             "excerpt".to_string(),
             json!(self.rendered_excerpt().unwrap_or(self.rendered_contents())),
         );
         page.insert("content".to_string(), json!(self.rendered_contents()));
+        page.insert("toc".to_string(), json!(self.toc()));
         page.into()
     }
 }
 
+impl RenderedSite<'_> {
+    /// Converts a page into a template value, including fields that depend on
+    /// information kept elsewhere in the site.
+    fn page_value(&self, page: RenderedPageRef<'_>) -> Value {
+        let mut value = page.value();
+        let syndication = self.index().syndication().copies(page.source_path());
+        value["syndication"] = json!(syndication);
+        value["external_domains"] = json!(self.external_domains(page.source()));
+        // Just enough to link to each one, since every page has a few
+        value["related_posts"] = self
+            .related_posts(page.source())
+            .map(|post| {
+                json!({
+                    "title": post.title(),
+                    "url": Path::new("/").join(post.url()),
+                    "date": post.publish_date(),
+                    "description": post.description(),
+                })
+            })
+            .collect();
+        value
+    }
+}
+
 impl ToValue for RenderedSite<'_> {
     fn value(&self) -> Value {
         // Add metadata from Site.toml
@@ -265,9 +1088,63 @@ impl ToValue for RenderedSite<'_> {
             "posts".to_string(),
             json!(posts
                 .into_iter()
-                .map(|post| post.value())
+                .map(|post| self.page_value(post))
                 .collect::<Vec<_>>()),
         );
+
+        for taxonomy in Taxonomy::ALL {
+            site.insert(taxonomy.name().to_string(), taxonomy.value(self));
+        }
+
+        site.insert(
+            "external_domains".to_string(),
+            json!(self
+                .external_domain_counts()
+                .iter()
+                .map(|(domain, count)| json!({ "domain": domain, "count": count }))
+                .collect::<Vec<_>>()),
+        );
+
+        site.insert("feeds".to_string(), feeds_value(self));
+
+        if self.config().favicon.is_some() {
+            site.insert("favicons".to_string(), json!(favicon_markup()));
+        }
+
+        if let Some(web_app) = &self.config().web_app {
+            site.insert("web_app".to_string(), json!(web_app_markup(web_app)));
+        }
+
+        if self.config().glossary.is_some() {
+            site.insert(
+                "glossary".to_string(),
+                json!(self
+                    .index()
+                    .glossary()
+                    .iter()
+                    .map(|(term, definition)| json!({ "term": term, "definition": definition }))
+                    .collect::<Vec<_>>()),
+            );
+        }
+
+        if let Some(forms) = &self.config().forms {
+            site.insert("forms".to_string(), forms_value(forms));
+        }
+
+        if self.config().on_this_day.enabled {
+            let today = chrono::Local::now().date_naive();
+            site.insert(
+                "on_this_day".to_string(),
+                json!(on_this_day(self, today)
+                    .into_iter()
+                    .map(|(post, years_ago)| {
+                        let mut value = self.page_value(post);
+                        value["years_ago"] = json!(years_ago);
+                        value
+                    })
+                    .collect::<Vec<_>>()),
+            );
+        }
         site.into()
     }
 }
@@ -0,0 +1,117 @@
+//! Finding posts that were published on the same day in earlier years
+//!
+//! This makes the output depend on the day the site is built, so it is only
+//! enabled when requested in `Site.toml`.
+
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, Local, NaiveDate};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{
+    index::{PageMetadata, SiteMetadata},
+    renderer::{RenderedPageRef, RenderedSite},
+};
+
+/// The `[on_this_day]` section of `Site.toml`
+#[derive(Deserialize, Debug, Default)]
+pub struct OnThisDayConfig {
+    /// Adds `site.on_this_day` to the template context
+    #[serde(default)]
+    pub enabled: bool,
+    /// Writes `on-this-day/MM-DD.json` for each day of the year that has posts
+    #[serde(default)]
+    pub json: bool,
+}
+
+/// Returns the posts published on the month and day of `today` in earlier
+/// years, newest first.
+pub(crate) fn on_this_day<'a>(
+    site: &'a RenderedSite<'_>,
+    today: NaiveDate,
+) -> Vec<(RenderedPageRef<'a>, i32)> {
+    let mut posts: Vec<_> = site
+        .posts()
+        .filter_map(|post| {
+            let date = post.publish_date()?.with_timezone(&Local).date_naive();
+            let same_day = (date.month(), date.day()) == (today.month(), today.day());
+            (same_day && date.year() < today.year()).then(|| (post, today.year() - date.year()))
+        })
+        .collect();
+    posts.sort_by_key(|(_, years_ago)| *years_ago);
+    posts
+}
+
+/// Groups all posts by the month and day they were published on, as
+/// `MM-DD` keys.
+pub(crate) fn posts_by_day(site: &RenderedSite<'_>) -> BTreeMap<String, Value> {
+    let mut days = BTreeMap::<_, Vec<_>>::new();
+    for post in site.posts() {
+        let Some(date) = post.publish_date() else {
+            continue;
+        };
+        let date = date.with_timezone(&Local);
+        days.entry(date.format("%m-%d").to_string())
+            .or_default()
+            .push((
+                date,
+                json!({
+                    "title": post.title(),
+                    "url": site.full_url(&post.url()),
+                    "date": date.to_rfc3339(),
+                    "year": date.year(),
+                }),
+            ));
+    }
+    days.into_iter()
+        .map(|(day, mut posts)| {
+            posts.sort_by_key(|(date, _)| std::cmp::Reverse(*date));
+            (
+                day,
+                json!(posts.into_iter().map(|(_, post)| post).collect::<Vec<_>>()),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::NaiveDate;
+
+    use crate::index::{PageMetadata, PageSource, SiteIndex, SourceFormat};
+
+    use super::{on_this_day, posts_by_day};
+
+    #[test]
+    fn find_posts_from_earlier_years() -> miette::Result<()> {
+        let mut site = SiteIndex::default();
+        for path in [
+            "_posts/2012-10-14-first.md",
+            "_posts/2013-10-14-second.md",
+            "_posts/2013-10-15-third.md",
+            "_posts/2014-10-14-today.md",
+        ] {
+            site.add_page(PageSource::from_string(path, SourceFormat::Markdown, ""));
+        }
+        let site = site.render()?;
+
+        let today = NaiveDate::from_ymd_opt(2014, 10, 14).unwrap();
+        let posts = on_this_day(&site, today)
+            .into_iter()
+            .map(|(post, years_ago)| (post.url(), years_ago))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            posts,
+            [
+                ("blog/2013/10/14/second/".to_string(), 1),
+                ("blog/2012/10/14/first/".to_string(), 2)
+            ]
+        );
+
+        let days = posts_by_day(&site);
+        assert_eq!(days.keys().collect::<Vec<_>>(), ["10-14", "10-15"]);
+        assert_eq!(days["10-14"].as_array().unwrap().len(), 3);
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+//! Stylesheets and scripts that individual pages ask for in their frontmatter
+//!
+//! These are copied into `assets/` with a hash of their contents in the file
+//! name, so they can be cached indefinitely.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    index::{PageSource, SiteMetadata},
+    renderer::RenderedSite,
+};
+
+use super::{writer::OutputWriter, GeneratorError};
+
+#[derive(Default)]
+pub(crate) struct PageAssets {
+    /// Maps the source path of each asset, relative to the site root, to the
+    /// URL of its fingerprinted copy
+    urls: HashMap<PathBuf, String>,
+}
+
+impl PageAssets {
+    /// Finds and fingerprints the assets used by every page on the site.
+    pub fn collect(site: &RenderedSite<'_>) -> Result<Self, GeneratorError> {
+        let mut urls = HashMap::new();
+        for page in site.all_pages() {
+            let page = page.source();
+            for link in page.extra_css().iter().chain(page.extra_js()) {
+                let path = resolve(page, link);
+                if urls.contains_key(&path) {
+                    continue;
+                }
+                let contents = std::fs::read(site.root_dir().join(&path)).map_err(|e| {
+                    GeneratorError::PageAsset(page.source_path().into(), link.clone(), e)
+                })?;
+                urls.insert(path.clone(), fingerprinted_url(&path, &contents));
+            }
+        }
+        Ok(Self { urls })
+    }
+
+    /// Returns the URLs for the assets listed in `links`, which came from the
+    /// frontmatter of `page`.
+    pub fn urls(&self, page: &PageSource, links: &[String]) -> Vec<String> {
+        links
+            .iter()
+            .filter_map(|link| self.urls.get(&resolve(page, link)).cloned())
+            .collect()
+    }
+
+    /// Copies every asset to its fingerprinted location.
+    pub fn copy(&self, root_dir: &Path, writer: &OutputWriter) {
+        for (source, url) in &self.urls {
+            writer.copy(&root_dir.join(source), url.trim_start_matches('/'));
+        }
+    }
+}
+
+/// Resolves an asset link from a page's frontmatter to a path relative to the
+/// site root.
+fn resolve(page: &PageSource, link: &str) -> PathBuf {
+    match link.strip_prefix('/') {
+        Some(link) => PathBuf::from(link),
+        None => page
+            .source_path()
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(link),
+    }
+}
+
+/// Chooses the URL of a copy of `path` in `/assets/` with a hash of its
+/// contents in the name.
+pub(super) fn fingerprinted_url(path: &Path, contents: &[u8]) -> String {
+    let hash = format!("{:x}", Sha256::digest(contents));
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    match path.extension() {
+        Some(ext) => format!("/assets/{stem}.{}.{}", &hash[..10], ext.to_string_lossy()),
+        None => format!("/assets/{stem}.{}", &hash[..10]),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use crate::index::{PageSource, SourceFormat};
+
+    use super::{fingerprinted_url, resolve};
+
+    #[test]
+    fn resolve_asset_links() {
+        let page = PageSource::from_string(
+            "_posts/2024-01-01-demo.md",
+            SourceFormat::Markdown,
+            "---\nlayout: post\njs: [demo.js, /js/shared.js]\n---\n",
+        );
+        assert_eq!(page.extra_js(), ["demo.js", "/js/shared.js"]);
+        assert_eq!(resolve(&page, "demo.js"), Path::new("_posts/demo.js"));
+        assert_eq!(resolve(&page, "/js/shared.js"), Path::new("js/shared.js"));
+    }
+
+    #[test]
+    fn fingerprint() {
+        assert_eq!(
+            fingerprinted_url(Path::new("js/demo.js"), b"hello"),
+            "/assets/demo.2cf24dba5f.js"
+        );
+    }
+}
@@ -0,0 +1,26 @@
+//! QR codes that link to posts, for slides and printed handouts
+
+use qrcode::{render::svg, types::QrError, QrCode};
+
+/// The name of the QR code image written next to each post
+pub(crate) const QR_CODE_FILE: &str = "qr.svg";
+
+/// Draws a QR code for `url` as an SVG image.
+pub(crate) fn qr_code_svg(url: &str) -> Result<String, QrError> {
+    Ok(QrCode::new(url)?
+        .render::<svg::Color>()
+        .min_dimensions(200, 200)
+        .build())
+}
+
+#[cfg(test)]
+mod test {
+    use super::qr_code_svg;
+
+    #[test]
+    fn draw_svg() {
+        let svg = qr_code_svg("https://example.com/blog/2024/01/01/hello/").unwrap();
+        assert!(svg.contains("<svg"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+}
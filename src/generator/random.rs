@@ -0,0 +1,112 @@
+//! Template filters that pick content at random, such as a "random older post"
+//! widget
+//!
+//! The randomness is seeded once per build from `random_seed` or
+//! `SOURCE_DATE_EPOCH`, never the clock, so every page in a build agrees on the
+//! choices and rebuilding the same sources gives the same output.
+
+use std::collections::HashMap;
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tera::{Filter, Tera};
+
+/// Adds the `shuffle` and `sample` filters to `tera`.
+pub(crate) fn register_random_filters(tera: &mut Tera, seed: u64) {
+    tera.register_filter(
+        "shuffle",
+        RandomFilter {
+            seed,
+            sample: false,
+        },
+    );
+    tera.register_filter("sample", RandomFilter { seed, sample: true });
+}
+
+struct RandomFilter {
+    seed: u64,
+    /// Whether to return only the first `n` items
+    sample: bool,
+}
+
+impl Filter for RandomFilter {
+    fn filter(&self, value: &Value, args: &HashMap<String, Value>) -> tera::Result<Value> {
+        let name = if self.sample { "sample" } else { "shuffle" };
+        let Value::Array(items) = value else {
+            return Err(format!("`{name}` can only be used on arrays").into());
+        };
+        let mut rng = SplitMix64(self.seed ^ seed_from(args.get("seed")));
+        let mut items = items.clone();
+        shuffle(&mut items, &mut rng);
+        if self.sample {
+            let n = match args.get("n") {
+                Some(n) => n
+                    .as_u64()
+                    .ok_or("`sample` expects `n` to be a non-negative integer")?,
+                None => 1,
+            };
+            items.truncate(n as usize);
+        }
+        Ok(Value::Array(items))
+    }
+}
+
+/// Turns the optional `seed` argument into a number, so that different uses
+/// of the filters on the same page can pick different items.
+fn seed_from(seed: Option<&Value>) -> u64 {
+    let Some(seed) = seed else {
+        return 0;
+    };
+    let digest = Sha256::digest(seed.to_string().as_bytes());
+    u64::from_le_bytes(digest[..8].try_into().unwrap())
+}
+
+/// A small, fast generator that is plenty for choosing posts to show
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+}
+
+/// Shuffles `items` in place with a Fisher-Yates shuffle.
+fn shuffle<T>(items: &mut [T], rng: &mut SplitMix64) {
+    for i in (1..items.len()).rev() {
+        let j = (rng.next() % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+    use tera::{Context, Tera};
+
+    use super::register_random_filters;
+
+    fn render(seed: u64, template: &str) -> String {
+        let mut tera = Tera::default();
+        register_random_filters(&mut tera, seed);
+        let mut context = Context::new();
+        context.insert("items", &json!([1, 2, 3, 4, 5, 6, 7, 8]));
+        tera.render_str(template, &context).unwrap()
+    }
+
+    #[test]
+    fn stable_within_a_build() {
+        let shuffled = render(1, "{{ items | shuffle | join(sep=',') }}");
+        assert_eq!(shuffled, render(1, "{{ items | shuffle | join(sep=',') }}"));
+        assert_ne!(shuffled, "1,2,3,4,5,6,7,8");
+        let mut sorted: Vec<_> = shuffled.split(',').collect();
+        sorted.sort();
+        assert_eq!(sorted.join(","), "1,2,3,4,5,6,7,8");
+
+        let sample = render(1, "{{ items | sample(n=3, seed='sidebar') | length }}");
+        assert_eq!(sample, "3");
+    }
+}
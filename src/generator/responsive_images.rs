@@ -0,0 +1,101 @@
+//! Generating the smaller copies of images listed in their `srcset`
+//!
+//! Each copy is written next to the original image as `<name>.<width>w.<ext>`.
+//! Copies are kept in the image cache so unchanged images aren't resized on
+//! every build.
+
+use image::imageops::FilterType;
+use rayon::prelude::*;
+
+use crate::{
+    index::SiteMetadata,
+    renderer::{resized_image_url, RenderedSite},
+};
+
+use super::{
+    image_cache::ImageCache,
+    thumbnail::{local_dest, resolve_local},
+    writer::OutputWriter,
+    GeneratorError,
+};
+
+/// Resizes every local image with a `srcset` on the pages the build includes.
+pub(crate) fn generate_resized_images(
+    site: &RenderedSite<'_>,
+    writer: &OutputWriter,
+) -> Result<(), GeneratorError> {
+    if site.config().images.is_none() {
+        return Ok(());
+    }
+    let images: Vec<_> = site
+        .all_pages()
+        .filter(|page| site.includes(page.source()))
+        .flat_map(|page| {
+            responsive_images(page.rendered_contents())
+                .into_iter()
+                .map(move |(src, widths)| (page, src, widths))
+        })
+        .collect();
+    let cache = ImageCache::new(site.root_dir());
+    images.par_iter().try_for_each(|(page, src, widths)| {
+        let Some(source) = resolve_local(site, *page, src) else {
+            return Ok(());
+        };
+        let variants: Vec<_> = widths
+            .iter()
+            .map(|&width| (format!("{width}w"), width))
+            .collect();
+        let copies = cache
+            .resize(&source, &variants, |image, &width| {
+                image.resize(width, u32::MAX, FilterType::Lanczos3)
+            })
+            .map_err(|e| GeneratorError::ResizeImage(source.clone(), e))?;
+        for (&width, copy) in widths.iter().zip(copies) {
+            writer.write(local_dest(*page, &resized_image_url(src, width)), copy);
+        }
+        Ok(())
+    })
+}
+
+/// Returns the source of each image in `html` that has a `srcset`, along with
+/// the widths of the copies it lists.
+fn responsive_images(html: &str) -> Vec<(String, Vec<u32>)> {
+    const IMG: &str = r#"<img src=""#;
+    html.match_indices(IMG)
+        .filter_map(|(start, _)| {
+            let rest = &html[start + IMG.len()..];
+            let src = unescape(&rest[..rest.find('"')?]);
+            let rest = rest[rest.find('"')?..].strip_prefix(r#"" srcset=""#)?;
+            let srcset = unescape(&rest[..rest.find('"')?]);
+            let widths = srcset
+                .split(", ")
+                .filter_map(|candidate| {
+                    let (url, width) = candidate.rsplit_once(' ')?;
+                    let width = width.strip_suffix('w')?.parse().ok()?;
+                    (url == resized_image_url(&src, width)).then_some(width)
+                })
+                .collect();
+            Some((src, widths))
+        })
+        .collect()
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&quot;", "\"")
+        .replace("&lt;", "<")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod test {
+    use super::responsive_images;
+
+    #[test]
+    fn find_responsive_images() {
+        let html = r#"<p><img src="a&amp;b.jpg" srcset="a&amp;b.480w.jpg 480w, a&amp;b.jpg 1200w" sizes="100vw" alt="" /><img src="c.png" alt="" /></p>"#;
+        assert_eq!(
+            responsive_images(html),
+            [("a&b.jpg".to_string(), vec![480])]
+        );
+    }
+}
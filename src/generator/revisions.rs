@@ -0,0 +1,175 @@
+//! The revision history of each page, taken from git
+//!
+//! Templates get `page.revisions`, which lists the commits that changed the
+//! page's source file, newest first. Pages with `changelog: true` in their
+//! frontmatter also get `page.changelog`, a ready-made list of those revisions.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+/// The `[revisions]` section of `Site.toml`
+#[derive(Deserialize, Debug)]
+pub struct RevisionsConfig {
+    /// A link to each commit, where `{rev}` is replaced by the commit hash,
+    /// such as `https://github.com/me/site/commit/{rev}`
+    pub commit_url: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub(crate) struct Revision {
+    pub rev: String,
+    pub date: DateTime<Utc>,
+    /// The first line of the commit message
+    pub summary: String,
+    pub url: Option<String>,
+}
+
+#[derive(Default)]
+pub(crate) struct Revisions {
+    /// The revisions of each file, keyed by the path relative to the site root
+    by_path: HashMap<PathBuf, Vec<Revision>>,
+}
+
+impl Revisions {
+    /// Reads the history of every file under `root_dir` using `git log`.
+    ///
+    /// If git is not installed or the site is not in a repository, no pages
+    /// have revisions.
+    pub fn load(root_dir: &Path, config: Option<&RevisionsConfig>) -> Self {
+        let Some(config) = config else {
+            return Self::default();
+        };
+        debug!("reading revision history");
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(root_dir)
+            .args([
+                "log",
+                "--no-renames",
+                "--name-only",
+                "--relative",
+                "--format=%x1e%H%x1f%aI%x1f%s",
+            ])
+            .output();
+        match output {
+            Ok(output) if output.status.success() => Self::parse(
+                &String::from_utf8_lossy(&output.stdout),
+                config.commit_url.as_deref(),
+            ),
+            Ok(output) => {
+                warn!(
+                    "could not read revision history: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+                Self::default()
+            }
+            Err(e) => {
+                warn!("could not run git to read revision history: {e}");
+                Self::default()
+            }
+        }
+    }
+
+    fn parse(log: &str, commit_url: Option<&str>) -> Self {
+        let mut by_path: HashMap<PathBuf, Vec<Revision>> = HashMap::new();
+        for commit in log.split('\x1e').filter(|commit| !commit.trim().is_empty()) {
+            let (header, files) = commit.split_once('\n').unwrap_or((commit, ""));
+            let mut fields = header.splitn(3, '\x1f');
+            let (Some(rev), Some(date), Some(summary)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            let Ok(date) = DateTime::parse_from_rfc3339(date) else {
+                continue;
+            };
+            let revision = Revision {
+                rev: rev.to_string(),
+                date: date.with_timezone(&Utc),
+                summary: summary.to_string(),
+                url: commit_url.map(|url| url.replace("{rev}", rev)),
+            };
+            for file in files.lines().filter(|file| !file.is_empty()) {
+                by_path
+                    .entry(PathBuf::from(file))
+                    .or_default()
+                    .push(revision.clone());
+            }
+        }
+        Self { by_path }
+    }
+
+    /// Returns the revisions of the file at `source_path`, newest first.
+    pub fn of(&self, source_path: &Path) -> &[Revision] {
+        self.by_path.get(source_path).map_or(&[], Vec::as_slice)
+    }
+}
+
+/// Renders a list of revisions for pages that ask for a changelog.
+pub(crate) fn changelog_markup(revisions: &[Revision]) -> String {
+    let items: String = revisions
+        .iter()
+        .map(|revision| {
+            let date = revision.date.format("%Y-%m-%d");
+            let summary = escape(&revision.summary);
+            match &revision.url {
+                Some(url) => format!(
+                    r#"<li><time datetime="{}">{date}</time> <a href="{}">{summary}</a></li>"#,
+                    revision.date.to_rfc3339(),
+                    escape(url)
+                ),
+                None => format!(
+                    r#"<li><time datetime="{}">{date}</time> {summary}</li>"#,
+                    revision.date.to_rfc3339()
+                ),
+            }
+        })
+        .collect();
+    format!(r#"<section class="changelog"><h2>Changelog</h2><ul>{items}</ul></section>"#)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::{changelog_markup, Revisions};
+
+    #[test]
+    fn parse_log() {
+        let log = "\x1eaaaa\x1f2024-03-01T10:00:00+01:00\x1fFix typo in <uses>\n\nuses.md\n_posts/2024-01-01-hello.md\n\x1ebbbb\x1f2024-01-01T00:00:00Z\x1fAdd uses page\n\nuses.md\n";
+        let revisions = Revisions::parse(log, Some("https://example.com/commit/{rev}"));
+
+        let uses = revisions.of(Path::new("uses.md"));
+        assert_eq!(uses.len(), 2);
+        assert_eq!(uses[0].rev, "aaaa");
+        assert_eq!(uses[0].date.to_rfc3339(), "2024-03-01T09:00:00+00:00");
+        assert_eq!(
+            uses[0].url.as_deref(),
+            Some("https://example.com/commit/aaaa")
+        );
+        assert_eq!(uses[1].summary, "Add uses page");
+        assert_eq!(
+            revisions.of(Path::new("_posts/2024-01-01-hello.md")).len(),
+            1
+        );
+        assert!(revisions.of(Path::new("missing.md")).is_empty());
+
+        assert_eq!(
+            changelog_markup(&uses[..1]),
+            r#"<section class="changelog"><h2>Changelog</h2><ul><li><time datetime="2024-03-01T09:00:00+00:00">2024-03-01</time> <a href="https://example.com/commit/aaaa">Fix typo in &lt;uses></a></li></ul></section>"#
+        );
+    }
+}
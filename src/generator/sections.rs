@@ -0,0 +1,209 @@
+//! Navigation for sections of the site outside the blog, such as `docs/`
+//!
+//! Each top-level directory of pages is a section. Its pages and
+//! subdirectories form a tree, ordered by the `weight` in each page's
+//! frontmatter and then by title. A directory's `index` page gives the
+//! directory its title, URL, and weight.
+
+use std::{
+    collections::BTreeMap,
+    path::{Component, Path, PathBuf},
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    index::PageMetadata,
+    renderer::{RenderedPageRef, RenderedSite},
+};
+
+#[derive(Default)]
+pub(crate) struct Sections {
+    sections: BTreeMap<String, Node>,
+}
+
+#[derive(Default)]
+struct Node {
+    /// The page itself, or for a directory, its `index` page
+    page: Option<Entry>,
+    /// The pages and directories inside a directory, by name
+    children: BTreeMap<String, Node>,
+}
+
+struct Entry {
+    source: PathBuf,
+    title: String,
+    url: String,
+    weight: Option<i64>,
+}
+
+impl Sections {
+    pub fn build(site: &RenderedSite<'_>) -> Self {
+        let mut sections = BTreeMap::new();
+        for page in site.all_pages().filter(|page| !page.source().is_post()) {
+            sections_add(&mut sections, page);
+        }
+        Self { sections }
+    }
+
+    /// Builds `site.sections`, which has the title, URL, and navigation tree
+    /// of each section.
+    pub fn value(&self) -> Value {
+        self.sections
+            .iter()
+            .map(|(name, node)| {
+                let mut value = node.value(name);
+                value["tree"] = value["children"].take();
+                (name.clone(), value)
+            })
+            .collect::<serde_json::Map<_, _>>()
+            .into()
+    }
+
+    /// Builds `page.section`, which names the page's section and links to the
+    /// pages before and after it.
+    pub fn page_value(&self, page: RenderedPageRef<'_>) -> Option<Value> {
+        let name = section_name(page.source_path())?;
+        let mut order = vec![];
+        self.sections.get(name)?.flatten(&mut order);
+        let index = order
+            .iter()
+            .position(|entry| entry.source == page.source_path())?;
+        let link = |entry: Option<&&Entry>| {
+            entry.map(|entry| json!({ "title": entry.title, "url": format!("/{}", entry.url) }))
+        };
+        Some(json!({
+            "name": name,
+            "prev": link(index.checked_sub(1).and_then(|i| order.get(i))),
+            "next": link(order.get(index + 1)),
+        }))
+    }
+}
+
+fn sections_add(sections: &mut BTreeMap<String, Node>, page: RenderedPageRef<'_>) {
+    let path = page.source_path();
+    let Some(name) = section_name(path) else {
+        return;
+    };
+    let Some(stem) = path.file_stem().and_then(|stem| stem.to_str()) else {
+        return;
+    };
+    let mut node = sections.entry(name.to_string()).or_default();
+    for dir in path.parent().into_iter().flat_map(Path::components).skip(1) {
+        node = node
+            .children
+            .entry(dir.as_os_str().to_string_lossy().into_owned())
+            .or_default();
+    }
+    if stem != "index" {
+        node = node.children.entry(stem.to_string()).or_default();
+    }
+    node.page = Some(Entry {
+        source: path.to_path_buf(),
+        title: page.title().to_string(),
+        url: page.url(),
+        weight: page.source().weight(),
+    });
+}
+
+/// Returns the section a page is in, which is the first directory in its
+/// path. Pages at the top level of the site are not in a section.
+fn section_name(path: &Path) -> Option<&str> {
+    let mut components = path.components();
+    let first = components.next()?;
+    components.next()?;
+    match first {
+        Component::Normal(name) => name.to_str(),
+        _ => None,
+    }
+}
+
+impl Node {
+    fn title<'a>(&'a self, name: &'a str) -> &'a str {
+        self.page.as_ref().map_or(name, |page| page.title.as_str())
+    }
+
+    fn sorted_children(&self) -> Vec<(&String, &Node)> {
+        let mut children: Vec<_> = self.children.iter().collect();
+        children.sort_by_key(|(name, node)| {
+            let weight = node.page.as_ref().and_then(|page| page.weight);
+            (weight.is_none(), weight, node.title(name).to_string())
+        });
+        children
+    }
+
+    fn value(&self, name: &str) -> Value {
+        json!({
+            "title": self.title(name),
+            "url": self.page.as_ref().map(|page| format!("/{}", page.url)),
+            "weight": self.page.as_ref().and_then(|page| page.weight),
+            "children": self
+                .sorted_children()
+                .into_iter()
+                .map(|(name, node)| node.value(name))
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    /// Lists the pages in the order they appear in the tree.
+    fn flatten<'a>(&'a self, order: &mut Vec<&'a Entry>) {
+        order.extend(&self.page);
+        for (_, child) in self.sorted_children() {
+            child.flatten(order);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::index::{PageSource, SiteIndex, SourceFormat};
+
+    use super::Sections;
+
+    #[test]
+    fn order_by_weight() {
+        let mut site = SiteIndex::default();
+        for (path, frontmatter) in [
+            ("about.md", "title: About"),
+            ("docs/index.md", "title: Docs"),
+            ("docs/install.md", "title: Install\nweight: 1"),
+            ("docs/usage.md", "title: Usage\nweight: 2"),
+            ("docs/faq.md", "title: FAQ"),
+            ("docs/guide/index.md", "title: Guide\nweight: 3"),
+            ("docs/guide/themes.md", "title: Themes"),
+        ] {
+            site.add_page(PageSource::from_string(
+                path,
+                SourceFormat::Markdown,
+                format!("---\nlayout: page\n{frontmatter}\n---\n"),
+            ));
+        }
+        let site = site.render().unwrap();
+        let sections = Sections::build(&site);
+
+        let value = sections.value();
+        assert_eq!(value.as_object().unwrap().len(), 1);
+        let docs = &value["docs"];
+        assert_eq!(docs["title"], "Docs");
+        let titles: Vec<_> = docs["tree"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|node| node["title"].as_str().unwrap())
+            .collect();
+        assert_eq!(titles, ["Install", "Usage", "Guide", "FAQ"]);
+        assert_eq!(docs["tree"][2]["children"][0]["title"], "Themes");
+
+        let page = |path: &str| {
+            site.all_pages()
+                .find(|page| page.source_path().to_str() == Some(path))
+                .unwrap()
+        };
+        let section = sections.page_value(page("docs/guide/themes.md")).unwrap();
+        assert_eq!(section["prev"]["title"], "Guide");
+        assert_eq!(section["next"]["title"], "FAQ");
+        let section = sections.page_value(page("docs/index.md")).unwrap();
+        assert!(section["prev"].is_null());
+        assert!(sections.page_value(page("about.md")).is_none());
+    }
+}
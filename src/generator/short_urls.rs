@@ -0,0 +1,177 @@
+//! Short links to posts, such as `/s/ab3x/`
+//!
+//! Each post gets a code derived from a hash of its URL, so the code stays the
+//! same from one build to the next. Codes are handed out oldest post first,
+//! and a post whose code is already taken gets a longer one, so new posts
+//! never take over the short URL of an older post.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+};
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    index::{PageMetadata, SiteMetadata},
+    renderer::{RenderedPageRef, RenderedSite},
+};
+
+use super::writer::OutputWriter;
+
+const ALPHABET: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+/// The `[short_urls]` section of `Site.toml`
+#[derive(Deserialize, Debug)]
+pub struct ShortUrlConfig {
+    /// The directory the short URLs live in
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+    /// How many characters a code has, unless it needs more to be unique
+    #[serde(default = "default_length")]
+    pub length: usize,
+}
+
+fn default_prefix() -> String {
+    "s".to_string()
+}
+
+fn default_length() -> usize {
+    4
+}
+
+/// The short URLs for every post on a site
+#[derive(Default)]
+pub(crate) struct ShortUrls {
+    prefix: String,
+    /// Maps the source path of each post to its code
+    codes: HashMap<PathBuf, String>,
+    /// Maps each code to the URL of its post, relative to the site root
+    targets: BTreeMap<String, String>,
+}
+
+impl ShortUrls {
+    pub fn assign(site: &RenderedSite<'_>) -> Self {
+        let Some(config) = &site.config().short_urls else {
+            return Self::default();
+        };
+
+        let mut posts: Vec<_> = site.posts().collect();
+        posts.sort_by_key(|post| (post.publish_date(), post.url()));
+        let targets = assign_codes(posts.iter().map(|post| post.url()), config.length);
+        let codes = targets
+            .iter()
+            .filter_map(|(code, url)| {
+                let post = posts.iter().find(|post| post.url() == *url)?;
+                Some((post.source_path().to_path_buf(), code.clone()))
+            })
+            .collect();
+        Self {
+            prefix: config.prefix.trim_matches('/').to_string(),
+            codes,
+            targets,
+        }
+    }
+
+    /// The absolute short URL for `page`, if it has one
+    pub fn short_url(&self, site: &RenderedSite<'_>, page: RenderedPageRef<'_>) -> Option<String> {
+        let code = self.codes.get(page.source_path())?;
+        Some(site.full_url(&format!("{}/{code}/", self.prefix)))
+    }
+
+    /// Writes a redirect page for each short URL, along with
+    /// `<prefix>/urls.json`, which maps each code to the full URL.
+    pub fn write(&self, site: &RenderedSite<'_>, writer: &OutputWriter) {
+        if self.targets.is_empty() {
+            return;
+        }
+        let mut mapping = BTreeMap::new();
+        for (code, url) in &self.targets {
+            let target = site.full_url(url);
+            writer.write(
+                format!("{}/{code}/index.html", self.prefix),
+                redirect_page(&target),
+            );
+            mapping.insert(code, target);
+        }
+        writer.write(
+            format!("{}/urls.json", self.prefix),
+            serde_json::to_string_pretty(&mapping).unwrap(),
+        );
+    }
+}
+
+/// Gives each URL a unique code of at least `length` characters. URLs earlier
+/// in the list get first choice of codes.
+fn assign_codes(urls: impl Iterator<Item = String>, length: usize) -> BTreeMap<String, String> {
+    let mut targets = BTreeMap::new();
+    for url in urls {
+        let digest = Sha256::digest(url.as_bytes());
+        let code: String = digest
+            .iter()
+            .map(|byte| ALPHABET[*byte as usize % ALPHABET.len()] as char)
+            .collect();
+        let Some(code) = (length.max(1)..=code.len())
+            .map(|len| &code[..len])
+            .find(|code| !targets.contains_key(*code))
+        else {
+            continue;
+        };
+        targets.insert(code.to_string(), url);
+    }
+    targets
+}
+
+fn redirect_page(target: &str) -> String {
+    let target = target
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;");
+    format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Redirecting…</title>\
+         <link rel=\"canonical\" href=\"{target}\">\
+         <meta http-equiv=\"refresh\" content=\"0; url={target}\"></head>\
+         <body><a href=\"{target}\">{target}</a></body></html>\n"
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use super::assign_codes;
+
+    #[test]
+    fn unique_stable_codes() {
+        let urls: Vec<_> = (0..100).map(|i| format!("blog/post-{i}/")).collect();
+        let codes = assign_codes(urls.iter().cloned(), 1);
+        assert_eq!(codes.len(), urls.len());
+        assert_eq!(codes.values().collect::<HashSet<_>>().len(), urls.len());
+
+        // Adding a newer post doesn't change the existing codes
+        let mut more = urls.clone();
+        more.push("blog/newest/".to_string());
+        let more_codes = assign_codes(more.into_iter(), 1);
+        for (code, url) in &codes {
+            assert_eq!(more_codes.get(code), Some(url));
+        }
+    }
+
+    /// Published short URLs break if the way codes are made changes
+    #[test]
+    fn frozen_codes() {
+        let codes = assign_codes(
+            ["blog/2024/01/02/hello/".to_string(), "about/".to_string()].into_iter(),
+            4,
+        );
+        let codes: Vec<_> = codes
+            .iter()
+            .map(|(code, url)| (code.as_str(), url.as_str()))
+            .collect();
+        assert_eq!(
+            codes,
+            [("og7r", "about/"), ("zqj3", "blog/2024/01/02/hello/")]
+        );
+    }
+}
@@ -0,0 +1,127 @@
+//! Generating `sitemap.xml` so search engines can find every page
+
+use quick_xml::{
+    events::{BytesDecl, BytesText, Event::*},
+    Writer,
+};
+
+use crate::{
+    index::{PageMetadata, SiteMetadata},
+    renderer::RenderedSite,
+};
+
+use super::{canonical_url, content_hashes::ContentHashes};
+
+/// Lists every page on the site, along with when it last changed.
+///
+/// `generated` has the URLs of pages that don't come from a source file, such
+/// as tag pages. Error pages and pages that were republished from elsewhere
+/// are left out, since search engines shouldn't index them.
+pub(crate) fn generate_sitemap(
+    site: &RenderedSite<'_>,
+    hashes: &ContentHashes,
+    generated: &[String],
+) -> Result<Vec<u8>, quick_xml::Error> {
+    let mut writer = Writer::new(vec![]);
+    writer.write_event(Decl(BytesDecl::new("1.0", Some("utf-8"), None)))?;
+    writer
+        .create_element("urlset")
+        .with_attribute(("xmlns", "http://www.sitemaps.org/schemas/sitemap/0.9"))
+        .write_inner_content(|writer| {
+            for page in site.all_pages() {
+                if page.source().canonical_url().is_some() || is_error_page(&page.url()) {
+                    continue;
+                }
+                writer.create_element("url").write_inner_content(|writer| {
+                    writer
+                        .create_element("loc")
+                        .write_text_content(BytesText::new(&canonical_url(site, page)))?;
+                    // Pages without a date in their filename get a placeholder
+                    // publish date, so only posts' dates are worth reporting
+                    let published = page.publish_date().filter(|_| page.source().is_post());
+                    if let Some(date) = hashes.updated(page).or(published) {
+                        writer
+                            .create_element("lastmod")
+                            .write_text_content(BytesText::new(&date.to_rfc3339()))?;
+                    }
+                    Ok::<_, quick_xml::Error>(())
+                })?;
+            }
+            for url in generated {
+                writer.create_element("url").write_inner_content(|writer| {
+                    writer
+                        .create_element("loc")
+                        .write_text_content(BytesText::new(&site.full_url(url)))?;
+                    Ok::<_, quick_xml::Error>(())
+                })?;
+            }
+            Ok::<_, quick_xml::Error>(())
+        })?;
+    Ok(writer.into_inner())
+}
+
+/// Whether the page at `url` is shown for an HTTP error, such as `404.html`.
+fn is_error_page(url: &str) -> bool {
+    let name = url
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or_default();
+    let status = name.strip_suffix(".html").unwrap_or(name);
+    status.len() == 3
+        && status.starts_with(['4', '5'])
+        && status.bytes().all(|b| b.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        generator::content_hashes::ContentHashes,
+        index::{PageSource, SiteIndex, SourceFormat},
+    };
+
+    use super::generate_sitemap;
+
+    #[test]
+    fn sitemap() -> miette::Result<()> {
+        let mut site = SiteIndex::default();
+        site.config_mut().url = Some("https://example.com".into());
+        site.add_page(PageSource::from_string(
+            "_posts/2024-01-02-hello.md",
+            SourceFormat::Markdown,
+            "---\nlayout: post\ntitle: Hello\n---\n",
+        ));
+        site.add_page(PageSource::from_string(
+            "about.md",
+            SourceFormat::Markdown,
+            "---\nlayout: page\ntitle: About\n---\n",
+        ));
+        site.add_page(PageSource::from_string(
+            "elsewhere.md",
+            SourceFormat::Markdown,
+            "---\nlayout: page\ntitle: Elsewhere\ncanonical_url: https://example.org/\n---\n",
+        ));
+        site.add_page(PageSource::from_string(
+            "404.md",
+            SourceFormat::Markdown,
+            "---\nlayout: page\ntitle: Not Found\n---\n",
+        ));
+        let site = site.render()?;
+
+        let sitemap = generate_sitemap(
+            &site,
+            &ContentHashes::default(),
+            &["/tags/rust/".to_string()],
+        )
+        .unwrap();
+        let sitemap = String::from_utf8(sitemap).unwrap();
+        assert_eq!(sitemap.matches("<url>").count(), 3);
+        assert!(sitemap
+            .contains("<loc>https://example.com/blog/2024/01/02/hello/</loc><lastmod>2024-01-02"));
+        assert!(sitemap.contains("<loc>https://example.com/about</loc></url>"));
+        assert!(sitemap.contains("<loc>https://example.com/tags/rust/</loc></url>"));
+        assert!(!sitemap.contains("example.org"));
+        assert!(!sitemap.contains("404"));
+        Ok(())
+    }
+}
@@ -0,0 +1,189 @@
+//! Summarizing the generated site and checking it against size budgets
+
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use super::MemoryReport;
+use crate::audit::Finding;
+
+/// The `[budgets]` section of `Site.toml`
+///
+/// All sizes are in bytes.
+#[derive(Deserialize, Debug, Default)]
+pub struct BudgetConfig {
+    /// The largest an HTML page may be
+    pub page: Option<u64>,
+    /// The largest an image may be
+    pub image: Option<u64>,
+    /// The largest the whole site may be
+    pub total: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BudgetKind {
+    Page,
+    Image,
+    Total,
+}
+
+/// A file, or the whole site, that is larger than its budget
+#[derive(Debug, PartialEq)]
+pub struct BudgetViolation {
+    pub kind: BudgetKind,
+    /// The file that is too large, relative to the destination directory, or
+    /// `None` for the total site budget
+    pub path: Option<PathBuf>,
+    pub size: u64,
+    pub budget: u64,
+}
+
+impl fmt::Display for BudgetViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let what = match &self.path {
+            Some(path) => format!("`{}`", path.display()),
+            None => "the site".to_string(),
+        };
+        let budget = match self.kind {
+            BudgetKind::Page => "page",
+            BudgetKind::Image => "image",
+            BudgetKind::Total => "total",
+        };
+        write!(
+            f,
+            "{what} is {} bytes, over the {budget} budget of {} bytes",
+            self.size, self.budget
+        )
+    }
+}
+
+/// Statistics about a generated site
+#[derive(Debug, Default)]
+pub struct BuildSummary {
+    pub pages: usize,
+    pub files: usize,
+    pub total_bytes: u64,
+    pub budget_violations: Vec<BudgetViolation>,
+    /// Accessibility problems, if the site asks for them to be checked
+    pub accessibility: Vec<Finding>,
+    /// How much memory the build used, if the site asks for it to be measured
+    pub memory: Option<MemoryReport>,
+    /// The URLs of the feeds that no page links to, which readers will have a
+    /// hard time finding
+    pub unreferenced_feeds: Vec<String>,
+    /// Why the output may differ from a build of the same sources
+    pub nondeterministic: Vec<String>,
+}
+
+impl BuildSummary {
+    /// Measures the files in `dest` and checks them against `budgets`.
+    pub(crate) fn measure(dest: &Path, budgets: &BudgetConfig) -> Self {
+        let mut summary = Self::default();
+        for entry in walkdir::WalkDir::new(dest)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_file())
+        {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            let path = entry.path().strip_prefix(dest).unwrap_or(entry.path());
+            summary.add_file(path, metadata.len(), budgets);
+        }
+        if let Some(budget) = budgets.total {
+            if summary.total_bytes > budget {
+                summary.budget_violations.push(BudgetViolation {
+                    kind: BudgetKind::Total,
+                    path: None,
+                    size: summary.total_bytes,
+                    budget,
+                });
+            }
+        }
+        summary
+    }
+
+    /// Counts the accessibility problems on each page, in the order the pages
+    /// first appear in the findings.
+    pub fn accessibility_by_page(&self) -> Vec<(&Path, usize)> {
+        let mut counts: Vec<(&Path, usize)> = vec![];
+        for finding in &self.accessibility {
+            match counts.iter_mut().find(|(page, _)| *page == finding.page) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((&finding.page, 1)),
+            }
+        }
+        counts
+    }
+
+    fn add_file(&mut self, path: &Path, size: u64, budgets: &BudgetConfig) {
+        self.files += 1;
+        self.total_bytes += size;
+
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_lowercase);
+        let (kind, budget) = match extension.as_deref() {
+            Some("html") => {
+                self.pages += 1;
+                (BudgetKind::Page, budgets.page)
+            }
+            Some("png" | "jpg" | "jpeg" | "gif" | "webp" | "avif" | "svg") => {
+                (BudgetKind::Image, budgets.image)
+            }
+            _ => return,
+        };
+        if let Some(budget) = budget {
+            if size > budget {
+                self.budget_violations.push(BudgetViolation {
+                    kind,
+                    path: Some(path.into()),
+                    size,
+                    budget,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::{BudgetConfig, BudgetKind, BuildSummary};
+
+    #[test]
+    fn check_budgets() {
+        let budgets = BudgetConfig {
+            page: Some(100),
+            image: Some(1000),
+            total: Some(1500),
+        };
+        let mut summary = BuildSummary::default();
+        summary.add_file(Path::new("index.html"), 50, &budgets);
+        summary.add_file(Path::new("about/index.html"), 150, &budgets);
+        summary.add_file(Path::new("images/big.PNG"), 2000, &budgets);
+        summary.add_file(Path::new("style.css"), 5000, &budgets);
+
+        assert_eq!(summary.pages, 2);
+        assert_eq!(summary.files, 4);
+        assert_eq!(summary.total_bytes, 7200);
+        let violations: Vec<_> = summary
+            .budget_violations
+            .iter()
+            .map(|v| (v.kind, v.path.as_deref().unwrap()))
+            .collect();
+        assert_eq!(
+            violations,
+            [
+                (BudgetKind::Page, Path::new("about/index.html")),
+                (BudgetKind::Image, Path::new("images/big.PNG")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,251 @@
+//! Pages that list the posts with each tag or category
+//!
+//! When the theme has a `tag.html` template, each tag gets a page at
+//! `/tags/<tag-slug>/`, and likewise each category gets a page at
+//! `/categories/<category-slug>/` when the theme has a `category.html`
+//! template.
+//!
+//! The terms a site means to use can be listed in the `[taxonomy]` section of
+//! `Site.toml`, so that `ebg list tags --unused` can find the ones no post has.
+
+use std::collections::BTreeMap;
+
+use miette::Diagnostic;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::{
+    index::{PageMetadata, PageSource, SiteIndex, SiteMetadata},
+    renderer::{RenderedPageRef, RenderedSite},
+};
+
+/// The `[taxonomy]` section of `Site.toml`
+#[derive(Deserialize, Debug, Default)]
+pub struct TaxonomyConfig {
+    /// The tags the site means to use
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// The categories the site means to use
+    #[serde(default)]
+    pub categories: Vec<String>,
+}
+
+#[derive(Diagnostic, Error, Debug)]
+pub enum TaxonomyError {
+    #[error(
+        "the {taxonomy} {} would all have the page `/{taxonomy}/{slug}/`; rename them so each \
+         has its own",
+        list(.terms)
+    )]
+    SlugCollision {
+        taxonomy: &'static str,
+        slug: String,
+        terms: Vec<String>,
+    },
+    #[error(
+        "the {taxonomy} `{term}` has no letters or digits, so it can't have a page; rename it"
+    )]
+    EmptySlug {
+        taxonomy: &'static str,
+        term: String,
+    },
+}
+
+fn list(terms: &[String]) -> String {
+    terms
+        .iter()
+        .map(|term| format!("`{term}`"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[derive(Clone, Copy)]
+pub enum Taxonomy {
+    Tags,
+    Categories,
+}
+
+impl Taxonomy {
+    pub const ALL: [Taxonomy; 2] = [Taxonomy::Tags, Taxonomy::Categories];
+
+    /// The name of the taxonomy, which is also where its pages go
+    pub fn name(self) -> &'static str {
+        match self {
+            Taxonomy::Tags => "tags",
+            Taxonomy::Categories => "categories",
+        }
+    }
+
+    /// The template used for the page of each term
+    pub fn template(self) -> &'static str {
+        match self {
+            Taxonomy::Tags => "tag.html",
+            Taxonomy::Categories => "category.html",
+        }
+    }
+
+    fn terms(self, page: &PageSource) -> &[String] {
+        match self {
+            Taxonomy::Tags => page.tags(),
+            Taxonomy::Categories => page.categories(),
+        }
+    }
+
+    /// The terms listed for this taxonomy in `Site.toml`
+    fn configured(self, site: &SiteIndex) -> &[String] {
+        let config = &site.config().taxonomy;
+        match self {
+            Taxonomy::Tags => &config.tags,
+            Taxonomy::Categories => &config.categories,
+        }
+    }
+
+    /// Counts the posts with each term, in alphabetical order.
+    pub fn counts(self, site: &SiteIndex) -> BTreeMap<&str, usize> {
+        let mut counts = BTreeMap::new();
+        for post in site.posts() {
+            for term in self.terms(post) {
+                *counts.entry(term.as_str()).or_default() += 1;
+            }
+        }
+        counts
+    }
+
+    /// Returns the terms listed in `Site.toml` that no post uses.
+    pub fn unused(self, site: &SiteIndex) -> Vec<&str> {
+        let counts = self.counts(site);
+        self.configured(site)
+            .iter()
+            .map(String::as_str)
+            .filter(|term| !counts.contains_key(term))
+            .collect()
+    }
+
+    /// Returns the slug used in the URL of the page for `term`.
+    pub fn slug(term: &str) -> String {
+        slug::slugify(term)
+    }
+
+    /// Checks that each of `terms` gets a page of its own, since terms like
+    /// `C++` and `C` or `Rust` and `rust` have the same slug.
+    pub fn check_slugs<'a>(
+        self,
+        terms: impl IntoIterator<Item = &'a str>,
+    ) -> Result<(), TaxonomyError> {
+        let mut slugs: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for term in terms {
+            let slug = Self::slug(term);
+            if slug.is_empty() {
+                return Err(TaxonomyError::EmptySlug {
+                    taxonomy: self.name(),
+                    term: term.to_string(),
+                });
+            }
+            slugs.entry(slug).or_default().push(term.to_string());
+        }
+        match slugs.into_iter().find(|(_, terms)| terms.len() > 1) {
+            Some((slug, terms)) => Err(TaxonomyError::SlugCollision {
+                taxonomy: self.name(),
+                slug,
+                terms,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the path of the page for `term`, relative to the site root.
+    pub fn path(self, term: &str) -> String {
+        format!("{}/{}/", self.name(), Self::slug(term))
+    }
+
+    /// Groups the site's posts by term, newest first.
+    pub fn posts<'a>(
+        self,
+        site: &'a RenderedSite<'_>,
+    ) -> BTreeMap<&'a str, Vec<RenderedPageRef<'a>>> {
+        let mut terms: BTreeMap<&str, Vec<_>> = BTreeMap::new();
+        for post in site.posts() {
+            for term in self.terms(post.source()) {
+                terms.entry(term.as_str()).or_default().push(post);
+            }
+        }
+        for posts in terms.values_mut() {
+            posts.sort_by_key(|post| std::cmp::Reverse(post.publish_date()));
+        }
+        terms
+    }
+
+    /// Lists each term with the URL of its page and how many posts have it,
+    /// for `site.tags` and `site.categories`.
+    pub fn value(self, site: &RenderedSite<'_>) -> Value {
+        self.posts(site)
+            .into_iter()
+            .map(|(term, posts)| {
+                json!({
+                    "name": term,
+                    "url": format!("/{}", self.path(term)),
+                    "count": posts.len(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::index::{PageSource, SiteIndex, SourceFormat};
+
+    use super::{Taxonomy, TaxonomyError};
+
+    #[test]
+    fn term_paths() {
+        assert_eq!(Taxonomy::Tags.path("Async Rust"), "tags/async-rust/");
+        assert_eq!(Taxonomy::Categories.path("C++"), "categories/c/");
+    }
+
+    #[test]
+    fn slug_conflicts() {
+        assert!(Taxonomy::Tags.check_slugs(["C++", "Rust"]).is_ok());
+        assert_eq!(
+            Taxonomy::Tags
+                .check_slugs(["C", "C#", "C++", "Rust"])
+                .unwrap_err()
+                .to_string(),
+            "the tags `C`, `C#`, `C++` would all have the page `/tags/c/`; rename them so each has \
+             its own"
+        );
+        assert!(matches!(
+            Taxonomy::Tags.check_slugs(["Rust", "rust"]),
+            Err(TaxonomyError::SlugCollision { .. })
+        ));
+        assert!(matches!(
+            Taxonomy::Categories.check_slugs(["!!!"]),
+            Err(TaxonomyError::EmptySlug { .. })
+        ));
+    }
+
+    #[test]
+    fn unused_terms() {
+        let mut site = SiteIndex::default();
+        site.config_mut().taxonomy.categories = vec!["Rust".into(), "Travel".into()];
+        site.add_page(PageSource::from_string(
+            "_posts/2024-01-01-one.md",
+            SourceFormat::Markdown,
+            "---\nlayout: post\ncategories: Rust, Music\ntags: [a, b]\n---\n",
+        ));
+        site.add_page(PageSource::from_string(
+            "_posts/2024-01-02-two.md",
+            SourceFormat::Markdown,
+            "---\nlayout: post\ncategories: Rust\n---\n",
+        ));
+
+        let counts = Taxonomy::Categories.counts(&site);
+        assert_eq!(
+            counts.into_iter().collect::<Vec<_>>(),
+            [("Music", 1), ("Rust", 2)]
+        );
+        assert_eq!(Taxonomy::Categories.unused(&site), ["Travel"]);
+        assert!(Taxonomy::Tags.unused(&site).is_empty());
+    }
+}
@@ -0,0 +1,294 @@
+//! Generating `humans.txt`, `security.txt`, and `robots.txt` from `Site.toml`
+
+use std::{collections::BTreeMap, fmt::Write};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::{
+    index::{PageMetadata, SiteMetadata},
+    renderer::RenderedSite,
+};
+
+use super::build_info::build_time;
+
+/// The `[humans_txt]` section of `Site.toml`
+///
+/// See <https://humanstxt.org/Standard.html>.
+#[derive(Deserialize, Debug, Default)]
+pub struct HumansConfig {
+    /// The people who made the site. If empty, the site's author is listed.
+    #[serde(default)]
+    pub team: Vec<Human>,
+    #[serde(default)]
+    pub thanks: Vec<Human>,
+    /// Entries for the `SITE` section, such as `Standards` or `Components`
+    #[serde(default)]
+    pub site: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Human {
+    pub name: String,
+    pub role: Option<String>,
+    pub contact: Option<String>,
+    pub location: Option<String>,
+}
+
+/// The `[security_txt]` section of `Site.toml`
+///
+/// See [RFC 9116](https://www.rfc-editor.org/rfc/rfc9116).
+#[derive(Deserialize, Debug)]
+pub struct SecurityConfig {
+    /// How to report vulnerabilities. Email addresses are turned into
+    /// `mailto:` URIs.
+    pub contact: Vec<String>,
+    /// A fixed expiration date
+    pub expires: Option<DateTime<Utc>>,
+    /// Sets the expiration date relative to when the site is built, if
+    /// `expires` is not given
+    #[serde(default = "default_expires_in_days")]
+    pub expires_in_days: u32,
+    pub encryption: Option<String>,
+    pub acknowledgments: Option<String>,
+    #[serde(default)]
+    pub preferred_languages: Vec<String>,
+    pub policy: Option<String>,
+    pub hiring: Option<String>,
+}
+
+/// The `[robots_txt]` section of `Site.toml`
+///
+/// See [RFC 9309](https://www.rfc-editor.org/rfc/rfc9309).
+#[derive(Deserialize, Debug, Default)]
+pub struct RobotsConfig {
+    /// Groups of rules, each for some crawlers. If empty, every crawler is
+    /// allowed everywhere.
+    #[serde(default)]
+    pub rules: Vec<RobotsRule>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RobotsRule {
+    /// The crawlers this rule applies to, such as `*` or `GPTBot`
+    #[serde(deserialize_with = "crate::index::deserialize_comma_separated_list")]
+    pub user_agent: Vec<String>,
+    #[serde(default)]
+    pub allow: Vec<String>,
+    #[serde(default)]
+    pub disallow: Vec<String>,
+}
+
+fn default_expires_in_days() -> u32 {
+    180
+}
+
+pub(crate) fn generate_humans(site: &RenderedSite, config: &HumansConfig) -> String {
+    let mut out = String::new();
+
+    let author = site.author().map(|author| Human {
+        name: author.to_string(),
+        role: None,
+        contact: site.author_email().map(String::from),
+        location: None,
+    });
+    let team = if config.team.is_empty() {
+        author.as_slice()
+    } else {
+        &config.team
+    };
+
+    out.push_str("/* TEAM */\n");
+    write_humans(&mut out, team, "Author");
+    if !config.thanks.is_empty() {
+        out.push_str("\n/* THANKS */\n");
+        write_humans(&mut out, &config.thanks, "Name");
+    }
+
+    out.push_str("\n/* SITE */\n");
+    // The newest post stands in for the last update so that rebuilding an
+    // unchanged site doesn't change the file
+    let updated = site
+        .posts()
+        .filter_map(|post| post.publish_date())
+        .max()
+        .unwrap_or_else(build_time);
+    let _ = writeln!(out, "Last update: {}", updated.format("%Y/%m/%d"));
+    for (key, value) in &config.site {
+        let _ = writeln!(out, "{key}: {value}");
+    }
+    let _ = writeln!(out, "Software: EBG {}", env!("CARGO_PKG_VERSION"));
+
+    out
+}
+
+fn write_humans(out: &mut String, humans: &[Human], default_role: &str) {
+    for (i, human) in humans.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        let role = human.role.as_deref().unwrap_or(default_role);
+        let _ = writeln!(out, "{role}: {}", human.name);
+        if let Some(contact) = &human.contact {
+            let _ = writeln!(out, "Contact: {contact}");
+        }
+        if let Some(location) = &human.location {
+            let _ = writeln!(out, "Location: {location}");
+        }
+    }
+}
+
+pub(crate) fn generate_security(
+    site: &RenderedSite,
+    config: &SecurityConfig,
+    now: DateTime<Utc>,
+) -> String {
+    let expires = match config.expires {
+        Some(expires) => {
+            if expires <= now {
+                warn!(
+                    "security.txt expired on {expires}, update `security_txt.expires` in Site.toml"
+                );
+            } else if expires > now + Duration::days(365) {
+                warn!("security.txt expires more than a year from now, which is not recommended");
+            }
+            expires
+        }
+        None => now + Duration::days(config.expires_in_days.into()),
+    };
+
+    let mut out = String::new();
+    for contact in &config.contact {
+        let contact = if contact.contains(':') || !contact.contains('@') {
+            contact.clone()
+        } else {
+            format!("mailto:{contact}")
+        };
+        let _ = writeln!(out, "Contact: {contact}");
+    }
+    let _ = writeln!(out, "Expires: {}", expires.format("%Y-%m-%dT%H:%M:%SZ"));
+    let fields = [
+        ("Encryption", &config.encryption),
+        ("Acknowledgments", &config.acknowledgments),
+        ("Policy", &config.policy),
+        ("Hiring", &config.hiring),
+    ];
+    for (name, value) in fields {
+        if let Some(value) = value {
+            let _ = writeln!(out, "{name}: {value}");
+        }
+    }
+    if !config.preferred_languages.is_empty() {
+        let _ = writeln!(
+            out,
+            "Preferred-Languages: {}",
+            config.preferred_languages.join(", ")
+        );
+    }
+    if !site.base_url().is_empty() {
+        let _ = writeln!(
+            out,
+            "Canonical: {}",
+            site.full_url(".well-known/security.txt")
+        );
+    }
+    out
+}
+
+pub(crate) fn generate_robots(site: &RenderedSite, config: &RobotsConfig) -> String {
+    let mut out = String::new();
+    for (i, rule) in config.rules.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        for user_agent in &rule.user_agent {
+            let _ = writeln!(out, "User-agent: {user_agent}");
+        }
+        for path in &rule.allow {
+            let _ = writeln!(out, "Allow: {path}");
+        }
+        for path in &rule.disallow {
+            let _ = writeln!(out, "Disallow: {path}");
+        }
+    }
+    if config.rules.is_empty() {
+        out.push_str("User-agent: *\nAllow: /\n");
+    }
+    // The health page is only meant for the site's author
+    if site.config().health_page {
+        out.push_str("\nUser-agent: *\nDisallow: /_health/\n");
+    }
+    if site.config().sitemap && !site.base_url().is_empty() {
+        let _ = writeln!(out, "\nSitemap: {}", site.full_url("sitemap.xml"));
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+
+    use crate::index::SiteIndex;
+
+    use super::{generate_robots, generate_security, RobotsConfig, SecurityConfig};
+
+    #[test]
+    fn robots_txt() -> miette::Result<()> {
+        let site = SiteIndex::default();
+        let site = site.render()?;
+        assert_eq!(
+            generate_robots(&site, &RobotsConfig::default()),
+            "User-agent: *\nAllow: /\n"
+        );
+
+        let config: RobotsConfig = toml::from_str(
+            r#"
+[[rules]]
+user_agent = "*"
+disallow = ["/drafts/", "/search"]
+
+[[rules]]
+user_agent = ["GPTBot", "CCBot"]
+disallow = ["/"]
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            generate_robots(&site, &config),
+            "User-agent: *
+Disallow: /drafts/
+Disallow: /search
+
+User-agent: GPTBot
+User-agent: CCBot
+Disallow: /
+"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn security_txt() -> miette::Result<()> {
+        let site = SiteIndex::default();
+        let site = site.render()?;
+        let config: SecurityConfig = toml::from_str(
+            r#"
+contact = ["security@example.com", "https://example.com/report"]
+expires_in_days = 30
+preferred_languages = ["en", "de"]
+"#,
+        )
+        .unwrap();
+        let now = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+        assert_eq!(
+            generate_security(&site, &config, now),
+            "Contact: mailto:security@example.com
+Contact: https://example.com/report
+Expires: 2024-01-31T12:00:00Z
+Preferred-Languages: en, de
+"
+        );
+        Ok(())
+    }
+}
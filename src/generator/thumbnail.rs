@@ -0,0 +1,290 @@
+//! Thumbnails that represent each post on index pages and social cards
+//!
+//! The thumbnail is made from the image in the post's frontmatter, or else the
+//! first image in its contents, and is written next to the post as
+//! `thumbnail.<ext>`.
+//!
+//! Images in galleries also get square thumbnails, written next to each image
+//! as `<name>.thumb.<ext>`.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use image::{imageops::FilterType, ImageFormat};
+use rayon::prelude::*;
+use serde::Deserialize;
+
+use crate::{
+    index::{PageMetadata, SiteMetadata},
+    renderer::{gallery_thumbnail_url, GalleryConfig, RenderedPageRef, RenderedSite},
+};
+
+use super::{image_cache::ImageCache, writer::OutputWriter, GeneratorError};
+
+/// The `[thumbnails]` section of `Site.toml`
+#[derive(Deserialize, Debug)]
+pub struct ThumbnailConfig {
+    /// The width of thumbnails, in pixels
+    #[serde(default = "default_width")]
+    pub width: u32,
+    /// The height of thumbnails, in pixels
+    #[serde(default = "default_height")]
+    pub height: u32,
+}
+
+fn default_width() -> u32 {
+    400
+}
+
+fn default_height() -> u32 {
+    225
+}
+
+struct Thumbnail {
+    /// The image the thumbnail is made from
+    source: PathBuf,
+    format: ImageFormat,
+}
+
+impl Thumbnail {
+    fn file_name(&self) -> String {
+        format!("thumbnail.{}", self.format.extensions_str()[0])
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Thumbnails {
+    /// Maps the URL of each post to its thumbnail
+    thumbnails: HashMap<String, Thumbnail>,
+}
+
+impl Thumbnails {
+    /// Picks the image for each post's thumbnail, if thumbnails are enabled.
+    pub fn collect(site: &RenderedSite<'_>) -> Self {
+        if site.config().thumbnails.is_none() {
+            return Self::default();
+        }
+        let thumbnails = site
+            .posts()
+            .filter_map(|post| {
+                let source = post
+                    .source()
+                    .image()
+                    .map(str::to_string)
+                    .or_else(|| first_image(post.rendered_contents()))
+                    .and_then(|src| resolve_local(site, post, &src))?;
+                let format = ImageFormat::from_path(&source).ok()?;
+                Some((post.url(), Thumbnail { source, format }))
+            })
+            .collect();
+        Self { thumbnails }
+    }
+
+    /// Returns the URL of the thumbnail for `page`.
+    pub fn url(&self, page: RenderedPageRef<'_>) -> Option<String> {
+        let url = page.url();
+        let thumbnail = self.thumbnails.get(&url)?;
+        Some(format!("/{url}{}", thumbnail.file_name()))
+    }
+
+    /// Resizes each image and writes the thumbnails for the pages the build
+    /// includes.
+    pub fn write(
+        &self,
+        site: &RenderedSite<'_>,
+        writer: &OutputWriter,
+    ) -> Result<(), GeneratorError> {
+        let Some(config) = &site.config().thumbnails else {
+            return Ok(());
+        };
+        let cache = ImageCache::new(site.root_dir());
+        let variant = [(format!("{}x{}", config.width, config.height), ())];
+        site.posts()
+            .filter(|post| site.includes(post.source()))
+            .collect::<Vec<_>>()
+            .par_iter()
+            .try_for_each(|post| {
+                let url = post.url();
+                let Some(thumbnail) = self.thumbnails.get(&url) else {
+                    return Ok(());
+                };
+                let mut copies = cache
+                    .resize(&thumbnail.source, &variant, |image, ()| {
+                        image.resize_to_fill(config.width, config.height, FilterType::Lanczos3)
+                    })
+                    .map_err(|e| GeneratorError::Thumbnail(thumbnail.source.clone(), e))?;
+                writer.write(
+                    Path::new(&url).join(thumbnail.file_name()),
+                    copies.remove(0),
+                );
+                Ok(())
+            })
+    }
+}
+
+/// Writes a thumbnail for each image in the galleries on the pages the build
+/// includes.
+pub(crate) fn generate_gallery_thumbnails(
+    site: &RenderedSite<'_>,
+    writer: &OutputWriter,
+) -> Result<(), GeneratorError> {
+    let size = site
+        .config()
+        .gallery
+        .as_ref()
+        .map_or(GalleryConfig::default().thumbnail_size, |config| {
+            config.thumbnail_size
+        });
+    let images: Vec<_> = site
+        .all_pages()
+        .filter(|page| site.includes(page.source()))
+        .flat_map(|page| {
+            gallery_images(page.rendered_contents())
+                .into_iter()
+                .map(move |src| (page, src))
+        })
+        .collect();
+    let cache = ImageCache::new(site.root_dir());
+    let variant = [(format!("{size}x{size}"), ())];
+    images.par_iter().try_for_each(|(page, src)| {
+        let Some(source) = resolve_local(site, *page, src) else {
+            return Ok(());
+        };
+        let mut copies = cache
+            .resize(&source, &variant, |image, ()| {
+                image.resize_to_fill(size, size, FilterType::Lanczos3)
+            })
+            .map_err(|e| GeneratorError::Thumbnail(source.clone(), e))?;
+        writer.write(
+            local_dest(*page, &gallery_thumbnail_url(src)),
+            copies.remove(0),
+        );
+        Ok(())
+    })
+}
+
+/// Returns the full-size image linked from each gallery item in `html`.
+fn gallery_images(html: &str) -> Vec<String> {
+    const ITEM: &str = r#"<figure class="gallery-item"><a href=""#;
+    html.match_indices(ITEM)
+        .filter_map(|(start, _)| {
+            let rest = &html[start + ITEM.len()..];
+            let src = rest[..rest.find('"')?]
+                .replace("&quot;", "\"")
+                .replace("&lt;", "<")
+                .replace("&amp;", "&");
+            Some(src)
+        })
+        .collect()
+}
+
+/// Returns the `src` attribute of the first `<img>` tag in `html`.
+fn first_image(html: &str) -> Option<String> {
+    html.match_indices("<img").find_map(|(start, _)| {
+        let tag = &html[start..start + html[start..].find('>')?];
+        let (_, rest) = tag.split_once("src=\"")?;
+        let (src, _) = rest.split_once('"')?;
+        Some(src.to_string())
+    })
+}
+
+/// Finds the file on disk for a link in `page`, if it is local.
+///
+/// Links are followed from where the page is published, the same way a
+/// browser would, so files next to a post's source only count for bundles,
+/// whose files are published next to the post.
+pub(super) fn resolve_local(
+    site: &RenderedSite<'_>,
+    page: RenderedPageRef<'_>,
+    src: &str,
+) -> Option<PathBuf> {
+    if src.contains("://") || src.starts_with("data:") || src.starts_with("//") {
+        return None;
+    }
+    let path = match src.strip_prefix('/') {
+        Some(path) => site.root_dir().join(path),
+        None if page.source().is_bundle() => site
+            .root_dir()
+            .join(page.source_path().parent().unwrap_or(Path::new("")))
+            .join(src),
+        None => site.root_dir().join(page.url()).join(src),
+    };
+    path.is_file().then_some(path)
+}
+
+/// Returns where a file linked from `page` as `src` is written, relative to
+/// the destination directory.
+pub(super) fn local_dest(page: RenderedPageRef<'_>, src: &str) -> PathBuf {
+    match src.strip_prefix('/') {
+        Some(path) => PathBuf::from(path),
+        None => Path::new(&page.url()).join(src),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::index::SiteIndex;
+
+    use super::{first_image, gallery_images, resolve_local};
+
+    #[test]
+    fn find_first_image() {
+        assert_eq!(
+            first_image(r#"<p><img alt="a" src="a.png"><img src="b.png"></p>"#).as_deref(),
+            Some("a.png")
+        );
+        assert_eq!(first_image(r#"<p><img alt="no source"></p>"#), None);
+    }
+
+    #[test]
+    fn find_gallery_images() {
+        let html = r#"<div class="gallery"><figure class="gallery-item"><a href="photos/a&amp;b.jpg"><img src="photos/a&amp;b.thumb.jpg"></a></figure><figure class="gallery-item"><a href="/c.png"></a></figure></div>"#;
+        assert_eq!(gallery_images(html), ["photos/a&b.jpg", "/c.png"]);
+    }
+
+    #[tokio::test]
+    async fn resolve_from_published_page() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("Site.toml"), "").unwrap();
+        for dir in ["_posts/2024-01-01-bundle", "images"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in [
+            "_posts/2024-01-01-bundle/index.md",
+            "_posts/2024-01-02-single.md",
+        ] {
+            std::fs::write(root.join(file), "---\nlayout: post\n---\n").unwrap();
+        }
+        for file in [
+            "_posts/2024-01-01-bundle/photo.jpg",
+            "_posts/photo.jpg",
+            "images/photo.jpg",
+        ] {
+            std::fs::write(root.join(file), "").unwrap();
+        }
+        let site = SiteIndex::from_directory(root, false).await.unwrap();
+        let site = site.render().unwrap();
+        let page = |name: &str| {
+            site.all_pages()
+                .find(|page| page.source_path().ends_with(name))
+                .unwrap()
+        };
+
+        assert_eq!(
+            resolve_local(&site, page("index.md"), "photo.jpg"),
+            Some(root.join("_posts/2024-01-01-bundle/photo.jpg"))
+        );
+        // Only a bundle's files are published next to the post
+        assert_eq!(
+            resolve_local(&site, page("2024-01-02-single.md"), "photo.jpg"),
+            None
+        );
+        assert_eq!(
+            resolve_local(&site, page("2024-01-02-single.md"), "/images/photo.jpg"),
+            Some(root.join("images/photo.jpg"))
+        );
+    }
+}
@@ -0,0 +1,222 @@
+//! Checking the finished output before it is deployed
+//!
+//! With `ebg build --verify`, the destination directory is read back once the
+//! build is done. Every URL in the sitemap has to lead to a file, every page
+//! the build generated has to exist and not be empty, and the feeds have to
+//! parse. Anything that fails points to a bug in the generator or a template,
+//! which is better found here than by readers.
+
+use std::path::Path;
+
+use quick_xml::{events::Event, Reader};
+
+use crate::{
+    index::{PageMetadata, SiteMetadata},
+    renderer::RenderedSite,
+};
+
+use super::{
+    feeds::site_feeds,
+    health::{find_target, resolve},
+};
+
+/// Checks the output in `dest` for `site`, returning a description of each
+/// problem found.
+pub(crate) fn verify_output(site: &RenderedSite<'_>, dest: &Path) -> Vec<String> {
+    let mut problems = vec![];
+    for page in site.all_pages() {
+        let path = Path::new(&page.url()).join("index.html");
+        problems.extend(check_page(dest, &path, page.source_path()));
+    }
+    if site.config().sitemap && !site.base_url().is_empty() {
+        problems.extend(check_sitemap(dest, site.base_url()));
+    }
+
+    for feed in site_feeds(site) {
+        let path = Path::new(&feed.url[1..]);
+        let result = if feed.url.ends_with(".json") {
+            check_json(dest, path)
+        } else {
+            check_xml(dest, path)
+        };
+        problems.extend(result.err());
+    }
+    problems
+}
+
+/// Formats `problems` as a list for an error message.
+pub(crate) fn list_problems(problems: &[String]) -> String {
+    problems
+        .iter()
+        .map(|problem| format!("\n  - {problem}"))
+        .collect()
+}
+
+/// Checks that the page generated from `source` was written to `path`.
+fn check_page(dest: &Path, path: &Path, source: &Path) -> Option<String> {
+    match dest.join(path).metadata() {
+        Ok(metadata) if metadata.len() > 0 => None,
+        Ok(_) => Some(format!(
+            "`{}`, generated from `{}`, is empty",
+            path.display(),
+            source.display()
+        )),
+        Err(_) => Some(format!(
+            "`{}`, generated from `{}`, is missing",
+            path.display(),
+            source.display()
+        )),
+    }
+}
+
+/// Checks that every URL in `sitemap.xml` leads to a file in `dest`.
+fn check_sitemap(dest: &Path, base_url: &str) -> Vec<String> {
+    let locs = match read_xml(dest, Path::new("sitemap.xml"))
+        .and_then(|contents| sitemap_locs(&contents))
+    {
+        Ok(locs) => locs,
+        Err(problem) => return vec![problem],
+    };
+    locs.into_iter()
+        .filter(|loc| {
+            resolve(Path::new(""), loc, base_url)
+                .and_then(|target| find_target(dest, &target))
+                .is_none()
+        })
+        .map(|loc| format!("`sitemap.xml` lists `{loc}`, which has no file"))
+        .collect()
+}
+
+/// Returns the text of each `<loc>` element in a sitemap.
+fn sitemap_locs(contents: &str) -> Result<Vec<String>, String> {
+    let mut reader = Reader::from_str(contents);
+    let mut locs = vec![];
+    let mut in_loc = false;
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(start)) => in_loc = start.local_name().as_ref() == b"loc",
+            Ok(Event::End(_)) => in_loc = false,
+            Ok(Event::Text(text)) if in_loc => locs.push(
+                text.unescape()
+                    .map_err(|e| format!("`sitemap.xml` is not valid XML: {e}"))?
+                    .trim()
+                    .to_string(),
+            ),
+            Ok(Event::Eof) => return Ok(locs),
+            Ok(_) => {}
+            // `read_xml` has already parsed the whole file
+            Err(e) => return Err(format!("`sitemap.xml` is not valid XML: {e}")),
+        }
+    }
+}
+
+/// Reads `path` and checks that it is well-formed XML.
+fn read_xml(dest: &Path, path: &Path) -> Result<String, String> {
+    let contents = std::fs::read_to_string(dest.join(path))
+        .map_err(|e| format!("`{}` could not be read: {e}", path.display()))?;
+    parse_xml(&contents).map_err(|e| format!("`{}` is not valid XML: {e}", path.display()))?;
+    Ok(contents)
+}
+
+fn check_xml(dest: &Path, path: &Path) -> Result<(), String> {
+    read_xml(dest, path).map(|_| ())
+}
+
+/// Checks that `contents` is a single, complete XML document.
+fn parse_xml(contents: &str) -> Result<(), String> {
+    let mut reader = Reader::from_str(contents);
+    let mut depth = 0usize;
+    let mut roots = 0;
+    loop {
+        match reader.read_event().map_err(|e| e.to_string())? {
+            Event::Start(_) => {
+                if depth == 0 {
+                    roots += 1;
+                }
+                depth += 1;
+            }
+            // Mismatched end tags are caught by the reader
+            Event::End(_) => depth -= 1,
+            Event::Empty(_) if depth == 0 => roots += 1,
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    match (depth, roots) {
+        (0, 1) => Ok(()),
+        (0, 0) => Err("there is no root element".to_string()),
+        (0, _) => Err("there is more than one root element".to_string()),
+        _ => Err("it ends before every element is closed".to_string()),
+    }
+}
+
+/// Reads `path` and checks that it is valid JSON.
+fn check_json(dest: &Path, path: &Path) -> Result<(), String> {
+    let contents = std::fs::read(dest.join(path))
+        .map_err(|e| format!("`{}` could not be read: {e}", path.display()))?;
+    serde_json::from_slice::<serde_json::Value>(&contents)
+        .map(|_| ())
+        .map_err(|e| format!("`{}` is not valid JSON: {e}", path.display()))
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::{check_json, check_page, check_sitemap, parse_xml};
+
+    #[test]
+    fn xml() {
+        assert_eq!(
+            parse_xml("<?xml version=\"1.0\"?><feed><entry/></feed>"),
+            Ok(())
+        );
+        assert!(parse_xml("<feed><entry></feed>").is_err());
+        assert_eq!(
+            parse_xml("<feed><entry/>"),
+            Err("it ends before every element is closed".to_string())
+        );
+        assert_eq!(
+            parse_xml("<feed/><feed/>"),
+            Err("there is more than one root element".to_string())
+        );
+        assert_eq!(parse_xml(""), Err("there is no root element".to_string()));
+    }
+
+    #[test]
+    fn output_files() {
+        let dest = tempfile::tempdir().unwrap();
+        let dest = dest.path();
+        std::fs::create_dir_all(dest.join("blog/hello")).unwrap();
+        std::fs::write(dest.join("blog/hello/index.html"), "<p>Hello</p>").unwrap();
+        std::fs::create_dir_all(dest.join("about")).unwrap();
+        std::fs::write(dest.join("about/index.html"), "").unwrap();
+        std::fs::write(
+            dest.join("sitemap.xml"),
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+             <urlset><url><loc>https://example.com/blog/hello/</loc></url>\
+             <url><loc>https://example.com/blog/gone/</loc></url></urlset>",
+        )
+        .unwrap();
+        std::fs::write(dest.join("feed.json"), "{\"items\": [}").unwrap();
+
+        let source = Path::new("_posts/2024-01-01-hello.md");
+        assert_eq!(
+            check_page(dest, Path::new("blog/hello/index.html"), source),
+            None
+        );
+        assert_eq!(
+            check_page(dest, Path::new("about/index.html"), Path::new("about.md")),
+            Some("`about/index.html`, generated from `about.md`, is empty".to_string())
+        );
+        assert!(check_page(dest, Path::new("missing/index.html"), source)
+            .is_some_and(|problem| problem.ends_with("is missing")));
+
+        assert_eq!(
+            check_sitemap(dest, "https://example.com"),
+            vec!["`sitemap.xml` lists `https://example.com/blog/gone/`, which has no file"]
+        );
+        assert!(check_json(dest, Path::new("feed.json"))
+            .is_err_and(|problem| problem.starts_with("`feed.json` is not valid JSON")));
+    }
+}
@@ -0,0 +1,146 @@
+//! Generating poster frames for the videos that pages embed
+//!
+//! The poster is written next to its video, as `<name>.poster.jpg`, by the
+//! command configured in the `[video]` section of `Site.toml`. Posters are
+//! kept in `.ebg-cache/posters`, named by a hash of the video and the command,
+//! so the command only runs for new or changed videos.
+
+use std::{
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+use crate::{
+    index::SiteMetadata,
+    renderer::{is_video, poster_url, RenderedSite},
+};
+
+use super::{
+    thumbnail::{local_dest, resolve_local},
+    writer::OutputWriter,
+    GeneratorError,
+};
+
+/// Where poster frames are kept, relative to the site root
+const POSTER_CACHE_DIR: &str = ".ebg-cache/posters";
+
+/// Runs the poster command for every local video on the pages the build
+/// includes.
+pub(crate) fn generate_posters(
+    site: &RenderedSite<'_>,
+    writer: &OutputWriter,
+) -> Result<(), GeneratorError> {
+    let Some(command) = site
+        .config()
+        .video
+        .as_ref()
+        .and_then(|config| config.poster_command.as_ref())
+    else {
+        return Ok(());
+    };
+
+    let videos: Vec<_> = site
+        .all_pages()
+        .filter(|page| site.includes(page.source()))
+        .flat_map(|page| {
+            video_sources(page.rendered_contents())
+                .into_iter()
+                .map(move |src| (page, src))
+        })
+        .collect();
+    let cache_dir = site.root_dir().join(POSTER_CACHE_DIR);
+    let dir = tempfile::tempdir().map_err(GeneratorError::PosterDir)?;
+    videos
+        .par_iter()
+        .enumerate()
+        .try_for_each(|(i, (page, src))| {
+            let Some(input) = resolve_local(site, *page, src) else {
+                return Ok(());
+            };
+            let dest = local_dest(*page, &poster_url(src));
+            let cached = cached_poster(&cache_dir, command, &input);
+            if let Some(cached) = cached.as_ref().filter(|cached| cached.is_file()) {
+                writer.copy(cached, dest);
+                return Ok(());
+            }
+            // Each video gets its own file, since the commands run in parallel
+            let output = dir.path().join(format!("{i}.jpg"));
+            run_poster_command(command, &input, &output)?;
+            if let Some(cached) = cached {
+                // The cache only saves time, so the build goes on without it
+                if let Err(e) = std::fs::create_dir_all(&cache_dir)
+                    .and_then(|()| std::fs::copy(&output, &cached))
+                {
+                    debug!("could not cache `{}`: {e}", cached.display());
+                }
+            }
+            writer.copy(&output, dest);
+            Ok(())
+        })
+}
+
+/// Returns where the poster that `command` makes for the video at `input` is
+/// cached, or `None` if the video can't be read.
+fn cached_poster(cache_dir: &Path, command: &[String], input: &Path) -> Option<PathBuf> {
+    let mut hasher = Sha256::new();
+    hasher.update(command.join("\0"));
+    io::copy(&mut File::open(input).ok()?, &mut hasher).ok()?;
+    Some(cache_dir.join(format!("{:x}.jpg", hasher.finalize())))
+}
+
+/// Returns the source of each video embedded in `html`.
+fn video_sources(html: &str) -> Vec<String> {
+    html.match_indices("<video src=\"")
+        .filter_map(|(start, tag)| {
+            let rest = &html[start + tag.len()..];
+            let src = rest[..rest.find('"')?]
+                .replace("&quot;", "\"")
+                .replace("&amp;", "&");
+            is_video(&src).then_some(src)
+        })
+        .collect()
+}
+
+fn run_poster_command(
+    command: &[String],
+    input: &Path,
+    output: &Path,
+) -> Result<(), GeneratorError> {
+    let Some((program, args)) = command.split_first() else {
+        return Err(GeneratorError::MissingPosterCommand);
+    };
+    let args: Vec<_> = args
+        .iter()
+        .map(|arg| {
+            arg.replace("{input}", &input.to_string_lossy())
+                .replace("{output}", &output.to_string_lossy())
+        })
+        .collect();
+    debug!("running {program} {args:?}");
+
+    let status = Command::new(program)
+        .args(&args)
+        .status()
+        .map_err(|e| GeneratorError::RunPosterCommand(program.clone(), e))?;
+    if !status.success() {
+        return Err(GeneratorError::PosterCommandFailed(input.into(), status));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::video_sources;
+
+    #[test]
+    fn find_videos() {
+        let html = r#"<p><video src="a.mp4" controls></video><video src="/clips/b&amp;c.webm"></video><img src="d.png"></p>"#;
+        assert_eq!(video_sources(html), ["a.mp4", "/clips/b&c.webm"]);
+    }
+}
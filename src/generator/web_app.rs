@@ -0,0 +1,224 @@
+//! Generating a web app manifest and an offline-caching service worker
+
+use std::path::Path;
+
+use serde::Deserialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    index::{PageMetadata, SiteMetadata},
+    renderer::RenderedSite,
+};
+
+use super::favicon::PNG_ICONS;
+
+/// The `[web_app]` section of `Site.toml`
+#[derive(Deserialize, Debug)]
+pub struct WebAppConfig {
+    /// Defaults to the site's title
+    pub name: Option<String>,
+    pub short_name: Option<String>,
+    pub theme_color: Option<String>,
+    pub background_color: Option<String>,
+    #[serde(default = "default_display")]
+    pub display: String,
+    /// Whether to generate `sw.js`, which caches pages for offline reading
+    #[serde(default = "default_true")]
+    pub service_worker: bool,
+    /// How many of the most recent posts the service worker caches up front
+    #[serde(default = "default_recent_posts")]
+    pub recent_posts: usize,
+    /// Extra URLs for the service worker to cache up front
+    #[serde(default)]
+    pub precache: Vec<String>,
+}
+
+fn default_display() -> String {
+    "standalone".into()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_recent_posts() -> usize {
+    5
+}
+
+pub(crate) fn generate_manifest(site: &RenderedSite, config: &WebAppConfig) -> String {
+    let name = config.name.as_deref().unwrap_or(site.title());
+    let mut manifest = json!({
+        "name": name,
+        "short_name": config.short_name.as_deref().unwrap_or(name),
+        "start_url": "/",
+        "display": config.display,
+    });
+    if let Some(color) = &config.theme_color {
+        manifest["theme_color"] = json!(color);
+    }
+    if let Some(color) = &config.background_color {
+        manifest["background_color"] = json!(color);
+    }
+    if site.config().favicon.is_some() {
+        manifest["icons"] = PNG_ICONS
+            .iter()
+            .filter(|(name, _)| name.starts_with("android-chrome"))
+            .map(|(name, size)| {
+                json!({
+                    "src": format!("/{name}"),
+                    "sizes": format!("{size}x{size}"),
+                    "type": "image/png",
+                })
+            })
+            .collect();
+    }
+    serde_json::to_string_pretty(&manifest).unwrap()
+}
+
+/// Lists the URLs the service worker caches when it is installed.
+fn precache_urls(site: &RenderedSite, config: &WebAppConfig) -> Vec<String> {
+    let mut urls = vec!["/".to_string()];
+
+    let mut posts: Vec<_> = site.posts().collect();
+    posts.sort_by_key(|post| std::cmp::Reverse(post.publish_date()));
+    urls.extend(
+        posts
+            .iter()
+            .take(config.recent_posts)
+            .map(|post| format!("/{}", post.url())),
+    );
+
+    // Stylesheets and scripts are needed to display any of the pages
+    urls.extend(site.raw_files().filter_map(|file| {
+        let relative = file.strip_prefix(site.root_dir()).ok()?;
+        matches!(
+            relative.extension().and_then(|ext| ext.to_str()),
+            Some("css" | "js")
+        )
+        .then(|| format!("/{}", relative.to_string_lossy().replace('\\', "/")))
+    }));
+
+    urls.extend(config.precache.iter().cloned());
+    urls
+}
+
+/// Generates the service worker script.
+///
+/// The cache name includes a hash of the precached files in `dest`, so the
+/// service worker changes, and browsers refresh their cache, whenever any of
+/// those files change.
+pub(crate) fn generate_service_worker(
+    site: &RenderedSite,
+    config: &WebAppConfig,
+    dest: &Path,
+) -> String {
+    let urls = precache_urls(site, config);
+
+    let mut hasher = Sha256::new();
+    for url in &urls {
+        let mut path = dest.join(url.trim_start_matches('/'));
+        if url.ends_with('/') {
+            path.push("index.html");
+        }
+        if let Ok(contents) = std::fs::read(&path) {
+            hasher.update(url.as_bytes());
+            hasher.update(contents);
+        }
+    }
+    let version = &format!("{:x}", hasher.finalize())[..16];
+
+    format!(
+        r#"const CACHE = "ebg-{version}";
+const PRECACHE = {};
+
+self.addEventListener("install", (event) => {{
+  event.waitUntil(caches.open(CACHE).then((cache) => cache.addAll(PRECACHE)));
+  self.skipWaiting();
+}});
+
+self.addEventListener("activate", (event) => {{
+  event.waitUntil(
+    caches.keys().then((keys) =>
+      Promise.all(keys.filter((key) => key !== CACHE).map((key) => caches.delete(key)))
+    ).then(() => self.clients.claim())
+  );
+}});
+
+// Prefer the network so readers see the latest version, but fall back on the
+// cache when offline.
+self.addEventListener("fetch", (event) => {{
+  const request = event.request;
+  if (request.method !== "GET" || new URL(request.url).origin !== self.location.origin) {{
+    return;
+  }}
+  event.respondWith(
+    fetch(request)
+      .then((response) => {{
+        const copy = response.clone();
+        caches.open(CACHE).then((cache) => cache.put(request, copy));
+        return response;
+      }})
+      .catch(() => caches.match(request))
+  );
+}});
+"#,
+        json!(urls)
+    )
+}
+
+/// The tags that link the manifest and register the service worker
+pub(crate) fn web_app_markup(config: &WebAppConfig) -> String {
+    let mut markup = vec![r#"<link rel="manifest" href="/site.webmanifest">"#.to_string()];
+    if let Some(color) = &config.theme_color {
+        markup.push(format!(
+            r#"<meta name="theme-color" content="{}">"#,
+            tera::escape_html(color)
+        ));
+    }
+    if config.service_worker {
+        markup.push(
+            r#"<script>if ("serviceWorker" in navigator) { navigator.serviceWorker.register("/sw.js"); }</script>"#
+                .to_string(),
+        );
+    }
+    markup.join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::index::{PageSource, SiteIndex, SourceFormat};
+
+    use super::{precache_urls, WebAppConfig};
+
+    #[test]
+    fn precache_recent_posts() -> miette::Result<()> {
+        let mut site = SiteIndex::default();
+        for path in [
+            "_posts/2012-10-14-first.md",
+            "_posts/2013-10-14-second.md",
+            "_posts/2014-10-14-third.md",
+        ] {
+            site.add_page(PageSource::from_string(path, SourceFormat::Markdown, ""));
+        }
+        let site = site.render()?;
+        let config: WebAppConfig = toml::from_str(
+            r#"
+recent_posts = 2
+precache = ["/offline/"]
+"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            precache_urls(&site, &config),
+            [
+                "/",
+                "/blog/2014/10/14/third/",
+                "/blog/2013/10/14/second/",
+                "/offline/"
+            ]
+        );
+        Ok(())
+    }
+}
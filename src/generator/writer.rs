@@ -0,0 +1,292 @@
+//! Writing generated files into the destination directory
+//!
+//! Pages are generated in parallel, so the writer is shared between threads.
+//! Rather than stopping at the first failure, it records every file that could
+//! not be written and reports them all at the end of the build.
+
+use std::{
+    collections::HashSet,
+    fs::{File, FileTimes},
+    io::{self, ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+
+use miette::Diagnostic;
+use thiserror::Error;
+use tracing::{debug, instrument, warn};
+
+/// How many times to try an operation that fails with a transient error
+const ATTEMPTS: u32 = 3;
+
+#[derive(Diagnostic, Debug, Error)]
+#[error("writing `{}`", .path.display())]
+pub struct WriteFailure {
+    path: PathBuf,
+    #[source]
+    source: io::Error,
+}
+
+#[derive(Diagnostic, Debug, Error)]
+#[error("{} file(s) could not be written", .failures.len())]
+pub struct WriteErrors {
+    #[related]
+    failures: Vec<WriteFailure>,
+}
+
+pub(crate) struct OutputWriter {
+    root: PathBuf,
+    /// Whether to flush every file to disk before finishing
+    fsync: bool,
+    failures: Mutex<Vec<WriteFailure>>,
+    /// Directories that need to be synced when `fsync` is set
+    dirs: Mutex<HashSet<PathBuf>>,
+}
+
+impl OutputWriter {
+    pub fn new(root: impl Into<PathBuf>, fsync: bool) -> Self {
+        Self {
+            root: root.into(),
+            fsync,
+            failures: Mutex::default(),
+            dirs: Mutex::default(),
+        }
+    }
+
+    /// Writes `contents` to `path`, relative to the destination directory.
+    ///
+    /// Failures are recorded and reported by [`OutputWriter::finish`].
+    #[instrument(level = "debug", skip_all, fields(path = %path.as_ref().display()))]
+    pub fn write(&self, path: impl AsRef<Path>, contents: impl AsRef<[u8]>) {
+        let dest = self.root.join(path);
+        let result = retry(|| {
+            self.create_parent(&dest)?;
+            let mut file = File::create(&dest)?;
+            file.write_all(contents.as_ref())?;
+            if self.fsync {
+                file.sync_all()?;
+            }
+            Ok(())
+        });
+        self.record(dest, result);
+    }
+
+    /// Copies the file at `from` to `path`, relative to the destination
+    /// directory.
+    ///
+    /// The copy keeps the permissions and modification time of the original,
+    /// so executable files stay executable and sync tools can tell which files
+    /// have not changed.
+    pub fn copy(&self, from: &Path, path: impl AsRef<Path>) {
+        let dest = self.root.join(path);
+        debug!("copying {} to {}", from.display(), dest.display());
+        let result = retry(|| {
+            self.create_parent(&dest)?;
+            // A read-only copy from an earlier build can't be opened for
+            // writing, and a symlink would be written through
+            if dest.symlink_metadata().is_ok() {
+                std::fs::remove_file(&dest)?;
+            }
+            let metadata = std::fs::metadata(from)?;
+            let mut file = File::create(&dest)?;
+            io::copy(&mut File::open(from)?, &mut file)?;
+            file.set_times(
+                FileTimes::new()
+                    .set_accessed(metadata.accessed()?)
+                    .set_modified(metadata.modified()?),
+            )?;
+            if self.fsync {
+                file.sync_all()?;
+            }
+            // Only now, since setting the times needs write access
+            std::fs::set_permissions(&dest, metadata.permissions())
+        });
+        self.record(dest, result);
+    }
+
+    /// Creates a symbolic link at `path`, relative to the destination
+    /// directory, that points to `target`.
+    pub fn symlink(&self, target: &Path, path: impl AsRef<Path>) {
+        let dest = self.root.join(path);
+        debug!("linking {} to {}", dest.display(), target.display());
+        let result = retry(|| {
+            self.create_parent(&dest)?;
+            if dest.symlink_metadata().is_ok() {
+                std::fs::remove_file(&dest)?;
+            }
+            symlink(target, &dest)
+        });
+        self.record(dest, result);
+    }
+
+    fn create_parent(&self, dest: &Path) -> io::Result<()> {
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent)?;
+            if self.fsync {
+                self.dirs.lock().unwrap().insert(parent.to_path_buf());
+            }
+        }
+        Ok(())
+    }
+
+    fn record(&self, path: PathBuf, result: io::Result<()>) {
+        if let Err(source) = result {
+            self.failures
+                .lock()
+                .unwrap()
+                .push(WriteFailure { path, source });
+        }
+    }
+
+    /// Syncs the directories that were written to, if requested, and reports
+    /// any writes that failed.
+    pub fn finish(self) -> Result<(), WriteErrors> {
+        let mut failures = self.failures.into_inner().unwrap();
+        for dir in self.dirs.into_inner().unwrap() {
+            if let Err(source) = sync_dir(&dir) {
+                failures.push(WriteFailure { path: dir, source });
+            }
+        }
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            failures.sort_by(|a, b| a.path.cmp(&b.path));
+            Err(WriteErrors { failures })
+        }
+    }
+}
+
+/// Retries `f` if it fails with an error that might go away on its own.
+fn retry(mut f: impl FnMut() -> io::Result<()>) -> io::Result<()> {
+    let mut attempt = 1;
+    loop {
+        match f() {
+            Err(e) if attempt < ATTEMPTS && is_transient(&e) => {
+                warn!("retrying after transient error: {e}");
+                thread::sleep(Duration::from_millis(10 << attempt));
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn is_transient(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        ErrorKind::Interrupted
+            | ErrorKind::WouldBlock
+            | ErrorKind::TimedOut
+            | ErrorKind::ResourceBusy
+    )
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    // Directories can't be opened as files on other platforms
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        fs::File,
+        time::{Duration, SystemTime},
+    };
+
+    use super::OutputWriter;
+
+    #[test]
+    fn collect_all_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let writer = OutputWriter::new(dir.path(), true);
+        writer.write("a/index.html", "hello");
+        // `a/index.html` is a file, so these can't be written
+        writer.write("a/index.html/b.html", "");
+        writer.copy(&dir.path().join("missing"), "c.html");
+
+        let errors = writer.finish().unwrap_err();
+        assert_eq!(errors.failures.len(), 2);
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("a/index.html")).unwrap(),
+            "hello"
+        );
+    }
+
+    #[test]
+    fn copy_preserves_metadata() {
+        let src = tempfile::tempdir().unwrap();
+        let from = src.path().join("script.sh");
+        std::fs::write(&from, "#!/bin/sh\n").unwrap();
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        File::options()
+            .write(true)
+            .open(&from)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&from, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+
+        let dest = tempfile::tempdir().unwrap();
+        let writer = OutputWriter::new(dest.path(), false);
+        writer.copy(&from, "bin/script.sh");
+        writer.finish().unwrap();
+
+        let metadata = std::fs::metadata(dest.path().join("bin/script.sh")).unwrap();
+        assert_eq!(metadata.modified().unwrap(), modified);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o755);
+        }
+    }
+
+    #[test]
+    fn copy_read_only() {
+        let src = tempfile::tempdir().unwrap();
+        let from = src.path().join("LICENSE");
+        std::fs::write(&from, "MIT").unwrap();
+        let mut permissions = std::fs::metadata(&from).unwrap().permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(&from, permissions).unwrap();
+
+        let dest = tempfile::tempdir().unwrap();
+        // Twice, since the second build finds a read-only file in the way
+        for _ in 0..2 {
+            let writer = OutputWriter::new(dest.path(), true);
+            writer.copy(&from, "LICENSE");
+            writer.finish().unwrap();
+        }
+
+        let copy = dest.path().join("LICENSE");
+        assert_eq!(std::fs::read_to_string(&copy).unwrap(), "MIT");
+        let metadata = std::fs::metadata(&copy).unwrap();
+        assert!(metadata.permissions().readonly());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(metadata.permissions().mode() & 0o777, 0o444);
+        }
+    }
+}
@@ -0,0 +1,105 @@
+//! Finding the links in HTML
+//!
+//! The audits, the health page, feeds, and link checking all need to know
+//! where pages link to, so they share this scanner.
+
+use lol_html::{element, rewrite_str, RewriteStrSettings};
+
+/// The element a link comes from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum LinkKind {
+    /// `<a href>`
+    Anchor,
+    /// `<link href>`, such as stylesheets and feeds
+    Link,
+    /// `<img src>`
+    Image,
+}
+
+/// What a page links to
+#[derive(Debug, Default)]
+pub(crate) struct PageLinks {
+    /// Each link's kind and target, in the order they appear
+    links: Vec<(LinkKind, String)>,
+    /// The number of images without alt text
+    pub images_without_alt: usize,
+    /// Whether the page redirects elsewhere with `<meta http-equiv="refresh">`
+    pub redirect: bool,
+}
+
+impl PageLinks {
+    pub fn scan(html: &str) -> Self {
+        let mut links = vec![];
+        let mut images_without_alt = 0;
+        let mut redirect = false;
+        let mut push = |kind, target: Option<String>| {
+            links.extend(target.map(|target| (kind, target)));
+        };
+        // Errors only mean the rest of the page couldn't be scanned
+        let _ = rewrite_str(
+            html,
+            RewriteStrSettings {
+                element_content_handlers: vec![
+                    element!("a[href], link[href], img", |el| {
+                        match el.tag_name().as_str() {
+                            "a" => push(LinkKind::Anchor, el.get_attribute("href")),
+                            "link" => push(LinkKind::Link, el.get_attribute("href")),
+                            _ => {
+                                if !el.has_attribute("alt") {
+                                    images_without_alt += 1;
+                                }
+                                push(LinkKind::Image, el.get_attribute("src"));
+                            }
+                        }
+                        Ok(())
+                    }),
+                    element!("meta[http-equiv]", |el| {
+                        redirect |= el
+                            .get_attribute("http-equiv")
+                            .is_some_and(|value| value.eq_ignore_ascii_case("refresh"));
+                        Ok(())
+                    }),
+                ],
+                ..RewriteStrSettings::new()
+            },
+        );
+        Self {
+            links,
+            images_without_alt,
+            redirect,
+        }
+    }
+
+    /// Returns the targets of the links of the given kinds, in order.
+    pub fn targets<'a>(&'a self, kinds: &'a [LinkKind]) -> impl Iterator<Item = &'a str> + 'a {
+        self.links
+            .iter()
+            .filter(|(kind, _)| kinds.contains(kind))
+            .map(|(_, target)| target.as_str())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{LinkKind, PageLinks};
+
+    #[test]
+    fn scan() {
+        let links = PageLinks::scan(
+            r#"<link rel="stylesheet" href="/style.css"><meta http-equiv="Refresh" content="0">
+               <a href="/a/">A</a><img src="b.png"><a name="top"></a><img src="c.png" alt="C">"#,
+        );
+        assert_eq!(
+            links.targets(&[LinkKind::Anchor]).collect::<Vec<_>>(),
+            ["/a/"]
+        );
+        assert_eq!(
+            links
+                .targets(&[LinkKind::Anchor, LinkKind::Image, LinkKind::Link])
+                .collect::<Vec<_>>(),
+            ["/style.css", "/a/", "b.png", "c.png"]
+        );
+        assert_eq!(links.images_without_alt, 1);
+        assert!(links.redirect);
+    }
+}
@@ -0,0 +1,473 @@
+//! A shared HTTP client for everything that talks to the network
+//!
+//! Requests to each host are spaced out so EBG never floods a server, and requests that
+//! fail in ways that might be temporary are retried with exponential backoff.
+//!
+//! `GET` requests made with [`HttpClient::get`] are cached on disk. A cached
+//! response is reused until it is older than the `max-age` the server gave it,
+//! and after that it is revalidated with its `ETag` or `Last-Modified` date so
+//! unchanged responses don't have to be downloaded again.
+//!
+//! In offline mode (`ebg --offline`), no requests are sent at all. Cached
+//! responses are used no matter how old they are, and anything else fails
+//! right away with [`HttpError::Offline`].
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use miette::Diagnostic;
+use reqwest::{header, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+use tokio::time::Instant;
+use tracing::debug;
+
+/// The `[http]` section of `Site.toml`
+#[derive(Deserialize, Debug)]
+pub struct HttpConfig {
+    /// Where cached responses are kept, relative to the site root
+    #[serde(default = "default_cache_dir")]
+    pub cache_dir: PathBuf,
+    /// How long to reuse responses that don't say how long they are fresh
+    #[serde(default = "default_max_age")]
+    pub default_max_age: u64,
+    /// The most requests to make to each host each second
+    #[serde(default = "default_requests_per_second")]
+    pub requests_per_second: f64,
+    /// How many times to retry a request that failed
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            cache_dir: default_cache_dir(),
+            default_max_age: default_max_age(),
+            requests_per_second: default_requests_per_second(),
+            retries: default_retries(),
+        }
+    }
+}
+
+fn default_cache_dir() -> PathBuf {
+    ".ebg-cache/http".into()
+}
+
+fn default_max_age() -> u64 {
+    24 * 60 * 60
+}
+
+fn default_requests_per_second() -> f64 {
+    5.0
+}
+
+fn default_retries() -> u32 {
+    3
+}
+
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether clients created after this are allowed to use the network.
+pub fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+#[derive(Diagnostic, Debug, Error)]
+pub enum HttpError {
+    #[error("requesting `{0}`")]
+    Request(String, #[source] reqwest::Error),
+    #[error("`{0}` responded with {1}")]
+    Status(String, StatusCode),
+    #[error("writing to the HTTP cache at `{}`", .0.display())]
+    WriteCache(PathBuf, #[source] std::io::Error),
+    #[error("cannot request `{0}` in offline mode")]
+    #[diagnostic(help("run again without `--offline` to allow network access"))]
+    Offline(String),
+}
+
+/// A response to a `GET` request, which may have come from the cache
+#[derive(Debug, Clone)]
+pub struct CachedResponse {
+    pub status: StatusCode,
+    pub body: Vec<u8>,
+    /// Whether the response came from the cache without asking the server
+    pub from_cache: bool,
+}
+
+/// What is stored alongside each cached body
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct CacheEntry {
+    url: String,
+    status: u16,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    /// When the response was last fetched or revalidated
+    fetched: DateTime<Utc>,
+    /// How many seconds the response is fresh for
+    max_age: u64,
+}
+
+impl CacheEntry {
+    fn is_fresh(&self, now: DateTime<Utc>) -> bool {
+        now < self.fetched + chrono::Duration::seconds(self.max_age as i64)
+    }
+}
+
+pub struct HttpClient {
+    client: reqwest::Client,
+    cache_dir: PathBuf,
+    default_max_age: u64,
+    retries: u32,
+    /// The time between requests to the same host
+    interval: Duration,
+    /// When the latest request to each host is due to be sent
+    last_request: Mutex<HashMap<String, Instant>>,
+    /// Whether to only use cached responses
+    offline: bool,
+}
+
+impl HttpClient {
+    /// Creates a client for the site at `root_dir`.
+    pub fn new(config: &HttpConfig, root_dir: &Path) -> Self {
+        let interval = if config.requests_per_second > 0.0 {
+            Duration::from_secs_f64(1.0 / config.requests_per_second)
+        } else {
+            Duration::ZERO
+        };
+        Self {
+            client: reqwest::Client::builder()
+                .user_agent(concat!("ebg/", env!("CARGO_PKG_VERSION")))
+                .build()
+                .unwrap(),
+            cache_dir: root_dir.join(&config.cache_dir),
+            default_max_age: config.default_max_age,
+            retries: config.retries,
+            interval,
+            last_request: Mutex::new(HashMap::new()),
+            offline: OFFLINE.load(Ordering::Relaxed),
+        }
+    }
+
+    /// The underlying client, for building requests to pass to
+    /// [`HttpClient::send`]
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    /// Sends a request, waiting for the rate limit and retrying temporary
+    /// failures. Responses with an error status are returned as errors.
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response, HttpError> {
+        let response = self.execute(request).await?;
+        let url = response.url().to_string();
+        match response.error_for_status_ref() {
+            Ok(_) => Ok(response),
+            Err(_) => Err(HttpError::Status(url, response.status())),
+        }
+    }
+
+    /// Fetches `url`, using the cached response if it is still fresh.
+    ///
+    /// Unlike [`HttpClient::send`], responses with an error status are
+    /// returned normally, since callers such as link checkers care about them.
+    pub async fn get(&self, url: &str) -> Result<CachedResponse, HttpError> {
+        let cached = self.load(url);
+        if let Some((entry, body)) = &cached {
+            if self.offline || entry.is_fresh(Utc::now()) {
+                debug!("using cached response for `{url}`");
+                return Ok(CachedResponse {
+                    status: StatusCode::from_u16(entry.status).unwrap_or(StatusCode::OK),
+                    body: body.clone(),
+                    from_cache: true,
+                });
+            }
+        }
+        if self.offline {
+            return Err(HttpError::Offline(url.to_string()));
+        }
+
+        let mut request = self.client.get(url);
+        if let Some((entry, _)) = &cached {
+            if let Some(etag) = &entry.etag {
+                request = request.header(header::IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &entry.last_modified {
+                request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+        let response = self.execute(request).await?;
+        let max_age = max_age(response.headers(), self.default_max_age);
+
+        if let (StatusCode::NOT_MODIFIED, Some((mut entry, body))) = (response.status(), cached) {
+            debug!("cached response for `{url}` is still valid");
+            entry.fetched = Utc::now();
+            entry.max_age = max_age.unwrap_or(entry.max_age);
+            self.store(&entry, None)?;
+            return Ok(CachedResponse {
+                status: StatusCode::from_u16(entry.status).unwrap_or(StatusCode::OK),
+                body,
+                from_cache: true,
+            });
+        }
+
+        let status = response.status();
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value: &header::HeaderValue| value.to_str().ok())
+                .map(str::to_string)
+        };
+        let entry = CacheEntry {
+            url: url.to_string(),
+            status: status.as_u16(),
+            etag: header(header::ETAG),
+            last_modified: header(header::LAST_MODIFIED),
+            fetched: Utc::now(),
+            max_age: max_age.unwrap_or(0),
+        };
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| HttpError::Request(url.to_string(), e))?
+            .to_vec();
+        // Server errors are likely temporary, so they aren't worth keeping
+        if max_age.is_some() && !status.is_server_error() {
+            self.store(&entry, Some(&body))?;
+        }
+        Ok(CachedResponse {
+            status,
+            body,
+            from_cache: false,
+        })
+    }
+
+    /// Sends a request, waiting for the rate limit and retrying temporary
+    /// failures, and returns the response whatever its status.
+    async fn execute(&self, mut request: RequestBuilder) -> Result<Response, HttpError> {
+        let mut attempt = 0;
+        loop {
+            // Requests with a streaming body can't be copied, so they are only
+            // tried once
+            let retry = if attempt < self.retries {
+                request.try_clone()
+            } else {
+                None
+            };
+            let (client, built) = request.build_split();
+            let built = built.map_err(|e| HttpError::Request(String::new(), e))?;
+            let url = built.url().to_string();
+            if self.offline {
+                return Err(HttpError::Offline(url));
+            }
+
+            self.wait_turn(built.url().host_str().unwrap_or_default())
+                .await;
+            let result = client.execute(built).await;
+            let delay = match &result {
+                Ok(response) if is_temporary(response.status()) => {
+                    Some(retry_after(response.headers()))
+                }
+                Ok(_) => None,
+                Err(e) if e.is_connect() || e.is_timeout() => Some(None),
+                Err(_) => None,
+            };
+            match (delay, retry) {
+                (Some(delay), Some(retry)) => {
+                    let delay =
+                        delay.unwrap_or_else(|| Duration::from_millis(500 * 2u64.pow(attempt)));
+                    debug!("retrying `{url}` in {delay:?}");
+                    tokio::time::sleep(delay).await;
+                    request = retry;
+                    attempt += 1;
+                }
+                _ => return result.map_err(|e| HttpError::Request(url, e)),
+            }
+        }
+    }
+
+    /// Waits until enough time has passed since the last request to `host`.
+    ///
+    /// Each caller reserves its slot before sleeping, so requests to other
+    /// hosts don't wait behind it.
+    async fn wait_turn(&self, host: &str) {
+        let now = Instant::now();
+        let turn = {
+            let mut last_request = self.last_request.lock().unwrap();
+            let turn = match last_request.get(host) {
+                Some(last) => (*last + self.interval).max(now),
+                None => now,
+            };
+            last_request.insert(host.to_string(), turn);
+            turn
+        };
+        tokio::time::sleep_until(turn).await;
+    }
+
+    fn cache_paths(&self, url: &str) -> (PathBuf, PathBuf) {
+        let key = format!("{:x}", Sha256::digest(url));
+        (
+            self.cache_dir.join(format!("{key}.json")),
+            self.cache_dir.join(format!("{key}.body")),
+        )
+    }
+
+    fn load(&self, url: &str) -> Option<(CacheEntry, Vec<u8>)> {
+        let (entry_path, body_path) = self.cache_paths(url);
+        let entry: CacheEntry = serde_json::from_slice(&std::fs::read(entry_path).ok()?).ok()?;
+        // Guard against hash collisions
+        if entry.url != url {
+            return None;
+        }
+        Some((entry, std::fs::read(body_path).ok()?))
+    }
+
+    /// Saves a cache entry, along with its body unless it is unchanged.
+    fn store(&self, entry: &CacheEntry, body: Option<&[u8]>) -> Result<(), HttpError> {
+        let (entry_path, body_path) = self.cache_paths(&entry.url);
+        let write = |path: &Path, contents: &[u8]| {
+            std::fs::create_dir_all(&self.cache_dir)
+                .and_then(|()| std::fs::write(path, contents))
+                .map_err(|e| HttpError::WriteCache(path.into(), e))
+        };
+        if let Some(body) = body {
+            write(&body_path, body)?;
+        }
+        write(&entry_path, &serde_json::to_vec(entry).unwrap())
+    }
+}
+
+/// Whether a request that got this status may succeed if tried again
+fn is_temporary(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Reads how long the server asked us to wait from `Retry-After`, if it is
+/// given in seconds.
+fn retry_after(headers: &header::HeaderMap) -> Option<Duration> {
+    let seconds = headers.get(header::RETRY_AFTER)?.to_str().ok()?;
+    Some(Duration::from_secs(seconds.trim().parse().ok()?))
+}
+
+/// Reads how many seconds a response may be cached for from `Cache-Control`.
+///
+/// Returns `None` if the response must not be cached, and `default` if the
+/// server doesn't say.
+fn max_age(headers: &header::HeaderMap, default: u64) -> Option<u64> {
+    let Some(cache_control) = headers
+        .get(header::CACHE_CONTROL)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return Some(default);
+    };
+    let mut max_age = default;
+    for directive in cache_control.split(',').map(str::trim) {
+        match directive.split_once('=') {
+            _ if directive.eq_ignore_ascii_case("no-store") => return None,
+            _ if directive.eq_ignore_ascii_case("no-cache") => max_age = 0,
+            Some((name, value)) if name.eq_ignore_ascii_case("max-age") => {
+                max_age = value.trim_matches('"').parse().unwrap_or(default);
+            }
+            _ => {}
+        }
+    }
+    Some(max_age)
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::{TimeZone, Utc};
+    use reqwest::header::{HeaderMap, HeaderValue, CACHE_CONTROL};
+
+    use super::{max_age, CacheEntry, HttpClient, HttpConfig, HttpError};
+
+    #[test]
+    fn cache_control() {
+        let headers = |value| {
+            let mut headers = HeaderMap::new();
+            headers.insert(CACHE_CONTROL, HeaderValue::from_static(value));
+            headers
+        };
+        assert_eq!(max_age(&HeaderMap::new(), 60), Some(60));
+        assert_eq!(max_age(&headers("public, max-age=3600"), 60), Some(3600));
+        assert_eq!(max_age(&headers("no-cache"), 60), Some(0));
+        assert_eq!(max_age(&headers("private, no-store"), 60), None);
+    }
+
+    #[test]
+    fn cache_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = HttpClient::new(&HttpConfig::default(), dir.path());
+        let fetched = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let entry = CacheEntry {
+            url: "https://example.com/".into(),
+            status: 200,
+            etag: Some("\"abc\"".into()),
+            last_modified: None,
+            fetched,
+            max_age: 60,
+        };
+        client.store(&entry, Some(b"hello")).unwrap();
+
+        let (loaded, body) = client.load("https://example.com/").unwrap();
+        assert_eq!(loaded, entry);
+        assert_eq!(body, b"hello");
+        assert!(client.load("https://example.org/").is_none());
+
+        assert!(entry.is_fresh(fetched + chrono::Duration::seconds(59)));
+        assert!(!entry.is_fresh(fetched + chrono::Duration::seconds(60)));
+    }
+
+    #[tokio::test]
+    async fn offline() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut client = HttpClient::new(&HttpConfig::default(), dir.path());
+        client.offline = true;
+        let entry = CacheEntry {
+            url: "https://example.com/".into(),
+            status: 200,
+            etag: None,
+            last_modified: None,
+            fetched: Utc.with_ymd_and_hms(2000, 1, 1, 0, 0, 0).unwrap(),
+            max_age: 60,
+        };
+        client.store(&entry, Some(b"stale")).unwrap();
+
+        // Stale responses are still used
+        let response = client.get("https://example.com/").await.unwrap();
+        assert_eq!(response.body, b"stale");
+        assert!(response.from_cache);
+
+        assert!(matches!(
+            client.get("https://example.org/").await,
+            Err(HttpError::Offline(_))
+        ));
+        assert!(matches!(
+            client
+                .send(client.client().get("https://example.org/"))
+                .await,
+            Err(HttpError::Offline(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rate_limit_per_host() {
+        let dir = tempfile::tempdir().unwrap();
+        let client = HttpClient::new(&HttpConfig::default(), dir.path());
+        let start = tokio::time::Instant::now();
+        client.wait_turn("a.example").await;
+        client.wait_turn("b.example").await;
+        assert!(start.elapsed() < client.interval);
+        client.wait_turn("a.example").await;
+        assert!(start.elapsed() >= client.interval);
+    }
+}
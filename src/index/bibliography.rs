@@ -0,0 +1,218 @@
+//! Loading the works that posts can cite
+//!
+//! Bibliographies can be CSL-JSON, as exported by most reference managers, or
+//! BibTeX. Only the fields needed to format a simple reference are read.
+
+use std::path::Path;
+
+use serde::Deserialize;
+
+use super::IndexError;
+
+/// A work that can be cited
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    pub key: String,
+    /// The authors' family names, or full names if they can't be split
+    pub authors: Vec<String>,
+    pub title: Option<String>,
+    pub year: Option<String>,
+    /// The journal, book, or site the work appeared in
+    pub container: Option<String>,
+    pub url: Option<String>,
+}
+
+pub(super) fn load_bibliography(path: &Path) -> Result<Vec<Reference>, IndexError> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| IndexError::ReadingBibliography(path.to_path_buf(), e))?;
+    if path.extension().is_some_and(|ext| ext == "bib") {
+        Ok(parse_bibtex(&contents))
+    } else {
+        let items: Vec<CslItem> = serde_json::from_str(&contents)
+            .map_err(|e| IndexError::ParsingBibliography(path.to_path_buf(), Box::new(e)))?;
+        Ok(items.into_iter().map(Reference::from).collect())
+    }
+}
+
+#[derive(Deserialize)]
+struct CslItem {
+    id: String,
+    title: Option<String>,
+    #[serde(default)]
+    author: Vec<CslName>,
+    issued: Option<CslDate>,
+    #[serde(rename = "container-title")]
+    container_title: Option<String>,
+    #[serde(rename = "URL")]
+    url: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CslName {
+    family: Option<String>,
+    literal: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CslDate {
+    #[serde(rename = "date-parts", default)]
+    date_parts: Vec<Vec<serde_json::Value>>,
+}
+
+impl From<CslItem> for Reference {
+    fn from(item: CslItem) -> Self {
+        Self {
+            key: item.id,
+            authors: item
+                .author
+                .into_iter()
+                .filter_map(|name| name.family.or(name.literal))
+                .collect(),
+            title: item.title,
+            year: item
+                .issued
+                .and_then(|date| date.date_parts.into_iter().next())
+                .and_then(|parts| parts.into_iter().next())
+                .map(|year| match year {
+                    serde_json::Value::String(year) => year,
+                    year => year.to_string(),
+                }),
+            container: item.container_title,
+            url: item.url,
+        }
+    }
+}
+
+/// Reads the entries in a BibTeX file, skipping anything that can't be parsed.
+fn parse_bibtex(contents: &str) -> Vec<Reference> {
+    let mut references = vec![];
+    let mut rest = contents;
+    while let Some(at) = rest.find('@') {
+        rest = &rest[at + 1..];
+        let Some(open) = rest.find('{') else {
+            break;
+        };
+        let kind = rest[..open].trim().to_lowercase();
+        rest = &rest[open + 1..];
+        if matches!(kind.as_str(), "comment" | "preamble" | "string") {
+            continue;
+        }
+        let Some(comma) = rest.find(',') else {
+            break;
+        };
+        let key = rest[..comma].trim().to_string();
+        rest = &rest[comma + 1..];
+
+        let mut reference = Reference {
+            key,
+            authors: vec![],
+            title: None,
+            year: None,
+            container: None,
+            url: None,
+        };
+        // Read `name = value` pairs until the closing brace of the entry
+        loop {
+            rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+            if rest.is_empty() || rest.starts_with('}') {
+                rest = rest.get(1..).unwrap_or("");
+                break;
+            }
+            let Some(eq) = rest.find('=') else {
+                rest = "";
+                break;
+            };
+            let name = rest[..eq].trim().to_lowercase();
+            rest = rest[eq + 1..].trim_start();
+            let (value, remaining) = bibtex_value(rest);
+            rest = remaining;
+            match name.as_str() {
+                "author" => {
+                    reference.authors = value.split(" and ").map(family_name).collect();
+                }
+                "title" => reference.title = Some(value),
+                "year" => reference.year = Some(value),
+                "journal" | "booktitle" | "publisher" | "howpublished" => {
+                    reference.container.get_or_insert(value);
+                }
+                "url" => reference.url = Some(value),
+                _ => {}
+            }
+        }
+        references.push(reference);
+    }
+    references
+}
+
+/// Reads a braced, quoted, or bare BibTeX value, returning it without its
+/// delimiters along with the rest of the input.
+fn bibtex_value(input: &str) -> (String, &str) {
+    let (value, rest) = if let Some(inner) = input.strip_prefix('{') {
+        let mut depth = 1;
+        let end = inner
+            .char_indices()
+            .find(|(_, c)| {
+                match c {
+                    '{' => depth += 1,
+                    '}' => depth -= 1,
+                    _ => {}
+                }
+                depth == 0
+            })
+            .map_or(inner.len(), |(i, _)| i);
+        (&inner[..end], inner.get(end + 1..).unwrap_or(""))
+    } else if let Some(inner) = input.strip_prefix('"') {
+        let end = inner.find('"').unwrap_or(inner.len());
+        (&inner[..end], inner.get(end + 1..).unwrap_or(""))
+    } else {
+        let end = input.find([',', '}']).unwrap_or(input.len());
+        (&input[..end], &input[end..])
+    };
+    let value = value.replace(['{', '}'], "");
+    (value.split_whitespace().collect::<Vec<_>>().join(" "), rest)
+}
+
+/// Finds the family name in a BibTeX name, which is written either as
+/// `Family, Given` or `Given Family`.
+fn family_name(name: &str) -> String {
+    let name = name.trim();
+    match name.split_once(',') {
+        Some((family, _)) => family.trim().to_string(),
+        None => name.rsplit(' ').next().unwrap_or(name).to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_bibtex;
+
+    #[test]
+    fn bibtex_entries() {
+        let references = parse_bibtex(
+            r#"
+            @comment{ignored}
+            @article{lamport78,
+              author = {Lamport, Leslie},
+              title = {Time, Clocks, and the {Ordering} of Events},
+              journal = "Communications of the ACM",
+              year = 1978,
+            }
+            @book{sicp, title={Structure and Interpretation of Computer Programs},
+              author={Harold Abelson and Gerald Jay Sussman}, year={1985}}
+            "#,
+        );
+        assert_eq!(references.len(), 2);
+        assert_eq!(references[0].key, "lamport78");
+        assert_eq!(references[0].authors, ["Lamport"]);
+        assert_eq!(
+            references[0].title.as_deref(),
+            Some("Time, Clocks, and the Ordering of Events")
+        );
+        assert_eq!(
+            references[0].container.as_deref(),
+            Some("Communications of the ACM")
+        );
+        assert_eq!(references[0].year.as_deref(), Some("1978"));
+        assert_eq!(references[1].authors, ["Abelson", "Sussman"]);
+    }
+}
@@ -0,0 +1,154 @@
+//! Comments stored alongside the site's source
+//!
+//! Comments live in `_comments/<post-slug>/`, one TOML file per comment. This
+//! keeps the site fully static: comments are moderated by adding their files,
+//! for example from an email or a pull request, and rendered with the rest of
+//! the site.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use pulldown_cmark::{html::push_html, CowStr, Event, Parser, Tag, TagEnd};
+use serde::Deserialize;
+use toml::value::Datetime;
+
+use super::IndexError;
+use crate::renderer::is_safe_url;
+
+#[derive(Deserialize, Debug)]
+pub struct Comment {
+    pub author: String,
+    /// A link to the author's website
+    pub url: Option<String>,
+    pub date: Datetime,
+    /// The comment itself, in Markdown
+    pub body: String,
+}
+
+impl Comment {
+    /// Renders the body of the comment to HTML.
+    ///
+    /// Raw HTML in the comment is escaped rather than passed through, and
+    /// links and images that could run scripts, such as `javascript:` URLs,
+    /// are left as plain text.
+    pub fn body_html(&self) -> String {
+        // Whether each link or image we're in was kept
+        let mut kept = vec![];
+        let events = Parser::new(&self.body).map(|event| match event {
+            Event::Html(html) | Event::InlineHtml(html) => Event::Text(html),
+            Event::Start(Tag::HtmlBlock) | Event::End(TagEnd::HtmlBlock) => {
+                Event::Text(CowStr::Borrowed(""))
+            }
+            Event::Start(Tag::Link { ref dest_url, .. } | Tag::Image { ref dest_url, .. }) => {
+                kept.push(is_safe_url(dest_url));
+                if kept.last() == Some(&true) {
+                    event
+                } else {
+                    Event::Text(CowStr::Borrowed(""))
+                }
+            }
+            Event::End(TagEnd::Link | TagEnd::Image) if kept.pop() == Some(false) => {
+                Event::Text(CowStr::Borrowed(""))
+            }
+            event => event,
+        });
+        let mut html = String::new();
+        push_html(&mut html, events);
+        html
+    }
+}
+
+/// Loads every comment under `dir`, grouped by the slug of the post they
+/// belong to and sorted oldest first.
+pub(super) fn load_comments(dir: &Path) -> Result<HashMap<String, Vec<Comment>>, IndexError> {
+    let mut comments = HashMap::new();
+    if !dir.is_dir() {
+        return Ok(comments);
+    }
+    for entry in walkdir::WalkDir::new(dir).min_depth(2).max_depth(2) {
+        let entry = entry.map_err(IndexError::WalkdirReadingDirectoryEntry)?;
+        let path = entry.path();
+        if !entry.file_type().is_file() || path.extension().is_none_or(|ext| ext != "toml") {
+            continue;
+        }
+        let Some(slug) = path
+            .parent()
+            .and_then(Path::file_name)
+            .and_then(|slug| slug.to_str())
+        else {
+            continue;
+        };
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| IndexError::ReadingComment(PathBuf::from(path), e))?;
+        let comment: Comment = toml::from_str(&contents)
+            .map_err(|e| IndexError::ParsingComment(PathBuf::from(path), Box::new(e)))?;
+        comments
+            .entry(slug.to_string())
+            .or_insert_with(Vec::new)
+            .push(comment);
+    }
+    for comments in comments.values_mut() {
+        comments.sort_by_key(|comment: &Comment| comment.date.to_string());
+    }
+    Ok(comments)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{load_comments, Comment};
+
+    #[test]
+    fn load_and_render() {
+        let dir = tempfile::tempdir().unwrap();
+        let post = dir.path().join("hello-world");
+        std::fs::create_dir(&post).unwrap();
+        std::fs::write(
+            post.join("b.toml"),
+            "author = \"Bo\"\ndate = 2024-01-03T10:00:00Z\nbody = \"Later\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            post.join("a.toml"),
+            "author = \"Al\"\nurl = \"https://al.example\"\ndate = 2024-01-02\nbody = \"*Nice* <script>x</script>\"\n",
+        )
+        .unwrap();
+
+        let comments = load_comments(dir.path()).unwrap();
+        let comments = &comments["hello-world"];
+        assert_eq!(
+            comments
+                .iter()
+                .map(|c| c.author.as_str())
+                .collect::<Vec<_>>(),
+            ["Al", "Bo"]
+        );
+        assert_eq!(
+            comments[0].body_html(),
+            "<p><em>Nice</em> &lt;script&gt;x&lt;/script&gt;</p>\n"
+        );
+    }
+
+    #[test]
+    fn unsafe_links() {
+        let comment = |body: &str| Comment {
+            author: "Al".to_string(),
+            url: None,
+            date: "2024-01-02".parse().unwrap(),
+            body: body.to_string(),
+        };
+        assert_eq!(
+            comment("[x](javascript:alert(1)) ![y](data:image/svg+xml,z) [ok](https://ok.example)")
+                .body_html(),
+            "<p>x y <a href=\"https://ok.example\">ok</a></p>\n"
+        );
+    }
+
+    #[test]
+    fn missing_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let comments = load_comments(&dir.path().join("_comments")).unwrap();
+        assert!(comments.is_empty());
+    }
+}
@@ -0,0 +1,138 @@
+//! Choosing which parts of a site to build
+//!
+//! Partial builds still index the whole site so links between pages resolve,
+//! but only write the pages that match the filter. Pages that may list posts
+//! need the posts' contents, so the posts are rendered whenever one of those
+//! is built.
+
+use std::path::Path;
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+use super::{IndexError, PageSource, SiteIndex};
+
+pub struct PageFilter {
+    globs: GlobSet,
+    /// Whether any post matched, in which case the pages that list posts are
+    /// built too
+    includes_posts: bool,
+    /// Whether any page that is not a post is built, in which case every post
+    /// is rendered so it can be listed with its excerpt
+    includes_listings: bool,
+}
+
+impl PageFilter {
+    /// Creates a filter from glob patterns, which are matched against paths
+    /// relative to the site root.
+    ///
+    /// Returns `None` if there are no patterns, meaning the whole site should
+    /// be built.
+    pub fn new(patterns: &[String], site: &SiteIndex) -> Result<Option<Self>, IndexError> {
+        if patterns.is_empty() {
+            return Ok(None);
+        }
+
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            let glob =
+                Glob::new(pattern).map_err(|e| IndexError::InvalidFilter(pattern.clone(), e))?;
+            builder.add(glob);
+        }
+        let globs = builder
+            .build()
+            .map_err(|e| IndexError::InvalidFilter(patterns.join(" "), e))?;
+
+        let includes_posts = site
+            .all_pages()
+            .any(|page| page.is_post() && globs.is_match(page.source_path()));
+
+        let includes_listings = includes_posts
+            || site
+                .all_pages()
+                .any(|page| !page.is_post() && globs.is_match(page.source_path()));
+
+        Ok(Some(Self {
+            globs,
+            includes_posts,
+            includes_listings,
+        }))
+    }
+
+    /// Whether the path, relative to the site root, matches the filter
+    pub fn matches(&self, path: &Path) -> bool {
+        self.globs.is_match(path)
+    }
+
+    /// Whether `page` should be built
+    ///
+    /// This includes the pages that match the filter, as well as every page
+    /// that is not a post if any of the matching pages are posts. Those are
+    /// the index and archive pages that may list the posts.
+    pub fn includes(&self, page: &PageSource) -> bool {
+        self.matches(page.source_path()) || (self.includes_posts && !page.is_post())
+    }
+
+    /// Whether `page` needs to be rendered, either to be built or because a
+    /// page that is built may list it
+    pub fn renders(&self, page: &PageSource) -> bool {
+        self.includes(page) || (self.includes_listings && page.is_post())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use crate::index::{PageSource, SiteIndex, SourceFormat};
+
+    use super::PageFilter;
+
+    #[test]
+    fn include_listing_pages() {
+        let mut site = SiteIndex::default();
+        for path in [
+            "_posts/2023-05-01-old.md",
+            "_posts/2024-02-01-new.md",
+            "index.html",
+            "docs/guide.md",
+        ] {
+            site.add_page(PageSource::from_string(path, SourceFormat::Markdown, ""));
+        }
+        let included = |filter: &PageFilter| {
+            site.all_pages()
+                .filter(|page| filter.includes(page))
+                .map(|page| page.source_path())
+                .collect::<Vec<_>>()
+        };
+
+        let posts = PageFilter::new(&["_posts/2024-*".into()], &site)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            included(&posts),
+            [
+                Path::new("_posts/2024-02-01-new.md"),
+                Path::new("index.html"),
+                Path::new("docs/guide.md"),
+            ]
+        );
+
+        let docs = PageFilter::new(&["docs/**".into()], &site)
+            .unwrap()
+            .unwrap();
+        assert_eq!(included(&docs), [Path::new("docs/guide.md")]);
+        // The guide may list posts, so they are rendered but not written
+        assert!(site
+            .all_pages()
+            .filter(|page| page.is_post())
+            .all(|page| docs.renders(page)));
+        assert!(!docs.renders(site.all_pages().find(|page| !page.is_post()).unwrap()));
+
+        let old = PageFilter::new(&["_posts/2023-*".into()], &site)
+            .unwrap()
+            .unwrap();
+        assert!(site.all_pages().all(|page| old.renders(page)));
+
+        assert!(PageFilter::new(&[], &site).unwrap().is_none());
+    }
+}
@@ -0,0 +1,29 @@
+//! Loading the glossary of terms used across the site
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use super::IndexError;
+
+/// The `[glossary]` section of `Site.toml`
+#[derive(Deserialize, Debug)]
+pub struct GlossaryConfig {
+    /// A TOML file, relative to `Site.toml`, that maps each term to its
+    /// definition
+    pub file: PathBuf,
+    /// Whether to generate `glossary/index.html` from the theme's
+    /// `glossary.html` template
+    #[serde(default)]
+    pub page: bool,
+}
+
+pub(super) fn load_glossary(path: &Path) -> Result<BTreeMap<String, String>, IndexError> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| IndexError::ReadingGlossary(path.to_path_buf(), e))?;
+    toml::from_str(&contents)
+        .map_err(|e| IndexError::ParsingGlossary(path.to_path_buf(), Box::new(e)))
+}
@@ -1,22 +1,63 @@
 //! Contains data structures that represent the full site.
 
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     path::{Path, PathBuf},
 };
 
+use chrono::Utc;
 use futures::StreamExt;
 use miette::{Diagnostic, Severity};
 use serde::Deserialize;
+use syntect::parsing::SyntaxSet;
 use thiserror::Error;
 use tokio::fs;
 use tokio_stream::wrappers::ReadDirStream;
+use tracing::instrument;
+
+use crate::audit::AuditConfig;
+use crate::cancel::CancellationToken;
+use crate::export::ExportConfig;
+use crate::generator::{
+    BudgetConfig, BundleConfig, DeployConfig, FeedConfig, FormsConfig, HumansConfig,
+    ImageMetadataConfig, MemoryConfig, MicroformatsConfig, OnThisDayConfig, RevisionsConfig,
+    RobotsConfig, SecurityConfig, ShortUrlConfig, TaxonomyConfig, ThumbnailConfig, WebAppConfig,
+};
+use crate::http::HttpConfig;
+use crate::renderer::{
+    load_syntaxes, shortcode_dirs, ColorScheme, DescriptionConfig, ExternalLinksConfig,
+    GalleryConfig, HighlightThemeError, HighlightThemes, ImagesConfig, MarkdownConfig,
+    MarkdownPipeline, ObfuscateConfig, ShortcodeError, Shortcodes, TypographyConfig, VideoConfig,
+};
+use crate::syndication::{SyndicationConfig, SyndicationError, SyndicationState};
 
+mod bibliography;
+mod comments;
+mod filter;
+mod glossary;
 mod page;
+mod related;
+mod rename;
+
+pub use bibliography::Reference;
+pub use comments::Comment;
+pub use filter::PageFilter;
+pub use glossary::GlossaryConfig;
+pub(crate) use page::deserialize_comma_separated_list;
+pub use page::{
+    FrontMatter, FrontMatterEdit, PageKind, PageMetadata, PageSource, SourceFormat, DRAFTS_DIR,
+};
+pub use related::RelatedPostsConfig;
+pub(crate) use rename::normalize_path;
+pub use rename::{
+    plan_post_renames, plan_publish, rewrite_source_links, DraftPublish, PostLayout, PostRename,
+    RenameOptions,
+};
 
-pub use page::{PageKind, PageMetadata, PageSource, SourceFormat};
-
-use self::page::PageLoadError;
+use self::{
+    bibliography::load_bibliography, comments::load_comments, glossary::load_glossary,
+    page::PageLoadError,
+};
 
 #[derive(Deserialize, Default)]
 pub struct Config {
@@ -27,16 +68,131 @@ pub struct Config {
     pub author_email: Option<String>,
     pub subtitle: Option<String>,
     pub posts: Option<PathBuf>,
+    pub comments: Option<PathBuf>,
     pub theme: Option<PathBuf>,
     #[serde(default)]
     pub content: Vec<PathBuf>,
     #[serde(default)]
-    pub macros: HashMap<String, PathBuf>,
+    pub macros: BTreeMap<String, PathBuf>,
     /// Options that are passed directly to to the theme
     ///
     /// Within theme templates, these are available under the `theme` variable.
     #[serde(default)]
     pub theme_opts: serde_json::Value,
+    /// Values that pages can use with placeholders like `{{ site.name }}`
+    #[serde(default)]
+    pub vars: HashMap<String, serde_json::Value>,
+    /// Whether to generate `calendar.ics` with the publication date of every
+    /// post
+    #[serde(default)]
+    pub calendar: bool,
+    /// The color schemes the site supports, which determines how code is
+    /// highlighted
+    pub color_scheme: Option<ColorScheme>,
+    /// Whether to inline the CSS each page needs for its first paint
+    #[serde(default)]
+    pub critical_css: bool,
+    /// An image to generate the site's favicons from
+    pub favicon: Option<PathBuf>,
+    /// The theme to color highlighted code with, either a built-in theme or a
+    /// `.tmTheme` file
+    pub highlight_theme: Option<String>,
+    /// The theme for code when the reader prefers a dark color scheme
+    pub highlight_dark_theme: Option<String>,
+    /// Whether to highlight code with CSS classes even without a color scheme
+    #[serde(default)]
+    pub highlight_classes: bool,
+    /// A directory of `.sublime-syntax` files to highlight code with, in
+    /// addition to the built-in syntaxes
+    pub syntaxes: Option<PathBuf>,
+    /// Maps code block languages to the syntax to highlight them with, given
+    /// as a file extension or a syntax name
+    #[serde(default)]
+    pub code_languages: BTreeMap<String, String>,
+    /// Whether to add JSON-LD structured data to the `<head>` of every post
+    #[serde(default)]
+    pub jsonld: bool,
+    /// Whether to write an internal page summarizing the site's health to
+    /// `/_health/`
+    #[serde(default)]
+    pub health_page: bool,
+    /// Whether to generate `sitemap.xml` listing every page
+    #[serde(default)]
+    pub sitemap: bool,
+    /// Whether to leave posts dated in the future out of the site until their
+    /// publication date
+    #[serde(default)]
+    pub schedule_posts: bool,
+    /// Whether symbolic links in the content are copied as links rather than
+    /// as the files they point to
+    #[serde(default)]
+    pub preserve_symlinks: bool,
+    /// Seeds the `shuffle` and `sample` template filters. By default they are
+    /// seeded from `SOURCE_DATE_EPOCH`, or with a fixed seed if it isn't set.
+    pub random_seed: Option<u64>,
+    /// Markers that end a post's excerpt, in addition to `<!-- MORE -->`
+    #[serde(default)]
+    pub excerpt_separators: Vec<String>,
+    /// Makes descriptions for pages that don't have one in their frontmatter
+    pub description: Option<DescriptionConfig>,
+    /// Whether to generate a QR code image linking to each post
+    #[serde(default)]
+    pub qr_codes: bool,
+    pub thumbnails: Option<ThumbnailConfig>,
+    #[serde(default)]
+    pub on_this_day: OnThisDayConfig,
+    #[serde(default)]
+    pub memory: MemoryConfig,
+    #[serde(default)]
+    pub taxonomy: TaxonomyConfig,
+    pub humans_txt: Option<HumansConfig>,
+    pub security_txt: Option<SecurityConfig>,
+    pub robots_txt: Option<RobotsConfig>,
+    pub web_app: Option<WebAppConfig>,
+    pub short_urls: Option<ShortUrlConfig>,
+    pub revisions: Option<RevisionsConfig>,
+    pub forms: Option<FormsConfig>,
+    pub glossary: Option<GlossaryConfig>,
+    /// A CSL-JSON or BibTeX file of works that pages can cite
+    pub bibliography: Option<PathBuf>,
+    pub typography: Option<TypographyConfig>,
+    pub video: Option<VideoConfig>,
+    pub gallery: Option<GalleryConfig>,
+    pub images: Option<ImagesConfig>,
+    pub external_links: Option<ExternalLinksConfig>,
+    pub obfuscate_links: Option<ObfuscateConfig>,
+    #[serde(default)]
+    pub markdown: MarkdownConfig,
+    #[serde(default)]
+    pub image_metadata: ImageMetadataConfig,
+    #[serde(default)]
+    pub feed: FeedConfig,
+    #[serde(default)]
+    pub deploy: DeployConfig,
+    pub bundle: Option<BundleConfig>,
+    #[serde(default)]
+    pub microformats: MicroformatsConfig,
+    #[serde(default)]
+    pub budgets: BudgetConfig,
+    #[serde(default)]
+    pub audit: AuditConfig,
+    #[serde(default)]
+    pub syndication: SyndicationConfig,
+    #[serde(default)]
+    pub export: ExportConfig,
+    #[serde(default)]
+    pub http: HttpConfig,
+    #[serde(default)]
+    pub related_posts: RelatedPostsConfig,
+}
+
+impl Config {
+    /// Returns the directory posts are loaded from, relative to the site root.
+    pub fn posts_dir(&self) -> &Path {
+        self.posts
+            .as_deref()
+            .unwrap_or(Path::new(page::DEFAULT_POSTS_DIR))
+    }
 }
 
 #[non_exhaustive]
@@ -54,6 +210,28 @@ pub enum IndexError {
     ReadingConfigFile(#[source] std::io::Error),
     #[error("parsing Site.toml")]
     ParsingConfigFile(#[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error("loading syndication state")]
+    LoadingSyndicationState(#[source] SyndicationError),
+    #[error("reading comment `{}`", .0.display())]
+    ReadingComment(PathBuf, #[source] std::io::Error),
+    #[error("parsing comment `{}`", .0.display())]
+    ParsingComment(PathBuf, #[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error("reading glossary `{}`", .0.display())]
+    ReadingGlossary(PathBuf, #[source] std::io::Error),
+    #[error("parsing glossary `{}`", .0.display())]
+    ParsingGlossary(PathBuf, #[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error("reading bibliography `{}`", .0.display())]
+    ReadingBibliography(PathBuf, #[source] std::io::Error),
+    #[error("parsing bibliography `{}`", .0.display())]
+    ParsingBibliography(PathBuf, #[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error("invalid path filter `{0}`")]
+    InvalidFilter(String, #[source] globset::Error),
+    #[error("loading syntaxes from `{}`", .0.display())]
+    LoadingSyntaxes(PathBuf, #[source] syntect::LoadingError),
+    #[error("loading highlight themes")]
+    LoadingHighlightThemes(#[diagnostic_source] HighlightThemeError),
+    #[error("loading shortcodes")]
+    LoadingShortcodes(#[diagnostic_source] ShortcodeError),
 }
 
 /// Holds what is essentially metadata about a site
@@ -66,6 +244,22 @@ pub struct SiteIndex {
     root_dir: PathBuf,
     pages: Vec<PageSource>,
     raw_files: Vec<PathBuf>,
+    /// Posts dated in the future, which are held back until their publication
+    /// date
+    scheduled: Vec<PageSource>,
+    /// Comments on each post, keyed by the post's slug
+    comments: HashMap<String, Vec<Comment>>,
+    /// Maps terms to their definitions
+    glossary: BTreeMap<String, String>,
+    /// Works that pages can cite, by key
+    bibliography: BTreeMap<String, Reference>,
+    syndication: SyndicationState,
+    markdown_pipeline: MarkdownPipeline,
+    highlight_themes: HighlightThemes,
+    /// The built-in syntaxes plus the site's own, if it has any
+    syntax_set: Option<SyntaxSet>,
+    shortcodes: Shortcodes,
+    cancellation: CancellationToken,
 }
 
 impl SiteIndex {
@@ -73,8 +267,11 @@ impl SiteIndex {
         path: impl Into<PathBuf>,
         include_unpublished: bool,
     ) -> Result<Self, IndexError> {
-        let root_dir = path.into();
+        Self::load(path.into(), include_unpublished).await
+    }
 
+    #[instrument(name = "index", skip_all, fields(root = %root_dir.display()))]
+    async fn load(root_dir: PathBuf, include_unpublished: bool) -> Result<Self, IndexError> {
         // FIXME: give friendly error reports for bad config files
         let config: Config = toml::from_str(
             &std::fs::read_to_string(root_dir.join("Site.toml"))
@@ -84,30 +281,116 @@ impl SiteIndex {
 
         let mut pages = vec![];
         let mut raw_files = Vec::new();
+        let posts_roots = [config.posts_dir().to_path_buf(), page::DRAFTS_DIR.into()];
 
         pages.extend(
             load_posts(
-                &root_dir.join(config.posts.as_ref().unwrap_or(&"_posts".into())),
+                &root_dir.join(config.posts_dir()),
                 &root_dir,
+                &posts_roots,
                 include_unpublished,
             )
             .await?,
         );
 
+        if include_unpublished {
+            pages.extend(
+                load_posts(
+                    &root_dir.join(page::DRAFTS_DIR),
+                    &root_dir,
+                    &posts_roots,
+                    include_unpublished,
+                )
+                .await?,
+            );
+        }
+
         for path in config.content.iter() {
-            match load_directory(root_dir.join(path), &root_dir, include_unpublished).await? {
-                (new_pages, files) => {
-                    pages.extend(new_pages.into_iter());
-                    raw_files.extend(files.into_iter());
-                }
-            }
+            let (new_pages, files) = load_directory(
+                root_dir.join(path),
+                &root_dir,
+                &posts_roots,
+                include_unpublished,
+            )
+            .await?;
+            pages.extend(new_pages);
+            raw_files.extend(files);
         }
 
+        // Directory listings come in whatever order the file system likes, so
+        // sort everything to make builds the same everywhere
+        pages.sort_by(|a, b| a.source_path().cmp(b.source_path()));
+        raw_files.sort();
+        raw_files.dedup();
+
+        // Hold back posts that are dated in the future if the site asks for it.
+        // Including unpublished posts also includes these so they can be
+        // previewed.
+        let scheduled = if include_unpublished || !config.schedule_posts {
+            vec![]
+        } else {
+            let now = Utc::now();
+            let (scheduled, published) = pages.into_iter().partition(|page: &PageSource| {
+                page.is_post() && page.publish_date().is_some_and(|date| date > now)
+            });
+            pages = published;
+            scheduled
+        };
+
+        let comments =
+            load_comments(&root_dir.join(config.comments.as_ref().unwrap_or(&"_comments".into())))?;
+
+        let glossary = match &config.glossary {
+            Some(glossary) => load_glossary(&root_dir.join(&glossary.file))?,
+            None => BTreeMap::new(),
+        };
+
+        let bibliography = match &config.bibliography {
+            Some(path) => load_bibliography(&root_dir.join(path))?
+                .into_iter()
+                .map(|reference| (reference.key.clone(), reference))
+                .collect(),
+            None => BTreeMap::new(),
+        };
+
+        let syndication = SyndicationState::load(&root_dir.join(&config.syndication.state))
+            .map_err(IndexError::LoadingSyndicationState)?;
+
+        let theme_dir = root_dir.join(config.theme.as_deref().unwrap_or(Path::new("theme")));
+        let highlight_themes = HighlightThemes::load(
+            config.highlight_theme.as_deref(),
+            config.highlight_dark_theme.as_deref(),
+            &root_dir,
+            &theme_dir,
+        )
+        .map_err(IndexError::LoadingHighlightThemes)?;
+
+        let shortcodes = Shortcodes::load(&shortcode_dirs(&root_dir, &theme_dir))
+            .map_err(IndexError::LoadingShortcodes)?;
+
+        let syntax_set = match &config.syntaxes {
+            Some(dir) => {
+                let dir = root_dir.join(dir);
+                Some(load_syntaxes(&dir).map_err(|e| IndexError::LoadingSyntaxes(dir, e))?)
+            }
+            None => None,
+        };
+
         Ok(SiteIndex {
             config,
             root_dir,
             pages,
             raw_files,
+            scheduled,
+            comments,
+            glossary,
+            bibliography,
+            syndication,
+            markdown_pipeline: MarkdownPipeline::default(),
+            highlight_themes,
+            syntax_set,
+            shortcodes,
+            cancellation: CancellationToken::new(),
         })
     }
 
@@ -121,6 +404,12 @@ impl SiteIndex {
         self.pages.iter()
     }
 
+    /// Returns the posts that are dated in the future and therefore not
+    /// included in the site yet
+    pub fn scheduled_posts(&self) -> impl Iterator<Item = &PageSource> {
+        self.scheduled.iter()
+    }
+
     /// Finds a page given its source path
     ///
     /// The path should be given relative to the site root.
@@ -128,6 +417,66 @@ impl SiteIndex {
         self.pages.iter().find(|page| page.source_path() == path)
     }
 
+    /// Returns the comments on `page`, oldest first
+    pub fn comments(&self, page: &PageSource) -> &[Comment] {
+        if !page.is_post() {
+            return &[];
+        }
+        self.comments
+            .get(page.title_slug())
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Returns the terms in the site's glossary and their definitions
+    pub fn glossary(&self) -> &BTreeMap<String, String> {
+        &self.glossary
+    }
+
+    /// Returns the works in the site's bibliography, by key
+    pub fn bibliography(&self) -> &BTreeMap<String, Reference> {
+        &self.bibliography
+    }
+
+    /// The themes code blocks are highlighted with
+    pub fn highlight_themes(&self) -> &HighlightThemes {
+        &self.highlight_themes
+    }
+
+    /// The syntaxes to highlight code with, if the site adds its own
+    pub fn syntax_set(&self) -> Option<&SyntaxSet> {
+        self.syntax_set.as_ref()
+    }
+
+    /// The templates for the shortcodes pages can use
+    pub fn shortcodes(&self) -> &Shortcodes {
+        &self.shortcodes
+    }
+
+    /// Lets `token` stop rendering and generating this site early
+    pub fn set_cancellation(&mut self, token: CancellationToken) {
+        self.cancellation = token;
+    }
+
+    pub fn cancellation(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    /// The passes each page's markdown goes through
+    pub fn markdown_pipeline(&self) -> &MarkdownPipeline {
+        &self.markdown_pipeline
+    }
+
+    /// Allows programs that use EBG as a library to add their own markdown
+    /// passes before the site is rendered.
+    pub fn markdown_pipeline_mut(&mut self) -> &mut MarkdownPipeline {
+        &mut self.markdown_pipeline
+    }
+
+    /// Returns the record of which posts have been syndicated to other services
+    pub fn syndication(&self) -> &SyndicationState {
+        &self.syndication
+    }
+
     /// Adds a new page to the site
     ///
     /// This generally shouldn't be needed since pages are loaded from the filesystem,
@@ -135,6 +484,12 @@ impl SiteIndex {
     pub fn add_page(&mut self, page: PageSource) {
         self.pages.push(page);
     }
+
+    /// Allows tests to change the configuration of mock sites
+    #[cfg(test)]
+    pub(crate) fn config_mut(&mut self) -> &mut Config {
+        &mut self.config
+    }
 }
 
 /// Accessor methods for various kinds of site metadata
@@ -150,6 +505,16 @@ pub trait SiteMetadata {
     fn raw_files(&self) -> impl Iterator<Item = &Path>
     where
         Self: Sized;
+
+    /// Joins the site's base URL and `path`, with one `/` between them
+    /// however either is written.
+    fn full_url(&self, path: &str) -> String {
+        format!(
+            "{}/{}",
+            self.base_url().trim_end_matches('/'),
+            path.trim_start_matches('/')
+        )
+    }
 }
 
 impl SiteMetadata for SiteIndex {
@@ -206,6 +571,7 @@ struct SkippedPost {
 async fn load_posts(
     path: &Path,
     root_dir: &Path,
+    posts_roots: &[PathBuf],
     include_unpublished: bool,
 ) -> Result<Vec<PageSource>, IndexError> {
     if !path.is_dir() {
@@ -220,9 +586,17 @@ async fn load_posts(
     );
     while let Some(entry) = dir_stream.next().await {
         let entry = entry.map_err(IndexError::ReadingDirectoryEntry)?;
-        let page = match PageSource::from_file(entry.path(), root_dir).await {
-            Ok(page) => page,
-            Err(e) if e.severity() <= Some(Severity::Warning) => {
+        let page = if entry.path().is_dir() {
+            PageSource::from_bundle(&entry.path(), root_dir, posts_roots)
+                .await
+                .transpose()
+        } else {
+            Some(PageSource::from_file(entry.path(), root_dir, posts_roots).await)
+        };
+        let page = match page {
+            None => continue,
+            Some(Ok(page)) => page,
+            Some(Err(e)) if e.severity() <= Some(Severity::Warning) => {
                 println!(
                     "{:?}",
                     miette::Report::new(SkippedPost {
@@ -232,7 +606,7 @@ async fn load_posts(
                 );
                 continue;
             }
-            Err(e) => panic!("I don't know what to do with this error: {}", e),
+            Some(Err(e)) => panic!("I don't know what to do with this error: {}", e),
         };
 
         if page.published() || include_unpublished {
@@ -248,6 +622,7 @@ async fn load_posts(
 async fn load_directory(
     path: impl AsRef<Path>,
     root_dir: &Path,
+    posts_roots: &[PathBuf],
     include_unpublished: bool,
 ) -> Result<(Vec<PageSource>, Vec<PathBuf>), IndexError> {
     let path = path.as_ref();
@@ -255,7 +630,7 @@ async fn load_directory(
     let mut raw_files = vec![];
 
     if path.is_file() {
-        if let Ok(page) = PageSource::from_file(path, root_dir).await {
+        if let Ok(page) = PageSource::from_file(path, root_dir, posts_roots).await {
             if page.published() || include_unpublished {
                 return Ok((vec![page], vec![]));
             } else {
@@ -266,16 +641,26 @@ async fn load_directory(
         }
     }
 
-    let walk = walkdir::WalkDir::new(path);
+    // Posts and drafts are loaded on their own, so leave them out even if
+    // they are inside a content directory
+    let is_posts_root = |path: &Path| {
+        path.strip_prefix(root_dir)
+            .is_ok_and(|relative| posts_roots.iter().any(|root| root == relative))
+    };
+    let walk = walkdir::WalkDir::new(path)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| !(entry.file_type().is_dir() && is_posts_root(entry.path())));
     for result in walk {
         let entry = result.map_err(IndexError::WalkdirReadingDirectoryEntry)?;
 
-        if !entry.file_type().is_file() {
+        // Links to files are included too, but not links to directories
+        if !entry.path().is_file() {
             continue;
         }
 
         let filename = entry.path();
-        if let Ok(page) = PageSource::from_file(&filename, root_dir).await {
+        if let Ok(page) = PageSource::from_file(&filename, root_dir, posts_roots).await {
             if page.published() || include_unpublished {
                 pages.push(page)
             }
@@ -289,7 +674,9 @@ async fn load_directory(
 
 #[cfg(test)]
 mod test {
-    use super::Config;
+    use std::path::Path;
+
+    use super::{Config, SiteIndex, SiteMetadata};
 
     #[test]
     fn parse_site_config() {
@@ -301,4 +688,91 @@ mod test {
 
         assert_eq!(config.url, Some("https://example.com".to_string()));
     }
+
+    #[test]
+    fn full_urls() {
+        let mut site = SiteIndex::default();
+        for url in ["https://example.com", "https://example.com/"] {
+            site.config_mut().url = Some(url.into());
+            assert_eq!(site.full_url("blog/"), "https://example.com/blog/");
+            assert_eq!(
+                site.full_url("/sitemap.xml"),
+                "https://example.com/sitemap.xml"
+            );
+            assert_eq!(site.full_url(""), "https://example.com/");
+        }
+    }
+
+    #[tokio::test]
+    async fn stable_ordering() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(
+            root.join("Site.toml"),
+            "content = [\"pages\", \"images\"]\n[macros]\nz = \"z.html\"\na = \"a.html\"\n",
+        )
+        .unwrap();
+        for dir in ["_posts", "pages/b", "pages/a", "images"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in [
+            "_posts/2024-02-01-second.md",
+            "_posts/2024-01-01-first.md",
+            "pages/z.md",
+            "pages/b/index.md",
+            "pages/a/index.md",
+        ] {
+            std::fs::write(root.join(file), "---\nlayout: page\n---\n").unwrap();
+        }
+        for file in ["images/c.png", "images/a.png", "images/b.png"] {
+            std::fs::write(root.join(file), "").unwrap();
+        }
+
+        let site = SiteIndex::from_directory(root, false).await.unwrap();
+        assert_eq!(
+            site.all_pages()
+                .map(|page| page.source_path())
+                .collect::<Vec<_>>(),
+            [
+                "_posts/2024-01-01-first.md",
+                "_posts/2024-02-01-second.md",
+                "pages/a/index.md",
+                "pages/b/index.md",
+                "pages/z.md",
+            ]
+            .map(Path::new)
+        );
+        assert_eq!(
+            site.raw_files,
+            ["images/a.png", "images/b.png", "images/c.png"].map(|file| root.join(file))
+        );
+        assert_eq!(site.config.macros.keys().collect::<Vec<_>>(), ["a", "z"]);
+    }
+
+    #[tokio::test]
+    async fn content_skips_posts() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("Site.toml"), "content = [\".\"]\n").unwrap();
+        for dir in ["_posts/2024-01-01-first", "_drafts"] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in [
+            "_posts/2024-01-01-first/index.md",
+            "_drafts/next.md",
+            "about.md",
+        ] {
+            std::fs::write(root.join(file), "---\nlayout: page\n---\n").unwrap();
+        }
+        std::fs::write(root.join("_posts/2024-01-01-first/photo.jpg"), "").unwrap();
+
+        let site = SiteIndex::from_directory(root, false).await.unwrap();
+        assert_eq!(
+            site.all_pages()
+                .map(|page| page.source_path())
+                .collect::<Vec<_>>(),
+            ["_posts/2024-01-01-first/index.md", "about.md"].map(Path::new)
+        );
+        assert_eq!(site.raw_files, [root.join("Site.toml")]);
+    }
 }
@@ -0,0 +1,583 @@
+//! Changing the frontmatter of a page without touching the rest of its source
+
+use serde_yaml::{Mapping, Value};
+
+use super::{FrontMatter, PageSource};
+
+/// A change to make to a page's frontmatter
+#[derive(Debug, Clone)]
+pub enum FrontMatterEdit {
+    /// Sets `key` to `value`, or only adds it if `if_missing` is set and the
+    /// key is not already there
+    Set {
+        key: String,
+        value: Value,
+        if_missing: bool,
+    },
+    /// Renames the key `from` to `to`
+    RenameKey { from: String, to: String },
+    /// Replaces `from` with `to` in the value of `key`, or in each of its
+    /// entries if it is a list
+    RenameValue {
+        key: String,
+        from: String,
+        to: String,
+    },
+    /// Removes `key` entirely
+    Remove { key: String },
+    /// Removes `value` from the list in `key`
+    RemoveValue { key: String, value: String },
+}
+
+impl FrontMatterEdit {
+    /// Applies the edit and returns whether anything changed.
+    pub fn apply(&self, frontmatter: &mut Mapping) -> bool {
+        match self {
+            FrontMatterEdit::Set {
+                key,
+                value,
+                if_missing,
+            } => {
+                let existing = frontmatter.get(key.as_str());
+                if existing.is_some() && (*if_missing || existing == Some(value)) {
+                    return false;
+                }
+                frontmatter.insert(key.as_str().into(), value.clone());
+                true
+            }
+            FrontMatterEdit::RenameKey { from, to } => {
+                if frontmatter.contains_key(to.as_str()) {
+                    return false;
+                }
+                let Some(value) = frontmatter.remove(from.as_str()) else {
+                    return false;
+                };
+                frontmatter.insert(to.as_str().into(), value);
+                true
+            }
+            FrontMatterEdit::RenameValue { key, from, to } => {
+                let Some(value) = frontmatter.get_mut(key.as_str()) else {
+                    return false;
+                };
+                map_entries(key, value, |entry| {
+                    if entry == from {
+                        Some(Some(to.clone()))
+                    } else {
+                        None
+                    }
+                })
+            }
+            FrontMatterEdit::Remove { key } => frontmatter.remove(key.as_str()).is_some(),
+            FrontMatterEdit::RemoveValue { key, value: target } => {
+                let Some(value) = frontmatter.get_mut(key.as_str()) else {
+                    return false;
+                };
+                if value.is_string() && !is_comma_separated(key) {
+                    // A single value is not a list to remove things from
+                    return false;
+                }
+                map_entries(key, value, |entry| (entry == target).then_some(None))
+            }
+        }
+    }
+}
+
+/// Tags may be written as a comma-separated string instead of a list
+fn is_comma_separated(key: &str) -> bool {
+    key == "tags"
+}
+
+/// Calls `f` on each string entry in `value`. If `f` returns `Some`, the entry
+/// is replaced, or removed if the replacement is `None`.
+fn map_entries(
+    key: &str,
+    value: &mut Value,
+    mut f: impl FnMut(&str) -> Option<Option<String>>,
+) -> bool {
+    match value {
+        Value::String(s) if is_comma_separated(key) && s.contains(',') => {
+            let mut changed = false;
+            let entries: Vec<_> = s
+                .split(',')
+                .map(str::trim)
+                .filter_map(|entry| match f(entry) {
+                    Some(replacement) => {
+                        changed = true;
+                        replacement
+                    }
+                    None => Some(entry.to_string()),
+                })
+                .collect();
+            if changed {
+                *s = entries.join(", ");
+            }
+            changed
+        }
+        Value::String(s) => match f(s) {
+            Some(Some(replacement)) => {
+                *s = replacement;
+                true
+            }
+            Some(None) => {
+                s.clear();
+                true
+            }
+            None => false,
+        },
+        Value::Sequence(entries) => {
+            let mut changed = false;
+            entries.retain_mut(|entry| {
+                let Some(s) = entry.as_str() else {
+                    return true;
+                };
+                match f(s) {
+                    Some(Some(replacement)) => {
+                        changed = true;
+                        *entry = Value::String(replacement);
+                        true
+                    }
+                    Some(None) => {
+                        changed = true;
+                        false
+                    }
+                    None => true,
+                }
+            });
+            changed
+        }
+        _ => false,
+    }
+}
+
+impl FrontMatter {
+    /// Applies `edit` to a block of YAML frontmatter and returns the new
+    /// block, or `None` if nothing changed.
+    ///
+    /// Only the keys the edit touches are rewritten. Other lines, including
+    /// comments and trailing whitespace, are kept exactly as they were, as is
+    /// the order of the keys. Values that are rewritten keep their quoting
+    /// style, and lists stay in flow (`[a, b]`) or block style.
+    pub fn edit(raw: &str, edit: &FrontMatterEdit) -> Result<Option<String>, serde_yaml::Error> {
+        let before: Mapping = if raw.trim().is_empty() {
+            Mapping::new()
+        } else {
+            serde_yaml::from_str(raw)?
+        };
+        let mut after = before.clone();
+        if !edit.apply(&mut after) {
+            return Ok(None);
+        }
+
+        let mut doc = Document::parse(raw);
+        if let FrontMatterEdit::RenameKey { from, to } = edit {
+            doc.rename_key(from, to);
+            return Ok(Some(doc.to_string()));
+        }
+        for (key, _) in before.iter() {
+            if let Some(key) = key.as_str() {
+                if !after.contains_key(key) {
+                    doc.remove(key);
+                }
+            }
+        }
+        for (key, value) in after.iter() {
+            if let Some(key) = key.as_str() {
+                if before.get(key) != Some(value) {
+                    doc.set(key, value)?;
+                }
+            }
+        }
+        Ok(Some(doc.to_string()))
+    }
+}
+
+impl PageSource {
+    /// Applies `edit` to the page's frontmatter and returns the new source of
+    /// the page, or `None` if nothing changed.
+    ///
+    /// Only the frontmatter block is rewritten, as described in
+    /// [`FrontMatter::edit`]. Everything after it is kept exactly as it was.
+    pub fn edit_frontmatter(
+        &self,
+        edit: &FrontMatterEdit,
+    ) -> Result<Option<String>, serde_yaml::Error> {
+        let Some(yaml) = FrontMatter::edit(self.raw_frontmatter().unwrap_or(""), edit)? else {
+            return Ok(None);
+        };
+        Ok(Some(match &self.frontmatter {
+            Some(range) => format!(
+                "{}{yaml}{}",
+                &self.contents[..range.start],
+                &self.contents[range.end..]
+            ),
+            None => format!("---\n{yaml}---\n{}", self.contents),
+        }))
+    }
+}
+
+/// The lines of a YAML frontmatter block, grouped by the top-level key they
+/// belong to
+struct Document {
+    lines: Vec<String>,
+    /// The line ending the block uses
+    newline: &'static str,
+}
+
+/// The lines that make up a top-level key and its value
+struct Entry {
+    key: String,
+    /// The first line, which holds the key
+    start: usize,
+    /// One past the last line of the value
+    end: usize,
+}
+
+impl Document {
+    fn parse(raw: &str) -> Self {
+        let newline = if raw.contains("\r\n") { "\r\n" } else { "\n" };
+        Self {
+            lines: raw.split_inclusive('\n').map(str::to_string).collect(),
+            newline,
+        }
+    }
+
+    fn entries(&self) -> Vec<Entry> {
+        let mut entries: Vec<Entry> = vec![];
+        for (i, line) in self.lines.iter().enumerate() {
+            if let Some(key) = key_of(line) {
+                entries.push(Entry {
+                    key,
+                    start: i,
+                    end: i + 1,
+                });
+            } else if let Some(entry) = entries.last_mut() {
+                if entry.end == i && is_continuation(line, &self.lines[i + 1..]) {
+                    entry.end = i + 1;
+                }
+            }
+        }
+        entries
+    }
+
+    fn find(&self, key: &str) -> Option<Entry> {
+        self.entries().into_iter().find(|entry| entry.key == key)
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.find(key) {
+            self.lines.drain(entry.start..entry.end);
+        }
+    }
+
+    fn rename_key(&mut self, from: &str, to: &str) {
+        if let Some(entry) = self.find(from) {
+            let line = &self.lines[entry.start];
+            let colon = key_colon(line).unwrap();
+            self.lines[entry.start] = format!("{to}{}", &line[colon..]);
+        }
+    }
+
+    fn set(&mut self, key: &str, value: &Value) -> Result<(), serde_yaml::Error> {
+        let Some(entry) = self.find(key) else {
+            // New keys go at the end
+            if self.lines.last().is_some_and(|line| !line.ends_with('\n')) {
+                self.lines.last_mut().unwrap().push_str(self.newline);
+            }
+            let lines = self.render(key, value, "", &[])?;
+            self.lines.extend(lines);
+            return Ok(());
+        };
+        let line = &self.lines[entry.start];
+        let colon = key_colon(line).unwrap();
+        let key_text = line[..colon].to_string();
+        let rest = line[colon + 1..].trim_end_matches(['\r', '\n']).to_string();
+        let old_value: Vec<_> = self.lines[entry.start + 1..entry.end].to_vec();
+        let lines = self.render(&key_text, value, &rest, &old_value)?;
+        self.lines.splice(entry.start..entry.end, lines);
+        Ok(())
+    }
+
+    /// Renders the lines for `key: value`, following the style of the old
+    /// value, which was `rest` on the key's line and `old_lines` after it.
+    fn render(
+        &self,
+        key: &str,
+        value: &Value,
+        rest: &str,
+        old_lines: &[String],
+    ) -> Result<Vec<String>, serde_yaml::Error> {
+        let nl = self.newline;
+        let (old_inline, comment) = split_comment(rest);
+        let old_inline = old_inline.trim();
+        match value {
+            Value::Sequence(items)
+                if old_inline.starts_with('[')
+                    || old_lines.is_empty() && !old_inline.is_empty() =>
+            {
+                let items = items
+                    .iter()
+                    .map(|item| scalar(item, ""))
+                    .collect::<Result<Option<Vec<_>>, _>>()?;
+                if let Some(items) = items {
+                    return Ok(vec![format!("{key}: [{}]{comment}{nl}", items.join(", "))]);
+                }
+                self.render_block(key, value, comment, old_lines)
+            }
+            Value::Sequence(_) | Value::Mapping(_) => {
+                self.render_block(key, value, comment, old_lines)
+            }
+            _ => match scalar(value, old_inline)? {
+                Some(scalar) => Ok(vec![format!("{key}: {scalar}{comment}{nl}")]),
+                None => self.render_block(key, value, comment, old_lines),
+            },
+        }
+    }
+
+    /// Renders a value as an indented block under its key.
+    fn render_block(
+        &self,
+        key: &str,
+        value: &Value,
+        comment: &str,
+        old_lines: &[String],
+    ) -> Result<Vec<String>, serde_yaml::Error> {
+        let nl = self.newline;
+        let default_indent = if value.is_mapping() { 2 } else { 0 };
+        let indent = old_lines
+            .iter()
+            .find(|line| !line.trim().is_empty())
+            .map_or(default_indent, |line| line.len() - line.trim_start().len());
+        let indent = " ".repeat(indent);
+        let yaml = serde_yaml::to_string(value)?;
+        let mut lines = vec![format!("{key}:{comment}{nl}")];
+        lines.extend(yaml.lines().map(|line| format!("{indent}{line}{nl}")));
+        Ok(lines)
+    }
+}
+
+impl std::fmt::Display for Document {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.lines.iter().try_for_each(|line| f.write_str(line))
+    }
+}
+
+/// Returns the position of the colon after the key, if `line` starts a
+/// top-level key.
+fn key_colon(line: &str) -> Option<usize> {
+    if line.starts_with([' ', '\t', '#', '-', '\r', '\n']) || line.starts_with("...") {
+        return None;
+    }
+    let bytes = line.as_bytes();
+    // Quoted keys may contain colons
+    let search_from = match bytes.first() {
+        Some(quote @ (b'"' | b'\'')) => line[1..].find(*quote as char)? + 2,
+        _ => 0,
+    };
+    line[search_from..]
+        .match_indices(':')
+        .map(|(i, _)| search_from + i)
+        .find(|&i| matches!(bytes.get(i + 1), None | Some(b' ' | b'\t' | b'\r' | b'\n')))
+}
+
+fn key_of(line: &str) -> Option<String> {
+    let key = line[..key_colon(line)?].trim();
+    Some(key.trim_matches(|c| c == '"' || c == '\'').to_string())
+}
+
+/// Whether `line` belongs to the value of the key before it. `following` are
+/// the lines after it.
+fn is_continuation(line: &str, following: &[String]) -> bool {
+    if line.starts_with([' ', '\t']) && !line.trim().is_empty() {
+        return true;
+    }
+    if line.starts_with('-') {
+        return true;
+    }
+    // Blank lines are part of a value if the value continues after them
+    line.trim().is_empty()
+        && following
+            .iter()
+            .find(|line| !line.trim().is_empty())
+            .is_some_and(|line| line.starts_with([' ', '\t']))
+}
+
+/// Splits a trailing comment, with the whitespace before it, off the value on
+/// a key's line.
+fn split_comment(rest: &str) -> (&str, &str) {
+    let mut quote = None;
+    let mut prev = ' ';
+    for (i, c) in rest.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '#') if prev.is_whitespace() => {
+                let value = rest[..i].trim_end();
+                return (value, &rest[value.len()..]);
+            }
+            _ => {}
+        }
+        prev = c;
+    }
+    (rest, "")
+}
+
+/// Renders a scalar on one line, using the same quotes as `old` if it was
+/// quoted. Returns `None` for values that need more than one line.
+fn scalar(value: &Value, old: &str) -> Result<Option<String>, serde_yaml::Error> {
+    if let Value::String(s) = value {
+        if !s.contains('\n') {
+            if old.starts_with('"') {
+                return Ok(Some(format!(
+                    "\"{}\"",
+                    s.replace('\\', "\\\\").replace('"', "\\\"")
+                )));
+            }
+            if old.starts_with('\'') {
+                return Ok(Some(format!("'{}'", s.replace('\'', "''"))));
+            }
+        }
+    }
+    if value.is_sequence() || value.is_mapping() {
+        return Ok(None);
+    }
+    let yaml = serde_yaml::to_string(value)?;
+    let yaml = yaml.trim_end();
+    Ok((!yaml.contains('\n')).then(|| yaml.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::index::{PageSource, SourceFormat};
+
+    use super::FrontMatterEdit;
+
+    fn edit(source: &str, edit: FrontMatterEdit) -> Option<String> {
+        PageSource::from_string("_posts/2024-01-01-test.md", SourceFormat::Markdown, source)
+            .edit_frontmatter(&edit)
+            .unwrap()
+    }
+
+    #[test]
+    fn preserve_body() {
+        let body = "\n# Title  \r\n\n---\nnot: frontmatter\n";
+        let edited = edit(
+            &format!("---\ntitle: Test\n---\n{body}"),
+            FrontMatterEdit::Set {
+                key: "layout".into(),
+                value: "post".into(),
+                if_missing: true,
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            edited,
+            format!("---\ntitle: Test\nlayout: post\n---\n{body}")
+        );
+    }
+
+    #[test]
+    fn set_if_missing() {
+        let edited = edit(
+            "---\nlayout: page\n---\nbody",
+            FrontMatterEdit::Set {
+                key: "layout".into(),
+                value: "post".into(),
+                if_missing: true,
+            },
+        );
+        assert_eq!(edited, None);
+    }
+
+    #[test]
+    fn rename_tag() {
+        let rename = || FrontMatterEdit::RenameValue {
+            key: "tags".into(),
+            from: "rust".into(),
+            to: "rustlang".into(),
+        };
+        assert_eq!(
+            edit("---\ntags: [rust, wasm]\n---\n", rename()).unwrap(),
+            "---\ntags: [rustlang, wasm]\n---\n"
+        );
+        assert_eq!(
+            edit("---\ntags: wasm, rust\n---\n", rename()).unwrap(),
+            "---\ntags: wasm, rustlang\n---\n"
+        );
+        assert_eq!(edit("---\ntags: [go]\n---\n", rename()), None);
+    }
+
+    #[test]
+    fn remove() {
+        assert_eq!(
+            edit(
+                "---\ntitle: Test\ncomments: true\n---\n",
+                FrontMatterEdit::Remove {
+                    key: "comments".into()
+                }
+            )
+            .unwrap(),
+            "---\ntitle: Test\n---\n"
+        );
+        assert_eq!(
+            edit(
+                "---\ntags:\n  - a\n  - b\n---\n",
+                FrontMatterEdit::RemoveValue {
+                    key: "tags".into(),
+                    value: "a".into()
+                }
+            )
+            .unwrap(),
+            "---\ntags:\n  - b\n---\n"
+        );
+    }
+
+    #[test]
+    fn preserve_formatting() {
+        let source = "---\n# Written by hand\ntitle: \"Hello, world\"   \nlayout: post # or page\nextra:\n  nested: true\n\ndate: 2024-01-01\n---\nbody\n";
+        assert_eq!(
+            edit(
+                source,
+                FrontMatterEdit::Set {
+                    key: "title".into(),
+                    value: "Goodbye \"world\"".into(),
+                    if_missing: false,
+                }
+            )
+            .unwrap(),
+            "---\n# Written by hand\ntitle: \"Goodbye \\\"world\\\"\"\nlayout: post # or page\nextra:\n  nested: true\n\ndate: 2024-01-01\n---\nbody\n"
+        );
+        assert_eq!(
+            edit(
+                source,
+                FrontMatterEdit::Set {
+                    key: "layout".into(),
+                    value: "page".into(),
+                    if_missing: false,
+                }
+            )
+            .unwrap(),
+            "---\n# Written by hand\ntitle: \"Hello, world\"   \nlayout: page # or page\nextra:\n  nested: true\n\ndate: 2024-01-01\n---\nbody\n"
+        );
+        assert_eq!(
+            edit(
+                source,
+                FrontMatterEdit::Remove {
+                    key: "extra".into()
+                }
+            )
+            .unwrap(),
+            "---\n# Written by hand\ntitle: \"Hello, world\"   \nlayout: post # or page\n\ndate: 2024-01-01\n---\nbody\n"
+        );
+        assert_eq!(
+            edit(
+                source,
+                FrontMatterEdit::RenameKey {
+                    from: "layout".into(),
+                    to: "template".into()
+                }
+            )
+            .unwrap(),
+            "---\n# Written by hand\ntitle: \"Hello, world\"   \ntemplate: post # or page\nextra:\n  nested: true\n\ndate: 2024-01-01\n---\nbody\n"
+        );
+    }
+}
@@ -4,21 +4,30 @@ use std::{
     ffi::OsStr,
     ops::{Range, RangeFrom},
     path::{Path, PathBuf},
+    time::SystemTime,
 };
 
 use chrono::{DateTime, Datelike, Local, TimeZone, Utc};
 use miette::Diagnostic;
+use pulldown_cmark::{Event, Options, Parser, Tag};
 use serde::Deserialize;
 use thiserror::Error;
 use tokio::fs::read_to_string;
 use tracing::debug;
 
-use self::parsing_helpers::{
-    deserialize_comma_separated_list, deserialize_date, find_frontmatter_delimiter,
+use crate::{
+    html_links::{LinkKind, PageLinks},
+    renderer::LinkDest,
 };
 
+pub(crate) use self::parsing_helpers::deserialize_comma_separated_list;
+use self::parsing_helpers::{deserialize_date, find_frontmatter_delimiter};
+
+mod edit;
 mod parsing_helpers;
 
+pub use edit::FrontMatterEdit;
+
 type Date = DateTime<Utc>;
 
 #[derive(Deserialize, Debug)]
@@ -26,14 +35,21 @@ pub struct FrontMatter {
     layout: String,
     #[serde(default)]
     title: String,
+    /// A short summary of the page for search results and link previews
+    description: Option<String>,
+    /// An image that represents the page, such as a cover photo
+    image: Option<String>,
+    /// Where the page was originally published, for pages republished from
+    /// elsewhere
+    canonical_url: Option<String>,
     #[serde(default)]
     #[serde(deserialize_with = "deserialize_date")]
     date: Option<Date>,
-    #[allow(unused)]
+    /// Whether readers may comment on the page
     comments: Option<bool>,
-    #[allow(unused)]
-    categories: Option<Vec<String>>,
-    #[allow(unused)]
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_comma_separated_list")]
+    categories: Vec<String>,
     #[serde(default)]
     #[serde(deserialize_with = "deserialize_comma_separated_list")]
     tags: Vec<String>,
@@ -44,6 +60,28 @@ pub struct FrontMatter {
     permalink: Option<String>,
     #[serde(default = "mk_true")]
     published: bool,
+    /// Makes the page a post or a page regardless of where it is
+    kind: Option<PageKind>,
+    /// The name of a series of posts this post belongs to
+    series: Option<String>,
+    /// Where the page goes among its siblings in a section, lowest first
+    weight: Option<i64>,
+    /// Whether to replace `{{ site.* }}` placeholders in the contents
+    #[serde(default)]
+    vars: bool,
+    /// Whether to show the page's revision history
+    #[serde(default)]
+    changelog: bool,
+    /// Stylesheets to include on this page only
+    #[serde(default)]
+    css: Vec<String>,
+    /// Scripts to include on this page only
+    #[serde(default)]
+    js: Vec<String>,
+    /// Markdown passes to skip on this page
+    #[serde(default)]
+    #[serde(deserialize_with = "deserialize_comma_separated_list")]
+    disable_passes: Vec<String>,
 }
 
 fn mk_true() -> bool {
@@ -56,7 +94,8 @@ pub enum SourceFormat {
     Markdown,
 }
 
-#[derive(PartialEq, Debug, Clone, Copy)]
+#[derive(PartialEq, Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum PageKind {
     Page,
     Post,
@@ -85,16 +124,33 @@ pub struct PageSource {
     frontmatter: Option<Range<usize>>,
     mainmatter: RangeFrom<usize>,
     parsed_frontmatter: Option<FrontMatter>,
+    /// The other files in the directory of a post bundle, which are copied
+    /// alongside the post
+    bundle_files: Vec<PathBuf>,
+    /// When the source file was last changed, if it was loaded from a file
+    modified: Option<SystemTime>,
 }
 
+/// Where posts are kept, unless `Site.toml` says otherwise
+pub const DEFAULT_POSTS_DIR: &str = "_posts";
+
+/// Where unfinished posts are kept. They are only loaded when unpublished
+/// posts are included.
+pub const DRAFTS_DIR: &str = "_drafts";
+
+/// The names a post's source file can have within a bundle directory
+const BUNDLE_INDEX_FILES: &[&str] = &["index.md", "index.markdown", "index.html", "index.htm"];
+
 impl PageSource {
     /// Reads the file `filename` into a `Page`
     ///
     /// The `root_dir` specifies the root directory for the site. This page will
-    /// be given a path relative to the root directory.
+    /// be given a path relative to the root directory. Pages within any of
+    /// `posts_roots`, which are also relative to the root directory, are posts.
     pub async fn from_file(
         filename: impl Into<PathBuf>,
         root_dir: &Path,
+        posts_roots: &[PathBuf],
     ) -> Result<Self, PageLoadError> {
         let filename: PathBuf = filename.into();
 
@@ -103,28 +159,49 @@ impl PageSource {
         let contents = read_to_string(&filename)
             .await
             .map_err(PageLoadError::ReadingPostContents)?;
-        Ok(Self::from_string(
-            pathdiff::diff_paths(filename, root_dir).unwrap(),
-            kind,
-            contents,
-        ))
+        let modified = tokio::fs::metadata(&filename)
+            .await
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        Ok(Self {
+            modified,
+            ..Self::from_string_with_roots(
+                pathdiff::diff_paths(filename, root_dir).unwrap(),
+                kind,
+                contents,
+                posts_roots,
+            )
+        })
     }
 
+    /// Creates a page from its source, where pages in `_posts` and `_drafts`
+    /// are posts.
     pub fn from_string(
         source: impl Into<PathBuf>,
         format: SourceFormat,
         contents: impl ToString,
+    ) -> Self {
+        Self::from_string_with_roots(
+            source,
+            format,
+            contents,
+            &[PathBuf::from(DEFAULT_POSTS_DIR), PathBuf::from(DRAFTS_DIR)],
+        )
+    }
+
+    /// Creates a page from its source, where pages in any of `posts_roots` are
+    /// posts.
+    ///
+    /// A `kind` in the frontmatter overrides this.
+    pub fn from_string_with_roots(
+        source: impl Into<PathBuf>,
+        format: SourceFormat,
+        contents: impl ToString,
+        posts_roots: &[PathBuf],
     ) -> Self {
         let source = source.into();
         debug!("creating page with source path `{}`", source.display());
         let contents = contents.to_string();
-        // FIXME: we need to determine the kind more precisely, since we might be loading from a
-        // directory other than _posts
-        let kind = if source.components().next().unwrap().as_os_str() == OsStr::new("_posts") {
-            PageKind::Post
-        } else {
-            PageKind::Page
-        };
         let frontmatter = find_frontmatter_delimiter(&contents).and_then(|range| {
             let start = range.end;
             let ending_delimiter = find_frontmatter_delimiter(&contents[start..])?;
@@ -138,10 +215,21 @@ impl PageSource {
             None => (None, 0..),
         };
 
-        let parsed_frontmatter = frontmatter
+        let parsed_frontmatter: Option<FrontMatter> = frontmatter
             .as_ref()
             .and_then(|frontmatter| serde_yaml::from_str(&contents[frontmatter.clone()]).ok());
 
+        let kind = parsed_frontmatter
+            .as_ref()
+            .and_then(|front| front.kind)
+            .unwrap_or_else(|| {
+                if posts_roots.iter().any(|root| source.starts_with(root)) {
+                    PageKind::Post
+                } else {
+                    PageKind::Page
+                }
+            });
+
         Self {
             kind,
             format,
@@ -150,9 +238,38 @@ impl PageSource {
             frontmatter,
             mainmatter,
             parsed_frontmatter,
+            bundle_files: vec![],
+            modified: None,
         }
     }
 
+    /// Loads a post bundle, which is a directory containing the post in
+    /// `index.md` along with any images or other files it uses.
+    ///
+    /// Returns `None` if the directory does not contain an index file.
+    pub async fn from_bundle(
+        dir: &Path,
+        root_dir: &Path,
+        posts_roots: &[PathBuf],
+    ) -> Result<Option<Self>, PageLoadError> {
+        let Some(index) = BUNDLE_INDEX_FILES
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.is_file())
+        else {
+            return Ok(None);
+        };
+        let mut page = Self::from_file(&index, root_dir, posts_roots).await?;
+        page.bundle_files = walkdir::WalkDir::new(dir)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_file() && entry.path() != index)
+            .map(|entry| entry.into_path())
+            .collect();
+        Ok(Some(page))
+    }
+
     pub fn raw_frontmatter(&self) -> Option<&str> {
         self.frontmatter
             .as_ref()
@@ -173,6 +290,28 @@ impl PageSource {
             .map(|frontmatter| frontmatter.title.as_str())
     }
 
+    /// Returns the description from the frontmatter, if one is given.
+    pub fn description(&self) -> Option<&str> {
+        self.frontmatter()
+            .and_then(|frontmatter| frontmatter.description.as_deref())
+    }
+
+    /// Returns the URL the page was originally published at, if it was
+    /// republished from elsewhere.
+    pub fn canonical_url(&self) -> Option<&str> {
+        self.frontmatter()
+            .and_then(|frontmatter| frontmatter.canonical_url.as_deref())
+    }
+
+    /// Returns the image from the frontmatter, if one is given.
+    ///
+    /// Paths starting with `/` are relative to the site root and others are
+    /// relative to the page's URL.
+    pub fn image(&self) -> Option<&str> {
+        self.frontmatter()
+            .and_then(|frontmatter| frontmatter.image.as_deref())
+    }
+
     pub fn title_slug(&self) -> &str {
         let (_, _, slug) = parse_filename(&self.source).unwrap();
         slug
@@ -190,17 +329,181 @@ impl PageSource {
         self.kind == PageKind::Post
     }
 
+    /// Whether the page is published, meaning it is not a draft and its
+    /// frontmatter doesn't say `published: false`
     pub fn published(&self) -> bool {
+        !self.is_draft() && self.marked_published()
+    }
+
+    /// Whether the frontmatter leaves the page published
+    pub(crate) fn marked_published(&self) -> bool {
         self.parsed_frontmatter
             .as_ref()
             .map(|front| front.published)
             .unwrap_or(true)
     }
 
+    /// Whether the page's contents use placeholders for values from
+    /// `Site.toml`
+    pub fn uses_vars(&self) -> bool {
+        self.parsed_frontmatter
+            .as_ref()
+            .is_some_and(|front| front.vars)
+    }
+
+    /// Whether the page shows its revision history
+    pub fn has_changelog(&self) -> bool {
+        self.parsed_frontmatter
+            .as_ref()
+            .is_some_and(|front| front.changelog)
+    }
+
+    /// Returns the page's `weight`, which orders pages within a section.
+    pub fn weight(&self) -> Option<i64> {
+        self.parsed_frontmatter
+            .as_ref()
+            .and_then(|front| front.weight)
+    }
+
+    /// Returns the name of the series this page belongs to, if any.
+    pub fn series(&self) -> Option<&str> {
+        self.parsed_frontmatter
+            .as_ref()
+            .and_then(|front| front.series.as_deref())
+    }
+
+    /// Returns the tags listed in the frontmatter.
+    pub fn tags(&self) -> &[String] {
+        self.parsed_frontmatter
+            .as_ref()
+            .map_or(&[], |front| front.tags.as_slice())
+    }
+
+    /// Returns the names of the markdown passes this page skips.
+    pub fn disabled_passes(&self) -> &[String] {
+        self.parsed_frontmatter
+            .as_ref()
+            .map_or(&[], |front| front.disable_passes.as_slice())
+    }
+
+    /// Returns the `http` and `https` URLs the page links to, in the order
+    /// they first appear.
+    pub fn external_links(&self) -> Vec<String> {
+        self.external_urls(false)
+    }
+
+    /// Returns the `http` and `https` URLs the page links to or shows images
+    /// from, in the order they first appear.
+    pub fn external_links_and_images(&self) -> Vec<String> {
+        self.external_urls(true)
+    }
+
+    fn external_urls(&self, images: bool) -> Vec<String> {
+        let mut hrefs = vec![];
+        match self.format {
+            SourceFormat::Markdown => {
+                for event in Parser::new_ext(self.mainmatter(), Options::all()) {
+                    match event {
+                        Event::Start(Tag::Link { dest_url, .. }) => {
+                            hrefs.push(dest_url.to_string())
+                        }
+                        Event::Start(Tag::Image { dest_url, .. }) if images => {
+                            hrefs.push(dest_url.to_string())
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            SourceFormat::Html => {
+                let kinds: &[_] = if images {
+                    &[LinkKind::Anchor, LinkKind::Image]
+                } else {
+                    &[LinkKind::Anchor]
+                };
+                let links = PageLinks::scan(self.mainmatter());
+                hrefs.extend(links.targets(kinds).map(str::to_string));
+            }
+        }
+        let mut links: Vec<String> = vec![];
+        for href in hrefs {
+            let Ok(dest) = LinkDest::parse(&href);
+            if let Some(url) = dest.external_url() {
+                if !links.iter().any(|link| link == url.as_str()) {
+                    links.push(url.to_string());
+                }
+            }
+        }
+        links
+    }
+
+    /// Returns the categories listed in the frontmatter.
+    pub fn categories(&self) -> &[String] {
+        self.parsed_frontmatter
+            .as_ref()
+            .map_or(&[], |front| front.categories.as_slice())
+    }
+
+    /// Returns whether the page allows comments, which it does unless its
+    /// frontmatter says `comments: false`.
+    pub fn comments_enabled(&self) -> bool {
+        self.parsed_frontmatter
+            .as_ref()
+            .and_then(|front| front.comments)
+            .unwrap_or(true)
+    }
+
+    /// Returns the extra stylesheets requested in the frontmatter.
+    ///
+    /// Paths starting with `/` are relative to the site root and others are
+    /// relative to the page's source file.
+    pub fn extra_css(&self) -> &[String] {
+        self.parsed_frontmatter
+            .as_ref()
+            .map_or(&[], |front| front.css.as_slice())
+    }
+
+    /// Returns the extra scripts requested in the frontmatter.
+    ///
+    /// These are resolved the same way as [`PageSource::extra_css`].
+    pub fn extra_js(&self) -> &[String] {
+        self.parsed_frontmatter
+            .as_ref()
+            .map_or(&[], |front| front.js.as_slice())
+    }
+
     /// Returns the path to this page's source file relative to the site root.
     pub fn source_path(&self) -> &Path {
         self.source.as_path()
     }
+
+    /// Returns the full source of the page, including the frontmatter.
+    pub fn contents(&self) -> &str {
+        &self.contents
+    }
+
+    /// Whether this is a post in the drafts directory
+    pub fn is_draft(&self) -> bool {
+        self.is_post() && self.source.starts_with(DRAFTS_DIR)
+    }
+
+    /// Whether this page is a post bundle, meaning its source is the index
+    /// file of a directory
+    pub fn is_bundle(&self) -> bool {
+        self.is_post()
+            && self
+                .source
+                .file_name()
+                .and_then(OsStr::to_str)
+                .is_some_and(|name| BUNDLE_INDEX_FILES.contains(&name))
+    }
+
+    /// Returns the files other than the post itself in a post bundle.
+    ///
+    /// These are copied into the post's output directory so it can link to
+    /// them with relative paths.
+    pub fn bundle_files(&self) -> &[PathBuf] {
+        &self.bundle_files
+    }
 }
 
 pub trait PageMetadata {
@@ -236,7 +539,19 @@ impl PageMetadata for PageSource {
     fn publish_date(&self) -> Option<Date> {
         let from_filename = {
             let (date, _, _) = parse_filename(&self.source).unwrap();
-            Some(date)
+            // Drafts usually don't have a date yet, so they are shown as if
+            // they were published on the day they last changed. Unlike today's
+            // date, this keeps their URL the same from one day to the next.
+            if self.is_draft() && date.timestamp() == 0 {
+                self.modified
+                    .map_or_else(Local::now, DateTime::<Local>::from)
+                    .date_naive()
+                    .and_hms_opt(0, 0, 0)
+                    .and_then(|today| today.and_local_timezone(Local).single())
+                    .map(|today| today.with_timezone(&Utc))
+            } else {
+                Some(date)
+            }
         };
         self.parsed_frontmatter
             .as_ref()
@@ -296,7 +611,24 @@ fn parse_filename(path: &Path) -> Result<(Date, SourceFormat, &str), ParseFilena
     };
 
     // FIXME: replace unwraps with diagnostics to explain why the date is wrong.
-    let filename = path.file_stem().unwrap().to_str().unwrap();
+    let mut filename = path.file_stem().unwrap().to_str().unwrap();
+    // The date and slug of a post bundle come from its directory. Drafts
+    // usually don't have a date yet.
+    if filename == "index" {
+        let is_draft = path
+            .parent()
+            .and_then(Path::parent)
+            .is_some_and(|parent| parent.ends_with(DRAFTS_DIR));
+        if let Some(dir) = path
+            .parent()
+            .and_then(Path::file_name)
+            .and_then(OsStr::to_str)
+        {
+            if parse_date_from_filename(dir).is_some() || is_draft {
+                filename = dir;
+            }
+        }
+    }
     match parse_date_from_filename(filename) {
         Some((date, rest)) => Ok((date, kind, rest)),
         None => Ok((
@@ -337,7 +669,7 @@ mod test {
     use super::{parse_filename, FrontMatter, PageSource};
     use chrono::{DateTime, Local, TimeZone, Utc};
     use miette::IntoDiagnostic;
-    use std::path::Path;
+    use std::path::{Path, PathBuf};
 
     #[test]
     fn parse_bare_filename() {
@@ -394,6 +726,68 @@ Coming soon!
         );
     }
 
+    #[test]
+    fn drafts() {
+        let draft = PageSource::from_string(
+            Path::new("_drafts").join("hello.md"),
+            SourceFormat::Markdown,
+            "---\nlayout: post\n---\nNot done yet\n",
+        );
+        assert!(draft.is_post() && draft.is_draft());
+        let today = Local::now().date_naive();
+        assert_eq!(
+            draft
+                .publish_date()
+                .map(|date| date.with_timezone(&Local).date_naive()),
+            Some(today)
+        );
+        assert!(draft.url().ends_with("/hello/"));
+
+        // A draft loaded from a file is dated the day it last changed
+        let changed = Local.with_ymd_and_hms(2021, 1, 14, 15, 30, 0).unwrap();
+        let draft = PageSource {
+            modified: Some(changed.into()),
+            ..draft
+        };
+        assert_eq!(
+            draft.publish_date(),
+            Some(
+                Local
+                    .with_ymd_and_hms(2021, 1, 14, 0, 0, 0)
+                    .unwrap()
+                    .with_timezone(&Utc)
+            )
+        );
+        assert!(draft.url().ends_with("2021/01/14/hello/"));
+
+        let bundle = PageSource::from_string(
+            Path::new("_drafts").join("hello").join("index.md"),
+            SourceFormat::Markdown,
+            "---\nlayout: post\n---\n",
+        );
+        assert_eq!(bundle.title_slug(), "hello");
+
+        let dated = PageSource::from_string(
+            Path::new("_drafts").join("2021-01-14-hello.md"),
+            SourceFormat::Markdown,
+            "---\nlayout: post\n---\n",
+        );
+        assert_eq!(
+            dated.publish_date(),
+            Local
+                .with_ymd_and_hms(2021, 1, 14, 0, 0, 0)
+                .single()
+                .map(|date| date.with_timezone(&Utc))
+        );
+
+        let post = PageSource::from_string(
+            Path::new("_posts").join("2021-01-14-hello.md"),
+            SourceFormat::Markdown,
+            "---\nlayout: post\n---\n",
+        );
+        assert!(!post.is_draft());
+    }
+
     #[test]
     fn url_from_path_path() {
         assert_eq!(
@@ -424,6 +818,26 @@ categories:
         Ok(())
     }
 
+    #[test]
+    fn tags_and_categories() {
+        let post = PageSource::from_string(
+            "hello.md",
+            SourceFormat::Markdown,
+            "---\nlayout: post\ntags: rust, wasm\ncategories:\n- Programming\n- Web\ncomments: false\n---\n",
+        );
+        assert_eq!(post.tags(), ["rust", "wasm"]);
+        assert_eq!(post.categories(), ["Programming", "Web"]);
+        assert!(!post.comments_enabled());
+
+        let post = PageSource::from_string(
+            "hello.md",
+            SourceFormat::Markdown,
+            "---\nlayout: post\ncategories:\n---\n",
+        );
+        assert!(post.categories().is_empty());
+        assert!(post.comments_enabled());
+    }
+
     #[test]
     fn parse_contents_with_frontmatter() {
         const SRC: &str = r#"---
@@ -526,6 +940,46 @@ Hello, world!
         assert_eq!(post.mainmatter(), "Hello, world!\r\n");
     }
 
+    #[test]
+    fn kind_from_posts_roots() {
+        let roots = [PathBuf::from("content/blog")];
+        let post = PageSource::from_string_with_roots(
+            "content/blog/2024-01-01-hello.md",
+            SourceFormat::Markdown,
+            "---\nlayout: post\n---\n",
+            &roots,
+        );
+        assert!(post.is_post());
+        assert_eq!(post.url(), "blog/2024/01/01/hello/");
+
+        let page = PageSource::from_string_with_roots(
+            "_posts/2024-01-01-hello.md",
+            SourceFormat::Markdown,
+            "---\nlayout: post\n---\n",
+            &roots,
+        );
+        assert!(!page.is_post());
+
+        let overridden = PageSource::from_string_with_roots(
+            "content/blog/archive.md",
+            SourceFormat::Markdown,
+            "---\nlayout: page\nkind: page\n---\n",
+            &roots,
+        );
+        assert!(!overridden.is_post());
+    }
+
+    #[test]
+    fn parse_bundle_filename() {
+        let post = PageSource::from_string(
+            "_posts/2022-10-14-hello/index.md",
+            SourceFormat::Markdown,
+            "---\nlayout: post\n---\n",
+        );
+        assert!(post.is_bundle());
+        assert_eq!(post.url(), "blog/2022/10/14/hello/");
+    }
+
     #[test]
     fn parse_filenames() {
         assert!(
@@ -607,4 +1061,26 @@ tags: tag1, tag2
         assert_eq!(front.tags, vec!["tag1".to_string(), "tag2".to_string()]);
         Ok(())
     }
+
+    #[test]
+    fn external_links() {
+        let page = PageSource::from_string(
+            "about.md",
+            SourceFormat::Markdown,
+            "---\nlayout: page\n---\n[a](https://example.com/a) [b](./b.md) \
+             ![c](http://example.com/c.png) <https://example.com/a> [d](mailto:me@example.com)\n",
+        );
+        assert_eq!(page.external_links(), ["https://example.com/a"]);
+        assert_eq!(
+            page.external_links_and_images(),
+            ["https://example.com/a", "http://example.com/c.png"]
+        );
+
+        let page = PageSource::from_string(
+            "about.html",
+            SourceFormat::Html,
+            "---\nlayout: page\n---\n<a href=\"https://example.com/\">x</a><a href=\"/y\">y</a>",
+        );
+        assert_eq!(page.external_links(), ["https://example.com/"]);
+    }
 }
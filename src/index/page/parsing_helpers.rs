@@ -26,11 +26,24 @@ fn date_from_str(s: &str) -> ParseResult<Date> {
         })
 }
 
+/// Reads a list that is written either as a YAML list or as a single string
+/// of comma separated items. An empty value is an empty list.
 pub fn deserialize_comma_separated_list<'de, D: Deserializer<'de>>(
     d: D,
 ) -> Result<Vec<String>, D::Error> {
-    let s = <&str as Deserialize>::deserialize(d)?;
-    Ok(s.split(',').map(|s| s.trim().to_string()).collect())
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum List {
+        CommaSeparated(String),
+        Items(Vec<String>),
+    }
+
+    let items = match <Option<List> as Deserialize>::deserialize(d)? {
+        Some(List::CommaSeparated(s)) => s.split(',').map(|s| s.trim().to_string()).collect(),
+        Some(List::Items(items)) => items,
+        None => vec![],
+    };
+    Ok(items.into_iter().filter(|item| !item.is_empty()).collect())
 }
 
 const FRONTMATTER_DELIMITER: &str = "---";
@@ -0,0 +1,118 @@
+//! Finds posts that are related to each page by their tags and categories
+
+use std::{cmp::Reverse, collections::HashMap};
+
+use serde::Deserialize;
+
+use super::{PageMetadata, SiteIndex};
+
+/// The `[related_posts]` section of `Site.toml`
+#[derive(Deserialize, Debug)]
+pub struct RelatedPostsConfig {
+    /// The most related posts to list for each page
+    #[serde(default = "default_max")]
+    pub max: usize,
+}
+
+impl Default for RelatedPostsConfig {
+    fn default() -> Self {
+        Self { max: default_max() }
+    }
+}
+
+fn default_max() -> usize {
+    5
+}
+
+impl SiteIndex {
+    /// Ranks the posts that share tags or categories with each page.
+    ///
+    /// Each shared tag or category counts for one point, and ties go to the
+    /// newer post. The result has an entry for each page, in the same order as
+    /// [`SiteIndex::all_pages`], listing the indices of up to `max` related
+    /// posts, best first.
+    pub fn related_posts(&self, max: usize) -> Vec<Vec<usize>> {
+        // Index the posts by term so each page only looks at posts it has
+        // something in common with
+        let mut posts_by_term: HashMap<(bool, &str), Vec<usize>> = HashMap::new();
+        for (i, page) in self.pages.iter().enumerate() {
+            if !page.is_post() {
+                continue;
+            }
+            for tag in page.tags() {
+                posts_by_term.entry((true, tag)).or_default().push(i);
+            }
+            for category in page.categories() {
+                posts_by_term.entry((false, category)).or_default().push(i);
+            }
+        }
+
+        self.pages
+            .iter()
+            .enumerate()
+            .map(|(i, page)| {
+                let terms = page
+                    .tags()
+                    .iter()
+                    .map(|tag| (true, tag.as_str()))
+                    .chain(page.categories().iter().map(|c| (false, c.as_str())));
+                let mut scores: HashMap<usize, usize> = HashMap::new();
+                for term in terms {
+                    for &post in posts_by_term.get(&term).into_iter().flatten() {
+                        if post != i {
+                            *scores.entry(post).or_default() += 1;
+                        }
+                    }
+                }
+
+                let mut related: Vec<_> = scores.into_iter().collect();
+                related.sort_by_key(|&(post, score)| {
+                    (
+                        Reverse(score),
+                        Reverse(self.pages[post].publish_date()),
+                        post,
+                    )
+                });
+                related
+                    .into_iter()
+                    .take(max)
+                    .map(|(post, _)| post)
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::index::{PageSource, SiteIndex, SourceFormat};
+
+    fn post(path: &str, tags: &str, categories: &str) -> PageSource {
+        PageSource::from_string(
+            path,
+            SourceFormat::Markdown,
+            format!(
+                "---\nlayout: post\ntitle: {path}\ntags: {tags}\ncategories: {categories}\n---\n"
+            ),
+        )
+    }
+
+    #[test]
+    fn ranking() {
+        let mut site = SiteIndex::default();
+        site.add_page(post("_posts/2024-01-01-a.md", "rust, async", "code"));
+        site.add_page(post("_posts/2024-01-02-b.md", "rust", "code"));
+        site.add_page(post("_posts/2024-01-03-c.md", "rust", ""));
+        site.add_page(post("_posts/2024-01-04-d.md", "cooking", ""));
+        site.add_page(post("_posts/2024-01-05-e.md", "rust, async", ""));
+        site.add_page(post("about.md", "rust", ""));
+
+        let related = site.related_posts(5);
+        // a shares two terms with b and e, but e is newer
+        assert_eq!(related[0], vec![4, 1, 2]);
+        assert_eq!(related[3], Vec::<usize>::new());
+        // Pages can have related posts, but are never related posts
+        assert_eq!(related[5], vec![4, 2, 1, 0]);
+        assert_eq!(site.related_posts(1)[0], vec![4]);
+    }
+}
@@ -0,0 +1,474 @@
+//! Planning consistent names for post source files
+//!
+//! Posts can either be a single file, like `_posts/2024-01-01-hello.md`, or a
+//! bundle directory with the post in `index.md`, like
+//! `_posts/2024-01-01-hello/index.md`. Renaming a post moves its source file
+//! and updates the source links in other pages that point to it, as well as
+//! the syndication state, which is keyed by source path.
+
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+};
+
+use chrono::Local;
+use tracing::warn;
+
+use crate::renderer::code_ranges;
+
+use super::{FrontMatterEdit, PageMetadata, PageSource, SiteIndex, SiteMetadata};
+
+/// How a post's source should be stored
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PostLayout {
+    /// A single file named after the post
+    Flat,
+    /// A directory named after the post, with the post in `index.md`
+    Bundle,
+}
+
+#[derive(Debug, Default)]
+pub struct RenameOptions {
+    /// Convert posts to this layout, or leave them as they are if `None`
+    pub layout: Option<PostLayout>,
+    /// Derive the slug from the post's title rather than keeping the current
+    /// one
+    pub slugify: bool,
+}
+
+/// A post whose source file should move
+#[derive(Debug, PartialEq)]
+pub struct PostRename {
+    /// The current source path, relative to the site root
+    pub from: PathBuf,
+    /// The new source path, relative to the site root
+    pub to: PathBuf,
+}
+
+/// Decides on new source paths for the posts in `site`.
+///
+/// The date prefix comes from the post's publication date, so a date given in
+/// the frontmatter wins over an outdated one in the filename. Posts that
+/// already have the right name are left out.
+pub fn plan_post_renames(site: &SiteIndex, options: &RenameOptions) -> Vec<PostRename> {
+    let mut renames = vec![];
+    let mut targets: HashMap<PathBuf, &Path> = HashMap::new();
+    // Drafts get their date when they are published
+    for post in site.posts().filter(|post| !post.is_draft()) {
+        let from = post.source_path();
+        let Some(to) = new_source_path(post, options) else {
+            continue;
+        };
+        if to == from {
+            continue;
+        }
+        if let Some(other) = targets.get(&to) {
+            warn!(
+                "not renaming `{}` because `{}` would have the same name",
+                from.display(),
+                other.display(),
+            );
+            continue;
+        }
+        if site.find_page_by_source_path(&to).is_some() || site.root_dir().join(&to).exists() {
+            warn!(
+                "not renaming `{}` because `{}` already exists",
+                from.display(),
+                to.display()
+            );
+            continue;
+        }
+        targets.insert(to.clone(), from);
+        renames.push(PostRename {
+            from: from.to_path_buf(),
+            to,
+        });
+    }
+    renames
+}
+
+/// The changes that publish a draft
+#[derive(Debug, PartialEq)]
+pub struct DraftPublish {
+    pub rename: PostRename,
+    /// The draft's new source, if it has `published: false` to remove or
+    /// relative links that have to follow it to its new directory
+    pub contents: Option<String>,
+}
+
+/// Decides where `draft` goes when it is published today, or returns `None` if
+/// it isn't a draft.
+///
+/// The date prefix is today's, whatever date the draft may have been given.
+pub fn plan_publish(site: &SiteIndex, draft: &PageSource) -> Option<DraftPublish> {
+    if !draft.is_draft() {
+        return None;
+    }
+    let from = draft.source_path();
+    let name = format!("{}-{}", Local::now().format("%Y-%m-%d"), draft.title_slug());
+    let posts_dir = site.config().posts_dir();
+    let to = if draft.is_bundle() {
+        posts_dir.join(name).join(from.file_name()?)
+    } else {
+        posts_dir.join(name).with_extension(from.extension()?)
+    };
+    let rename = PostRename {
+        from: from.to_path_buf(),
+        to,
+    };
+
+    // Both edits go into one source, so neither undoes the other
+    let linked = rewrite_source_links(site, draft, std::slice::from_ref(&rename));
+    let relinked = linked
+        .as_ref()
+        .map(|contents| PageSource::from_string(from, draft.source_format(), contents));
+    let page = relinked.as_ref().unwrap_or(draft);
+    let unmarked = if page.marked_published() {
+        None
+    } else {
+        let edit = FrontMatterEdit::Remove {
+            key: "published".into(),
+        };
+        // The frontmatter was already parsed when the site was loaded
+        page.edit_frontmatter(&edit).ok().flatten()
+    };
+    Some(DraftPublish {
+        rename,
+        contents: unmarked.or(linked),
+    })
+}
+
+fn new_source_path(post: &PageSource, options: &RenameOptions) -> Option<PathBuf> {
+    let from = post.source_path();
+    let mut layout = if post.is_bundle() {
+        PostLayout::Bundle
+    } else {
+        PostLayout::Flat
+    };
+    let posts_dir = match layout {
+        PostLayout::Flat => from.parent()?,
+        PostLayout::Bundle => from.parent()?.parent()?,
+    };
+    if let Some(new_layout) = options.layout {
+        if new_layout == PostLayout::Flat && !post.bundle_files().is_empty() {
+            warn!(
+                "keeping `{}` as a bundle because it contains other files",
+                from.display()
+            );
+        } else {
+            layout = new_layout;
+        }
+    }
+
+    let date = post
+        .publish_date()?
+        .with_timezone(&Local)
+        .format("%Y-%m-%d");
+    let slug = match title(post).filter(|_| options.slugify) {
+        Some(title) => slug::slugify(title),
+        None => post.title_slug().to_string(),
+    };
+    let name = format!("{date}-{slug}");
+    let extension = from.extension()?;
+
+    Some(match layout {
+        PostLayout::Flat => posts_dir.join(name).with_extension(extension),
+        PostLayout::Bundle => posts_dir.join(name).join("index").with_extension(extension),
+    })
+}
+
+/// Finds the title in the frontmatter or else in a leading `#` heading
+fn title(post: &PageSource) -> Option<&str> {
+    post.title()
+        .filter(|title| !title.is_empty())
+        .or_else(|| {
+            post.mainmatter()
+                .lines()
+                .map(str::trim)
+                .find(|line| !line.is_empty())?
+                .strip_prefix("# ")
+        })
+        .map(str::trim)
+}
+
+/// Updates the source links in `page` to account for `renames`, returning the
+/// new contents of the page if any links changed.
+///
+/// If the page itself is being moved, its relative links to other pages are
+/// updated to work from the new location.
+pub fn rewrite_source_links(
+    site: &SiteIndex,
+    page: &PageSource,
+    renames: &[PostRename],
+) -> Option<String> {
+    let renamed = |path: &Path| {
+        renames
+            .iter()
+            .find(|rename| rename.from == path)
+            .map(|rename| rename.to.clone())
+    };
+    let old_dir = page.source_path().parent().unwrap_or(Path::new(""));
+    let new_path = renamed(page.source_path());
+    let new_dir = new_path
+        .as_deref()
+        .and_then(Path::parent)
+        .unwrap_or(old_dir)
+        .to_path_buf();
+
+    rewrite_links(page.contents(), |dest| {
+        let (path, fragment) = match dest.split_once('#') {
+            Some((path, fragment)) => (path, Some(fragment)),
+            None => (dest, None),
+        };
+        if path.is_empty() || path.contains("://") || path.starts_with("mailto:") {
+            return None;
+        }
+
+        let new_dest = if let Some(absolute) = path.strip_prefix('/') {
+            format!("/{}", to_link(&renamed(Path::new(absolute))?))
+        } else {
+            let target = normalize_path(&old_dir.join(path.strip_prefix("./").unwrap_or(path)));
+            site.find_page_by_source_path(&target)?;
+            let new_target = renamed(&target);
+            if new_target.is_none() && new_path.is_none() {
+                return None;
+            }
+            let new_target = new_target.unwrap_or(target);
+            let relative = pathdiff::diff_paths(&new_target, &new_dir)?;
+            let prefix = if path.starts_with("./") && !relative.starts_with("..") {
+                "./"
+            } else {
+                ""
+            };
+            format!("{prefix}{}", to_link(&relative))
+        };
+        Some(match fragment {
+            Some(fragment) => format!("{new_dest}#{fragment}"),
+            None => new_dest,
+        })
+    })
+}
+
+fn to_link(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+/// Resolves `.` and `..` in a relative path
+pub(crate) fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Calls `f` on the destination of every Markdown link, both inline links like
+/// `[text](dest)` and reference definitions like `[id]: dest`, and replaces the
+/// destination when `f` returns `Some`.
+///
+/// Links in code spans and code blocks are left alone.
+fn rewrite_links(contents: &str, mut f: impl FnMut(&str) -> Option<String>) -> Option<String> {
+    let code = code_ranges(contents);
+    let in_code = |pos: usize| code.iter().any(|code| code.contains(&pos));
+    let mut output = String::with_capacity(contents.len());
+    let mut changed = false;
+    let mut line_start = 0;
+    for line in contents.split_inclusive('\n') {
+        let offset = line_start;
+        line_start += line.len();
+        let mut rest = line;
+        // reference definitions
+        let trimmed = line.trim_start();
+        if let Some(end) = trimmed.strip_prefix('[').and_then(|s| s.find("]:")) {
+            let prefix_len = line.len() - trimmed.len() + 1 + end + 2;
+            let (prefix, after) = line.split_at(prefix_len);
+            let dest_start = after.len() - after.trim_start().len();
+            let dest_len = after[dest_start..]
+                .find(char::is_whitespace)
+                .unwrap_or(after.len() - dest_start);
+            let dest = &after[dest_start..dest_start + dest_len];
+            if let Some(new_dest) = f(dest).filter(|_| !in_code(offset)) {
+                output.push_str(prefix);
+                output.push_str(&after[..dest_start]);
+                output.push_str(&new_dest);
+                output.push_str(&after[dest_start + dest_len..]);
+                changed = true;
+                continue;
+            }
+        }
+        // inline links
+        while let Some(start) = rest.find("](") {
+            let (before, after) = rest.split_at(start + 2);
+            output.push_str(before);
+            let dest_len = after
+                .find(|c: char| c == ')' || c.is_whitespace())
+                .unwrap_or(after.len());
+            let dest = &after[..dest_len];
+            let pos = offset + (line.len() - rest.len()) + start;
+            match f(dest).filter(|_| !in_code(pos)) {
+                Some(new_dest) => {
+                    output.push_str(&new_dest);
+                    changed = true;
+                }
+                None => output.push_str(dest),
+            }
+            rest = &after[dest_len..];
+        }
+        output.push_str(rest);
+    }
+    changed.then_some(output)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use chrono::Local;
+
+    use crate::index::{PageSource, SiteIndex, SourceFormat};
+
+    use super::{
+        plan_post_renames, plan_publish, rewrite_source_links, DraftPublish, PostLayout,
+        PostRename, RenameOptions,
+    };
+
+    fn site() -> SiteIndex {
+        let mut site = SiteIndex::default();
+        site.add_page(PageSource::from_string(
+            "_posts/2024-01-01-hello.md",
+            SourceFormat::Markdown,
+            "---\nlayout: post\ndate: 2024-01-02 10:00\n---\n# Hello, World!\n",
+        ));
+        site.add_page(PageSource::from_string(
+            "_posts/2024-02-01-second.md",
+            SourceFormat::Markdown,
+            "---\nlayout: post\n---\nSee [the first](./2024-01-01-hello.md#intro) and [about](../about.md).\n\n[ref]: 2024-01-01-hello.md\n",
+        ));
+        site.add_page(PageSource::from_string(
+            "about.md",
+            SourceFormat::Markdown,
+            "---\nlayout: page\n---\n[first](/_posts/2024-01-01-hello.md)\n\n`[x](/_posts/2024-01-01-hello.md)`\n\n```\n[y]: /_posts/2024-01-01-hello.md\n```\n",
+        ));
+        site.add_page(PageSource::from_string(
+            "_drafts/next.md",
+            SourceFormat::Markdown,
+            "---\nlayout: post\n---\nComing soon\n",
+        ));
+        site
+    }
+
+    #[test]
+    fn plan_renames() {
+        let site = site();
+        let renames = plan_post_renames(
+            &site,
+            &RenameOptions {
+                layout: None,
+                slugify: true,
+            },
+        );
+        assert_eq!(
+            renames,
+            [PostRename {
+                from: "_posts/2024-01-01-hello.md".into(),
+                to: "_posts/2024-01-02-hello-world.md".into(),
+            }]
+        );
+
+        let renames = plan_post_renames(
+            &site,
+            &RenameOptions {
+                layout: Some(PostLayout::Bundle),
+                slugify: false,
+            },
+        );
+        assert_eq!(
+            renames.iter().map(|r| r.to.clone()).collect::<Vec<_>>(),
+            [
+                PathBuf::from("_posts/2024-01-02-hello/index.md"),
+                PathBuf::from("_posts/2024-02-01-second/index.md"),
+            ]
+        );
+    }
+
+    #[test]
+    fn publish_drafts() {
+        let site = site();
+        let today = Local::now().format("%Y-%m-%d");
+        let draft = site.find_page_by_source_path("_drafts/next.md".as_ref());
+        assert_eq!(
+            plan_publish(&site, draft.unwrap()),
+            Some(DraftPublish {
+                rename: PostRename {
+                    from: "_drafts/next.md".into(),
+                    to: format!("_posts/{today}-next.md").into(),
+                },
+                contents: None,
+            })
+        );
+
+        let bundle = PageSource::from_string(
+            "_drafts/later/index.md",
+            SourceFormat::Markdown,
+            "---\nlayout: post\npublished: false\n---\nLater\n",
+        );
+        let publish = plan_publish(&site, &bundle).unwrap();
+        assert_eq!(
+            publish.rename.to,
+            PathBuf::from(format!("_posts/{today}-later/index.md"))
+        );
+        assert_eq!(
+            publish.contents.as_deref(),
+            Some("---\nlayout: post\n---\nLater\n")
+        );
+
+        // Links are rewritten for the new directory as well
+        let linked = PageSource::from_string(
+            "_drafts/linked.md",
+            SourceFormat::Markdown,
+            "---\nlayout: post\npublished: false\n---\nSee [hello](../_posts/2024-01-01-hello.md).\n",
+        );
+        assert_eq!(
+            plan_publish(&site, &linked).unwrap().contents.as_deref(),
+            Some("---\nlayout: post\n---\nSee [hello](2024-01-01-hello.md).\n")
+        );
+
+        let post = site.find_page_by_source_path("_posts/2024-02-01-second.md".as_ref());
+        assert_eq!(plan_publish(&site, post.unwrap()), None);
+    }
+
+    #[test]
+    fn rewrite_links() {
+        let site = site();
+        let renames = [
+            PostRename {
+                from: "_posts/2024-01-01-hello.md".into(),
+                to: "_posts/2024-01-02-hello/index.md".into(),
+            },
+            PostRename {
+                from: "_posts/2024-02-01-second.md".into(),
+                to: "_posts/2024-02-01-second/index.md".into(),
+            },
+        ];
+        let page = |path: &str| site.find_page_by_source_path(path.as_ref()).unwrap();
+
+        assert_eq!(
+            rewrite_source_links(&site, page("_posts/2024-02-01-second.md"), &renames).unwrap(),
+            "---\nlayout: post\n---\nSee [the first](../2024-01-02-hello/index.md#intro) and [about](../../about.md).\n\n[ref]: ../2024-01-02-hello/index.md\n",
+        );
+        assert_eq!(
+            rewrite_source_links(&site, page("about.md"), &renames).unwrap(),
+            "---\nlayout: page\n---\n[first](/_posts/2024-01-02-hello/index.md)\n\n`[x](/_posts/2024-01-01-hello.md)`\n\n```\n[y]: /_posts/2024-01-01-hello.md\n```\n",
+        );
+        assert_eq!(
+            rewrite_source_links(&site, page("_posts/2024-01-01-hello.md"), &renames),
+            None
+        );
+    }
+}
@@ -35,11 +35,19 @@
 use generator::GeneratorError;
 use miette::Diagnostic;
 
+pub mod audit;
+pub mod cancel;
+pub mod export;
+pub mod generator;
+pub mod http;
 pub mod index;
+pub mod link_check;
+pub mod lock;
 pub mod renderer;
-pub mod generator;
+pub mod syndication;
 
 mod diagnostics;
+mod html_links;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
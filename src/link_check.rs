@@ -0,0 +1,228 @@
+//! Checking that the links to other sites still work
+//!
+//! Each external URL is requested once, no matter how many pages link to it.
+//! A `HEAD` request is tried first, since it doesn't download the page, and
+//! servers that don't support it are asked again with `GET`. When a link is
+//! dead, the Wayback Machine is asked for an archived copy to link to instead.
+//!
+//! The domains each page links to are also available to themes, as
+//! `page.external_domains` and `site.external_domains`.
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use futures::{stream, StreamExt};
+use miette::Diagnostic;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use thiserror::Error;
+use tracing::{debug, warn};
+use url::Url;
+
+use crate::{
+    http::{HttpClient, HttpError},
+    index::{PageSource, SiteIndex, SiteMetadata},
+};
+
+/// How many links to check at once unless told otherwise
+pub const DEFAULT_CONCURRENCY: usize = 8;
+
+/// A link to another site that no longer works
+#[derive(Diagnostic, Error, Debug)]
+#[error("`{url}` {reason}")]
+#[diagnostic(severity(warning))]
+pub struct DeadLink {
+    pub url: String,
+    /// Why the link is considered dead, such as `responded with 404 Not Found`
+    pub reason: String,
+    /// The pages that link to the URL
+    pub pages: Vec<PathBuf>,
+    /// An archived copy of the page, if the Wayback Machine has one
+    pub snapshot: Option<String>,
+    #[help]
+    help: String,
+}
+
+impl DeadLink {
+    fn new(url: String, reason: String, pages: Vec<PathBuf>, snapshot: Option<String>) -> Self {
+        let linked_from = pages
+            .iter()
+            .map(|page| format!("`{}`", page.display()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let mut help = format!("linked from {linked_from}");
+        if let Some(snapshot) = &snapshot {
+            help += &format!("\nan archived copy is at {snapshot}");
+        }
+        Self {
+            url,
+            reason,
+            pages,
+            snapshot,
+            help,
+        }
+    }
+}
+
+/// Requests every external URL linked from `site`, checking up to
+/// `concurrency` at a time, and returns the ones that don't work.
+pub async fn check_external_links(
+    site: &SiteIndex,
+    client: &HttpClient,
+    concurrency: usize,
+) -> Vec<DeadLink> {
+    let mut links: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    for page in site.all_pages() {
+        for url in page.external_links_and_images() {
+            links
+                .entry(url)
+                .or_default()
+                .push(page.source_path().to_path_buf());
+        }
+    }
+
+    let mut dead: Vec<_> = stream::iter(links)
+        .map(|(url, pages)| async move {
+            let reason = match check_url(client, &url).await {
+                Ok(None) => return None,
+                Ok(Some(reason)) => reason,
+                Err(HttpError::Offline(_)) => {
+                    warn!("not checking `{url}` in offline mode");
+                    return None;
+                }
+                Err(e) => format!("could not be requested: {}", error_chain(&e)),
+            };
+            let snapshot = wayback_snapshot(client, &url).await;
+            Some(DeadLink::new(url, reason, pages, snapshot))
+        })
+        .buffer_unordered(concurrency.max(1))
+        .filter_map(|dead| async move { dead })
+        .collect()
+        .await;
+    // Links finish in whatever order the servers answer
+    dead.sort_by(|a, b| a.url.cmp(&b.url));
+    dead
+}
+
+/// Returns the domains `page` links to, in the order they first appear.
+///
+/// A leading `www.` is dropped, and the site's own domain is left out.
+pub fn external_domains(site: &SiteIndex, page: &PageSource) -> Vec<String> {
+    let own = Url::parse(site.base_url())
+        .ok()
+        .and_then(|url| url.host_str().map(domain_name));
+    let mut domains = vec![];
+    for link in page.external_links() {
+        let Some(domain) = Url::parse(&link)
+            .ok()
+            .and_then(|url| url.host_str().map(domain_name))
+        else {
+            continue;
+        };
+        if Some(&domain) != own.as_ref() && !domains.contains(&domain) {
+            domains.push(domain);
+        }
+    }
+    domains
+}
+
+fn domain_name(host: &str) -> String {
+    host.strip_prefix("www.").unwrap_or(host).to_string()
+}
+
+/// Returns why `url` is dead, or `None` if it works.
+async fn check_url(client: &HttpClient, url: &str) -> Result<Option<String>, HttpError> {
+    debug!("checking `{url}`");
+    match client.send(client.client().head(url)).await {
+        Ok(_) => return Ok(None),
+        // Some servers refuse `HEAD` requests but answer `GET` ones
+        Err(HttpError::Status(
+            _,
+            StatusCode::METHOD_NOT_ALLOWED | StatusCode::FORBIDDEN | StatusCode::NOT_IMPLEMENTED,
+        )) => {}
+        Err(HttpError::Status(_, status)) => return Ok(Some(format!("responded with {status}"))),
+        Err(e) => return Err(e),
+    }
+    // Not through the cache, which is for responses whose bodies are used
+    match client.send(client.client().get(url)).await {
+        Ok(_) => Ok(None),
+        Err(HttpError::Status(_, status)) => Ok(Some(format!("responded with {status}"))),
+        Err(e) => Err(e),
+    }
+}
+
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(error) = source {
+        message += &format!(": {error}");
+        source = error.source();
+    }
+    message
+}
+
+#[derive(Deserialize)]
+struct WaybackResponse {
+    archived_snapshots: WaybackSnapshots,
+}
+
+#[derive(Deserialize)]
+struct WaybackSnapshots {
+    closest: Option<WaybackSnapshot>,
+}
+
+#[derive(Deserialize)]
+struct WaybackSnapshot {
+    available: bool,
+    url: String,
+}
+
+/// Asks the Wayback Machine for the most recent archived copy of `url`.
+async fn wayback_snapshot(client: &HttpClient, url: &str) -> Option<String> {
+    let mut api = url::Url::parse("https://archive.org/wayback/available").unwrap();
+    api.query_pairs_mut().append_pair("url", url);
+    let response = client.get(api.as_str()).await.ok()?;
+    if !response.status.is_success() {
+        return None;
+    }
+    parse_wayback(&response.body)
+}
+
+fn parse_wayback(body: &[u8]) -> Option<String> {
+    let response: WaybackResponse = serde_json::from_slice(body).ok()?;
+    let snapshot = response.archived_snapshots.closest?;
+    snapshot
+        .available
+        .then(|| snapshot.url.replacen("http://", "https://", 1))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::index::{PageSource, SiteIndex, SourceFormat};
+
+    use super::{external_domains, parse_wayback};
+
+    #[test]
+    fn domains() {
+        let site = SiteIndex::default();
+        let page = PageSource::from_string(
+            "about.md",
+            SourceFormat::Markdown,
+            "---\nlayout: page\n---\n[a](https://www.github.com/a) [b](https://lobste.rs/)\n\
+             [c](https://github.com/c) [d](/d) ![e](http://img.example/e.png)\n",
+        );
+        assert_eq!(external_domains(&site, &page), ["github.com", "lobste.rs"]);
+    }
+
+    #[test]
+    fn wayback_response() {
+        let body = br#"{"url": "example.com/gone", "archived_snapshots": {"closest": {"status": "200", "available": true, "url": "http://web.archive.org/web/20200101000000/https://example.com/gone", "timestamp": "20200101000000"}}}"#;
+        assert_eq!(
+            parse_wayback(body).as_deref(),
+            Some("https://web.archive.org/web/20200101000000/https://example.com/gone")
+        );
+        assert_eq!(
+            parse_wayback(br#"{"url": "example.com/new", "archived_snapshots": {}}"#),
+            None
+        );
+    }
+}
@@ -0,0 +1,243 @@
+//! Keeping two processes from writing to the same directory at once
+//!
+//! `ebg serve` and `ebg build` both replace the whole destination directory,
+//! so running them against the same one at the same time leaves a mix of
+//! both. Before writing, each takes an advisory lock on a file next to the
+//! directory, such as `.publish.lock` for `publish`. The operating system
+//! releases the lock when the process exits, even if it crashes.
+//!
+//! The lock file holds the process ID and command line of whoever has it, so
+//! others can say who they are waiting for. The holder removes it when done,
+//! so anyone who was waiting on the removed file tries again with a new one.
+
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    io::{self, Read, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+use miette::Diagnostic;
+use thiserror::Error;
+
+#[derive(Diagnostic, Debug, Error)]
+pub enum LockError {
+    #[error("opening lock file `{}`", .0.display())]
+    Open(PathBuf, #[source] io::Error),
+    #[error("locking `{}`", .0.display())]
+    Lock(PathBuf, #[source] io::Error),
+    #[error("`{}` is in use by {holder}", .dir.display())]
+    #[diagnostic(help(
+        "wait for it to finish, or pass `--wait` to wait for it or `--force` to build anyway"
+    ))]
+    Held { dir: PathBuf, holder: String },
+}
+
+/// What to do when another process has the lock
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LockMode {
+    /// Report an error naming the other process
+    #[default]
+    Fail,
+    /// Wait until the other process is done
+    Wait,
+    /// Don't lock at all
+    Force,
+}
+
+/// A lock on a directory, which is released when this is dropped
+#[derive(Debug)]
+pub struct DirLock {
+    /// The lock file and its path, or `None` when the lock was skipped with
+    /// [`LockMode::Force`]
+    file: Option<(File, PathBuf)>,
+}
+
+impl DirLock {
+    /// Locks `dir`, which doesn't have to exist yet.
+    ///
+    /// With [`LockMode::Wait`], `on_wait` is called with a description of the
+    /// other process before waiting for it.
+    pub fn acquire(
+        dir: &Path,
+        mode: LockMode,
+        on_wait: impl FnOnce(&str),
+    ) -> Result<Self, LockError> {
+        if mode == LockMode::Force {
+            return Ok(Self { file: None });
+        }
+        let path = lock_path(dir);
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+        {
+            std::fs::create_dir_all(parent).map_err(|e| LockError::Open(path.clone(), e))?;
+        }
+        let mut on_wait = Some(on_wait);
+        let mut file = loop {
+            let file = lock_file(dir, &path, mode, &mut on_wait)?;
+            // The last holder may have removed the file while we waited on it
+            if is_same_file(&file, &path) {
+                break file;
+            }
+        };
+        // Only the holder writes to the file, so this can't clobber anyone
+        // else's details. Failing to write them just makes errors vaguer.
+        let _ = file.set_len(0).and_then(|()| {
+            file.rewind()?;
+            let command = std::env::args().collect::<Vec<_>>().join(" ");
+            write!(file, "{}\n{command}\n", std::process::id())
+        });
+        Ok(Self {
+            file: Some((file, path)),
+        })
+    }
+}
+
+impl Drop for DirLock {
+    fn drop(&mut self) {
+        // Removed before the file is closed, which releases the lock
+        if let Some((_, path)) = &self.file {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Opens the lock file at `path` and locks it, calling `on_wait` the first
+/// time it has to wait.
+fn lock_file(
+    dir: &Path,
+    path: &Path,
+    mode: LockMode,
+    on_wait: &mut Option<impl FnOnce(&str)>,
+) -> Result<File, LockError> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .map_err(|e| LockError::Open(path.to_path_buf(), e))?;
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            let holder = holder(&mut file);
+            if mode == LockMode::Fail {
+                return Err(LockError::Held {
+                    dir: dir.to_path_buf(),
+                    holder,
+                });
+            }
+            if let Some(on_wait) = on_wait.take() {
+                on_wait(&holder);
+            }
+            file.lock()
+                .map_err(|e| LockError::Lock(path.to_path_buf(), e))?;
+        }
+        Err(TryLockError::Error(e)) => return Err(LockError::Lock(path.to_path_buf(), e)),
+    }
+    Ok(file)
+}
+
+/// Checks whether `file` is still the one at `path`
+#[cfg(unix)]
+fn is_same_file(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (file.metadata(), std::fs::metadata(path)) {
+        (Ok(open), Ok(current)) => open.dev() == current.dev() && open.ino() == current.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_same_file(_file: &File, path: &Path) -> bool {
+    path.exists()
+}
+
+/// Returns the lock file for `dir`, which sits next to it
+fn lock_path(dir: &Path) -> PathBuf {
+    match dir.file_name() {
+        Some(name) => dir.with_file_name(format!(".{}.lock", name.to_string_lossy())),
+        // Something like `.`, which has no name to go by
+        None => dir.join(".ebg.lock"),
+    }
+}
+
+/// Describes the process that holds the lock on `file`
+fn holder(file: &mut File) -> String {
+    let mut contents = String::new();
+    let _ = file.read_to_string(&mut contents);
+    describe_holder(&contents)
+}
+
+fn describe_holder(contents: &str) -> String {
+    let mut lines = contents.lines();
+    match (lines.next(), lines.next()) {
+        (Some(pid), Some(command)) => format!("`{command}` (process {pid})"),
+        _ => "another process".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::{describe_holder, lock_path, DirLock, LockError, LockMode};
+
+    #[test]
+    fn lock_paths() {
+        assert_eq!(lock_path(Path::new("publish")), Path::new(".publish.lock"));
+        assert_eq!(
+            lock_path(Path::new("/srv/site/out/")),
+            Path::new("/srv/site/.out.lock")
+        );
+        assert_eq!(lock_path(Path::new(".")), Path::new("./.ebg.lock"));
+    }
+
+    #[test]
+    fn holders() {
+        assert_eq!(
+            describe_holder("1234\nebg serve\n"),
+            "`ebg serve` (process 1234)"
+        );
+        assert_eq!(describe_holder(""), "another process");
+    }
+
+    #[test]
+    fn second_lock_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let publish = dir.path().join("publish");
+        let first = DirLock::acquire(&publish, LockMode::Fail, |_| {}).unwrap();
+
+        let second = DirLock::acquire(&publish, LockMode::Fail, |_| {});
+        let Err(LockError::Held { holder, .. }) = second else {
+            panic!("expected the lock to be held, got {second:?}");
+        };
+        assert!(holder.contains(&format!("(process {})", std::process::id())));
+        assert!(DirLock::acquire(&publish, LockMode::Force, |_| unreachable!()).is_ok());
+
+        drop(first);
+        assert!(!lock_path(&publish).exists());
+        assert!(DirLock::acquire(&publish, LockMode::Fail, |_| {}).is_ok());
+    }
+
+    #[test]
+    fn waiting_survives_removal() {
+        let dir = tempfile::tempdir().unwrap();
+        let publish = dir.path().join("publish");
+        let first = DirLock::acquire(&publish, LockMode::Fail, |_| {}).unwrap();
+
+        let (send, recv) = std::sync::mpsc::channel();
+        let waiter = std::thread::spawn({
+            let publish = publish.clone();
+            move || DirLock::acquire(&publish, LockMode::Wait, |_| send.send(()).unwrap()).unwrap()
+        });
+        recv.recv().unwrap();
+        drop(first);
+        let second = waiter.join().unwrap();
+        // The waiter holds a lock on a file others can see
+        assert!(lock_path(&publish).exists());
+        assert!(DirLock::acquire(&publish, LockMode::Fail, |_| {}).is_err());
+        drop(second);
+    }
+}
@@ -1,9 +1,17 @@
 use clap::Parser;
-use cli::{about::AboutOptions, list::ListOptions, new_post::NewPostOptions};
+use cli::{
+    about::AboutOptions, audit::AuditOptions, check_links::CheckLinksOptions,
+    console::ConsoleOptions, debug::DebugOptions, dev::DevOptions, export::ExportOptions,
+    frontmatter::FrontmatterOptions, list::ListOptions, new_post::NewPostOptions,
+    posts::PostsOptions, render::RenderOptions, syndicate::SyndicateOptions,
+};
 use serve::ServerOptions;
 
+use std::path::PathBuf;
+
 use ebg::generator::Options;
-use tracing_subscriber::{prelude::*, EnvFilter};
+use tracing_chrome::ChromeLayerBuilder;
+use tracing_subscriber::{filter::Targets, prelude::*, EnvFilter};
 
 use crate::cli::Command;
 
@@ -13,6 +21,15 @@ mod serve;
 #[derive(Parser)]
 #[command(author, version, about)]
 struct Cli {
+    /// Never use the network; use cached responses or fail instead
+    #[arg(long, global = true)]
+    offline: bool,
+
+    /// Record how long each step takes to this file, which can be opened in
+    /// Chrome's `about:tracing` or Perfetto
+    #[arg(long, global = true, value_name = "FILE")]
+    trace_out: Option<PathBuf>,
+
     #[clap(subcommand)]
     command: Commands,
 }
@@ -20,26 +37,72 @@ struct Cli {
 #[derive(Parser)]
 enum Commands {
     About(AboutOptions),
+    /// Check the generated site for common problems
+    Audit(AuditOptions),
     Build(Options),
+    /// Check that links to other source files lead somewhere
+    CheckLinks(CheckLinksOptions),
+    Console(ConsoleOptions),
+    /// Tools for debugging sites and themes
+    Debug(DebugOptions),
+    /// Tools for developing EBG itself
+    Dev(DevOptions),
+    /// Export parts of the site into other formats
+    Export(ExportOptions),
+    /// Change the frontmatter of many pages at once
+    Frontmatter(FrontmatterOptions),
     List(ListOptions),
     NewPost(NewPostOptions),
+    /// Manage post source files
+    Posts(PostsOptions),
+    Render(RenderOptions),
     Serve(ServerOptions),
+    /// Announce new posts on other services
+    Syndicate(SyndicateOptions),
 }
 
 fn main() -> miette::Result<()> {
     let args = Cli::parse();
 
+    // The trace records EBG's own spans regardless of `EBG_LOG`. The guard
+    // finishes writing the file when it is dropped at the end of `main`.
+    let (chrome_layer, _trace_guard) = match &args.trace_out {
+        Some(path) => {
+            let (layer, guard) = ChromeLayerBuilder::new()
+                .file(path)
+                .include_args(true)
+                .build();
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
     tracing_subscriber::registry()
-        .with(tracing_subscriber::fmt::layer().pretty())
-        .with(EnvFilter::from_env("EBG_LOG"))
+        .with(
+            tracing_subscriber::fmt::layer()
+                .pretty()
+                .with_filter(EnvFilter::from_env("EBG_LOG")),
+        )
+        .with(chrome_layer.with_filter(Targets::new().with_target("ebg", tracing::Level::TRACE)))
         .init();
 
+    ebg::http::set_offline(args.offline);
+
     match args.command {
         Commands::Build(args) => args.run()?,
+        Commands::CheckLinks(options) => options.run()?,
+        Commands::Console(options) => options.run()?,
+        Commands::Debug(options) => options.run()?,
+        Commands::Dev(options) => options.run()?,
+        Commands::Export(options) => options.run()?,
+        Commands::Frontmatter(options) => options.run()?,
         Commands::List(args) => args.run()?,
         Commands::NewPost(options) => options.run()?,
+        Commands::Posts(options) => options.run()?,
+        Commands::Render(options) => options.run()?,
         Commands::Serve(options) => options.run()?,
         Commands::About(cmd) => cmd.run()?,
+        Commands::Audit(options) => options.run()?,
+        Commands::Syndicate(options) => options.run()?,
     }
 
     Ok(())
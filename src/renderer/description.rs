@@ -0,0 +1,145 @@
+//! Describing pages that don't have a `description` in their frontmatter
+//!
+//! With a `[description]` section in `Site.toml`, each page without a
+//! description gets one made from the start of its excerpt, or its contents if
+//! it has no excerpt. Headings, code, and footnotes are left out, since they
+//! read poorly on their own.
+
+use lol_html::{element, rewrite_str, RewriteStrSettings};
+use serde::Deserialize;
+
+use crate::syndication::html_to_text;
+
+/// The `[description]` section of `Site.toml`
+#[derive(Deserialize, Debug)]
+pub struct DescriptionConfig {
+    /// The most words a description can have
+    #[serde(default = "default_words")]
+    pub words: usize,
+    /// The most characters a description can have, including the ellipsis
+    /// that shows it was shortened
+    #[serde(default = "default_chars")]
+    pub chars: usize,
+}
+
+impl Default for DescriptionConfig {
+    fn default() -> Self {
+        Self {
+            words: default_words(),
+            chars: default_chars(),
+        }
+    }
+}
+
+fn default_words() -> usize {
+    40
+}
+
+/// Search engines show about this much of a description
+fn default_chars() -> usize {
+    160
+}
+
+/// Makes a plain text description from the rendered `html` of a page, or
+/// returns `None` if it has no text to use.
+pub(crate) fn describe(html: &str, config: &DescriptionConfig) -> Option<String> {
+    let text = html_to_text(&strip_asides(html));
+    let description = shorten(&text, config.words, config.chars);
+    (!description.is_empty()).then_some(description)
+}
+
+/// Removes the parts of `html` that don't belong in a summary, and the tags
+/// around text within a paragraph.
+///
+/// [`html_to_text`] puts a space where each tag was, which would leave gaps
+/// like `a link .` if inline tags were still there.
+fn strip_asides(html: &str) -> String {
+    rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![
+                element!(
+                    "h1, h2, h3, h4, h5, h6, pre, script, style, figure, sup, .footnote-definition",
+                    |el| {
+                        el.remove();
+                        Ok(())
+                    }
+                ),
+                element!(
+                    "a, abbr, b, cite, code, del, em, i, ins, kbd, mark, q, s, small, span, strong, sub, u",
+                    |el| {
+                        el.remove_and_keep_content();
+                        Ok(())
+                    }
+                ),
+            ],
+            ..RewriteStrSettings::new()
+        },
+    )
+    // Pages are already valid HTML by now, but if not, tags are stripped
+    // either way
+    .unwrap_or_else(|_| html.to_string())
+}
+
+/// Cuts `text` down to at most `words` words and `chars` characters, ending
+/// with an ellipsis if anything was cut.
+fn shorten(text: &str, words: usize, chars: usize) -> String {
+    if text.split_whitespace().count() <= words && text.chars().count() <= chars {
+        return text.to_string();
+    }
+    let mut shortened = String::new();
+    for word in text.split_whitespace().take(words) {
+        // Leave room for a space and the ellipsis
+        if shortened.chars().count() + word.chars().count() + 2 > chars {
+            break;
+        }
+        if !shortened.is_empty() {
+            shortened.push(' ');
+        }
+        shortened += word;
+    }
+    // End on the word rather than on punctuation after it
+    let shortened = shortened.trim_end_matches([',', ';', ':', '.', '-', '—']);
+    format!("{shortened}…")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{describe, shorten, DescriptionConfig};
+
+    #[test]
+    fn shortening() {
+        assert_eq!(shorten("Just a few words.", 10, 100), "Just a few words.");
+        assert_eq!(shorten("one two three four", 2, 100), "one two…");
+        assert_eq!(shorten("one two, three four", 2, 100), "one two…");
+        assert_eq!(shorten("one two three four", 10, 12), "one two…");
+        assert!(shorten(&"word ".repeat(100), 1000, 160).chars().count() <= 160);
+    }
+
+    #[test]
+    fn descriptions() {
+        let config = DescriptionConfig::default();
+        assert_eq!(
+            describe(
+                "<h1>Hello</h1>\n<p>This is <em>my</em> <a href=\"/\">first post</a>&amp;more.<sup class=\"footnote-reference\"><a href=\"#1\">1</a></sup></p>\n\
+                 <pre><code>fn main() {}</code></pre>\n<p>It has code.</p>\n\
+                 <div class=\"footnote-definition\" id=\"1\"><p>A footnote</p></div>",
+                &config
+            )
+            .as_deref(),
+            Some("This is my first post&more. It has code.")
+        );
+        assert_eq!(describe("<h2>Only a heading</h2>", &config), None);
+        assert_eq!(
+            describe(
+                &"<p>word</p>".repeat(100),
+                &DescriptionConfig {
+                    words: 3,
+                    chars: 160
+                }
+            )
+            .as_deref(),
+            Some("word word word…")
+        );
+    }
+}
@@ -29,9 +29,8 @@ impl HeadingAnchors {
 
         let mut header_start = None;
 
-        let mut out_events = Vec::with_capacity(match events.size_hint() {
-            (min, max) => max.unwrap_or(min),
-        });
+        let (min, max) = events.size_hint();
+        let mut out_events = Vec::with_capacity(max.unwrap_or(min));
 
         for mut event in events {
             match &mut event {
@@ -0,0 +1,221 @@
+//! Citing works from the site's bibliography
+//!
+//! Markdown can cite works with `[@key]`, or several at once with
+//! `[@key1; @key2]`. Each citation becomes a link to the page's list of
+//! references, which is added to the end of the page.
+
+use std::collections::BTreeMap;
+
+use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+
+use crate::index::Reference;
+
+pub fn render_citations<'a>(
+    events: impl Iterator<Item = Event<'a>>,
+    bibliography: &BTreeMap<String, Reference>,
+    mut unknown: impl FnMut(&str),
+) -> Vec<Event<'a>> {
+    if bibliography.is_empty() {
+        return events.collect();
+    }
+    let mut cited: Vec<&Reference> = vec![];
+    let mut output = vec![];
+    // How many code blocks and links we are inside of
+    let mut skip_depth = 0usize;
+    for event in merge_text(events) {
+        match event {
+            Event::Start(Tag::CodeBlock(_) | Tag::Link { .. }) => {
+                skip_depth += 1;
+                output.push(event);
+            }
+            Event::End(TagEnd::CodeBlock | TagEnd::Link) => {
+                skip_depth = skip_depth.saturating_sub(1);
+                output.push(event);
+            }
+            Event::Text(text) if skip_depth == 0 && text.contains("[@") => {
+                let mut rest: &str = &text;
+                while let Some((range, keys)) = find_citation(rest) {
+                    let references: Option<Vec<_>> =
+                        keys.iter().map(|key| bibliography.get(*key)).collect();
+                    let Some(references) = references else {
+                        for key in keys.iter().filter(|key| !bibliography.contains_key(**key)) {
+                            unknown(key);
+                        }
+                        output.push(Event::Text(CowStr::from(rest[..range.end].to_string())));
+                        rest = &rest[range.end..];
+                        continue;
+                    };
+                    output.push(Event::Text(CowStr::from(rest[..range.start].to_string())));
+                    output.push(Event::InlineHtml(CowStr::from(citation_html(&references))));
+                    for reference in references {
+                        if !cited.iter().any(|r| r.key == reference.key) {
+                            cited.push(reference);
+                        }
+                    }
+                    rest = &rest[range.end..];
+                }
+                output.push(Event::Text(CowStr::from(rest.to_string())));
+            }
+            event => output.push(event),
+        }
+    }
+    if !cited.is_empty() {
+        output.push(Event::Html(CowStr::from(references_html(&cited))));
+    }
+    output
+}
+
+/// Joins adjacent text events, since the parser splits text at brackets.
+fn merge_text<'a>(events: impl Iterator<Item = Event<'a>>) -> Vec<Event<'a>> {
+    let mut output: Vec<Event<'a>> = vec![];
+    for event in events {
+        match (output.last_mut(), event) {
+            (Some(Event::Text(previous)), Event::Text(text)) => {
+                *previous = CowStr::from(format!("{previous}{text}"));
+            }
+            (_, event) => output.push(event),
+        }
+    }
+    output
+}
+
+/// Finds the first citation in `text`, returning where it is and the keys it
+/// cites.
+fn find_citation(text: &str) -> Option<(std::ops::Range<usize>, Vec<&str>)> {
+    let mut offset = 0;
+    while let Some(start) = text[offset..].find("[@").map(|i| i + offset) {
+        let end = start + text[start..].find(']')?;
+        let keys: Option<Vec<_>> = text[start + 1..end]
+            .split(';')
+            .map(|key| {
+                key.trim().strip_prefix('@').filter(|key| {
+                    !key.is_empty()
+                        && key
+                            .chars()
+                            .all(|c| c.is_alphanumeric() || "_-:.".contains(c))
+                })
+            })
+            .collect();
+        if let Some(keys) = keys {
+            return Some((start..end + 1, keys));
+        }
+        offset = start + 2;
+    }
+    None
+}
+
+/// The short form of a reference used in the text, such as `Lamport 1978`
+fn short_label(reference: &Reference) -> String {
+    let authors = match reference.authors.as_slice() {
+        [] => reference
+            .title
+            .clone()
+            .unwrap_or_else(|| reference.key.clone()),
+        [one] => one.clone(),
+        [first, second] => format!("{first} & {second}"),
+        [first, ..] => format!("{first} et al."),
+    };
+    match &reference.year {
+        Some(year) => format!("{authors} {year}"),
+        None => authors,
+    }
+}
+
+fn citation_html(references: &[&Reference]) -> String {
+    let links = references
+        .iter()
+        .map(|reference| {
+            format!(
+                r##"<a href="#ref-{}">{}</a>"##,
+                escape(&reference.key),
+                escape(&short_label(reference))
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("; ");
+    format!(r#"<cite class="citation">({links})</cite>"#)
+}
+
+fn references_html(references: &[&Reference]) -> String {
+    let mut html = String::from("<section class=\"references\">\n<h2>References</h2>\n<ol>\n");
+    for reference in references {
+        let mut entry = reference.authors.join(", ");
+        if let Some(year) = &reference.year {
+            entry += &format!(" ({year})");
+        }
+        if !entry.is_empty() {
+            entry += ". ";
+        }
+        let mut entry = escape(&entry);
+        if let Some(title) = &reference.title {
+            entry += &format!("{}. ", escape(title));
+        }
+        if let Some(container) = &reference.container {
+            entry += &format!("<em>{}</em>. ", escape(container));
+        }
+        if let Some(url) = &reference.url {
+            entry += &format!(r#"<a href="{0}">{0}</a>"#, escape(url));
+        }
+        html += &format!(
+            "<li id=\"ref-{}\">{}</li>\n",
+            escape(&reference.key),
+            entry.trim_end()
+        );
+    }
+    html + "</ol>\n</section>\n"
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use pulldown_cmark::{html::push_html, Parser};
+
+    use crate::index::Reference;
+
+    use super::render_citations;
+
+    #[test]
+    fn cite_and_list_references() {
+        let reference = |key: &str, authors: &[&str], year: &str| Reference {
+            key: key.to_string(),
+            authors: authors.iter().map(ToString::to_string).collect(),
+            title: Some(format!("Title of {key}")),
+            year: Some(year.to_string()),
+            container: None,
+            url: None,
+        };
+        let bibliography = BTreeMap::from([
+            ("a".to_string(), reference("a", &["Lamport"], "1978")),
+            (
+                "b".to_string(),
+                reference("b", &["Abelson", "Sussman"], "1985"),
+            ),
+        ]);
+        let mut unknown = vec![];
+        let events = render_citations(
+            Parser::new("See [@a; @b] and [@c], not `[@a]`."),
+            &bibliography,
+            |key| unknown.push(key.to_string()),
+        );
+        let mut html = String::new();
+        push_html(&mut html, events.into_iter());
+        assert_eq!(
+            html,
+            "<p>See <cite class=\"citation\">(<a href=\"#ref-a\">Lamport 1978</a>; \
+             <a href=\"#ref-b\">Abelson &amp; Sussman 1985</a>)</cite> and [@c], not <code>[@a]</code>.</p>\n\
+             <section class=\"references\">\n<h2>References</h2>\n<ol>\n\
+             <li id=\"ref-a\">Lamport (1978). Title of a.</li>\n\
+             <li id=\"ref-b\">Abelson, Sussman (1985). Title of b.</li>\n\
+             </ol>\n</section>\n"
+        );
+        assert_eq!(unknown, ["c"]);
+    }
+}
@@ -1,44 +1,220 @@
 //! Markdown filters for syntax highlighting and other code formatting.
 
+use miette::Diagnostic;
 use pulldown_cmark::{CodeBlockKind, Event, Tag, TagEnd};
-use std::collections::HashMap;
-use syntect::{highlighting::ThemeSet, html::highlighted_html_for_string, parsing::SyntaxSet};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+use syntect::{
+    highlighting::{Theme, ThemeSet},
+    html::{
+        css_for_theme_with_class_style, highlighted_html_for_string, ClassStyle,
+        ClassedHTMLGenerator,
+    },
+    parsing::SyntaxSet,
+    util::LinesWithEndings,
+};
+use thiserror::Error;
+
+use crate::index::{SiteIndex, SiteMetadata};
+
+/// The color schemes the site supports
+///
+/// When this is set, code blocks are highlighted with CSS classes instead of
+/// inline styles, and the colors come from the generated `highlight.css`.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorScheme {
+    Light,
+    Dark,
+    /// Follow the reader's `prefers-color-scheme` setting
+    Auto,
+}
+
+const LIGHT_THEME: &str = "InspiredGitHub";
+const DARK_THEME: &str = "base16-ocean.dark";
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+#[derive(Diagnostic, Error, Debug)]
+pub enum HighlightThemeError {
+    #[error("loading highlight theme `{}`", .0.display())]
+    Loading(PathBuf, #[source] syntect::LoadingError),
+    #[error("unknown highlight theme `{0}`")]
+    #[diagnostic(help(
+        "use the name of a built-in theme such as `{LIGHT_THEME}`, or the path of a .tmTheme file"
+    ))]
+    Unknown(String),
+}
+
+/// The themes used to color highlighted code
+#[derive(Clone)]
+pub struct HighlightThemes {
+    /// Used for inline styles, and for light color schemes
+    light: Theme,
+    dark: Theme,
+}
+
+impl HighlightThemes {
+    /// Loads the themes named by `light` and `dark`, falling back to the
+    /// built-in defaults.
+    ///
+    /// Names ending in `.tmTheme` are files, which are looked for relative to
+    /// `root_dir` and then relative to the site's theme directory `theme_dir`.
+    /// Other names refer to the themes that come with syntect.
+    pub fn load(
+        light: Option<&str>,
+        dark: Option<&str>,
+        root_dir: &Path,
+        theme_dir: &Path,
+    ) -> Result<Self, HighlightThemeError> {
+        let mut builtin = ThemeSet::load_defaults();
+        let mut load = |name: &str| -> Result<Theme, HighlightThemeError> {
+            if name.ends_with(".tmTheme") {
+                let path = [root_dir.join(name), theme_dir.join(name)]
+                    .into_iter()
+                    .find(|path| path.exists())
+                    .unwrap_or_else(|| root_dir.join(name));
+                ThemeSet::get_theme(&path).map_err(|e| HighlightThemeError::Loading(path, e))
+            } else {
+                builtin
+                    .themes
+                    .remove(name)
+                    .ok_or_else(|| HighlightThemeError::Unknown(name.to_string()))
+            }
+        };
+        Ok(Self {
+            light: load(light.unwrap_or(LIGHT_THEME))?,
+            dark: load(dark.unwrap_or(DARK_THEME))?,
+        })
+    }
+
+    /// Generates the stylesheet with the highlighting colors for a color
+    /// scheme.
+    pub fn stylesheet(&self, scheme: ColorScheme) -> String {
+        let css = |theme: &Theme| css_for_theme_with_class_style(theme, CLASS_STYLE).unwrap();
+        match scheme {
+            ColorScheme::Light => css(&self.light),
+            ColorScheme::Dark => css(&self.dark),
+            ColorScheme::Auto => format!(
+                "{}\n@media (prefers-color-scheme: dark) {{\n{}}}\n",
+                css(&self.light),
+                css(&self.dark)
+            ),
+        }
+    }
+}
+
+impl Default for HighlightThemes {
+    fn default() -> Self {
+        let mut themes = ThemeSet::load_defaults().themes;
+        Self {
+            light: themes.remove(LIGHT_THEME).unwrap(),
+            dark: themes.remove(DARK_THEME).unwrap(),
+        }
+    }
+}
+
+/// Adds the `.sublime-syntax` files in `dir` and its subdirectories to the
+/// built-in syntaxes.
+pub fn load_syntaxes(dir: &Path) -> Result<SyntaxSet, syntect::LoadingError> {
+    let mut builder = SyntaxSet::load_defaults_newlines().into_builder();
+    builder.add_from_folder(dir, true)?;
+    Ok(builder.build())
+}
 
 pub struct CodeFormatter {
     /// Maps language names that would show up in a code block header to a file extension that can
     /// be used to select a syntax set.
-    language_map: HashMap<&'static str, &'static str>,
+    language_map: HashMap<String, String>,
     syntax_set: SyntaxSet,
-    theme_set: ThemeSet,
+    themes: HighlightThemes,
+    /// Whether to use CSS classes rather than inline styles
+    classes: bool,
 }
 
 impl CodeFormatter {
     pub fn new() -> Self {
         Self {
-            language_map: [("rust", "rs")].into(),
+            language_map: [("rust".into(), "rs".into())].into(),
             syntax_set: SyntaxSet::load_defaults_newlines(),
-            theme_set: ThemeSet::load_defaults(),
+            themes: HighlightThemes::default(),
+            classes: false,
         }
     }
 
+    /// Creates a formatter that uses the site's syntaxes and highlight themes,
+    /// and CSS classes if the site has a color scheme or asks for them.
+    pub fn for_site(site: &SiteIndex) -> Self {
+        let config = site.config();
+        let mut formatter = Self {
+            themes: site.highlight_themes().clone(),
+            classes: config.color_scheme.is_some() || config.highlight_classes,
+            ..Self::new()
+        };
+        if let Some(syntax_set) = site.syntax_set() {
+            formatter.syntax_set = syntax_set.clone();
+        }
+        formatter.language_map.extend(config.code_languages.clone());
+        formatter
+    }
+
+    fn highlight_with_classes(
+        &self,
+        code: &str,
+        syntax: &syntect::parsing::SyntaxReference,
+        marked: &[RangeInclusive<usize>],
+    ) -> String {
+        let mut generator =
+            ClassedHTMLGenerator::new_with_class_style(syntax, &self.syntax_set, CLASS_STYLE);
+        for line in LinesWithEndings::from(code) {
+            generator
+                .parse_html_for_line_which_includes_newline(line)
+                .unwrap();
+        }
+        format!(
+            "<pre class=\"hl-code\"><code>{}</code></pre>\n",
+            mark_lines(&generator.finalize(), marked)
+        )
+    }
+
     fn highlight_code(&self, code: String, lang: LangOptions<'_>) -> Vec<Event<'_>> {
         let lines: Option<usize> = lang.line_numbers.then(|| code.lines().map(|_| 1).sum());
 
         let syntax = lang.lang.and_then(|lang| {
-            let extension = self.language_map.get(lang).unwrap_or(&lang);
-            self.syntax_set.find_syntax_by_extension(extension)
+            let token = self.language_map.get(lang).map_or(lang, String::as_str);
+            self.syntax_set.find_syntax_by_token(token)
         });
 
         let body = match syntax {
+            Some(ss) if self.classes => {
+                vec![Event::Html(
+                    self.highlight_with_classes(&code, ss, &lang.marked).into(),
+                )]
+            }
             Some(ss) => {
+                let html =
+                    highlighted_html_for_string(&code, &self.syntax_set, ss, &self.themes.light)
+                        .unwrap();
+                // The code goes between a `<pre>` line and a closing `</pre>`
+                let (pre, code) = html.split_at(html.find('\n').map_or(0, |i| i + 1));
+                let code = code.strip_suffix("</pre>\n").unwrap_or(code);
                 vec![Event::Html(
-                    highlighted_html_for_string(
-                        &code,
-                        &self.syntax_set,
-                        ss,
-                        &self.theme_set.themes["InspiredGitHub"],
+                    format!("{pre}{}</pre>\n", mark_lines(code, &lang.marked)).into(),
+                )]
+            }
+            None if !lang.marked.is_empty() => {
+                let class = lang
+                    .lang
+                    .map(|lang| format!(" class=\"language-{}\"", escape(lang)))
+                    .unwrap_or_default();
+                vec![Event::Html(
+                    format!(
+                        "<pre><code{class}>{}</code></pre>\n",
+                        mark_lines(&escape(&code), &lang.marked)
                     )
-                    .unwrap()
                     .into(),
                 )]
             }
@@ -51,9 +227,10 @@ impl CodeFormatter {
             ],
         };
 
+        let mut events = vec![Event::Html(figure_start(&lang).into())];
         match lines {
             Some(count) => {
-                let mut events = vec![
+                events.extend([
                     Event::Html("<table class=\"codenum\"><tbody><tr><td>".into()),
                     Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced("".into()))),
                     Event::Text(
@@ -65,13 +242,14 @@ impl CodeFormatter {
                     ),
                     Event::End(TagEnd::CodeBlock),
                     Event::Html("</td><td>".into()),
-                ];
+                ]);
                 events.extend(body);
                 events.push(Event::Html("</td></tr></tbody></table>".into()));
-                events
             }
-            None => body,
+            None => events.extend(body),
         }
+        events.push(Event::Html("</figure>\n".into()));
+        events
     }
 
     pub fn format_codeblocks<'a>(
@@ -124,21 +302,311 @@ impl Default for CodeFormatter {
     }
 }
 
+/// Opens the `<figure>` that holds a fenced code block, with a caption if it
+/// has a title.
+///
+/// The wrapper gives themes a place to put things like a copy button.
+fn figure_start(lang: &LangOptions<'_>) -> String {
+    let mut html = String::from("<figure class=\"code\">");
+    if let Some(title) = lang.title {
+        html += &format!("<figcaption>{}</figcaption>", escape(title));
+    }
+    html + "\n"
+}
+
+/// Wraps the lines of highlighted code whose numbers are in `marked` in a
+/// `<span class="line-highlight">`.
+///
+/// Highlighting spans can continue onto the next line, so the spans that are
+/// open where a marked line starts and ends are closed and opened again around
+/// the wrapper.
+fn mark_lines(html: &str, marked: &[RangeInclusive<usize>]) -> String {
+    if marked.is_empty() {
+        return html.to_string();
+    }
+    let mut output = String::with_capacity(html.len());
+    let mut open: Vec<&str> = vec![];
+    for (i, line) in html.split_inclusive('\n').enumerate() {
+        let is_marked = marked.iter().any(|range| range.contains(&(i + 1)));
+        if is_marked {
+            output += &"</span>".repeat(open.len());
+            output += "<span class=\"line-highlight\">";
+            output.extend(open.iter().copied());
+        }
+        // Text is escaped, so every `<` starts a tag
+        let mut rest = line;
+        while let Some(start) = rest.find('<') {
+            let end = rest[start..]
+                .find('>')
+                .map_or(rest.len(), |i| start + i + 1);
+            let tag = &rest[start..end];
+            if tag.starts_with("</") {
+                open.pop();
+            } else {
+                open.push(tag);
+            }
+            output += &rest[..end];
+            rest = &rest[end..];
+        }
+        output += rest;
+        if is_marked {
+            output += &"</span>".repeat(open.len());
+            output += "</span>";
+            output.extend(open.iter().copied());
+        }
+    }
+    output
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
 struct LangOptions<'a> {
     lang: Option<&'a str>,
     line_numbers: bool,
+    /// The lines to highlight, numbered from 1
+    marked: Vec<RangeInclusive<usize>>,
+    /// A caption to show above the code, such as a file name
+    title: Option<&'a str>,
 }
 
+/// Parses the info string of a code block, such as `rust`, `rust=` for line
+/// numbers, or `rust{3,5-7}` to highlight lines 3 and 5 through 7.
+///
+/// Attributes can follow after a space, as in `rust title="main.rs"`.
 fn parse_lang(s: &str) -> LangOptions<'_> {
+    // Line ranges can have spaces in them, so attributes start after those
+    let ranges_end = match s.find('{') {
+        Some(open) if !s[..open].contains(char::is_whitespace) => s[open..]
+            .find('}')
+            .map_or(s.len(), |close| open + close + 1),
+        _ => 0,
+    };
+    let (s, attributes) = match s[ranges_end..].find(char::is_whitespace) {
+        Some(i) => s.split_at(ranges_end + i),
+        None => (s, ""),
+    };
+    let title = parse_attributes(attributes)
+        .into_iter()
+        .find_map(|(key, value)| (key == "title").then_some(value));
     let line_numbers = s.ends_with('=');
     let lang = s.rsplit_once('=').map(|(lang, _)| lang).unwrap_or(s);
+    let (lang, marked) = match lang
+        .strip_suffix('}')
+        .and_then(|lang| lang.rsplit_once('{'))
+    {
+        Some((lang, lines)) => (lang, parse_line_ranges(lines)),
+        None => (lang, vec![]),
+    };
     let lang = (!lang.is_empty()).then_some(lang);
-    LangOptions { lang, line_numbers }
+    LangOptions {
+        lang,
+        line_numbers,
+        marked,
+        title,
+    }
+}
+
+/// Parses attributes such as `title="main.rs" open=1`, where values with
+/// spaces need quotes.
+fn parse_attributes(mut s: &str) -> Vec<(&str, &str)> {
+    let mut attributes = vec![];
+    loop {
+        s = s.trim_start();
+        let Some((key, rest)) = s.split_once('=') else {
+            break;
+        };
+        let (value, rest) = match rest.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => rest.split_once(char::is_whitespace).unwrap_or((rest, "")),
+        };
+        attributes.push((key.trim(), value));
+        s = rest;
+    }
+    attributes
+}
+
+/// Parses a list of line numbers and ranges such as `3,5-7`, skipping any that
+/// aren't valid.
+fn parse_line_ranges(s: &str) -> Vec<RangeInclusive<usize>> {
+    s.split(',')
+        .filter_map(|part| {
+            let part = part.trim();
+            match part.split_once('-') {
+                Some((start, end)) => Some(start.trim().parse().ok()?..=end.trim().parse().ok()?),
+                None => {
+                    let line = part.parse().ok()?;
+                    Some(line..=line)
+                }
+            }
+        })
+        .collect()
 }
 
 #[cfg(test)]
 mod test {
-    use crate::renderer::markdown::code::parse_lang;
+    use pulldown_cmark::{html::push_html, Parser};
+
+    use crate::renderer::markdown::code::{mark_lines, parse_lang};
+
+    use super::{load_syntaxes, CodeFormatter, ColorScheme, HighlightThemeError, HighlightThemes};
+
+    #[test]
+    fn highlight_with_classes() {
+        let fmt = CodeFormatter {
+            classes: true,
+            ..CodeFormatter::new()
+        };
+        let mut html = String::new();
+        push_html(
+            &mut html,
+            fmt.format_codeblocks(Parser::new("```rust\nfn main() {}\n```")),
+        );
+        assert!(html.starts_with("<figure class=\"code\">\n<pre class=\"hl-code\"><code>"));
+        assert!(html.contains("class=\"hl-"));
+        assert!(!html.contains("style="));
+
+        let css = HighlightThemes::default().stylesheet(ColorScheme::Auto);
+        assert!(css.contains(".hl-code {"));
+        assert!(css.contains("@media (prefers-color-scheme: dark)"));
+    }
+
+    #[test]
+    fn load_themes() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme_dir = dir.path().join("theme");
+        std::fs::create_dir(&theme_dir).unwrap();
+        std::fs::write(theme_dir.join("plain.tmTheme"), PLAIN_THEME).unwrap();
+
+        let themes = HighlightThemes::load(
+            Some("plain.tmTheme"),
+            Some("Solarized (dark)"),
+            dir.path(),
+            &theme_dir,
+        )
+        .unwrap();
+        assert!(themes
+            .stylesheet(ColorScheme::Light)
+            .contains("color: #123456"));
+        assert_eq!(themes.dark.name.as_deref(), Some("Solarized (dark)"),);
+
+        assert!(matches!(
+            HighlightThemes::load(Some("nope"), None, dir.path(), &theme_dir),
+            Err(HighlightThemeError::Unknown(_))
+        ));
+        assert!(matches!(
+            HighlightThemes::load(Some("missing.tmTheme"), None, dir.path(), &theme_dir),
+            Err(HighlightThemeError::Loading(..))
+        ));
+    }
+
+    const PLAIN_THEME: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>name</key>
+    <string>Plain</string>
+    <key>settings</key>
+    <array>
+        <dict>
+            <key>settings</key>
+            <dict>
+                <key>background</key>
+                <string>#ffffff</string>
+                <key>foreground</key>
+                <string>#123456</string>
+            </dict>
+        </dict>
+    </array>
+</dict>
+</plist>
+"#;
+
+    #[test]
+    fn marked_lines() {
+        assert_eq!(
+            mark_lines(
+                "<span class=\"a\">x\n<span class=\"b\">y</span>\nz</span>\n",
+                &[2..=2]
+            ),
+            "<span class=\"a\">x\n</span><span class=\"line-highlight\"><span class=\"a\">\
+             <span class=\"b\">y</span>\n</span></span><span class=\"a\">z</span>\n"
+        );
+
+        for fmt in [
+            CodeFormatter::new(),
+            CodeFormatter {
+                classes: true,
+                ..CodeFormatter::new()
+            },
+        ] {
+            let mut html = String::new();
+            push_html(
+                &mut html,
+                fmt.format_codeblocks(Parser::new("```rust{2}\nfn main() {\n    x();\n}\n```")),
+            );
+            assert_eq!(html.matches("line-highlight").count(), 1, "{html}");
+            assert_eq!(
+                html.matches("<span").count(),
+                html.matches("</span>").count()
+            );
+        }
+
+        let mut html = String::new();
+        push_html(
+            &mut html,
+            CodeFormatter::new().format_codeblocks(Parser::new("```unknown{1}\na < b\n```")),
+        );
+        assert_eq!(
+            html,
+            "<figure class=\"code\">\n<pre><code class=\"language-unknown\"><span class=\"line-highlight\">a &lt; b\n</span></code></pre>\n</figure>\n"
+        );
+    }
+
+    #[test]
+    fn titles() {
+        let mut html = String::new();
+        push_html(
+            &mut html,
+            CodeFormatter::new()
+                .format_codeblocks(Parser::new("```text title=\"<notes>\"\nhi\n```")),
+        );
+        assert_eq!(
+            html,
+            "<figure class=\"code\"><figcaption>&lt;notes&gt;</figcaption>\n\
+             <pre><code class=\"language-text\">hi\n</code></pre>\n</figure>\n"
+        );
+    }
+
+    #[test]
+    fn custom_syntaxes() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("frob.sublime-syntax"),
+            "%YAML 1.2\n---\nname: Frob\nfile_extensions: [frob]\nscope: source.frob\n\
+             contexts:\n  main:\n    - match: '\\bfrob\\b'\n      scope: keyword.control.frob\n",
+        )
+        .unwrap();
+        let mut fmt = CodeFormatter {
+            syntax_set: load_syntaxes(dir.path()).unwrap(),
+            classes: true,
+            ..CodeFormatter::new()
+        };
+        fmt.language_map.insert("frobnicate".into(), "frob".into());
+
+        for fence in ["frob", "Frob", "frobnicate"] {
+            let mut html = String::new();
+            push_html(
+                &mut html,
+                fmt.format_codeblocks(Parser::new(&format!("```{fence}\nfrob\n```"))),
+            );
+            assert!(html.contains("hl-keyword"), "{fence}: {html}");
+        }
+    }
 
     #[test]
     fn parse_lang_options() -> miette::Result<()> {
@@ -158,6 +626,16 @@ mod test {
         assert_eq!(opts.lang, None);
         assert!(opts.line_numbers);
 
+        let opts = parse_lang("rust{3, 5-7,x}=");
+        assert_eq!(opts.lang, Some("rust"));
+        assert!(opts.line_numbers);
+        assert_eq!(opts.marked, [3..=3, 5..=7]);
+
+        let opts = parse_lang("rust{2} title=\"src/main.rs & more\" open=1");
+        assert_eq!(opts.lang, Some("rust"));
+        assert_eq!(opts.marked, [2..=2]);
+        assert_eq!(opts.title, Some("src/main.rs & more"));
+
         Ok(())
     }
 }
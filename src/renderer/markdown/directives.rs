@@ -0,0 +1,152 @@
+//! Finding the `{{ ... }}` directives in markdown
+//!
+//! Includes, shortcodes, galleries, and site variables are all written in
+//! double braces and expanded before the markdown is parsed. Directives in
+//! code spans and code blocks are left alone, so pages can show them as
+//! written.
+//!
+//! The rendered page goes through Tera afterwards to import the site's macros,
+//! so braces that are meant to be shown as they are, whether in code or in an
+//! escaped shortcode, are written as `&#123;` to keep Tera from reading them.
+
+use std::ops::Range;
+
+use lol_html::{text, RewriteStrSettings};
+use pulldown_cmark::{Event, Parser, Tag};
+
+/// Stands in for the first `{` of a directive that is meant to be shown as
+/// it is, until the markdown has been rendered and it can be written as
+/// `&#123;`
+const LITERAL_BRACE: char = '\u{E000}';
+
+/// A `{{ ... }}` outside of code
+pub(super) struct Directive<'a> {
+    /// Where the directive is, including its braces
+    pub range: Range<usize>,
+    /// The text between the braces
+    pub inner: &'a str,
+}
+
+/// Finds the directives in `text` that aren't in code, in order.
+///
+/// A directive that starts with `{{<` ends at the next `>}}`, so shortcode
+/// arguments can contain `}}`.
+pub(super) fn directives(text: &str) -> Vec<Directive<'_>> {
+    let mut directives = vec![];
+    if !text.contains("{{") {
+        return directives;
+    }
+    let code = code_ranges(text);
+    let mut offset = 0;
+    while let Some(start) = text[offset..].find("{{").map(|i| i + offset) {
+        if let Some(code) = code.iter().find(|code| code.contains(&start)) {
+            offset = code.end;
+            continue;
+        }
+        let close = if text[start + 2..].starts_with('<') {
+            ">}}"
+        } else {
+            "}}"
+        };
+        let Some(len) = text[start + 2..].find(close) else {
+            break;
+        };
+        let end = start + 2 + len + close.len();
+        directives.push(Directive {
+            range: start..end,
+            inner: &text[start + 2..end - 2],
+        });
+        offset = end;
+    }
+    directives
+}
+
+/// Returns where the code spans and code blocks are in `text`, a markdown
+/// document.
+pub(crate) fn code_ranges(text: &str) -> Vec<Range<usize>> {
+    Parser::new(text)
+        .into_offset_iter()
+        .filter_map(|(event, range)| match event {
+            Event::Code(_) | Event::Start(Tag::CodeBlock(_)) => Some(range),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Marks `text`, a directive, to be shown as it is rather than expanded.
+pub(super) fn literal(text: &str) -> String {
+    match text.strip_prefix('{') {
+        Some(rest) => format!("{LITERAL_BRACE}{rest}"),
+        None => text.to_string(),
+    }
+}
+
+/// Writes the braces in `html`, a rendered page, that are meant to be shown
+/// as they are so that Tera leaves them alone.
+pub(super) fn protect_braces(html: String) -> String {
+    let html = if html.contains(LITERAL_BRACE) {
+        html.replace(LITERAL_BRACE, "&#123;")
+    } else {
+        html
+    };
+    if !["{{", "{%", "{#"].iter().any(|tag| html.contains(tag)) {
+        return html;
+    }
+    lol_html::rewrite_str(
+        &html,
+        RewriteStrSettings {
+            element_content_handlers: vec![text!("code", |chunk| {
+                let escaped = escape_tera(chunk.as_str());
+                if escaped != chunk.as_str() {
+                    chunk.replace(&escaped, lol_html::html_content::ContentType::Html);
+                }
+                Ok(())
+            })],
+            ..RewriteStrSettings::new()
+        },
+    )
+    // The markdown renderer only writes valid HTML
+    .unwrap_or(html)
+}
+
+/// Replaces each `{` that could start a Tera tag with `&#123;`.
+///
+/// A `{` at the end is replaced too, since the text may continue in another
+/// chunk.
+fn escape_tera(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '{' && matches!(chars.peek(), None | Some('{' | '%' | '#')) {
+            escaped.push_str("&#123;");
+        } else {
+            escaped.push(c);
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::{directives, literal, protect_braces};
+
+    #[test]
+    fn skip_code() {
+        let text =
+            "A {{ a }} `{{ b }}` {{< c x=\"}}\" >}}\n\n```\n{{ d }}\n```\n\n    {{ e }}\n\n{{ f }}";
+        let found: Vec<_> = directives(text).iter().map(|d| d.inner).collect();
+        assert_eq!(found, [" a ", "< c x=\"}}\" >", " f "]);
+        assert!(directives("{{ unclosed").is_empty());
+    }
+
+    #[test]
+    fn protect() {
+        assert_eq!(
+            protect_braces(
+                literal("{{< x >}}")
+                    + " {{ macros::y() }}<pre><code>{{ z }} {% a %} {}</code></pre>"
+            ),
+            "&#123;{< x >}} {{ macros::y() }}<pre><code>&#123;{ z }} &#123;% a %} {}</code></pre>"
+        );
+    }
+}
@@ -0,0 +1,371 @@
+//! Photo galleries made from a directory of images
+//!
+//! `{{ gallery dir="photos/trip" }}` becomes a grid of thumbnails, each linking
+//! to the full image. The directory is relative to the page's file, or to the
+//! site root if it starts with `/`, and is usually part of a post bundle.
+//!
+//! Images are shown in filename order, and captions come from the filenames.
+//! A `gallery.toml` in the directory can set the order and captions instead:
+//!
+//! ```toml
+//! order = ["sunset.jpg", "beach.jpg"]
+//!
+//! [captions]
+//! "beach.jpg" = "The beach, the next morning"
+//! ```
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use miette::Diagnostic;
+use serde::Deserialize;
+use thiserror::Error;
+
+use super::directives::directives;
+use crate::{
+    index::{normalize_path, PageSource, SiteMetadata},
+    renderer::RenderContext,
+};
+
+/// File extensions that are included in galleries
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp"];
+
+/// The `[gallery]` section of `Site.toml`
+#[derive(Deserialize, Debug)]
+pub struct GalleryConfig {
+    /// The width and height of thumbnails, in pixels
+    #[serde(default = "default_thumbnail_size")]
+    pub thumbnail_size: u32,
+    /// How many thumbnails are in each row, unless a gallery says otherwise
+    #[serde(default = "default_columns")]
+    pub columns: u32,
+}
+
+impl Default for GalleryConfig {
+    fn default() -> Self {
+        Self {
+            thumbnail_size: default_thumbnail_size(),
+            columns: default_columns(),
+        }
+    }
+}
+
+fn default_thumbnail_size() -> u32 {
+    300
+}
+
+fn default_columns() -> u32 {
+    3
+}
+
+#[derive(Diagnostic, Debug, Error)]
+pub enum GalleryError {
+    #[error("`{}` has a gallery without a `dir`", .page.display())]
+    MissingDir { page: PathBuf },
+    #[error("reading gallery `{}` in `{}`", .dir.display(), .page.display())]
+    ReadDir {
+        dir: PathBuf,
+        page: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("parsing `{}`", .path.display())]
+    ParseSidecar {
+        path: PathBuf,
+        #[source]
+        source: toml::de::Error,
+    },
+}
+
+/// The optional `gallery.toml` in a gallery's directory
+#[derive(Deserialize, Default)]
+struct Sidecar {
+    #[serde(default)]
+    order: Vec<String>,
+    #[serde(default)]
+    captions: HashMap<String, String>,
+}
+
+/// Returns the URL of the thumbnail for the gallery image at `src`.
+pub fn gallery_thumbnail_url(src: &str) -> String {
+    match src.rsplit_once('.') {
+        Some((stem, ext)) => format!("{stem}.thumb.{ext}"),
+        None => format!("{src}.thumb"),
+    }
+}
+
+/// Replaces every gallery directive in `contents` with the gallery's markup.
+///
+/// Problems are reported through the render context, and the directive is
+/// left in place.
+pub fn expand_galleries<'a>(
+    contents: Cow<'a, str>,
+    page: &PageSource,
+    rcx: &RenderContext<'_>,
+) -> Cow<'a, str> {
+    let galleries = find_galleries(&contents);
+    if galleries.is_empty() {
+        return contents;
+    }
+    let default = GalleryConfig::default();
+    let config = rcx.site.config().gallery.as_ref().unwrap_or(&default);
+
+    let mut output = String::with_capacity(contents.len());
+    let mut copied = 0;
+    for (range, args) in galleries {
+        output.push_str(&contents[copied..range.start]);
+        match gallery(&args, page, rcx.site.root_dir(), config) {
+            Ok(markup) => output.push_str(&markup),
+            Err(e) => {
+                rcx.dcx.lock().unwrap().record(e);
+                output.push_str(&contents[range.clone()]);
+            }
+        }
+        copied = range.end;
+    }
+    output.push_str(&contents[copied..]);
+    Cow::Owned(output)
+}
+
+/// Builds the markup for a gallery on `page` with the given arguments.
+fn gallery(
+    args: &HashMap<String, String>,
+    page: &PageSource,
+    root_dir: &Path,
+    config: &GalleryConfig,
+) -> Result<String, GalleryError> {
+    let page_path = page.source_path();
+    let Some(dir) = args.get("dir") else {
+        return Err(GalleryError::MissingDir {
+            page: page_path.into(),
+        });
+    };
+    let source_dir = match dir.strip_prefix('/') {
+        Some(dir) => PathBuf::from(dir),
+        None => normalize_path(&page_path.parent().unwrap_or(Path::new("")).join(dir)),
+    };
+    // A bundle's files are published next to the post, so relative links to
+    // them work. Other pages are published somewhere other than their source,
+    // so they link to where the directory is copied instead.
+    let url_dir = if dir.starts_with('/') || page.is_bundle() {
+        dir.trim_end_matches('/').to_string()
+    } else {
+        format!("/{}", source_dir.to_string_lossy().replace('\\', "/"))
+    };
+    let images =
+        list_images(&root_dir.join(&source_dir)).map_err(|source| GalleryError::ReadDir {
+            dir: source_dir.clone(),
+            page: page_path.into(),
+            source,
+        })?;
+    let sidecar_path = root_dir.join(&source_dir).join("gallery.toml");
+    let sidecar = match std::fs::read_to_string(&sidecar_path) {
+        Ok(sidecar) => toml::from_str(&sidecar).map_err(|source| GalleryError::ParseSidecar {
+            path: source_dir.join("gallery.toml"),
+            source,
+        })?,
+        Err(_) => Sidecar::default(),
+    };
+    let columns = args
+        .get("columns")
+        .and_then(|columns| columns.parse().ok())
+        .unwrap_or(config.columns);
+    Ok(gallery_markup(
+        &url_dir,
+        &order_images(images, &sidecar.order),
+        &sidecar.captions,
+        columns,
+    ))
+}
+
+/// Lists the names of the images in `dir`, in filename order.
+fn list_images(dir: &Path) -> std::io::Result<Vec<String>> {
+    let mut images = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let name = entry?.file_name().to_string_lossy().into_owned();
+        let is_image = name.rsplit_once('.').is_some_and(|(stem, ext)| {
+            !stem.ends_with(".thumb")
+                && IMAGE_EXTENSIONS
+                    .iter()
+                    .any(|image| ext.eq_ignore_ascii_case(image))
+        });
+        if is_image {
+            images.push(name);
+        }
+    }
+    images.sort();
+    Ok(images)
+}
+
+/// Puts the images listed in `order` first, followed by the rest.
+fn order_images(mut images: Vec<String>, order: &[String]) -> Vec<String> {
+    let mut ordered: Vec<String> = order
+        .iter()
+        .filter(|name| images.contains(name))
+        .cloned()
+        .collect();
+    images.retain(|name| !order.contains(name));
+    ordered.extend(images);
+    ordered
+}
+
+/// Makes a caption from a filename such as `01-sunset_over-the-lake.jpg`,
+/// which becomes "Sunset over the lake".
+fn caption_from_filename(name: &str) -> String {
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    let stem = stem.trim_start_matches(|c: char| c.is_ascii_digit() || c == '-' || c == '_');
+    let words = stem.replace(['-', '_'], " ");
+    let mut chars = words.trim().chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn gallery_markup(
+    dir: &str,
+    images: &[String],
+    captions: &HashMap<String, String>,
+    columns: u32,
+) -> String {
+    let group = escape(dir.trim_start_matches('/'));
+    let mut markup = format!(
+        r#"<div class="gallery" style="--gallery-columns: {columns}" data-gallery="{group}">"#
+    );
+    for name in images {
+        let src = format!("{dir}/{name}");
+        let caption = captions
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| caption_from_filename(name));
+        let caption = escape(&caption);
+        markup += &format!(
+            r#"<figure class="gallery-item"><a href="{}" data-lightbox="{group}" data-caption="{caption}"><img src="{}" alt="{caption}" loading="lazy"></a><figcaption>{caption}</figcaption></figure>"#,
+            escape(&src),
+            escape(&gallery_thumbnail_url(&src)),
+        );
+    }
+    markup + "</div>"
+}
+
+/// Finds the gallery directives in `text` that aren't in code, returning
+/// their locations and arguments.
+fn find_galleries(text: &str) -> Vec<(Range<usize>, HashMap<String, String>)> {
+    directives(text)
+        .into_iter()
+        .filter_map(|directive| {
+            let args = directive
+                .inner
+                .trim()
+                .strip_prefix("gallery")
+                .filter(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))?;
+            Some((directive.range, parse_args(args)))
+        })
+        .collect()
+}
+
+/// Parses arguments like `dir="photos/trip" columns=4`.
+fn parse_args(mut args: &str) -> HashMap<String, String> {
+    let mut parsed = HashMap::new();
+    while let Some((name, rest)) = args.trim_start().split_once('=') {
+        let (value, rest) = match rest.strip_prefix('"') {
+            Some(rest) => rest.split_once('"').unwrap_or((rest, "")),
+            None => rest.split_once(char::is_whitespace).unwrap_or((rest, "")),
+        };
+        parsed.insert(name.trim().to_string(), value.to_string());
+        args = rest;
+    }
+    parsed
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use crate::index::{PageSource, SourceFormat};
+
+    use super::{caption_from_filename, find_galleries, gallery, GalleryConfig};
+
+    #[test]
+    fn directive_arguments() {
+        let text = r#"Photos: {{ gallery dir="photos/my trip" columns=4 }}"#;
+        let (range, args) = find_galleries(text).remove(0);
+        assert_eq!(
+            &text[range],
+            r#"{{ gallery dir="photos/my trip" columns=4 }}"#
+        );
+        assert_eq!(args["dir"], "photos/my trip");
+        assert_eq!(args["columns"], "4");
+        assert!(find_galleries("{{ galleries }}").is_empty());
+        assert!(find_galleries("```\n{{ gallery dir=\"a\" }}\n```").is_empty());
+    }
+
+    #[test]
+    fn captions_from_filenames() {
+        assert_eq!(
+            caption_from_filename("01-sunset_over-the-lake.jpg"),
+            "Sunset over the lake"
+        );
+        assert_eq!(caption_from_filename("IMG_1234.JPG"), "IMG 1234");
+    }
+
+    #[test]
+    fn ordered_gallery() {
+        let dir = tempfile::tempdir().unwrap();
+        let photos = dir.path().join("_posts/2024-01-01-trip/photos");
+        std::fs::create_dir_all(&photos).unwrap();
+        for name in ["1-beach.jpg", "2-hills.png", "3-lake.jpg", "notes.txt"] {
+            std::fs::write(photos.join(name), "").unwrap();
+        }
+        std::fs::write(
+            photos.join("gallery.toml"),
+            "order = [\"3-lake.jpg\"]\n[captions]\n\"2-hills.png\" = \"Hills & <sky>\"\n",
+        )
+        .unwrap();
+
+        let args = HashMap::from([("dir".to_string(), "photos".to_string())]);
+        let page = |path| PageSource::from_string(path, SourceFormat::Markdown, "");
+        let markup = gallery(
+            &args,
+            &page("_posts/2024-01-01-trip/index.md"),
+            dir.path(),
+            &GalleryConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            markup,
+            concat!(
+                r#"<div class="gallery" style="--gallery-columns: 3" data-gallery="photos">"#,
+                r#"<figure class="gallery-item"><a href="photos/3-lake.jpg" data-lightbox="photos" data-caption="Lake"><img src="photos/3-lake.thumb.jpg" alt="Lake" loading="lazy"></a><figcaption>Lake</figcaption></figure>"#,
+                r#"<figure class="gallery-item"><a href="photos/1-beach.jpg" data-lightbox="photos" data-caption="Beach"><img src="photos/1-beach.thumb.jpg" alt="Beach" loading="lazy"></a><figcaption>Beach</figcaption></figure>"#,
+                r#"<figure class="gallery-item"><a href="photos/2-hills.png" data-lightbox="photos" data-caption="Hills &amp; &lt;sky>"><img src="photos/2-hills.thumb.png" alt="Hills &amp; &lt;sky>" loading="lazy"></a><figcaption>Hills &amp; &lt;sky></figcaption></figure>"#,
+                "</div>",
+            )
+        );
+
+        // Other pages are published away from their source, so they link to
+        // where the photos are copied
+        std::fs::create_dir_all(dir.path().join("docs/photos")).unwrap();
+        std::fs::write(dir.path().join("docs/photos/a.jpg"), "").unwrap();
+        let markup = gallery(
+            &args,
+            &page("docs/trip.md"),
+            dir.path(),
+            &GalleryConfig::default(),
+        )
+        .unwrap();
+        assert!(markup.contains(
+            r#"<a href="/docs/photos/a.jpg" data-lightbox="docs/photos" data-caption="A"><img src="/docs/photos/a.thumb.jpg""#
+        ));
+    }
+}
@@ -0,0 +1,107 @@
+//! Explaining jargon with `<abbr>` elements
+//!
+//! The first time a page uses a term from the site's glossary, the term is
+//! wrapped in an `<abbr>` whose title is the definition. Terms in code, links,
+//! and image descriptions are left alone.
+
+use std::collections::{BTreeMap, HashSet};
+
+use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+
+pub fn mark_glossary_terms<'a>(
+    events: impl Iterator<Item = Event<'a>>,
+    glossary: &BTreeMap<String, String>,
+) -> Vec<Event<'a>> {
+    if glossary.is_empty() {
+        return events.collect();
+    }
+    let mut remaining: HashSet<&str> = glossary.keys().map(String::as_str).collect();
+    // How many code blocks, links, and images we are inside of
+    let mut skip_depth = 0usize;
+    let mut output = vec![];
+    for event in events {
+        match event {
+            Event::Start(Tag::CodeBlock(_) | Tag::Link { .. } | Tag::Image { .. }) => {
+                skip_depth += 1;
+                output.push(event);
+            }
+            Event::End(TagEnd::CodeBlock | TagEnd::Link | TagEnd::Image) => {
+                skip_depth = skip_depth.saturating_sub(1);
+                output.push(event);
+            }
+            Event::Text(text) if skip_depth == 0 && !remaining.is_empty() => {
+                let mut rest: &str = &text;
+                while let Some((start, term)) = first_term(rest, &remaining) {
+                    remaining.remove(term);
+                    output.push(Event::Text(CowStr::from(rest[..start].to_string())));
+                    output.push(Event::InlineHtml(CowStr::from(format!(
+                        r#"<abbr title="{}">"#,
+                        escape_attribute(&glossary[term])
+                    ))));
+                    output.push(Event::Text(CowStr::from(term.to_string())));
+                    output.push(Event::InlineHtml(CowStr::Borrowed("</abbr>")));
+                    rest = &rest[start + term.len()..];
+                }
+                output.push(Event::Text(CowStr::from(rest.to_string())));
+            }
+            event => output.push(event),
+        }
+    }
+    output
+}
+
+/// Finds the earliest whole-word use of any of `terms` in `text`, preferring
+/// the longest term when several start at the same place.
+fn first_term<'t>(text: &str, terms: &HashSet<&'t str>) -> Option<(usize, &'t str)> {
+    terms
+        .iter()
+        .filter_map(|term| {
+            text.match_indices(term)
+                .find(|(start, _)| {
+                    let before = text[..*start].chars().next_back();
+                    let after = text[start + term.len()..].chars().next();
+                    !before.is_some_and(char::is_alphanumeric)
+                        && !after.is_some_and(char::is_alphanumeric)
+                })
+                .map(|(start, _)| (start, *term))
+        })
+        .min_by_key(|(start, term)| (*start, std::cmp::Reverse(term.len())))
+}
+
+fn escape_attribute(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use pulldown_cmark::{html::push_html, Parser};
+
+    use super::mark_glossary_terms;
+
+    #[test]
+    fn first_use_only() {
+        let glossary = BTreeMap::from([
+            (
+                "API".to_string(),
+                "Application Programming Interface".to_string(),
+            ),
+            ("GC".to_string(), "Garbage \"Collection\"".to_string()),
+        ]);
+        let markdown = "`API` and [API](x) then the API, APIs, API and GC.";
+        let mut html = String::new();
+        push_html(
+            &mut html,
+            mark_glossary_terms(Parser::new(markdown), &glossary).into_iter(),
+        );
+        assert_eq!(
+            html,
+            "<p><code>API</code> and <a href=\"x\">API</a> then the \
+             <abbr title=\"Application Programming Interface\">API</abbr>, APIs, API and \
+             <abbr title=\"Garbage &quot;Collection&quot;\">GC</abbr>.</p>\n"
+        );
+    }
+}
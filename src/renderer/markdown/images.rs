@@ -0,0 +1,232 @@
+//! Responsive images with `srcset`
+//!
+//! When the `[images]` section of `Site.toml` is present, local images get a
+//! `srcset` listing smaller copies, so browsers on small screens can download
+//! less. The copies are written next to the original as `<name>.<width>w.<ext>`
+//! when the site is generated.
+
+use std::path::Path;
+
+use image::{metadata::Orientation, ImageDecoder, ImageReader};
+use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+use serde::Deserialize;
+
+use crate::index::{PageMetadata, PageSource};
+
+/// File extensions of images that can be resized
+const RESIZABLE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "webp"];
+
+/// The `[images]` section of `Site.toml`
+#[derive(Deserialize, Debug)]
+pub struct ImagesConfig {
+    /// The widths, in pixels, to make copies of each image at. Images are
+    /// never enlarged, so only widths smaller than the original are used.
+    #[serde(default = "default_widths")]
+    pub widths: Vec<u32>,
+    /// The `sizes` attribute, which tells browsers how wide images are shown
+    #[serde(default = "default_sizes")]
+    pub sizes: String,
+}
+
+impl Default for ImagesConfig {
+    fn default() -> Self {
+        Self {
+            widths: default_widths(),
+            sizes: default_sizes(),
+        }
+    }
+}
+
+fn default_widths() -> Vec<u32> {
+    vec![480, 960, 1920]
+}
+
+fn default_sizes() -> String {
+    "100vw".into()
+}
+
+/// Returns the URL of the copy of the image at `src` that is `width` pixels
+/// wide.
+pub fn resized_image_url(src: &str, width: u32) -> String {
+    match src.rsplit_once('.') {
+        Some((stem, ext)) if !ext.contains('/') => format!("{stem}.{width}w.{ext}"),
+        _ => format!("{src}.{width}w"),
+    }
+}
+
+fn is_resizable(src: &str) -> bool {
+    if src.contains("://") || src.starts_with("data:") || src.starts_with("//") {
+        return false;
+    }
+    src.rsplit_once('.').is_some_and(|(_, ext)| {
+        RESIZABLE_EXTENSIONS
+            .iter()
+            .any(|resizable| ext.eq_ignore_ascii_case(resizable))
+    })
+}
+
+/// Adds `srcset` and `sizes` to local images.
+///
+/// `image_width` returns how wide the image at a given `src` is, or `None` if
+/// it can't be found.
+pub fn add_srcsets<'a>(
+    events: impl Iterator<Item = Event<'a>>,
+    config: Option<&ImagesConfig>,
+    image_width: impl Fn(&str) -> Option<u32>,
+) -> Vec<Event<'a>> {
+    let Some(config) = config else {
+        return events.collect();
+    };
+    let mut output = vec![];
+    // The image being rewritten, with its srcset, title, and alt text
+    let mut image: Option<(CowStr<'a>, String, CowStr<'a>, String)> = None;
+    for event in events {
+        match event {
+            Event::Start(Tag::Image {
+                ref dest_url,
+                ref title,
+                ..
+            }) if image.is_none() => {
+                let srcset = is_resizable(dest_url)
+                    .then(|| srcset(dest_url, config, &image_width))
+                    .flatten();
+                match srcset {
+                    Some(srcset) => {
+                        image = Some((dest_url.clone(), srcset, title.clone(), String::new()))
+                    }
+                    None => output.push(event),
+                }
+            }
+            Event::End(TagEnd::Image) if image.is_some() => {
+                let (src, srcset, title, alt) = image.take().unwrap();
+                let mut markup = format!(
+                    r#"<img src="{}" srcset="{}" sizes="{}" alt="{}""#,
+                    escape(&src),
+                    escape(&srcset),
+                    escape(&config.sizes),
+                    escape(&alt)
+                );
+                if !title.is_empty() {
+                    markup += &format!(r#" title="{}""#, escape(&title));
+                }
+                markup += " />";
+                output.push(Event::InlineHtml(CowStr::from(markup)));
+            }
+            Event::Text(text) | Event::Code(text) if image.is_some() => {
+                image.as_mut().unwrap().3.push_str(&text);
+            }
+            _ if image.is_some() => {}
+            event => output.push(event),
+        }
+    }
+    output
+}
+
+/// Builds the `srcset` for an image, or returns `None` if there are no smaller
+/// copies to offer.
+fn srcset(
+    src: &str,
+    config: &ImagesConfig,
+    image_width: impl Fn(&str) -> Option<u32>,
+) -> Option<String> {
+    let width = image_width(src)?;
+    let mut widths: Vec<_> = config
+        .widths
+        .iter()
+        .copied()
+        .filter(|w| *w < width)
+        .collect();
+    if widths.is_empty() {
+        return None;
+    }
+    widths.sort_unstable();
+    widths.dedup();
+    let candidates = widths
+        .into_iter()
+        .map(|w| format!("{} {w}w", resized_image_url(src, w)))
+        .chain([format!("{src} {width}w")]);
+    Some(candidates.collect::<Vec<_>>().join(", "))
+}
+
+/// Reads the width of a local image linked from `page` as `src`, as it is
+/// shown once turned upright.
+///
+/// Relative links start from where the page is published, which is next to
+/// its source only for bundles.
+pub(super) fn local_image_width(root_dir: &Path, page: &PageSource, src: &str) -> Option<u32> {
+    let path = match src.strip_prefix('/') {
+        Some(path) => root_dir.join(path),
+        None if page.is_bundle() => root_dir
+            .join(page.source_path().parent().unwrap_or(Path::new("")))
+            .join(src),
+        None => root_dir.join(page.url()).join(src),
+    };
+    let mut decoder = ImageReader::open(path)
+        .ok()?
+        .with_guessed_format()
+        .ok()?
+        .into_decoder()
+        .ok()?;
+    let (width, height) = decoder.dimensions();
+    match decoder.orientation().ok()? {
+        Orientation::Rotate90
+        | Orientation::Rotate270
+        | Orientation::Rotate90FlipH
+        | Orientation::Rotate270FlipH => Some(height),
+        _ => Some(width),
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+}
+
+#[cfg(test)]
+mod test {
+    use pulldown_cmark::{html, Parser};
+
+    use super::{add_srcsets, resized_image_url, ImagesConfig};
+
+    fn render(markdown: &str, config: Option<&ImagesConfig>) -> String {
+        let mut out = String::new();
+        let width = |src: &str| match src {
+            "big.jpg" => Some(1200),
+            "small.png" => Some(300),
+            _ => None,
+        };
+        html::push_html(
+            &mut out,
+            add_srcsets(Parser::new(markdown), config, width).into_iter(),
+        );
+        out
+    }
+
+    #[test]
+    fn resized_urls() {
+        assert_eq!(resized_image_url("photos/a.jpg", 480), "photos/a.480w.jpg");
+        assert_eq!(resized_image_url("../x.y/a", 480), "../x.y/a.480w");
+    }
+
+    #[test]
+    fn srcset() {
+        let config = ImagesConfig::default();
+        assert_eq!(
+            render("![A *big* photo](big.jpg \"Big\")", Some(&config)),
+            "<p><img src=\"big.jpg\" srcset=\"big.480w.jpg 480w, big.960w.jpg 960w, big.jpg 1200w\" sizes=\"100vw\" alt=\"A big photo\" title=\"Big\" /></p>\n"
+        );
+        // Images that are already small, missing, or remote are left alone
+        assert_eq!(
+            render(
+                "![s](small.png) ![m](missing.png) ![r](https://example.com/a.png)",
+                Some(&config)
+            ),
+            "<p><img src=\"small.png\" alt=\"s\" /> <img src=\"missing.png\" alt=\"m\" /> <img src=\"https://example.com/a.png\" alt=\"r\" /></p>\n"
+        );
+        assert_eq!(
+            render("![b](big.jpg)", None),
+            "<p><img src=\"big.jpg\" alt=\"b\" /></p>\n"
+        );
+    }
+}
@@ -0,0 +1,166 @@
+//! Including one markdown file in another
+//!
+//! A page can pull in a shared snippet with `{{ include "snippets/note.md" }}`.
+//! Paths are relative to the including file, or to the site root if they
+//! start with `/`. Included files can include other files, as long as no file
+//! ends up including itself.
+
+use std::{
+    borrow::Cow,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use miette::Diagnostic;
+use thiserror::Error;
+
+use super::directives::directives;
+use crate::{
+    index::{normalize_path, PageSource, SiteMetadata},
+    renderer::RenderContext,
+};
+
+#[derive(Diagnostic, Debug, Error)]
+pub enum IncludeError {
+    #[error("could not include `{}` in `{}`", .path.display(), .from.display())]
+    Read {
+        path: PathBuf,
+        from: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("`{}` includes itself", .path.display())]
+    #[diagnostic(help("the chain of includes is {chain}"))]
+    Cycle { path: PathBuf, chain: String },
+}
+
+/// Replaces every include directive in the page's markdown with the contents
+/// of the file it names.
+///
+/// Problems are reported through the render context, and the directive is
+/// left in place.
+pub fn expand_includes<'a>(page: &'a PageSource, rcx: &RenderContext<'_>) -> Cow<'a, str> {
+    let contents = page.mainmatter();
+    if find_includes(contents).is_empty() {
+        return Cow::Borrowed(contents);
+    }
+    let mut stack = vec![page.source_path().to_path_buf()];
+    Cow::Owned(expand(
+        contents,
+        &mut stack,
+        rcx.site.root_dir(),
+        &mut |e| rcx.dcx.lock().unwrap().record(e),
+    ))
+}
+
+fn expand(
+    contents: &str,
+    stack: &mut Vec<PathBuf>,
+    root_dir: &Path,
+    report: &mut impl FnMut(IncludeError),
+) -> String {
+    let mut output = String::with_capacity(contents.len());
+    let mut copied = 0;
+    for (range, target) in find_includes(contents) {
+        output.push_str(&contents[copied..range.start]);
+        let from = stack.last().unwrap().clone();
+        let path = match target.strip_prefix('/') {
+            Some(target) => PathBuf::from(target),
+            None => normalize_path(&from.parent().unwrap_or(Path::new("")).join(target)),
+        };
+
+        if stack.contains(&path) {
+            let chain = stack
+                .iter()
+                .chain([&path])
+                .map(|path| format!("`{}`", path.display()))
+                .collect::<Vec<_>>()
+                .join(" → ");
+            report(IncludeError::Cycle { path, chain });
+            output.push_str(&contents[range.clone()]);
+        } else {
+            match std::fs::read_to_string(root_dir.join(&path)) {
+                Ok(included) => {
+                    stack.push(path);
+                    output.push_str(&expand(&included, stack, root_dir, report));
+                    stack.pop();
+                }
+                Err(source) => {
+                    report(IncludeError::Read { path, from, source });
+                    output.push_str(&contents[range.clone()]);
+                }
+            }
+        }
+        copied = range.end;
+    }
+    output.push_str(&contents[copied..]);
+    output
+}
+
+/// Finds the include directives in `text` that aren't in code, returning
+/// their locations and the paths they name.
+fn find_includes(text: &str) -> Vec<(Range<usize>, &str)> {
+    directives(text)
+        .into_iter()
+        .filter_map(|directive| {
+            let target = directive
+                .inner
+                .trim()
+                .strip_prefix("include")
+                .filter(|rest| rest.starts_with(char::is_whitespace))
+                .map(str::trim)
+                .and_then(|rest| rest.strip_prefix('"'))
+                .and_then(|rest| rest.strip_suffix('"'))?;
+            Some((directive.range, target))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::{expand, find_includes, IncludeError};
+
+    #[test]
+    fn find_directive() {
+        let text = r#"Hi {{ site.title }} and {{ include "snippets/a.md" }}!"#;
+        let found = find_includes(text);
+        let (range, target) = found[0].clone();
+        assert_eq!(target, "snippets/a.md");
+        assert_eq!(&text[range], r#"{{ include "snippets/a.md" }}"#);
+        assert!(find_includes("{{ included }}").is_empty());
+        assert!(find_includes(r#"`{{ include "a.md" }}`"#).is_empty());
+    }
+
+    #[test]
+    fn nested_includes_and_cycles() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("snippets")).unwrap();
+        std::fs::write(
+            dir.path().join("snippets/a.md"),
+            r#"A({{ include "b.md" }})"#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("snippets/b.md"),
+            r#"B{{ include "/snippets/a.md" }}"#,
+        )
+        .unwrap();
+
+        let mut errors = vec![];
+        let mut stack = vec![PathBuf::from("_posts/2024-01-01-hello.md")];
+        let output = expand(
+            r#"Start {{ include "../snippets/a.md" }} {{ include "/missing.md" }}"#,
+            &mut stack,
+            dir.path(),
+            &mut |e| errors.push(e),
+        );
+        assert_eq!(
+            output,
+            r#"Start A(B{{ include "/snippets/a.md" }}) {{ include "/missing.md" }}"#
+        );
+        assert!(matches!(&errors[0], IncludeError::Cycle { .. }));
+        assert!(matches!(&errors[1], IncludeError::Read { .. }));
+    }
+}
@@ -0,0 +1,309 @@
+//! Attributes for links to other sites
+//!
+//! When the `[external_links]` section of `Site.toml` is present, links that
+//! leave the site get a `rel` attribute, can open in a new tab, and can have
+//! tracking parameters such as `utm_source` removed from their URL.
+//!
+//! Rewrite rules change the links to particular domains, such as sending links
+//! to one site to a mirror of it or adding an affiliate tag.
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+use serde::{de::Error as _, Deserialize, Deserializer};
+use url::{Host, Url};
+
+use super::LinkDest;
+use crate::index::{SiteIndex, SiteMetadata};
+
+/// The `[external_links]` section of `Site.toml`
+#[derive(Deserialize, Debug)]
+pub struct ExternalLinksConfig {
+    /// The `rel` attribute to give external links, or an empty string for none
+    #[serde(default = "default_rel")]
+    pub rel: String,
+    /// Whether external links open in a new tab
+    #[serde(default)]
+    pub new_tab: bool,
+    /// Whether to remove `utm_*` tracking parameters from external URLs
+    #[serde(default)]
+    pub strip_utm: bool,
+    /// Changes to make to links to particular domains
+    #[serde(default)]
+    pub rewrite: Vec<RewriteRule>,
+}
+
+/// A change to make to links to one domain
+#[derive(Deserialize, Debug)]
+pub struct RewriteRule {
+    /// The domain the rule applies to, which includes its subdomains
+    pub domain: String,
+    /// The host to send links to instead, such as a mirror
+    #[serde(default, deserialize_with = "deserialize_host")]
+    pub host: Option<String>,
+    /// Query parameters to remove
+    #[serde(default)]
+    pub strip_params: Vec<String>,
+    /// Query parameters to add, replacing any with the same name
+    #[serde(default)]
+    pub add_params: BTreeMap<String, String>,
+}
+
+fn deserialize_host<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    let host = String::deserialize(deserializer)?;
+    Host::parse(&host).map_err(|e| D::Error::custom(format!("invalid host `{host}`: {e}")))?;
+    Ok(Some(host))
+}
+
+impl RewriteRule {
+    fn matches(&self, host: &str) -> bool {
+        host == self.domain
+            || host
+                .strip_suffix(&self.domain)
+                .is_some_and(|sub| sub.ends_with('.'))
+    }
+}
+
+impl Default for ExternalLinksConfig {
+    fn default() -> Self {
+        Self {
+            rel: default_rel(),
+            new_tab: false,
+            strip_utm: false,
+            rewrite: vec![],
+        }
+    }
+}
+
+impl ExternalLinksConfig {
+    /// Returns what `url` should be rewritten to, or `None` if it stays the
+    /// same.
+    pub fn rewrite(&self, original: &Url) -> Option<String> {
+        let mut params: Vec<(String, String)> = original
+            .query_pairs()
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect();
+        let original_params = params.len();
+        let mut query_changed = false;
+        if self.strip_utm {
+            params.retain(|(key, _)| !key.starts_with("utm_"));
+        }
+
+        let mut url = original.clone();
+        let host = url.host_str().unwrap_or_default().to_string();
+        for rule in self.rewrite.iter().filter(|rule| rule.matches(&host)) {
+            // Hosts are checked when the config is loaded, so this only fails
+            // for URLs that can't have a host at all
+            if let Some(host) = &rule.host {
+                if url.set_host(Some(host)).is_err() {
+                    continue;
+                }
+            }
+            params.retain(|(key, _)| !rule.strip_params.contains(key));
+            for (key, value) in &rule.add_params {
+                let mut existing = params.iter().filter(|(k, _)| k == key);
+                if existing.next().is_some_and(|(_, v)| v == value) && existing.next().is_none() {
+                    continue;
+                }
+                params.retain(|(k, _)| k != key);
+                params.push((key.clone(), value.clone()));
+                query_changed = true;
+            }
+        }
+
+        if query_changed || params.len() != original_params {
+            if params.is_empty() {
+                url.set_query(None);
+            } else {
+                url.query_pairs_mut().clear().extend_pairs(params);
+            }
+        }
+        (url != *original).then(|| url.to_string())
+    }
+}
+
+fn default_rel() -> String {
+    "noopener noreferrer".into()
+}
+
+/// Adds the configured attributes to links that leave the site.
+///
+/// Links to `base_url` are on the site itself, even though they are absolute,
+/// so they are left alone.
+pub fn decorate_external_links<'a>(
+    events: impl Iterator<Item = Event<'a>>,
+    config: Option<&ExternalLinksConfig>,
+    base_url: &str,
+) -> Vec<Event<'a>> {
+    let Some(config) = config else {
+        return events.collect();
+    };
+    // Links can't contain other links, so only one can be open at a time
+    let mut in_external_link = false;
+    events
+        .map(|event| match event {
+            Event::Start(Tag::Link {
+                ref dest_url,
+                ref title,
+                ..
+            }) => {
+                let Ok(dest) = LinkDest::parse(dest_url);
+                let url = match dest.external_url() {
+                    Some(url) if !is_internal(url, base_url) => url,
+                    _ => return event,
+                };
+                in_external_link = true;
+                let href = config.rewrite(url).unwrap_or_else(|| dest_url.to_string());
+                let mut markup = format!(r#"<a href="{}""#, escape(&href));
+                if !title.is_empty() {
+                    markup += &format!(r#" title="{}""#, escape(title));
+                }
+                if !config.rel.is_empty() {
+                    markup += &format!(r#" rel="{}""#, escape(&config.rel));
+                }
+                if config.new_tab {
+                    markup += r#" target="_blank""#;
+                }
+                markup += ">";
+                Event::InlineHtml(CowStr::from(markup))
+            }
+            Event::End(TagEnd::Link) if in_external_link => {
+                in_external_link = false;
+                Event::InlineHtml(CowStr::Borrowed("</a>"))
+            }
+            event => event,
+        })
+        .collect()
+}
+
+fn is_internal(url: &Url, base_url: &str) -> bool {
+    let base_url = base_url.trim_end_matches('/');
+    !base_url.is_empty()
+        && url
+            .as_str()
+            .strip_prefix(base_url)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(['/', '?', '#']))
+}
+
+/// A link that rewrite rules would change
+#[derive(Debug, PartialEq)]
+pub struct LinkRewrite {
+    pub page: PathBuf,
+    pub from: String,
+    pub to: String,
+}
+
+/// Lists the changes the rewrite rules in `Site.toml` make to the external
+/// links in `site`, without rendering it.
+pub fn planned_link_rewrites(site: &SiteIndex) -> Vec<LinkRewrite> {
+    let Some(config) = &site.config().external_links else {
+        return vec![];
+    };
+    let mut rewrites = vec![];
+    for page in site.all_pages() {
+        for link in page.external_links() {
+            let Ok(url) = Url::parse(&link) else {
+                continue;
+            };
+            if is_internal(&url, site.base_url()) {
+                continue;
+            }
+            if let Some(to) = config.rewrite(&url) {
+                rewrites.push(LinkRewrite {
+                    page: page.source_path().to_path_buf(),
+                    from: link,
+                    to,
+                });
+            }
+        }
+    }
+    rewrites
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+}
+
+#[cfg(test)]
+mod test {
+    use pulldown_cmark::{html, Parser};
+
+    use url::Url;
+
+    use super::{decorate_external_links, ExternalLinksConfig};
+
+    fn render(markdown: &str, config: Option<&ExternalLinksConfig>) -> String {
+        let mut out = String::new();
+        html::push_html(
+            &mut out,
+            decorate_external_links(Parser::new(markdown), config, "https://blog.example")
+                .into_iter(),
+        );
+        out
+    }
+
+    #[test]
+    fn external_links() {
+        let config = ExternalLinksConfig {
+            new_tab: true,
+            strip_utm: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            render(
+                "[*a*](https://example.com/?utm_source=x&id=1 \"A\") [b](/b) [c](https://blog.example/c)",
+                Some(&config)
+            ),
+            "<p><a href=\"https://example.com/?id=1\" title=\"A\" rel=\"noopener noreferrer\" target=\"_blank\"><em>a</em></a> <a href=\"/b\">b</a> <a href=\"https://blog.example/c\">c</a></p>\n"
+        );
+        assert_eq!(
+            render("[a](https://example.com/?utm_source=x)", None),
+            "<p><a href=\"https://example.com/?utm_source=x\">a</a></p>\n"
+        );
+    }
+
+    #[test]
+    fn rewrite_rules() {
+        let config: ExternalLinksConfig = toml::from_str(
+            r#"
+            [[rewrite]]
+            domain = "twitter.com"
+            host = "nitter.net"
+            strip_params = ["s"]
+
+            [[rewrite]]
+            domain = "amazon.com"
+            add_params = { tag = "me-20" }
+            "#,
+        )
+        .unwrap();
+        let rewrite = |url: &str| config.rewrite(&Url::parse(url).unwrap());
+        assert_eq!(
+            rewrite("https://mobile.twitter.com/eholk/status/1?s=20").as_deref(),
+            Some("https://nitter.net/eholk/status/1")
+        );
+        assert_eq!(
+            rewrite("https://www.amazon.com/dp/1?tag=other-20&th=1").as_deref(),
+            Some("https://www.amazon.com/dp/1?th=1&tag=me-20")
+        );
+        assert_eq!(
+            rewrite("https://amazon.com/dp/1?tag=me-20&th=1").as_deref(),
+            None
+        );
+        assert_eq!(rewrite("https://nottwitter.com/?s=1"), None);
+        assert_eq!(rewrite("https://example.com/?utm_source=x"), None);
+    }
+
+    #[test]
+    fn invalid_host() {
+        let error = toml::from_str::<ExternalLinksConfig>(
+            "[[rewrite]]\ndomain = \"twitter.com\"\nhost = \"nitter net\"\n",
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("invalid host `nitter net`"));
+    }
+}
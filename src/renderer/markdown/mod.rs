@@ -2,53 +2,225 @@
 //!
 //! These are implemented as iterators from markdown events to markdown events.
 
-use self::anchors::HeadingAnchors;
+use self::{
+    anchors::HeadingAnchors,
+    citations::render_citations,
+    directives::protect_braces,
+    gallery::expand_galleries,
+    glossary::mark_glossary_terms,
+    images::add_srcsets,
+    include::expand_includes,
+    link_attrs::decorate_external_links,
+    obfuscate::obfuscate_links,
+    raw_html::check_raw_html,
+    shortcodes::expand_shortcodes,
+    toc::{collect_toc, insert_toc},
+    typography::improve_typography,
+    vars::expand_vars,
+    video::embed_videos,
+};
+
+use std::collections::BTreeSet;
 
 use super::RenderContext;
-use crate::index::PageSource;
-use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use crate::index::{PageSource, SiteMetadata};
+use miette::diagnostic;
+use pulldown_cmark::{Event, HeadingLevel, Parser, Tag, TagEnd};
 
 mod anchors;
+mod citations;
 mod code;
+mod directives;
 mod footnotes;
+mod gallery;
+mod glossary;
+mod images;
+mod include;
+mod link_attrs;
+mod obfuscate;
+mod pipeline;
+mod raw_html;
+mod shortcodes;
 mod source_links;
+mod toc;
+mod typography;
+mod vars;
+mod video;
 
-pub use code::CodeFormatter;
+pub(crate) use code::load_syntaxes;
+pub use code::{CodeFormatter, ColorScheme, HighlightThemeError, HighlightThemes};
+pub(crate) use directives::code_ranges;
 pub use footnotes::collect_footnotes;
-pub use source_links::adjust_relative_links;
+pub use gallery::{gallery_thumbnail_url, GalleryConfig};
+pub use images::{resized_image_url, ImagesConfig};
+pub use link_attrs::{planned_link_rewrites, ExternalLinksConfig, LinkRewrite, RewriteRule};
+pub use obfuscate::{ObfuscateConfig, ObfuscationMethod};
+pub(crate) use obfuscate::{DECODE_SCRIPT, HIDDEN_HREF};
+pub use pipeline::{
+    BuiltinPass, Extension, MarkdownConfig, MarkdownPass, MarkdownPipeline, MarkdownProfile, Pass,
+};
+pub(crate) use raw_html::is_safe_url;
+pub use raw_html::{RawHtmlError, RawHtmlPolicy};
+pub(crate) use shortcodes::shortcode_dirs;
+pub use shortcodes::{ShortcodeError, Shortcodes};
+pub(crate) use source_links::LinkDest;
+pub use source_links::{adjust_relative_links, check_links, BrokenLink};
+pub use toc::TocEntry;
+pub use typography::TypographyConfig;
+pub use video::{is_video, poster_url, VideoConfig};
+
+/// What the markdown passes learn about a page besides its HTML
+#[derive(Default)]
+pub(super) struct MarkdownMetadata {
+    /// The title, if this is a new-style post (i.e. one that starts with an h1
+    /// that indicates the title)
+    pub title: Option<String>,
+    pub toc: Vec<TocEntry>,
+}
 
 /// Renders a page's markdown contents
-///
-/// If this is a new-style post (i.e. one that starts with an h1 that indicates the title), the
-/// second field of the returned tuple will be the page's title extracted from the markdown
-/// contents.
 pub(super) fn render_markdown(
     source: &PageSource,
     rcx: &RenderContext<'_>,
-) -> (String, Option<String>) {
-    let contents = source.mainmatter();
-    let parser = Parser::new_ext(
-        contents,
-        Options::ENABLE_FOOTNOTES
-            | Options::ENABLE_STRIKETHROUGH
-            | Options::ENABLE_TABLES
-            | Options::ENABLE_HEADING_ATTRIBUTES,
-    );
+) -> (String, MarkdownMetadata) {
+    let contents = expand_shortcodes(expand_includes(source, rcx), source, rcx);
+    let contents = expand_vars(expand_galleries(contents, source, rcx), source, rcx);
+    let markdown = &rcx.site.config().markdown;
+    let parser = Parser::new_ext(&contents, markdown.options(source.source_path()));
 
-    let (parser, title) = extract_title_and_adjust_headers(parser);
+    let disabled = markdown.disabled(source.source_path());
+    // Names from Site.toml are checked once by `check_disabled_passes`
+    for name in source.disabled_passes() {
+        report_unknown_pass(rcx, &source.source_path().display().to_string(), name);
+    }
+    let mut events: Vec<_> = parser.collect();
+    let mut metadata = MarkdownMetadata::default();
+    for pass in rcx.site.markdown_pipeline().passes() {
+        let name = pass.name();
+        if disabled
+            .iter()
+            .chain(source.disabled_passes())
+            .any(|d| d == name)
+        {
+            continue;
+        }
+        events = match pass {
+            Pass::Builtin(pass) => run_builtin(*pass, events, source, rcx, &mut metadata),
+            Pass::Custom(pass) => pass.run(events, source),
+        };
+    }
 
-    let parser = adjust_relative_links(parser.collect(), source, rcx);
+    let mut markdown_buffer = String::with_capacity(contents.len() * 2);
+    pulldown_cmark::html::push_html(&mut markdown_buffer, events.into_iter());
+    (protect_braces(markdown_buffer), metadata)
+}
 
-    let mut anchors = HeadingAnchors::new();
-    let parser = anchors.add_anchors(parser.into_iter());
+/// Reports the passes that Site.toml disables but that don't exist.
+///
+/// This runs once per render rather than once per page, and only once any
+/// passes from programs using EBG as a library have been added.
+pub(crate) fn check_disabled_passes(rcx: &RenderContext<'_>) {
+    let markdown = &rcx.site.config().markdown;
+    let collections = markdown
+        .collections
+        .values()
+        .filter_map(|profile| profile.disable.as_deref());
+    let names: BTreeSet<_> = [markdown.disable.as_slice()]
+        .into_iter()
+        .chain(collections)
+        .flatten()
+        .collect();
+    for name in names {
+        report_unknown_pass(rcx, "Site.toml", name);
+    }
+}
 
-    let mut markdown_buffer = String::with_capacity(contents.len() * 2);
-    pulldown_cmark::html::push_html(
-        &mut markdown_buffer,
-        rcx.code_formatter
-            .format_codeblocks(collect_footnotes(parser)),
-    );
-    (markdown_buffer, title)
+fn report_unknown_pass(rcx: &RenderContext<'_>, from: &str, name: &str) {
+    let pipeline = rcx.site.markdown_pipeline();
+    if pipeline.passes().any(|pass| pass.name() == name) {
+        return;
+    }
+    let known = pipeline
+        .passes()
+        .map(|pass| format!("`{}`", pass.name()))
+        .collect::<Vec<_>>()
+        .join(", ");
+    rcx.dcx.lock().unwrap().record(diagnostic!(
+        help = format!("the passes are {known}"),
+        "`{from}` disables the markdown pass `{name}`, which does not exist",
+    ));
+}
+
+/// Runs one of the built-in markdown passes, recording what it finds in
+/// `metadata`.
+fn run_builtin<'a, 'r: 'a>(
+    pass: BuiltinPass,
+    events: Vec<Event<'a>>,
+    source: &PageSource,
+    rcx: &RenderContext<'r>,
+    metadata: &mut MarkdownMetadata,
+) -> Vec<Event<'a>> {
+    let config = rcx.site.config();
+    match pass {
+        BuiltinPass::RawHtml => {
+            let markdown = &config.markdown;
+            check_raw_html(
+                events.into_iter(),
+                markdown.raw_html(source.source_path()),
+                &markdown.allowed_html,
+                source,
+                |error| rcx.dcx.lock().unwrap().record(error),
+            )
+        }
+        BuiltinPass::Title => {
+            let (events, found) = extract_title_and_adjust_headers(events.into_iter());
+            let events = events.collect();
+            metadata.title = found;
+            events
+        }
+        BuiltinPass::Links => adjust_relative_links(events, source, rcx),
+        BuiltinPass::ExternalLinks => decorate_external_links(
+            events.into_iter(),
+            config.external_links.as_ref(),
+            rcx.site.base_url(),
+        ),
+        BuiltinPass::Obfuscate => {
+            obfuscate_links(events.into_iter(), config.obfuscate_links.as_ref())
+        }
+        // The anchors borrow from `HeadingAnchors`, so they need to be copied
+        // out before it goes away
+        BuiltinPass::Anchors => HeadingAnchors::new()
+            .add_anchors(events.into_iter())
+            .map(Event::into_static)
+            .collect(),
+        BuiltinPass::Toc => {
+            metadata.toc = collect_toc(&events);
+            insert_toc(events.into_iter(), &metadata.toc)
+        }
+        BuiltinPass::Videos => embed_videos(events.into_iter(), config.video.as_ref()),
+        BuiltinPass::Images => add_srcsets(events.into_iter(), config.images.as_ref(), |src| {
+            images::local_image_width(rcx.site.root_dir(), source, src)
+        }),
+        BuiltinPass::Glossary => mark_glossary_terms(events.into_iter(), rcx.site.glossary()),
+        BuiltinPass::Citations => {
+            render_citations(events.into_iter(), rcx.site.bibliography(), |key| {
+                rcx.dcx.lock().unwrap().record(diagnostic!(
+                    severity = miette::Severity::Warning,
+                    help = "check the key against the bibliography in Site.toml",
+                    "`{}` cites `{key}`, which is not in the bibliography",
+                    source.source_path().display(),
+                ))
+            })
+        }
+        BuiltinPass::Typography => {
+            improve_typography(events.into_iter(), config.typography.as_ref())
+        }
+        BuiltinPass::Footnotes => collect_footnotes(events.into_iter()).collect(),
+        BuiltinPass::Code => rcx
+            .code_formatter
+            .format_codeblocks(events.into_iter())
+            .collect(),
+    }
 }
 
 // pub fn trace_events<'a>(
@@ -139,9 +311,37 @@ fn promote_heading(level: HeadingLevel) -> HeadingLevel {
 
 #[cfg(test)]
 mod test {
-    use pulldown_cmark::{Event, HeadingLevel, Parser, Tag, TagEnd};
+    use std::collections::BTreeMap;
 
-    use super::extract_title_and_adjust_headers;
+    use pulldown_cmark::{html::push_html, Event, HeadingLevel, Parser, Tag, TagEnd};
+
+    use super::{
+        decorate_external_links, extract_title_and_adjust_headers, mark_glossary_terms,
+        BuiltinPass, ExternalLinksConfig,
+    };
+
+    #[test]
+    fn glossary_skips_external_links() {
+        let glossary = BTreeMap::from([("Rust".to_string(), "A language".to_string())]);
+        let config = ExternalLinksConfig::default();
+        let mut events: Vec<_> = Parser::new("[Rust](https://rust-lang.org) is Rust").collect();
+        for pass in BuiltinPass::ALL {
+            events = match pass {
+                BuiltinPass::Glossary => mark_glossary_terms(events.into_iter(), &glossary),
+                BuiltinPass::ExternalLinks => {
+                    decorate_external_links(events.into_iter(), Some(&config), "")
+                }
+                _ => events,
+            };
+        }
+        let mut html = String::new();
+        push_html(&mut html, events.into_iter());
+        assert_eq!(
+            html,
+            "<p><a href=\"https://rust-lang.org\" rel=\"noopener noreferrer\">Rust</a> \
+             is <abbr title=\"A language\">Rust</abbr></p>\n"
+        );
+    }
 
     #[test]
     fn extract_title_heading() {
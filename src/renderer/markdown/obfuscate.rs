@@ -0,0 +1,154 @@
+//! Hiding email addresses and phone numbers from harvesters
+//!
+//! When the `[obfuscate_links]` section of `Site.toml` is present, `mailto:`
+//! links, and optionally `tel:` links, are written so that programs scanning
+//! the HTML for addresses have a harder time finding them.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+use serde::Deserialize;
+
+use super::LinkDest;
+
+/// The `[obfuscate_links]` section of `Site.toml`
+#[derive(Deserialize, Debug, Default)]
+pub struct ObfuscateConfig {
+    #[serde(default)]
+    pub method: ObfuscationMethod,
+    /// Whether to hide `tel:` links as well
+    #[serde(default)]
+    pub phone: bool,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ObfuscationMethod {
+    /// Writes every character as an HTML entity, which browsers show as usual
+    #[default]
+    Entities,
+    /// Leaves the address out of the `href` and fills it in with a small
+    /// script, so readers without JavaScript get a link that goes nowhere
+    Javascript,
+}
+
+/// The attribute that holds the encoded URL of a link hidden with the
+/// `javascript` method
+pub(crate) const HIDDEN_HREF: &str = "data-hidden-href";
+
+/// Fills in the `href` of each hidden link on the page.
+///
+/// Pages with hidden links get this once, at the end of the `<body>`, rather
+/// than in their content, so that feeds don't carry scripts. The URL is
+/// base64-encoded UTF-8, which `atob` alone would garble outside of ASCII.
+pub(crate) const DECODE_SCRIPT: &str = "<script>document.querySelectorAll('a[data-hidden-href]')\
+     .forEach(function(a){var b=atob(a.dataset.hiddenHref.split('').reverse().join(''));\
+     a.href=new TextDecoder().decode(Uint8Array.from(b,function(c){return c.charCodeAt(0)}))})\
+     </script>";
+
+/// Returns the URL to hide for a link to `dest`, if it is an email address or
+/// a phone number that should be hidden.
+fn hidden_href(dest: &str, config: &ObfuscateConfig) -> Option<String> {
+    let Ok(link) = LinkDest::parse(dest);
+    match link {
+        // Autolinks such as `<me@example.com>` leave out the scheme
+        LinkDest::Email(address) => Some(format!("mailto:{address}")),
+        LinkDest::External(url) if url.scheme() == "mailto" => Some(dest.to_string()),
+        LinkDest::External(url) if url.scheme() == "tel" && config.phone => Some(dest.to_string()),
+        _ => None,
+    }
+}
+
+/// Rewrites email links, and phone links if enabled, so their addresses don't
+/// appear in the HTML as plain text.
+pub fn obfuscate_links<'a>(
+    events: impl Iterator<Item = Event<'a>>,
+    config: Option<&ObfuscateConfig>,
+) -> Vec<Event<'a>> {
+    let Some(config) = config else {
+        return events.collect();
+    };
+    // Links can't contain other links, so only one can be open at a time
+    let mut in_hidden_link = false;
+    let mut output = vec![];
+    for event in events {
+        match event {
+            Event::Start(Tag::Link {
+                ref dest_url,
+                ref title,
+                ..
+            }) => {
+                let Some(href) = hidden_href(dest_url, config) else {
+                    output.push(event);
+                    continue;
+                };
+                in_hidden_link = true;
+                let mut markup = match config.method {
+                    ObfuscationMethod::Entities => format!(r#"<a href="{}""#, entities(&href)),
+                    ObfuscationMethod::Javascript => {
+                        let encoded: String = STANDARD.encode(&href).chars().rev().collect();
+                        format!(r##"<a href="#" {HIDDEN_HREF}="{encoded}""##)
+                    }
+                };
+                if !title.is_empty() {
+                    markup += &format!(r#" title="{}""#, entities(title));
+                }
+                markup += ">";
+                output.push(Event::InlineHtml(CowStr::from(markup)));
+            }
+            // The text of the link is often the address itself
+            Event::Text(text) | Event::Code(text) if in_hidden_link => {
+                output.push(Event::InlineHtml(CowStr::from(entities(&text))));
+            }
+            Event::End(TagEnd::Link) if in_hidden_link => {
+                in_hidden_link = false;
+                output.push(Event::InlineHtml(CowStr::Borrowed("</a>")));
+            }
+            event => output.push(event),
+        }
+    }
+    output
+}
+
+/// Writes every character of `s` as a decimal HTML entity.
+fn entities(s: &str) -> String {
+    s.chars().map(|c| format!("&#{};", c as u32)).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use pulldown_cmark::{html, Parser};
+
+    use super::{obfuscate_links, ObfuscateConfig, ObfuscationMethod};
+
+    fn render(markdown: &str, config: &ObfuscateConfig) -> String {
+        let mut out = String::new();
+        html::push_html(
+            &mut out,
+            obfuscate_links(Parser::new(markdown), Some(config)).into_iter(),
+        );
+        out
+    }
+
+    #[test]
+    fn entities() {
+        let config = ObfuscateConfig::default();
+        assert_eq!(
+            render("<a@b.c> [call](tel:1) [web](https://a.b)", &config),
+            "<p><a href=\"&#109;&#97;&#105;&#108;&#116;&#111;&#58;&#97;&#64;&#98;&#46;&#99;\">\
+             &#97;&#64;&#98;&#46;&#99;</a> <a href=\"tel:1\">call</a> <a href=\"https://a.b\">web</a></p>\n"
+        );
+    }
+
+    #[test]
+    fn javascript() {
+        let config = ObfuscateConfig {
+            method: ObfuscationMethod::Javascript,
+            phone: true,
+        };
+        let html = render("[call me](tel:1)", &config);
+        // "tel:1" in base64, reversed
+        assert!(html.starts_with("<p><a href=\"#\" data-hidden-href=\"=EjOsVGd\">"));
+        assert!(!html.contains("<script>"));
+        assert!(!html.contains("tel:"));
+    }
+}
@@ -0,0 +1,412 @@
+//! The ordered list of passes that markdown events go through
+//!
+//! Each pass takes the events from the one before it. Sites can turn built-in
+//! passes off in `Site.toml` or in a page's frontmatter, and programs that use
+//! EBG as a library can add their own passes with [`MarkdownPipeline`].
+//!
+//! Collections of pages, such as everything in `papers/`, can have their own
+//! profile that changes which Markdown extensions are enabled and which passes
+//! are skipped.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use pulldown_cmark::{Event, Options};
+use serde::Deserialize;
+
+use crate::index::PageSource;
+
+use super::raw_html::{default_allowed_html, RawHtmlPolicy};
+
+/// The `[markdown]` section of `Site.toml`
+#[derive(Deserialize, Debug)]
+pub struct MarkdownConfig {
+    /// The Markdown extensions to enable
+    #[serde(default = "default_extensions")]
+    pub extensions: Vec<Extension>,
+    /// The names of passes to skip on every page
+    #[serde(default)]
+    pub disable: Vec<String>,
+    /// Profiles for the pages in particular directories, which are relative
+    /// to the site root
+    #[serde(default)]
+    pub collections: BTreeMap<PathBuf, MarkdownProfile>,
+    /// What to do with raw HTML tags that aren't in `allowed_html`
+    #[serde(default)]
+    pub raw_html: RawHtmlPolicy,
+    #[serde(default = "default_allowed_html")]
+    pub allowed_html: Vec<String>,
+}
+
+impl Default for MarkdownConfig {
+    fn default() -> Self {
+        Self {
+            extensions: default_extensions(),
+            disable: vec![],
+            collections: BTreeMap::new(),
+            raw_html: RawHtmlPolicy::default(),
+            allowed_html: default_allowed_html(),
+        }
+    }
+}
+
+fn default_extensions() -> Vec<Extension> {
+    vec![
+        Extension::Footnotes,
+        Extension::Strikethrough,
+        Extension::Tables,
+        Extension::HeadingAttributes,
+    ]
+}
+
+impl MarkdownConfig {
+    /// Returns the profile for the deepest collection containing `page`, if
+    /// any.
+    fn profile(&self, page: &Path) -> Option<&MarkdownProfile> {
+        self.collections
+            .iter()
+            .filter(|(dir, _)| page.starts_with(dir))
+            .max_by_key(|(dir, _)| dir.components().count())
+            .map(|(_, profile)| profile)
+    }
+
+    /// Returns the parser options for the page at `page`.
+    pub fn options(&self, page: &Path) -> Options {
+        let extensions = self
+            .profile(page)
+            .and_then(|profile| profile.extensions.as_ref())
+            .unwrap_or(&self.extensions);
+        extensions
+            .iter()
+            .fold(Options::empty(), |options, ext| options | ext.option())
+    }
+
+    /// Returns the names of the passes to skip for the page at `page`.
+    pub fn disabled(&self, page: &Path) -> &[String] {
+        self.profile(page)
+            .and_then(|profile| profile.disable.as_deref())
+            .unwrap_or(&self.disable)
+    }
+
+    /// Returns the raw HTML policy for the page at `page`.
+    pub fn raw_html(&self, page: &Path) -> RawHtmlPolicy {
+        self.profile(page)
+            .and_then(|profile| profile.raw_html)
+            .unwrap_or(self.raw_html)
+    }
+}
+
+/// Settings for the pages in one collection, which replace the site-wide ones
+#[derive(Deserialize, Debug, Default)]
+pub struct MarkdownProfile {
+    pub extensions: Option<Vec<Extension>>,
+    pub disable: Option<Vec<String>>,
+    pub raw_html: Option<RawHtmlPolicy>,
+}
+
+/// Markdown syntax beyond CommonMark
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Extension {
+    Footnotes,
+    Strikethrough,
+    Tables,
+    /// `# Heading {#id .class}`
+    HeadingAttributes,
+    /// `- [ ]` and `- [x]`
+    Tasklists,
+    /// Curly quotes, en and em dashes, and ellipses
+    SmartPunctuation,
+    /// `$inline$` and `$$display$$` math
+    Math,
+    /// GitHub-style blockquote alerts, such as `> [!NOTE]`
+    Gfm,
+    DefinitionList,
+}
+
+impl Extension {
+    fn option(self) -> Options {
+        match self {
+            Extension::Footnotes => Options::ENABLE_FOOTNOTES,
+            Extension::Strikethrough => Options::ENABLE_STRIKETHROUGH,
+            Extension::Tables => Options::ENABLE_TABLES,
+            Extension::HeadingAttributes => Options::ENABLE_HEADING_ATTRIBUTES,
+            Extension::Tasklists => Options::ENABLE_TASKLISTS,
+            Extension::SmartPunctuation => Options::ENABLE_SMART_PUNCTUATION,
+            Extension::Math => Options::ENABLE_MATH,
+            Extension::Gfm => Options::ENABLE_GFM,
+            Extension::DefinitionList => Options::ENABLE_DEFINITION_LIST,
+        }
+    }
+}
+
+/// The passes that are built into EBG, in the order they run by default
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuiltinPass {
+    /// Applies the raw HTML policy to the HTML written in the page
+    RawHtml,
+    /// Uses a leading `# Heading` as the page's title
+    Title,
+    /// Turns links to source files into links to their pages
+    Links,
+    /// Adds attributes to links to other sites
+    ExternalLinks,
+    /// Hides email addresses and phone numbers in links
+    Obfuscate,
+    /// Gives headings ids so they can be linked to
+    Anchors,
+    /// Collects the table of contents and fills in `<!-- TOC -->`
+    Toc,
+    Videos,
+    /// Adds `srcset` to images
+    Images,
+    Glossary,
+    Citations,
+    Typography,
+    /// Moves footnotes to the end of the page
+    Footnotes,
+    /// Highlights code blocks
+    Code,
+}
+
+impl BuiltinPass {
+    pub const ALL: [BuiltinPass; 14] = [
+        BuiltinPass::RawHtml,
+        BuiltinPass::Title,
+        BuiltinPass::Links,
+        BuiltinPass::Anchors,
+        BuiltinPass::Toc,
+        BuiltinPass::Videos,
+        BuiltinPass::Images,
+        BuiltinPass::Glossary,
+        BuiltinPass::Citations,
+        BuiltinPass::Typography,
+        // These turn links into raw HTML, so they come after the passes that
+        // look for links
+        BuiltinPass::ExternalLinks,
+        BuiltinPass::Obfuscate,
+        BuiltinPass::Footnotes,
+        BuiltinPass::Code,
+    ];
+
+    /// The name used to disable the pass
+    pub fn name(self) -> &'static str {
+        match self {
+            BuiltinPass::RawHtml => "raw_html",
+            BuiltinPass::Title => "title",
+            BuiltinPass::Links => "links",
+            BuiltinPass::ExternalLinks => "external_links",
+            BuiltinPass::Obfuscate => "obfuscate_links",
+            BuiltinPass::Anchors => "anchors",
+            BuiltinPass::Toc => "toc",
+            BuiltinPass::Videos => "videos",
+            BuiltinPass::Images => "images",
+            BuiltinPass::Glossary => "glossary",
+            BuiltinPass::Citations => "citations",
+            BuiltinPass::Typography => "typography",
+            BuiltinPass::Footnotes => "footnotes",
+            BuiltinPass::Code => "code",
+        }
+    }
+}
+
+/// A pass over the markdown events of a page, for programs that use EBG as a
+/// library
+pub trait MarkdownPass: Send + Sync {
+    /// The name used to disable the pass and to insert other passes after it
+    fn name(&self) -> &str;
+
+    fn run<'a>(&self, events: Vec<Event<'a>>, page: &PageSource) -> Vec<Event<'a>>;
+}
+
+pub enum Pass {
+    Builtin(BuiltinPass),
+    Custom(Box<dyn MarkdownPass>),
+}
+
+impl Pass {
+    pub fn name(&self) -> &str {
+        match self {
+            Pass::Builtin(pass) => pass.name(),
+            Pass::Custom(pass) => pass.name(),
+        }
+    }
+}
+
+/// The passes that every page's markdown goes through, in order
+pub struct MarkdownPipeline {
+    passes: Vec<Pass>,
+}
+
+impl Default for MarkdownPipeline {
+    fn default() -> Self {
+        Self {
+            passes: BuiltinPass::ALL.into_iter().map(Pass::Builtin).collect(),
+        }
+    }
+}
+
+impl MarkdownPipeline {
+    pub fn passes(&self) -> impl Iterator<Item = &Pass> {
+        self.passes.iter()
+    }
+
+    /// Adds a pass to the end of the pipeline, after code is highlighted.
+    pub fn push(&mut self, pass: impl MarkdownPass + 'static) {
+        self.passes.push(Pass::Custom(Box::new(pass)));
+    }
+
+    /// Adds a pass to run right after the pass named `after`.
+    ///
+    /// Returns `false`, leaving the pipeline unchanged, if there is no pass
+    /// with that name.
+    pub fn insert_after(&mut self, after: &str, pass: impl MarkdownPass + 'static) -> bool {
+        let Some(i) = self.passes.iter().position(|pass| pass.name() == after) else {
+            return false;
+        };
+        self.passes.insert(i + 1, Pass::Custom(Box::new(pass)));
+        true
+    }
+
+    /// Removes every pass named `name`.
+    pub fn remove(&mut self, name: &str) {
+        self.passes.retain(|pass| pass.name() != name);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use pulldown_cmark::{Event, Options, Tag};
+
+    use crate::{
+        index::{PageSource, SiteIndex, SourceFormat},
+        renderer::RenderError,
+    };
+
+    use super::{MarkdownConfig, MarkdownPass};
+
+    /// Counts the links on each page
+    struct CountLinks;
+
+    impl MarkdownPass for CountLinks {
+        fn name(&self) -> &str {
+            "count-links"
+        }
+
+        fn run<'a>(&self, mut events: Vec<Event<'a>>, _page: &PageSource) -> Vec<Event<'a>> {
+            let links = events
+                .iter()
+                .filter(|event| matches!(event, Event::Start(Tag::Link { .. })))
+                .count();
+            events.push(Event::Html(format!("<p>{links} links</p>").into()));
+            events
+        }
+    }
+
+    #[test]
+    fn disable_and_add_passes() -> miette::Result<()> {
+        let mut site = SiteIndex::default();
+        site.add_page(PageSource::from_string(
+            "index.md",
+            SourceFormat::Markdown,
+            "---\nlayout: page\ndisable_passes: anchors\n---\n## Welcome\n\n[Home](/)\n",
+        ));
+        site.add_page(PageSource::from_string(
+            "about.md",
+            SourceFormat::Markdown,
+            "---\nlayout: page\n---\n## About\n",
+        ));
+        assert!(site
+            .markdown_pipeline_mut()
+            .insert_after("typography", CountLinks));
+        assert!(!site
+            .markdown_pipeline_mut()
+            .insert_after("wayback", CountLinks));
+        let site = site.render()?;
+
+        let page = |name: &str| {
+            site.all_pages()
+                .find(|page| page.source().source_path().ends_with(name))
+                .unwrap()
+                .rendered_contents()
+                .to_string()
+        };
+        assert_eq!(
+            page("index.md"),
+            "<h2>Welcome</h2>\n<p><a href=\"/\">Home</a></p>\n<p>1 links</p>\n<hr />\n"
+        );
+        assert!(page("about.md").contains("<h2 id=\"about\">"));
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_pass_is_an_error() {
+        let mut site = SiteIndex::default();
+        site.add_page(PageSource::from_string(
+            "index.md",
+            SourceFormat::Markdown,
+            "---\nlayout: page\ndisable_passes: anchor\n---\n## Welcome\n",
+        ));
+        assert!(site.render().is_err());
+    }
+
+    #[test]
+    fn unknown_site_pass_is_reported_once() {
+        let mut site = SiteIndex::default();
+        site.config_mut().markdown.disable = vec!["anchor".into()];
+        for name in ["a.md", "b.md"] {
+            site.add_page(PageSource::from_string(
+                name,
+                SourceFormat::Markdown,
+                "---\nlayout: page\n---\n## Welcome\n",
+            ));
+        }
+        let Err(RenderError::PageRenderingErrors(errors)) = site.render() else {
+            panic!("expected the unknown pass to be an error");
+        };
+        assert_eq!(errors.iter().count(), 1);
+    }
+
+    #[test]
+    fn collection_profiles() {
+        let config: MarkdownConfig = toml::from_str(
+            r#"
+            disable = ["typography"]
+
+            [collections.papers]
+            extensions = ["footnotes", "math"]
+
+            [collections."papers/drafts"]
+            disable = []
+            "#,
+        )
+        .unwrap();
+
+        let post = Path::new("_posts/2024-01-02-hello.md");
+        assert_eq!(
+            config.options(post),
+            Options::ENABLE_FOOTNOTES
+                | Options::ENABLE_STRIKETHROUGH
+                | Options::ENABLE_TABLES
+                | Options::ENABLE_HEADING_ATTRIBUTES
+        );
+        assert_eq!(config.disabled(post), ["typography"]);
+
+        let paper = Path::new("papers/gc.md");
+        assert_eq!(
+            config.options(paper),
+            Options::ENABLE_FOOTNOTES | Options::ENABLE_MATH
+        );
+        assert_eq!(config.disabled(paper), ["typography"]);
+
+        let draft = Path::new("papers/drafts/new.md");
+        assert!(config.disabled(draft).is_empty());
+        // Profiles fall back to the site-wide settings, not to the collections
+        // they are nested in
+        assert!(!config.options(draft).contains(Options::ENABLE_MATH));
+    }
+}
@@ -0,0 +1,496 @@
+//! Checking the raw HTML written in Markdown
+//!
+//! Markdown allows any HTML, which is convenient for an author's own posts but
+//! risky for guest posts or posts imported from elsewhere. The policy in the
+//! `[markdown]` section of `Site.toml` decides what happens to tags that aren't
+//! in the allowed list.
+
+use std::ops::Range;
+
+use miette::{Diagnostic, NamedSource, SourceSpan};
+use pulldown_cmark::{CowStr, Event};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::index::PageSource;
+
+/// What to do with raw HTML tags that aren't allowed
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RawHtmlPolicy {
+    /// Keep every tag
+    #[default]
+    Allow,
+    /// Escape disallowed tags so they are shown as text
+    Sanitize,
+    /// Keep disallowed tags, but warn about them
+    Warn,
+    /// Fail the build
+    Deny,
+}
+
+/// Tags that are allowed unless `Site.toml` lists others
+pub(super) fn default_allowed_html() -> Vec<String> {
+    [
+        "a",
+        "abbr",
+        "b",
+        "blockquote",
+        "br",
+        "cite",
+        "code",
+        "dd",
+        "del",
+        "details",
+        "dfn",
+        "div",
+        "dl",
+        "dt",
+        "em",
+        "figcaption",
+        "figure",
+        "h1",
+        "h2",
+        "h3",
+        "h4",
+        "h5",
+        "h6",
+        "hr",
+        "i",
+        "img",
+        "ins",
+        "kbd",
+        "li",
+        "mark",
+        "ol",
+        "p",
+        "picture",
+        "pre",
+        "q",
+        "s",
+        "samp",
+        "small",
+        "source",
+        "span",
+        "strong",
+        "sub",
+        "summary",
+        "sup",
+        "table",
+        "tbody",
+        "td",
+        "tfoot",
+        "th",
+        "thead",
+        "time",
+        "tr",
+        "u",
+        "ul",
+        "var",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect()
+}
+
+#[derive(Diagnostic, Error, Debug)]
+pub enum RawHtmlError {
+    #[error("`{page}` uses `<{tag}>`, which is not in the allowed HTML")]
+    #[diagnostic(
+        severity(warning),
+        help("add it to `allowed_html` in the `[markdown]` section of Site.toml if it is safe")
+    )]
+    Disallowed {
+        tag: String,
+        page: String,
+        #[source_code]
+        source_code: NamedSource<String>,
+        #[label("this tag")]
+        span: Option<SourceSpan>,
+    },
+    #[error("`{page}` uses `<{tag}>`, which is not in the allowed HTML")]
+    #[diagnostic(
+        severity(error),
+        help("remove the tag, or add it to `allowed_html` in the `[markdown]` section of Site.toml if it is safe")
+    )]
+    Denied {
+        tag: String,
+        page: String,
+        #[source_code]
+        source_code: NamedSource<String>,
+        #[label("this tag")]
+        span: Option<SourceSpan>,
+    },
+}
+
+/// A tag in a chunk of HTML, such as `<script src="x.js">` or `</script>`
+struct HtmlTag {
+    range: Range<usize>,
+    name: String,
+    closing: bool,
+}
+
+fn find_tags(html: &str) -> Vec<HtmlTag> {
+    let mut tags = vec![];
+    let mut rest = 0;
+    while let Some(start) = html[rest..].find('<').map(|i| i + rest) {
+        let after = &html[start + 1..];
+        // Browsers don't read tags in comments, so neither do we
+        if after.starts_with("!--") {
+            rest = html[start + 4..]
+                .find("-->")
+                .map_or(html.len(), |i| start + 4 + i + 3);
+            continue;
+        }
+        let closing = after.starts_with('/');
+        let name: String = after[closing as usize..]
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '-')
+            .collect();
+        if !name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            rest = start + 1;
+            continue;
+        }
+        let end = tag_end(&html[start..]).map_or(html.len(), |i| start + i);
+        tags.push(HtmlTag {
+            range: start..end,
+            name: name.to_ascii_lowercase(),
+            closing,
+        });
+        rest = end;
+    }
+    tags
+}
+
+/// Returns the length of the tag that `html` starts with, which ends at the
+/// first `>` outside of a quoted attribute value.
+fn tag_end(html: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in html.char_indices() {
+        match (quote, c) {
+            (None, '>') => return Some(i + 1),
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Applies `policy` to the raw HTML on `page`, calling `report` for each
+/// disallowed opening tag when the policy is to warn or deny.
+pub fn check_raw_html<'a>(
+    events: impl Iterator<Item = Event<'a>>,
+    policy: RawHtmlPolicy,
+    allowed: &[String],
+    page: &PageSource,
+    mut report: impl FnMut(RawHtmlError),
+) -> Vec<Event<'a>> {
+    if policy == RawHtmlPolicy::Allow {
+        return events.collect();
+    }
+    let is_allowed = |name: &str| allowed.iter().any(|tag| tag.eq_ignore_ascii_case(name));
+    // Where to start looking for the next tag in the page's source, so that
+    // repeated tags point at the right place
+    let mut cursor = 0;
+    events
+        .map(|event| {
+            let (html, inline) = match event {
+                Event::Html(html) => (html, false),
+                Event::InlineHtml(html) => (html, true),
+                event => return event,
+            };
+            let mut output = String::with_capacity(html.len());
+            let mut copied = 0;
+            for tag in find_tags(&html) {
+                let text = &html[tag.range.clone()];
+                if is_allowed(&tag.name) {
+                    if policy == RawHtmlPolicy::Sanitize {
+                        if let Some(safe) = safe_attributes(text, &tag) {
+                            output.push_str(&html[copied..tag.range.start]);
+                            output.push_str(&safe);
+                            copied = tag.range.end;
+                        }
+                    }
+                    continue;
+                }
+                match policy {
+                    RawHtmlPolicy::Sanitize => {
+                        output.push_str(&html[copied..tag.range.start]);
+                        output.push_str(&escape(text));
+                        copied = tag.range.end;
+                    }
+                    RawHtmlPolicy::Warn | RawHtmlPolicy::Deny if !tag.closing => {
+                        let span = page.contents()[cursor..].find(text).map(|i| {
+                            cursor += i + text.len();
+                            cursor - text.len()..cursor
+                        });
+                        report(disallowed(policy, tag.name, page, span));
+                    }
+                    _ => {}
+                }
+            }
+            if copied == 0 {
+                return if inline {
+                    Event::InlineHtml(html)
+                } else {
+                    Event::Html(html)
+                };
+            }
+            output.push_str(&html[copied..]);
+            let html = CowStr::from(output);
+            if inline {
+                Event::InlineHtml(html)
+            } else {
+                Event::Html(html)
+            }
+        })
+        .collect()
+}
+
+/// Returns `text`, an allowed opening tag, without any attributes that could
+/// run scripts, or `None` if it has none to remove.
+///
+/// Even allowed tags can run scripts through event handlers like `onerror`,
+/// styles, `srcdoc`, and `javascript:` URLs.
+fn safe_attributes(text: &str, tag: &HtmlTag) -> Option<String> {
+    if tag.closing {
+        return None;
+    }
+    let attributes = &text[1 + tag.name.len()..];
+    let body = attributes.trim_end_matches('>');
+    let end = &attributes[body.len()..];
+    let (body, self_closing) = match body.trim_end().strip_suffix('/') {
+        Some(body) => (body, true),
+        None => (body, false),
+    };
+    let attributes = parse_attributes(body);
+    if attributes
+        .iter()
+        .all(|(_, name, value)| is_safe(name, value))
+    {
+        return None;
+    }
+    let mut safe = text[..1 + tag.name.len()].to_string();
+    for (raw, ..) in attributes
+        .iter()
+        .filter(|(_, name, value)| is_safe(name, value))
+    {
+        safe.push(' ');
+        safe.push_str(raw);
+    }
+    if self_closing {
+        safe.push_str(" /");
+    }
+    safe.push_str(end);
+    Some(safe)
+}
+
+/// Splits the attributes of a tag into their source text, lowercase name,
+/// and unquoted value.
+fn parse_attributes(mut body: &str) -> Vec<(&str, String, &str)> {
+    let mut attributes = vec![];
+    loop {
+        body = body.trim_start_matches(|c: char| c.is_ascii_whitespace() || c == '/');
+        if body.is_empty() {
+            return attributes;
+        }
+        let name_len = body
+            .find(|c: char| c.is_ascii_whitespace() || c == '=' || c == '/')
+            .unwrap_or(body.len())
+            .max(1);
+        let name = body[..name_len].to_ascii_lowercase();
+        let after_name = body[name_len..].trim_start();
+        let Some(after_equals) = after_name.strip_prefix('=') else {
+            attributes.push((&body[..name_len], name, ""));
+            body = &body[name_len..];
+            continue;
+        };
+        let quoted = after_equals.trim_start();
+        let value_start = body.len() - quoted.len();
+        let (value, len) = match quoted.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let inner = &quoted[1..];
+                match inner.find(quote) {
+                    Some(close) => (&inner[..close], close + 2),
+                    None => (inner, quoted.len()),
+                }
+            }
+            _ => {
+                let len = quoted
+                    .find(|c: char| c.is_ascii_whitespace())
+                    .unwrap_or(quoted.len());
+                (&quoted[..len], len)
+            }
+        };
+        let end = value_start + len;
+        attributes.push((&body[..end], name, value));
+        body = &body[end..];
+    }
+}
+
+/// Attributes whose values are URLs
+const URL_ATTRIBUTES: &[&str] = &[
+    "action",
+    "background",
+    "cite",
+    "formaction",
+    "href",
+    "poster",
+    "src",
+    "srcset",
+    "xlink:href",
+];
+
+fn is_safe(name: &str, value: &str) -> bool {
+    if name.starts_with("on") || name == "style" || name == "srcdoc" {
+        return false;
+    }
+    if !URL_ATTRIBUTES.contains(&name) {
+        return true;
+    }
+    if name == "srcset" {
+        value.split(',').all(is_safe_url)
+    } else {
+        is_safe_url(value)
+    }
+}
+
+/// Checks that `url` is relative or uses a scheme that can't run scripts.
+pub(crate) fn is_safe_url(url: &str) -> bool {
+    // Browsers ignore whitespace and control characters in schemes, so
+    // `java\tscript:` still runs
+    let url: String = url
+        .trim()
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace() && !c.is_ascii_control())
+        .collect();
+    let prefix = url.split(['/', '?', '#']).next().unwrap_or_default();
+    // An entity could hide the `:` of a scheme
+    if prefix.contains('&') {
+        return false;
+    }
+    match prefix.split_once(':') {
+        Some((scheme, _)) => ["http", "https", "mailto", "tel"]
+            .iter()
+            .any(|safe| scheme.eq_ignore_ascii_case(safe)),
+        None => true,
+    }
+}
+
+fn disallowed(
+    policy: RawHtmlPolicy,
+    tag: String,
+    page: &PageSource,
+    span: Option<Range<usize>>,
+) -> RawHtmlError {
+    let page_name = page.source_path().display().to_string();
+    let source_code = NamedSource::new(page_name.clone(), page.contents().to_string());
+    if policy == RawHtmlPolicy::Deny {
+        RawHtmlError::Denied {
+            tag,
+            page: page_name,
+            source_code,
+            span: span.map(Into::into),
+        }
+    } else {
+        RawHtmlError::Disallowed {
+            tag,
+            page: page_name,
+            source_code,
+            span: span.map(Into::into),
+        }
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod test {
+    use pulldown_cmark::{html, Parser};
+
+    use crate::index::{PageSource, SourceFormat};
+
+    use super::{check_raw_html, default_allowed_html, RawHtmlError, RawHtmlPolicy};
+
+    fn render(markdown: &str, policy: RawHtmlPolicy) -> (String, Vec<RawHtmlError>) {
+        let page = PageSource::from_string("guest.md", SourceFormat::Markdown, markdown);
+        let mut errors = vec![];
+        let events = check_raw_html(
+            Parser::new(page.mainmatter()),
+            policy,
+            &default_allowed_html(),
+            &page,
+            |error| errors.push(error),
+        );
+        let mut out = String::new();
+        html::push_html(&mut out, events.into_iter());
+        (out, errors)
+    }
+
+    const GUEST: &str = "Hi <kbd>x</kbd> <blink>y</blink>\n\n<script>\nalert(1)\n</script>\n";
+
+    #[test]
+    fn sanitize() {
+        let (html, errors) = render(GUEST, RawHtmlPolicy::Sanitize);
+        assert_eq!(
+            html,
+            "<p>Hi <kbd>x</kbd> &lt;blink&gt;y&lt;/blink&gt;</p>\n&lt;script&gt;\nalert(1)\n&lt;/script&gt;\n"
+        );
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn sanitize_attributes() {
+        let (html, _) = render(
+            "<img src=x onerror=alert(1)> <a href=\"javascript:alert(1)\" title='hi'>x</a>\n\
+             <a href=\" Java\tScript:alert(1)\">y</a> <a href=\"&#106;avascript:alert(1)\">z</a>\n\
+             <img src=\"data:text/html,<script>\" style=\"x\" /> <a href=\"/about/\" class=\"on\">ok</a>\n\
+             <a href=\"https://example.com/?a=1&b=2\">ok</a> <div ONCLICK=\"x()\" id=d>ok</div>",
+            RawHtmlPolicy::Sanitize,
+        );
+        assert_eq!(
+            html,
+            "<p><img src=x> <a title='hi'>x</a>\n\
+             <a>y</a> <a>z</a>\n\
+             <img /> <a href=\"/about/\" class=\"on\">ok</a>\n\
+             <a href=\"https://example.com/?a=1&b=2\">ok</a> <div id=d>ok</div></p>\n"
+        );
+    }
+
+    #[test]
+    fn comments() {
+        let (html, _) = render(
+            "<!-- <script>alert(1)</script> -->\n\n<blink>x</blink>",
+            RawHtmlPolicy::Sanitize,
+        );
+        assert_eq!(
+            html,
+            "<!-- <script>alert(1)</script> -->\n<p>&lt;blink&gt;x&lt;/blink&gt;</p>\n"
+        );
+    }
+
+    #[test]
+    fn warn_and_deny() {
+        let (html, errors) = render(GUEST, RawHtmlPolicy::Warn);
+        assert!(html.contains("<script>"));
+        let spans: Vec<_> = errors
+            .iter()
+            .map(|error| match error {
+                RawHtmlError::Disallowed { span, .. } => {
+                    let span = span.unwrap();
+                    &GUEST[span.offset()..span.offset() + span.len()]
+                }
+                RawHtmlError::Denied { .. } => panic!("warnings should not deny"),
+            })
+            .collect();
+        assert_eq!(spans, ["<blink>", "<script>"]);
+
+        let (_, errors) = render(GUEST, RawHtmlPolicy::Deny);
+        assert!(matches!(errors[0], RawHtmlError::Denied { .. }));
+    }
+}
@@ -0,0 +1,431 @@
+//! Shortcodes, which insert the output of a small template into markdown
+//!
+//! `{{< youtube dQw4w9WgXcQ >}}` renders the `youtube.html` shortcode template
+//! with `args` set to `["dQw4w9WgXcQ"]`. Named arguments such as
+//! `{{< figure src="a.png" caption="A cat" >}}` become variables of their own,
+//! and a shortcode with a closing tag, like `{{< note >}}Hi{{< /note >}}`, gets
+//! the text in between as `inner`. Templates also see the `page` and `site`.
+//!
+//! Templates are `.html` files in the theme's `shortcodes/` directory or the
+//! site's `_shortcodes/` directory, with the site's taking precedence. They
+//! are separate from the theme's page templates, so they can't use its macros.
+//!
+//! `{{</* youtube id */>}}` is left as the literal text `{{< youtube id >}}`,
+//! for writing about shortcodes, and so are shortcodes in code.
+
+use std::{
+    borrow::Cow,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use miette::Diagnostic;
+use serde_json::{json, Map, Value};
+use tera::Tera;
+use thiserror::Error;
+
+use super::directives::{directives, literal, Directive};
+use crate::{
+    index::{PageMetadata, PageSource, SiteMetadata},
+    renderer::RenderContext,
+};
+
+#[derive(Diagnostic, Debug, Error)]
+pub enum ShortcodeError {
+    #[error("reading shortcodes from `{}`", .0.display())]
+    Read(PathBuf, #[source] std::io::Error),
+    #[error("loading shortcode `{}`", .0.display())]
+    Parse(PathBuf, #[source] tera::Error),
+    #[error("`{}` uses the shortcode `{name}`, which does not exist", .page.display())]
+    #[diagnostic(
+        severity(warning),
+        help("add `{name}.html` to `_shortcodes/` or the theme's `shortcodes/` directory")
+    )]
+    Unknown { page: PathBuf, name: String },
+    #[error("rendering the shortcode `{name}` in `{}`", .page.display())]
+    Render {
+        page: PathBuf,
+        name: String,
+        #[source]
+        source: tera::Error,
+    },
+}
+
+/// The shortcode templates a site can use
+#[derive(Default)]
+pub struct Shortcodes {
+    templates: Tera,
+}
+
+impl Shortcodes {
+    /// Loads the templates in each of `dirs`, which don't have to exist.
+    /// Templates in later directories replace those with the same name in
+    /// earlier ones.
+    pub fn load(dirs: &[PathBuf]) -> Result<Self, ShortcodeError> {
+        let mut templates = Tera::default();
+        // Like the theme, shortcodes are trusted to produce HTML
+        templates.autoescape_on(vec![]);
+        for dir in dirs.iter().filter(|dir| dir.is_dir()) {
+            let mut paths = std::fs::read_dir(dir)
+                .and_then(|entries| {
+                    entries
+                        .map(|entry| entry.map(|entry| entry.path()))
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .map_err(|e| ShortcodeError::Read(dir.clone(), e))?;
+            paths.sort();
+            for path in paths {
+                if path.extension().is_none_or(|ext| ext != "html") {
+                    continue;
+                }
+                let Some(name) = path.file_stem().and_then(|name| name.to_str()) else {
+                    continue;
+                };
+                let contents = std::fs::read_to_string(&path)
+                    .map_err(|e| ShortcodeError::Read(path.clone(), e))?;
+                templates
+                    .add_raw_template(name, &contents)
+                    .map_err(|e| ShortcodeError::Parse(path.clone(), e))?;
+            }
+        }
+        Ok(Self { templates })
+    }
+
+    /// Renders the shortcode `name` with `context`, or returns `None` if
+    /// there is no such shortcode.
+    fn render(&self, name: &str, context: &Value) -> Option<Result<String, tera::Error>> {
+        self.templates
+            .get_template_names()
+            .any(|template| template == name)
+            .then(|| {
+                self.templates
+                    .render(name, &tera::Context::from_serialize(context)?)
+            })
+    }
+}
+
+/// Replaces the shortcodes in `contents` with their output.
+///
+/// Problems are reported through the render context, and the shortcode is
+/// left in place.
+pub fn expand_shortcodes<'a>(
+    contents: Cow<'a, str>,
+    page: &PageSource,
+    rcx: &RenderContext<'_>,
+) -> Cow<'a, str> {
+    let shortcodes = rcx.site.shortcodes();
+    if !contents.contains("{{<") {
+        return contents;
+    }
+    let base = json!({
+        "page": page_value(page),
+        "site": site_value(rcx.site),
+    });
+    let report = |e| rcx.dcx.lock().unwrap().record(e);
+    Cow::Owned(expand(&contents, &mut |call: Call<'_>| {
+        let Some(result) = shortcodes.render(call.name, &call.context(&base)) else {
+            report(ShortcodeError::Unknown {
+                page: page.source_path().to_path_buf(),
+                name: call.name.to_string(),
+            });
+            return None;
+        };
+        result
+            .map_err(|source| {
+                report(ShortcodeError::Render {
+                    page: page.source_path().to_path_buf(),
+                    name: call.name.to_string(),
+                    source,
+                })
+            })
+            .ok()
+    }))
+}
+
+fn page_value(page: &PageSource) -> Value {
+    json!({
+        "title": page.title(),
+        "url": page.url(),
+        "date": page.publish_date(),
+        "source_path": page.source_path(),
+        "tags": page.tags(),
+        "categories": page.categories(),
+    })
+}
+
+fn site_value(site: &dyn SiteMetadata) -> Value {
+    json!({
+        "title": site.title(),
+        "url": site.base_url(),
+        "author": site.author(),
+        "subtitle": site.subtitle(),
+        "vars": site.config().vars,
+    })
+}
+
+/// One use of a shortcode
+struct Call<'a> {
+    name: &'a str,
+    args: Vec<String>,
+    named: Vec<(&'a str, String)>,
+    /// The text between the opening and closing tags, with its own shortcodes
+    /// already expanded
+    inner: Option<String>,
+}
+
+impl Call<'_> {
+    fn context(&self, base: &Value) -> Value {
+        let mut context: Map<String, Value> = self
+            .named
+            .iter()
+            .map(|(name, value)| (name.to_string(), json!(value)))
+            .collect();
+        if let Value::Object(base) = base {
+            context.extend(base.clone());
+        }
+        context.insert("args".into(), json!(self.args));
+        context.insert("inner".into(), json!(self.inner));
+        Value::Object(context)
+    }
+}
+
+/// Expands every shortcode in `text`, using `render` to get each one's output.
+/// Shortcodes that `render` returns `None` for are left as they are.
+fn expand(text: &str, render: &mut impl FnMut(Call<'_>) -> Option<String>) -> String {
+    let tags: Vec<_> = directives(text).into_iter().filter_map(find_tag).collect();
+    let mut output = String::with_capacity(text.len());
+    let mut copied = 0;
+    for (i, tag) in tags.iter().enumerate() {
+        // Already expanded as part of the text between an opening and
+        // closing tag
+        if tag.range.start < copied {
+            continue;
+        }
+        output.push_str(&text[copied..tag.range.start]);
+        let mut end = tag.range.end;
+        match tag.kind {
+            TagKind::Escaped => output.push_str(&literal(&format!("{{{{<{}>}}}}", tag.inner))),
+            // A closing tag without an opening one
+            TagKind::Closing => output.push_str(&literal(&text[tag.range.clone()])),
+            TagKind::Opening => match parse_call(tag.inner) {
+                Some(mut call) => {
+                    call.inner = tags[i + 1..]
+                        .iter()
+                        .find(|close| close.closes(call.name))
+                        .map(|close| {
+                            let inner = &text[end..close.range.start];
+                            end = close.range.end;
+                            expand(inner, render)
+                        });
+                    match render(call) {
+                        Some(rendered) => output.push_str(&rendered),
+                        None => output.push_str(&literal(&text[tag.range.start..end])),
+                    }
+                }
+                None => output.push_str(&literal(&text[tag.range.clone()])),
+            },
+        }
+        copied = end;
+    }
+    output.push_str(&text[copied..]);
+    output
+}
+
+enum TagKind {
+    Opening,
+    Closing,
+    /// `{{</* ... */>}}`
+    Escaped,
+}
+
+struct Tag<'a> {
+    range: Range<usize>,
+    /// The text between `{{<` and `>}}`, or between the comment markers for an
+    /// escaped tag
+    inner: &'a str,
+    kind: TagKind,
+}
+
+impl Tag<'_> {
+    /// Whether this is the tag that closes the shortcode `name`
+    fn closes(&self, name: &str) -> bool {
+        matches!(self.kind, TagKind::Closing)
+            && self.inner.trim().strip_prefix('/').map(str::trim) == Some(name)
+    }
+}
+
+/// Returns the shortcode tag written as `directive`, if it is one.
+fn find_tag(directive: Directive<'_>) -> Option<Tag<'_>> {
+    let inner = directive.inner.strip_prefix('<')?.strip_suffix('>')?;
+    if let Some(escaped) = inner
+        .strip_prefix("/*")
+        .and_then(|inner| inner.strip_suffix("*/"))
+    {
+        return Some(Tag {
+            range: directive.range,
+            inner: escaped,
+            kind: TagKind::Escaped,
+        });
+    }
+    Some(Tag {
+        range: directive.range,
+        inner,
+        kind: if inner.trim_start().starts_with('/') {
+            TagKind::Closing
+        } else {
+            TagKind::Opening
+        },
+    })
+}
+
+/// Parses the inside of a shortcode's opening tag into its name and
+/// arguments.
+///
+/// Arguments are separated by whitespace, and can be quoted to include spaces.
+fn parse_call(inner: &str) -> Option<Call<'_>> {
+    let inner = inner.trim();
+    let name_len = inner
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+        .unwrap_or(inner.len());
+    if name_len == 0 {
+        return None;
+    }
+    let (name, mut rest) = inner.split_at(name_len);
+    let mut args = vec![];
+    let mut named = vec![];
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            break;
+        }
+        let key = rest.find('=').map(|i| &rest[..i]).filter(|key| {
+            !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        });
+        if let Some(key) = key {
+            rest = &rest[key.len() + 1..];
+        }
+        let value;
+        (value, rest) = parse_value(rest)?;
+        match key {
+            Some(key) => named.push((key, value)),
+            None => args.push(value),
+        }
+    }
+    Some(Call {
+        name,
+        args,
+        named,
+        inner: None,
+    })
+}
+
+/// Parses one argument from the start of `text`, returning it and the text
+/// after it.
+fn parse_value(text: &str) -> Option<(String, &str)> {
+    match text.strip_prefix('"') {
+        Some(quoted) => {
+            let mut value = String::new();
+            let mut chars = quoted.char_indices();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '"' => return Some((value, &quoted[i + 1..])),
+                    '\\' => value.push(chars.next()?.1),
+                    c => value.push(c),
+                }
+            }
+            // No closing quote
+            None
+        }
+        None => {
+            let end = text.find(char::is_whitespace).unwrap_or(text.len());
+            Some((text[..end].to_string(), &text[end..]))
+        }
+    }
+}
+
+/// The directories shortcodes are loaded from, in order of precedence from
+/// lowest to highest
+pub(crate) fn shortcode_dirs(root_dir: &Path, theme_dir: &Path) -> Vec<PathBuf> {
+    vec![theme_dir.join("shortcodes"), root_dir.join("_shortcodes")]
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{expand, parse_call, Shortcodes};
+
+    #[test]
+    fn parse_arguments() {
+        let call = parse_call(r#" figure a.png "two words" caption="A \"cat\"" w=3 "#).unwrap();
+        assert_eq!(call.name, "figure");
+        assert_eq!(call.args, ["a.png", "two words"]);
+        assert_eq!(
+            call.named,
+            [
+                ("caption", r#"A "cat""#.to_string()),
+                ("w", "3".to_string())
+            ]
+        );
+        assert!(parse_call(r#"x "unterminated"#).is_none());
+        assert!(parse_call("").is_none());
+    }
+
+    #[test]
+    fn expand_shortcodes() {
+        let text = "A {{< youtube abc >}} B {{< note kind=tip >}}x {{< youtube d >}}{{< /note >}} \
+                    C {{</* youtube e */>}} D {{< missing >}} {{< /stray >}}";
+        let output = expand(text, &mut |call| match call.name {
+            "youtube" => Some(format!("[video {}]", call.args[0])),
+            "note" => Some(format!(
+                "[{} {}]",
+                call.named[0].1,
+                call.inner.unwrap_or_default()
+            )),
+            _ => None,
+        });
+        assert_eq!(
+            output,
+            "A [video abc] B [tip x [video d]] C \u{E000}{< youtube e >}} \
+             D \u{E000}{< missing >}} \u{E000}{< /stray >}}"
+        );
+    }
+
+    #[test]
+    fn skip_code() {
+        let text = "`{{< youtube a >}}`\n\n```\n{{< youtube b >}}\n```\n\n{{< youtube c >}}";
+        let output = expand(text, &mut |call| Some(format!("[video {}]", call.args[0])));
+        assert_eq!(
+            output,
+            "`{{< youtube a >}}`\n\n```\n{{< youtube b >}}\n```\n\n[video c]"
+        );
+    }
+
+    #[test]
+    fn templates() {
+        let dir = tempfile::tempdir().unwrap();
+        let theme = dir.path().join("theme/shortcodes");
+        let site = dir.path().join("_shortcodes");
+        std::fs::create_dir_all(&theme).unwrap();
+        std::fs::create_dir_all(&site).unwrap();
+        std::fs::write(theme.join("youtube.html"), "theme {{ args.0 }}").unwrap();
+        std::fs::write(theme.join("note.html"), "<aside>{{ inner }}</aside>").unwrap();
+        std::fs::write(
+            site.join("youtube.html"),
+            "site {{ args.0 }} on {{ page.title }}",
+        )
+        .unwrap();
+
+        let shortcodes = Shortcodes::load(&[theme, site]).unwrap();
+        let context = json!({ "args": ["abc"], "inner": "<b>hi</b>", "page": { "title": "Hi" } });
+        assert_eq!(
+            shortcodes.render("youtube", &context).unwrap().unwrap(),
+            "site abc on Hi"
+        );
+        assert_eq!(
+            shortcodes.render("note", &context).unwrap().unwrap(),
+            "<aside><b>hi</b></aside>"
+        );
+        assert!(shortcodes.render("missing", &context).is_none());
+    }
+}
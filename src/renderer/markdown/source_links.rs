@@ -1,14 +1,14 @@
-use std::fmt::Formatter;
+use std::{fmt::Formatter, ops::Range, path::PathBuf};
 
 use email_address_parser::EmailAddress;
-use miette::{diagnostic, Diagnostic};
-use pulldown_cmark::{CowStr, Event, Tag};
+use miette::{diagnostic, Diagnostic, NamedSource};
+use pulldown_cmark::{CowStr, Event, Parser, Tag};
 use thiserror::Error;
 use tracing::debug;
 use url::Url;
 
 use crate::{
-    index::{PageMetadata, PageSource, SiteMetadata},
+    index::{normalize_path, PageMetadata, PageSource, SiteIndex, SiteMetadata, SourceFormat},
     renderer::RenderContext,
 };
 
@@ -32,36 +32,24 @@ pub fn adjust_relative_links<'a>(
     let map_url = |url: &CowStr<'_>| {
         let url = LinkDest::parse(url).ok()?;
         let anchor = url.fragment();
-        if url.is_possible_source_link() {
-            debug!("found possible source link to {url}");
-            let path = if url.is_relative() {
-                let parent = page.source_path().parent()?;
-                debug!("searching relative to `{}`", parent.display());
-                parent.join(url.path())
-            } else {
-                rcx.site.root_dir().join(url.path())
-            };
-            debug!("mapped path to {}", path.display());
-            let Some(page) = rcx.site.find_page_by_source_path(&path) else {
-                debug!("no page found for {}", path.display());
-                rcx.dcx.lock().unwrap().record(diagnostic!(
-                    severity = miette::Severity::Warning,
-                    help = "did you mean to link to an external page?",
-                    "Could not find target for apparent source link to `{url}`",
-                ));
-                return None;
-            };
-            let url = format!(
-                "/{}{}",
-                // rcx.site.base_url(),
-                page.url(),
-                anchor.map(|a| format!("#{}", a)).unwrap_or_default()
-            );
-            debug!("linking to {url}");
-            Some(url)
-        } else {
-            None
-        }
+        let path = source_link_target(&url, page)?;
+        let Some(page) = rcx.site.find_page_by_source_path(&path) else {
+            debug!("no page found for {}", path.display());
+            rcx.dcx.lock().unwrap().record(diagnostic!(
+                severity = miette::Severity::Warning,
+                help = "did you mean to link to an external page?",
+                "Could not find target for apparent source link to `{url}`",
+            ));
+            return None;
+        };
+        let url = format!(
+            "/{}{}",
+            // rcx.site.base_url(),
+            page.url(),
+            anchor.map(|a| format!("#{}", a)).unwrap_or_default()
+        );
+        debug!("linking to {url}");
+        Some(url)
     };
 
     markdown
@@ -88,8 +76,74 @@ pub fn adjust_relative_links<'a>(
         .collect()
 }
 
+/// Returns the source path, relative to the site root, that `url` points to if
+/// it looks like a link to a source file.
+fn source_link_target(url: &LinkDest, page: &PageSource) -> Option<PathBuf> {
+    if !url.is_possible_source_link() {
+        return None;
+    }
+    debug!("found possible source link to {url}");
+    let parent = page.source_path().parent()?;
+    debug!("searching relative to `{}`", parent.display());
+    let path = normalize_path(&parent.join(url.path()));
+    debug!("mapped path to {}", path.display());
+    Some(path)
+}
+
+/// A source link that doesn't lead to a page or file in the site
+#[derive(Diagnostic, Debug, Error)]
+#[error("`{link}` does not lead to a page or file in the site")]
+#[diagnostic(help("check the path relative to `{}`", page.display()))]
+pub struct BrokenLink {
+    pub page: PathBuf,
+    pub link: String,
+    #[source_code]
+    source_code: NamedSource<String>,
+    #[label("linked here")]
+    span: Range<usize>,
+}
+
+/// Finds the source links in every Markdown page of `site` that don't lead to
+/// another page or to a file.
+pub fn check_links(site: &SiteIndex) -> Vec<BrokenLink> {
+    let mut broken = vec![];
+    for page in site.all_pages() {
+        if page.source_format() != SourceFormat::Markdown {
+            continue;
+        }
+        let contents = page.contents();
+        // Offsets from the parser are within the mainmatter
+        let offset = contents.len() - page.mainmatter().len();
+        let options = site.config().markdown.options(page.source_path());
+        for (event, range) in Parser::new_ext(page.mainmatter(), options).into_offset_iter() {
+            let Event::Start(Tag::Link { dest_url, .. }) = event else {
+                continue;
+            };
+            let Ok(url) = LinkDest::parse(&dest_url);
+            let Some(path) = source_link_target(&url, page) else {
+                continue;
+            };
+            if site.find_page_by_source_path(&path).is_some()
+                || site.root_dir().join(&path).exists()
+            {
+                continue;
+            }
+            broken.push(BrokenLink {
+                page: page.source_path().to_path_buf(),
+                link: dest_url.to_string(),
+                source_code: NamedSource::new(
+                    page.source_path().display().to_string(),
+                    contents.to_string(),
+                ),
+                span: range.start + offset..range.end + offset,
+            });
+        }
+    }
+    broken
+}
+
 #[derive(Debug)]
-enum LinkDest {
+pub(crate) enum LinkDest {
     External(Url),
     Local(String),
     /// The link is an email address
@@ -97,7 +151,7 @@ enum LinkDest {
 }
 
 impl LinkDest {
-    fn parse(s: &str) -> Result<Self, LinkDestError> {
+    pub(crate) fn parse(s: &str) -> Result<Self, LinkDestError> {
         if let Ok(url) = Url::parse(s) {
             Ok(Self::External(url))
         } else if EmailAddress::parse(s, None).is_some() {
@@ -107,6 +161,14 @@ impl LinkDest {
         }
     }
 
+    /// Returns the URL if the link leads to another site.
+    pub(crate) fn external_url(&self) -> Option<&Url> {
+        match self {
+            Self::External(url) if matches!(url.scheme(), "http" | "https") => Some(url),
+            _ => None,
+        }
+    }
+
     fn is_local(&self) -> bool {
         match self {
             Self::External(_) | Self::Email(_) => false,
@@ -138,13 +200,9 @@ impl LinkDest {
             Self::External(url) => url.path(),
             Self::Local(s) => {
                 let path = s.split_once('#').map_or(s.as_str(), |(p, _)| p);
-                if path.starts_with("./") {
-                    &path[2..]
-                } else {
-                    path
-                }
+                path.strip_prefix("./").unwrap_or(path)
             }
-            Self::Email(source) => &source,
+            Self::Email(source) => source,
         }
     }
 
@@ -185,11 +243,13 @@ impl std::fmt::Display for LinkDest {
 }
 
 #[derive(Diagnostic, Debug, Error)]
-enum LinkDestError {}
+pub(crate) enum LinkDestError {}
 
 #[cfg(test)]
 mod test {
-    use super::LinkDest;
+    use crate::index::{PageSource, SiteIndex, SourceFormat};
+
+    use super::{check_links, LinkDest};
 
     #[test]
     fn external_link() -> miette::Result<()> {
@@ -279,4 +339,28 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn broken_links() {
+        let mut site = SiteIndex::default();
+        let about = "---\nlayout: page\n---\nSee [one](./_posts/2024-01-01-one.md),\n[two](./_posts/2024-01-02-two.md), and [the web](https://example.com).\n";
+        site.add_page(PageSource::from_string(
+            "about.md",
+            SourceFormat::Markdown,
+            about,
+        ));
+        site.add_page(PageSource::from_string(
+            "_posts/2024-01-01-one.md",
+            SourceFormat::Markdown,
+            "---\nlayout: post\n---\n",
+        ));
+
+        let broken = check_links(&site);
+        assert_eq!(broken.len(), 1);
+        assert_eq!(broken[0].link, "./_posts/2024-01-02-two.md");
+        assert_eq!(
+            &about[broken[0].span.clone()],
+            "[two](./_posts/2024-01-02-two.md)"
+        );
+    }
 }
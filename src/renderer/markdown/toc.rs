@@ -0,0 +1,163 @@
+//! Tables of contents built from a page's headings
+//!
+//! The headings are collected after anchors are added, so only headings with
+//! an id are included. Templates can use the table as `page.toc`, and a
+//! `<!-- TOC -->` comment in the Markdown is replaced with it as a nested list.
+
+use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+use serde::Serialize;
+
+/// A heading in a table of contents, along with the headings under it
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TocEntry {
+    /// The heading level, from 1 for `<h1>` to 6 for `<h6>`
+    pub level: u8,
+    pub title: String,
+    /// The id of the heading, for linking to it
+    pub id: String,
+    pub children: Vec<TocEntry>,
+}
+
+/// Collects the headings with ids into a tree.
+pub fn collect_toc(events: &[Event<'_>]) -> Vec<TocEntry> {
+    let mut headings = vec![];
+    let mut current: Option<TocEntry> = None;
+    for event in events {
+        match event {
+            Event::Start(Tag::Heading {
+                level,
+                id: Some(id),
+                ..
+            }) => {
+                current = Some(TocEntry {
+                    level: *level as u8,
+                    title: String::new(),
+                    id: id.to_string(),
+                    children: vec![],
+                });
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some(heading) = &mut current {
+                    heading.title += text;
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => headings.extend(current.take()),
+            _ => {}
+        }
+    }
+    nest(headings)
+}
+
+/// Puts each heading under the closest earlier heading with a lower level.
+fn nest(headings: Vec<TocEntry>) -> Vec<TocEntry> {
+    // The chain of open headings, each a child of the one before it
+    let mut stack: Vec<TocEntry> = vec![];
+    let mut roots = vec![];
+    for heading in headings {
+        while stack.last().is_some_and(|open| open.level >= heading.level) {
+            close(&mut stack, &mut roots);
+        }
+        stack.push(heading);
+    }
+    while !stack.is_empty() {
+        close(&mut stack, &mut roots);
+    }
+    roots
+}
+
+fn close(stack: &mut Vec<TocEntry>, roots: &mut Vec<TocEntry>) {
+    let heading = stack.pop().unwrap();
+    match stack.last_mut() {
+        Some(parent) => parent.children.push(heading),
+        None => roots.push(heading),
+    }
+}
+
+fn is_marker(html: &str) -> bool {
+    html.trim()
+        .strip_prefix("<!--")
+        .and_then(|rest| rest.strip_suffix("-->"))
+        .is_some_and(|comment| comment.trim().eq_ignore_ascii_case("toc"))
+}
+
+/// Replaces `<!-- TOC -->` comments with `toc` as a nested list.
+pub fn insert_toc<'a>(events: impl Iterator<Item = Event<'a>>, toc: &[TocEntry]) -> Vec<Event<'a>> {
+    events
+        .map(|event| match event {
+            Event::Html(html) | Event::InlineHtml(html) if is_marker(&html) => Event::Html(
+                CowStr::from(format!("<nav class=\"toc\">{}</nav>\n", render_list(toc))),
+            ),
+            event => event,
+        })
+        .collect()
+}
+
+fn render_list(entries: &[TocEntry]) -> String {
+    if entries.is_empty() {
+        return String::new();
+    }
+    let items: String = entries
+        .iter()
+        .map(|entry| {
+            format!(
+                "<li><a href=\"#{}\">{}</a>{}</li>",
+                escape(&entry.id),
+                escape(&entry.title),
+                render_list(&entry.children)
+            )
+        })
+        .collect();
+    format!("<ul>{items}</ul>")
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+}
+
+#[cfg(test)]
+mod test {
+    use pulldown_cmark::{html, Options, Parser};
+
+    use super::{collect_toc, insert_toc, TocEntry};
+
+    fn entry(level: u8, id: &str, children: Vec<TocEntry>) -> TocEntry {
+        TocEntry {
+            level,
+            title: id.to_uppercase(),
+            id: id.to_string(),
+            children,
+        }
+    }
+
+    #[test]
+    fn toc() {
+        let markdown = "<!-- TOC -->\n\n## A {#a}\n\n### B {#b}\n\n#### C {#c}\n\n### D {#d}\n\n## E {#e}\n\n## No id\n";
+        let events: Vec<_> =
+            Parser::new_ext(markdown, Options::ENABLE_HEADING_ATTRIBUTES).collect();
+        let toc = collect_toc(&events);
+        assert_eq!(
+            toc,
+            [
+                entry(
+                    2,
+                    "a",
+                    vec![
+                        entry(3, "b", vec![entry(4, "c", vec![])]),
+                        entry(3, "d", vec![])
+                    ]
+                ),
+                entry(2, "e", vec![]),
+            ]
+        );
+
+        let mut out = String::new();
+        html::push_html(&mut out, insert_toc(events.into_iter(), &toc).into_iter());
+        assert!(out.starts_with(
+            "<nav class=\"toc\"><ul><li><a href=\"#a\">A</a><ul><li><a href=\"#b\">B</a><ul>\
+             <li><a href=\"#c\">C</a></li></ul></li><li><a href=\"#d\">D</a></li></ul></li>\
+             <li><a href=\"#e\">E</a></li></ul></nav>\n"
+        ));
+    }
+}
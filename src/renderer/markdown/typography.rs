@@ -0,0 +1,173 @@
+//! Small typographic fixes that would be tedious to make by hand
+//!
+//! Short words such as prepositions and initials are tied to the following
+//! word with a non-breaking space, so a line never ends with them, and the
+//! last two words of each heading are tied together so a heading never ends
+//! with a single word on its own line.
+
+use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+use serde::Deserialize;
+
+const NBSP: char = '\u{a0}';
+
+/// The `[typography]` section of `Site.toml`
+#[derive(Deserialize, Debug)]
+pub struct TypographyConfig {
+    /// The language the site is written in, which chooses the default short
+    /// words
+    #[serde(default = "default_language")]
+    pub language: String,
+    /// Words that should not end a line, replacing the language's defaults
+    pub short_words: Option<Vec<String>>,
+    /// Whether to keep the last two words of headings together
+    #[serde(default = "default_true")]
+    pub headings: bool,
+}
+
+fn default_language() -> String {
+    "en".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl TypographyConfig {
+    fn short_words(&self) -> Vec<String> {
+        if let Some(words) = &self.short_words {
+            return words.iter().map(|word| word.to_lowercase()).collect();
+        }
+        let words: &[&str] = match self.language.as_str() {
+            "cs" | "sk" => &["a", "i", "k", "o", "s", "u", "v", "z"],
+            "pl" => &["a", "i", "o", "u", "w", "z"],
+            "fr" => &["à", "a", "au", "de", "du", "en", "et", "la", "le", "un"],
+            "de" => &["am", "an", "im", "in", "zu", "der", "die", "das"],
+            _ => &["a", "an", "at", "by", "in", "of", "on", "the", "to", "I"],
+        };
+        words.iter().map(|word| word.to_lowercase()).collect()
+    }
+}
+
+pub fn improve_typography<'a>(
+    events: impl Iterator<Item = Event<'a>>,
+    config: Option<&TypographyConfig>,
+) -> Vec<Event<'a>> {
+    let Some(config) = config else {
+        return events.collect();
+    };
+    let short_words = config.short_words();
+    let mut output: Vec<Event<'a>> = vec![];
+    let mut in_code = false;
+    // Where the current heading starts in `output`
+    let mut heading_start = None;
+    for event in events {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => {
+                in_code = true;
+                output.push(event);
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                in_code = false;
+                output.push(event);
+            }
+            Event::Start(Tag::Heading { .. }) => {
+                heading_start = Some(output.len());
+                output.push(event);
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some(start) = heading_start.take().filter(|_| config.headings) {
+                    prevent_widow(&mut output[start..]);
+                }
+                output.push(event);
+            }
+            Event::Text(text) if !in_code => {
+                output.push(Event::Text(CowStr::from(tie_short_words(
+                    &text,
+                    &short_words,
+                ))));
+            }
+            event => output.push(event),
+        }
+    }
+    output
+}
+
+/// Replaces the space after each short word or initial with a non-breaking
+/// space.
+fn tie_short_words(text: &str, short_words: &[String]) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut words = text.split(' ').peekable();
+    while let Some(word) = words.next() {
+        output.push_str(word);
+        if words.peek().is_none() {
+            break;
+        }
+        let bare = word.trim_start_matches(|c: char| !c.is_alphanumeric());
+        let is_initial = {
+            let mut chars = bare.chars();
+            matches!(
+                (chars.next(), chars.next(), chars.next()),
+                (Some(c), Some('.'), None) if c.is_uppercase()
+            )
+        };
+        let is_short = short_words.contains(&bare.to_lowercase());
+        output.push(if is_initial || is_short { NBSP } else { ' ' });
+    }
+    output
+}
+
+/// Ties the last two words of a heading together.
+fn prevent_widow(heading: &mut [Event<'_>]) {
+    for event in heading.iter_mut().rev() {
+        let Event::Text(text) = event else {
+            continue;
+        };
+        let trimmed = text.trim_end();
+        if let Some(space) = trimmed.rfind(' ') {
+            // Leave one-word and two-word headings alone
+            if trimmed[..space].trim().contains([' ', NBSP]) {
+                let mut fixed = text.to_string();
+                fixed.replace_range(space..space + 1, &NBSP.to_string());
+                *text = CowStr::from(fixed);
+            }
+            return;
+        }
+        if !trimmed.is_empty() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pulldown_cmark::{html::push_html, Parser};
+
+    use super::{improve_typography, TypographyConfig};
+
+    fn render(markdown: &str, language: &str) -> String {
+        let config = TypographyConfig {
+            language: language.to_string(),
+            short_words: None,
+            headings: true,
+        };
+        let mut html = String::new();
+        push_html(
+            &mut html,
+            improve_typography(Parser::new(markdown), Some(&config)).into_iter(),
+        );
+        html.replace('\u{a0}', "~")
+    }
+
+    #[test]
+    fn tie_words() {
+        assert_eq!(
+            render("# A guide to the new compiler\n\nBy J. R. Tolkien in a `a b` hole.", "en"),
+            "<h1>A~guide to~the~new~compiler</h1>\n<p>By~J.~R.~Tolkien in~a~<code>a b</code> hole.</p>\n"
+        );
+        assert_eq!(
+            render("Šel k lesu s psem", "cs"),
+            "<p>Šel k~lesu s~psem</p>\n"
+        );
+        assert_eq!(render("# Two words", "cs"), "<h1>Two words</h1>\n");
+    }
+}
@@ -0,0 +1,103 @@
+//! Substituting values from `Site.toml` into markdown
+//!
+//! Pages that set `vars: true` in their frontmatter can use placeholders like
+//! `{{ site.latest_release }}`, which are replaced before the markdown is
+//! parsed. Pages have to opt in so that code samples containing braces are
+//! left alone everywhere else.
+
+use std::borrow::Cow;
+
+use miette::diagnostic;
+use serde_json::Value;
+
+use super::directives::directives;
+use crate::{
+    index::{PageSource, SiteMetadata},
+    renderer::RenderContext,
+};
+
+/// Replaces the variable placeholders in `contents` if `page` asks for it.
+pub fn expand_vars<'a>(
+    contents: Cow<'a, str>,
+    page: &PageSource,
+    rcx: &RenderContext<'_>,
+) -> Cow<'a, str> {
+    if !page.uses_vars() {
+        return contents;
+    }
+    let site = rcx.site;
+    let lookup = |name: &str| -> Option<String> {
+        if let Some(value) = site.config().vars.get(name) {
+            return Some(match value {
+                Value::String(s) => s.clone(),
+                value => value.to_string(),
+            });
+        }
+        match name {
+            "title" => Some(site.title().to_string()),
+            "url" => Some(site.base_url().to_string()),
+            "author" => site.author().map(ToString::to_string),
+            "subtitle" => site.subtitle().map(ToString::to_string),
+            _ => None,
+        }
+    };
+    Cow::Owned(substitute(&contents, lookup, |name| {
+        rcx.dcx.lock().unwrap().record(diagnostic!(
+            severity = miette::Severity::Warning,
+            help = "add it to the `[vars]` section of Site.toml",
+            "`{}` uses `site.{name}`, which is not defined",
+            page.source_path().display(),
+        ))
+    }))
+}
+
+fn substitute(
+    contents: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+    mut missing: impl FnMut(&str),
+) -> String {
+    let mut output = String::with_capacity(contents.len());
+    let mut copied = 0;
+    for directive in directives(contents) {
+        output.push_str(&contents[copied..directive.range.start]);
+        let placeholder = &contents[directive.range.clone()];
+        let name = directive.inner.trim().strip_prefix("site.").filter(|name| {
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        });
+        match name.map(|name| (name, lookup(name))) {
+            Some((_, Some(value))) => output.push_str(&value),
+            Some((name, None)) => {
+                missing(name);
+                output.push_str(placeholder);
+            }
+            None => output.push_str(placeholder),
+        }
+        copied = directive.range.end;
+    }
+    output.push_str(&contents[copied..]);
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::substitute;
+
+    #[test]
+    fn substitute_placeholders() {
+        let mut missing = vec![];
+        let output = substitute(
+            "Get {{ site.latest_release }} from {{site.url}}. {{ site.nope }} {{ x }} `{{ site.url }}`",
+            |name| match name {
+                "latest_release" => Some("1.2.3".to_string()),
+                "url" => Some("https://example.com".to_string()),
+                _ => None,
+            },
+            |name| missing.push(name.to_string()),
+        );
+        assert_eq!(
+            output,
+            "Get 1.2.3 from https://example.com. {{ site.nope }} {{ x }} `{{ site.url }}`"
+        );
+        assert_eq!(missing, ["nope"]);
+    }
+}
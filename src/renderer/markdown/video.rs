@@ -0,0 +1,138 @@
+//! Embedding videos with `<video>` elements
+//!
+//! Markdown has no syntax for video, so images whose source is a video file,
+//! such as `![A demo](demo.mp4)`, become a `<video>` with controls. The
+//! description is kept as a link to the file for browsers that cannot play it.
+
+use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+use serde::Deserialize;
+
+/// File extensions that are treated as videos
+const VIDEO_EXTENSIONS: &[&str] = &["mp4", "m4v", "webm", "ogv", "mov"];
+
+/// The `[video]` section of `Site.toml`
+#[derive(Deserialize, Debug)]
+pub struct VideoConfig {
+    /// A command that writes the first frame of a video to an image, which is
+    /// shown until the video plays
+    ///
+    /// `{input}` and `{output}` are replaced by the video and the image to
+    /// write.
+    pub poster_command: Option<Vec<String>>,
+}
+
+/// Returns the URL of the poster frame for the video at `src`.
+pub fn poster_url(src: &str) -> String {
+    let stem = src.rsplit_once('.').map_or(src, |(stem, _)| stem);
+    format!("{stem}.poster.jpg")
+}
+
+pub fn is_video(src: &str) -> bool {
+    let path = src.split(['?', '#']).next().unwrap_or(src);
+    path.rsplit_once('.').is_some_and(|(_, ext)| {
+        VIDEO_EXTENSIONS
+            .iter()
+            .any(|video| ext.eq_ignore_ascii_case(video))
+    })
+}
+
+pub fn embed_videos<'a>(
+    events: impl Iterator<Item = Event<'a>>,
+    config: Option<&VideoConfig>,
+) -> Vec<Event<'a>> {
+    let posters = config.is_some_and(|config| config.poster_command.is_some());
+    let mut output = vec![];
+    // The source and title of the video being embedded, and its description
+    let mut video: Option<(CowStr<'a>, CowStr<'a>, String)> = None;
+    for event in events {
+        match event {
+            Event::Start(Tag::Image {
+                dest_url, title, ..
+            }) if is_video(&dest_url) => video = Some((dest_url, title, String::new())),
+            Event::End(TagEnd::Image) if video.is_some() => {
+                let (src, title, description) = video.take().unwrap();
+                output.push(Event::InlineHtml(CowStr::from(video_markup(
+                    &src,
+                    &title,
+                    &description,
+                    posters,
+                ))));
+            }
+            Event::Text(text) | Event::Code(text) if video.is_some() => {
+                video.as_mut().unwrap().2.push_str(&text);
+            }
+            _ if video.is_some() => {}
+            event => output.push(event),
+        }
+    }
+    output
+}
+
+fn video_markup(src: &str, title: &str, description: &str, poster: bool) -> String {
+    let mut attributes = format!(r#"src="{}" controls preload="metadata""#, escape(src));
+    if poster {
+        attributes += &format!(r#" poster="{}""#, escape(&poster_url(src)));
+    }
+    if !title.is_empty() {
+        attributes += &format!(r#" title="{}""#, escape(title));
+    }
+    let description = if description.is_empty() {
+        "Download the video"
+    } else {
+        description
+    };
+    format!(
+        r#"<video {attributes}><a href="{}">{}</a></video>"#,
+        escape(src),
+        escape(description)
+    )
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+}
+
+#[cfg(test)]
+mod test {
+    use pulldown_cmark::{html, Parser};
+
+    use super::{embed_videos, is_video, VideoConfig};
+
+    fn render(markdown: &str, config: Option<&VideoConfig>) -> String {
+        let mut out = String::new();
+        html::push_html(
+            &mut out,
+            embed_videos(Parser::new(markdown), config).into_iter(),
+        );
+        out
+    }
+
+    #[test]
+    fn video_extensions() {
+        assert!(is_video("demo.mp4"));
+        assert!(is_video("/media/Demo.WEBM?v=2"));
+        assert!(!is_video("photo.png"));
+        assert!(!is_video("mp4"));
+    }
+
+    #[test]
+    fn embed_video() {
+        assert_eq!(
+            render("![A *quick* demo](demo.mp4 \"Demo\") ![x](a.png)", None),
+            "<p><video src=\"demo.mp4\" controls preload=\"metadata\" title=\"Demo\"><a href=\"demo.mp4\">A quick demo</a></video> <img src=\"a.png\" alt=\"x\" /></p>\n"
+        );
+    }
+
+    #[test]
+    fn poster_frame() {
+        let config = VideoConfig {
+            poster_command: Some(vec!["ffmpeg".into()]),
+        };
+        assert_eq!(
+            render("![](clips/demo.mp4)", Some(&config)),
+            "<p><video src=\"clips/demo.mp4\" controls preload=\"metadata\" poster=\"clips/demo.poster.jpg\"><a href=\"clips/demo.mp4\">Download the video</a></video></p>\n"
+        );
+    }
+}
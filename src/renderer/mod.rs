@@ -1,19 +1,36 @@
-use std::sync::Mutex;
+use std::{collections::HashMap, path::Path, sync::Mutex};
 
 use miette::Diagnostic;
 use rayon::prelude::*;
 use thiserror::Error;
+use tracing::instrument;
 
 use crate::{
     diagnostics::{DiagnosticContext, ErrorSet},
-    index::{PageMetadata, PageSource, SiteIndex, SiteMetadata, SourceFormat},
+    index::{PageFilter, PageMetadata, PageSource, SiteIndex, SiteMetadata, SourceFormat},
+    link_check::external_domains,
 };
 
-use self::markdown::render_markdown;
+use self::{description::describe, markdown::render_markdown};
 
+mod description;
 mod markdown;
 
-pub(crate) use self::markdown::CodeFormatter;
+pub use self::description::DescriptionConfig;
+
+pub(crate) use self::markdown::LinkDest;
+pub use self::markdown::{
+    check_links, planned_link_rewrites, BrokenLink, BuiltinPass, ColorScheme, Extension,
+    ExternalLinksConfig, GalleryConfig, HighlightThemeError, HighlightThemes, ImagesConfig,
+    LinkRewrite, MarkdownConfig, MarkdownPass, MarkdownPipeline, MarkdownProfile, ObfuscateConfig,
+    ObfuscationMethod, Pass, RawHtmlError, RawHtmlPolicy, RewriteRule, ShortcodeError, Shortcodes,
+    TocEntry, TypographyConfig, VideoConfig,
+};
+pub(crate) use self::markdown::{
+    code_ranges, gallery_thumbnail_url, is_safe_url, is_video, poster_url, resized_image_url,
+    DECODE_SCRIPT, HIDDEN_HREF,
+};
+pub(crate) use self::markdown::{load_syntaxes, shortcode_dirs, CodeFormatter};
 
 /// Contains all the generated contents of a site
 ///
@@ -21,9 +38,22 @@ pub(crate) use self::markdown::CodeFormatter;
 pub struct RenderedSite<'a> {
     source: &'a SiteIndex,
     pages: Vec<RenderedPage>,
+    /// Limits which pages were rendered, for partial builds
+    filter: Option<PageFilter>,
+    /// The indices of the posts related to each page, by source path
+    related: HashMap<&'a Path, Vec<usize>>,
+    /// The domains each page links to, by source path
+    external_domains: HashMap<&'a Path, Vec<String>>,
+    /// How many posts link to each domain, most linked first
+    external_domain_counts: Vec<(String, usize)>,
 }
 
 impl<'a> RenderedSite<'a> {
+    /// Returns the index this site was rendered from
+    pub fn index(&self) -> &'a SiteIndex {
+        self.source
+    }
+
     pub fn all_pages(&self) -> impl Iterator<Item = RenderedPageRef<'_>> {
         self.pages
             .iter()
@@ -31,6 +61,52 @@ impl<'a> RenderedSite<'a> {
             .map(move |(page, source)| RenderedPageRef::new(source, page))
     }
 
+    /// Finds a page given its source path relative to the site root
+    pub fn find_page_by_source_path(&self, path: &std::path::Path) -> Option<RenderedPageRef<'_>> {
+        self.all_pages().find(|page| page.source_path() == path)
+    }
+
+    /// Returns the posts most related to `page`, best first
+    pub fn related_posts(&self, page: &PageSource) -> impl Iterator<Item = RenderedPageRef<'_>> {
+        self.related
+            .get(page.source_path())
+            .into_iter()
+            .flatten()
+            .filter_map(|&i| {
+                Some(RenderedPageRef::new(
+                    self.source.all_pages().nth(i)?,
+                    &self.pages[i],
+                ))
+            })
+    }
+
+    /// Returns the domains `page` links to, other than the site's own
+    pub fn external_domains(&self, page: &PageSource) -> &[String] {
+        self.external_domains
+            .get(page.source_path())
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Returns how many posts link to each domain, most linked first
+    pub fn external_domain_counts(&self) -> &[(String, usize)] {
+        &self.external_domain_counts
+    }
+
+    /// Whether the page was rendered and should be written out
+    ///
+    /// This is true for every page unless the site was rendered with
+    /// [`SiteIndex::render_only`].
+    pub fn includes(&self, page: &PageSource) -> bool {
+        self.filter
+            .as_ref()
+            .is_none_or(|filter| filter.includes(page))
+    }
+
+    /// The filter used for a partial build, if any
+    pub fn filter(&self) -> Option<&PageFilter> {
+        self.filter.as_ref()
+    }
+
     pub fn posts(&self) -> impl Iterator<Item = RenderedPageRef<'_>> {
         self.source
             .all_pages()
@@ -79,19 +155,68 @@ impl<'a> SiteMetadata for RenderedSite<'a> {
 }
 
 impl SiteIndex {
-    pub fn render(&self) -> Result<RenderedSite, RenderError> {
-        let code_formatter = CodeFormatter::new();
-        let pages = RenderContext::run_dcx(&self, &code_formatter, |ctx| {
+    pub fn render(&self) -> Result<RenderedSite<'_>, RenderError> {
+        self.render_filtered(None)
+    }
+
+    /// Renders only the pages `filter` needs, which are the ones it includes
+    /// and any posts those may list.
+    ///
+    /// The other pages keep their titles but have no contents.
+    pub fn render_only(&self, filter: PageFilter) -> Result<RenderedSite<'_>, RenderError> {
+        self.render_filtered(Some(filter))
+    }
+
+    #[instrument(name = "render", skip_all)]
+    fn render_filtered(&self, filter: Option<PageFilter>) -> Result<RenderedSite<'_>, RenderError> {
+        let code_formatter = CodeFormatter::for_site(self);
+        let pages = RenderContext::run_dcx(self, &code_formatter, |ctx| {
+            markdown::check_disabled_passes(ctx);
             self.all_pages()
                 .collect::<Vec<_>>()
                 .par_iter()
-                .map(|page| page.render(&ctx))
+                .map(|page| match &filter {
+                    Some(filter) if !filter.renders(page) => Ok(RenderedPage::skipped(page)),
+                    // Skip the rest once the build is cancelled, which is
+                    // reported below
+                    _ if self.cancellation().is_cancelled() => Ok(RenderedPage::skipped(page)),
+                    _ => page.render(ctx),
+                })
                 .collect::<Result<Vec<_>, _>>()
         })
         .map_err(RenderError::PageRenderingErrors)?;
+        if self.cancellation().is_cancelled() {
+            return Err(RenderError::Cancelled);
+        }
+        let related = self
+            .all_pages()
+            .map(PageSource::source_path)
+            .zip(self.related_posts(self.config().related_posts.max))
+            .collect();
+        let external_domains = self
+            .all_pages()
+            .collect::<Vec<_>>()
+            .par_iter()
+            .map(|page| (page.source_path(), external_domains(self, page)))
+            .collect::<HashMap<_, _>>();
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for post in self.posts() {
+            for domain in &external_domains[post.source_path()] {
+                *counts.entry(domain).or_default() += 1;
+            }
+        }
+        let mut external_domain_counts: Vec<_> = counts
+            .into_iter()
+            .map(|(domain, count)| (domain.to_string(), count))
+            .collect();
+        external_domain_counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
         Ok(RenderedSite {
             source: self,
             pages,
+            filter,
+            related,
+            external_domains,
+            external_domain_counts,
         })
     }
 }
@@ -115,9 +240,31 @@ impl<'a> RenderedPageRef<'a> {
         self.page.rendered_contents()
     }
 
+    pub fn toc(&self) -> &[TocEntry] {
+        self.page.toc()
+    }
+
     pub fn rendered_excerpt(&self) -> Option<&str> {
         self.page.rendered_excerpt()
     }
+
+    /// Returns the description from the frontmatter, or one made from the
+    /// page's contents if the site asks for them.
+    pub fn description(&self) -> Option<&'a str> {
+        self.source
+            .description()
+            .or(self.page.description.as_deref())
+    }
+
+    /// Returns the path to this page's source file relative to the site root.
+    pub fn source_path(&self) -> &std::path::Path {
+        self.source.source_path()
+    }
+
+    /// Returns the page's source, including its frontmatter
+    pub fn source(&self) -> &'a PageSource {
+        self.source
+    }
 }
 
 impl<'a> PageMetadata for RenderedPageRef<'a> {
@@ -140,13 +287,42 @@ impl<'a> PageMetadata for RenderedPageRef<'a> {
 pub struct RenderedPage {
     /// The contents of this page rendered as HTML
     rendered_contents: String,
+    /// Where the excerpt ends in `rendered_contents`, if the page has one
+    excerpt_len: Option<usize>,
     /// The title that comes from the content if it is markdown and starts with an h1.
     ///
     /// Filled in by [Page::render].
     content_title: String,
+    /// The headings of the page, if it is markdown
+    toc: Vec<TocEntry>,
+    /// A description made from the contents, for pages without one in their
+    /// frontmatter
+    description: Option<String>,
 }
 
 impl RenderedPage {
+    /// Stands in for a page that was left out of a partial build
+    fn skipped(page: &PageSource) -> Self {
+        Self {
+            rendered_contents: String::new(),
+            excerpt_len: None,
+            content_title: page.title().unwrap_or("⛔Untitled⛔").to_string(),
+            toc: vec![],
+            description: None,
+        }
+    }
+
+    /// Makes a description for `page` from its contents, if it needs one.
+    fn describe(mut self, page: &PageSource, rcx: &RenderContext) -> Self {
+        if let Some(config) = rcx.site.config().description.as_ref() {
+            if page.description().is_none() {
+                let summary = self.rendered_excerpt().unwrap_or(&self.rendered_contents);
+                self.description = describe(summary, config);
+            }
+        }
+        self
+    }
+
     pub fn title(&self) -> &str {
         &self.content_title
     }
@@ -155,13 +331,51 @@ impl RenderedPage {
         self.rendered_contents.as_str()
     }
 
+    pub fn toc(&self) -> &[TocEntry] {
+        &self.toc
+    }
+
     pub fn rendered_excerpt(&self) -> Option<&str> {
-        let (excerpt, rest) = self.rendered_contents().split_once("<!--")?;
-        let (comment, _) = rest.split_once("-->")?;
-        (comment.trim() == "MORE").then_some(excerpt)
+        Some(&self.rendered_contents[..self.excerpt_len?])
     }
 }
 
+/// Finds the first excerpt separator in `html` and removes it, returning the
+/// contents without the separator and where the excerpt ends.
+///
+/// Any comment that reads `MORE`, ignoring case and spacing, is a separator,
+/// as is anything in `separators`. A separator that was in a paragraph of its
+/// own is removed along with the paragraph.
+fn split_excerpt(mut html: String, separators: &[String]) -> (String, Option<usize>) {
+    let comment = html.match_indices("<!--").find_map(|(start, _)| {
+        let len = html[start..].find("-->")? + 3;
+        html[start + 4..start + len - 3]
+            .trim()
+            .eq_ignore_ascii_case("more")
+            .then_some(start..start + len)
+    });
+    let custom = separators
+        .iter()
+        .filter(|separator| !separator.is_empty())
+        .filter_map(|separator| {
+            html.find(separator.as_str())
+                .map(|i| i..i + separator.len())
+        });
+    let Some(mut range) = comment
+        .into_iter()
+        .chain(custom)
+        .min_by_key(|range| range.start)
+    else {
+        return (html, None);
+    };
+    if html[..range.start].ends_with("<p>") && html[range.end..].starts_with("</p>") {
+        range = range.start - 3..range.end + 4;
+    }
+    let end = range.end + usize::from(html[range.end..].starts_with('\n'));
+    html.replace_range(range.start..end, "");
+    (html, Some(range.start))
+}
+
 /// Holds dynamic state and configuration needed to render a site.
 pub struct RenderContext<'a> {
     site: &'a SiteIndex,
@@ -219,25 +433,42 @@ pub trait RenderSource {
 }
 
 impl RenderSource for PageSource {
+    #[instrument(name = "render_page", skip_all, fields(page = %self.source_path().display()))]
     fn render(&self, rcx: &RenderContext) -> Result<RenderedPage, RenderError> {
-        Ok(match self.source_format() {
-            SourceFormat::Html => RenderedPage {
-                rendered_contents: self.mainmatter().to_string(),
-                // FIXME: generate a title from the filename or something if there's no title given
-                content_title: self.title().unwrap_or("⛔Untitled⛔").to_string(),
-            },
+        let page = match self.source_format() {
+            SourceFormat::Html => {
+                let (rendered_contents, excerpt_len) = split_excerpt(
+                    self.mainmatter().to_string(),
+                    &rcx.site.config().excerpt_separators,
+                );
+                RenderedPage {
+                    rendered_contents,
+                    excerpt_len,
+                    // FIXME: generate a title from the filename or something if there's no title given
+                    content_title: self.title().unwrap_or("⛔Untitled⛔").to_string(),
+                    toc: vec![],
+                    description: None,
+                }
+            }
             SourceFormat::Markdown => {
-                let (rendered_contents, content_title) = render_markdown(self, rcx);
-                let content_title = content_title
+                let (rendered_contents, metadata) = render_markdown(self, rcx);
+                let (rendered_contents, excerpt_len) =
+                    split_excerpt(rendered_contents, &rcx.site.config().excerpt_separators);
+                let content_title = metadata
+                    .title
                     .or_else(|| self.title().map(ToString::to_string))
                     // FIXME: generate a title from the filename or something if there's no title given
                     .unwrap_or("⛔Untitled⛔".to_string());
                 RenderedPage {
                     rendered_contents,
+                    excerpt_len,
                     content_title,
+                    toc: metadata.toc,
+                    description: None,
                 }
             }
-        })
+        };
+        Ok(page.describe(self, rcx))
     }
 }
 
@@ -245,7 +476,10 @@ impl RenderSource for PageSource {
 #[derive(Diagnostic, Debug, Error)]
 pub enum RenderError {
     #[error("failed to render pages")]
-    PageRenderingErrors(ErrorSet),
+    PageRenderingErrors(#[diagnostic_source] ErrorSet),
+    #[error("build cancelled while rendering pages")]
+    #[diagnostic(help("nothing was written to the destination directory"))]
+    Cancelled,
 }
 
 #[cfg(test)]
@@ -253,8 +487,12 @@ mod test {
     use std::path::PathBuf;
 
     use crate::{
+        cancel::CancellationToken,
         index::{PageSource, SiteIndex, SourceFormat},
-        renderer::{markdown::CodeFormatter, RenderContext, RenderSource},
+        renderer::{
+            markdown::CodeFormatter, split_excerpt, DescriptionConfig, RenderContext, RenderError,
+            RenderSource,
+        },
     };
 
     #[test]
@@ -273,16 +511,86 @@ this is *not an excerpt*",
 
         let site = SiteIndex::default();
         let code_formatter = CodeFormatter::new();
-        let page = RenderContext::run_dcx(&site, &code_formatter, |rcx| page.render(&rcx))?;
+        let page = RenderContext::run_dcx(&site, &code_formatter, |rcx| page.render(rcx))?;
 
         assert_eq!(
             page.rendered_excerpt(),
             Some("<p>this is <em>an excerpt</em></p>\n")
         );
+        assert_eq!(
+            page.rendered_contents(),
+            "<p>this is <em>an excerpt</em></p>\n<p>this is <em>not an excerpt</em></p>\n<hr />\n"
+        );
 
         Ok(())
     }
 
+    #[test]
+    fn descriptions() -> miette::Result<()> {
+        let mut site = SiteIndex::default();
+        site.config_mut().description = Some(DescriptionConfig::default());
+        site.add_page(PageSource::from_string(
+            "about.md",
+            SourceFormat::Markdown,
+            "---\ntitle: About\nlayout: page\n---\n# About me\n\nI write *about* Rust.\n<!-- MORE -->\nAnd more.",
+        ));
+        site.add_page(PageSource::from_string(
+            "now.md",
+            SourceFormat::Markdown,
+            "---\ntitle: Now\nlayout: page\ndescription: What I'm up to\n---\nWriting.",
+        ));
+        let site = site.render()?;
+
+        let descriptions = site
+            .all_pages()
+            .map(|page| page.description())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            descriptions,
+            [Some("I write about Rust."), Some("What I'm up to")]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn cancelled_render() {
+        let mut site = SiteIndex::default();
+        site.add_page(PageSource::from_string(
+            "about.md",
+            SourceFormat::Markdown,
+            "---\ntitle: About\nlayout: page\n---\nhello",
+        ));
+        let token = CancellationToken::new();
+        site.set_cancellation(token.clone());
+        assert!(site.render().is_ok());
+
+        token.cancel();
+        assert!(matches!(site.render(), Err(RenderError::Cancelled)));
+    }
+
+    #[test]
+    fn excerpt_separators() {
+        let separators = ["[[more]]".to_string()];
+        let split = |html: &str| split_excerpt(html.to_string(), &separators);
+
+        assert_eq!(
+            split("<p>a</p>\n<!--more-->\n<p>b</p>"),
+            ("<p>a</p>\n<p>b</p>".to_string(), Some(9))
+        );
+        assert_eq!(
+            split("<p>a</p>\n<p>[[more]]</p>\n<p>b</p>"),
+            ("<p>a</p>\n<p>b</p>".to_string(), Some(9))
+        );
+        assert_eq!(
+            split("<p>a <!-- More --> b</p>"),
+            ("<p>a  b</p>".to_string(), Some(5))
+        );
+        assert_eq!(
+            split("<p>a</p><!-- less -->"),
+            ("<p>a</p><!-- less -->".to_string(), None)
+        );
+    }
+
     #[test]
     fn leading_h1_as_title() -> miette::Result<()> {
         const SRC: &str = r#"---
@@ -302,7 +610,7 @@ categories:
         );
         let site = SiteIndex::default();
         let code_formatter = CodeFormatter::new();
-        let post = RenderContext::run_dcx(&site, &code_formatter, |rcx| post.render(&rcx))?;
+        let post = RenderContext::run_dcx(&site, &code_formatter, |rcx| post.render(rcx))?;
         assert_eq!(post.title(), "This is the title");
         Ok(())
     }
@@ -327,7 +635,7 @@ categories:
             .unwrap();
 
         let rendered_page =
-            RenderContext::run_dcx(&site, &code_formatter, |rcx| render_page.render(&rcx))?;
+            RenderContext::run_dcx(&site, &code_formatter, |rcx| render_page.render(rcx))?;
 
         assert_eq!(
             rendered_page.rendered_contents(),
@@ -357,7 +665,7 @@ categories:
             .unwrap();
 
         let rendered_page =
-            RenderContext::run_dcx(&site, &code_formatter, |rcx| render_page.render(&rcx))?;
+            RenderContext::run_dcx(&site, &code_formatter, |rcx| render_page.render(rcx))?;
         assert_eq!(
             rendered_page.rendered_contents(),
             "<p><a href=\"/blog/2012/10/14/hello/#title\">hello</a></p>\n<hr />\n"
@@ -4,6 +4,7 @@ use clap::Args;
 use ebg::{
     generator::{GeneratorContext, Options},
     index::SiteIndex,
+    lock::DirLock,
 };
 use hyper::{
     service::{make_service_fn, service_fn},
@@ -12,8 +13,8 @@ use hyper::{
 use miette::IntoDiagnostic;
 use notify::{Event, RecursiveMode, Watcher};
 use thiserror::Error;
-use tokio::runtime::Runtime;
-use tracing::{debug, error, info};
+use tokio::{runtime::Runtime, sync::broadcast::error::RecvError};
+use tracing::{debug, error, info, warn};
 
 use crate::cli::{build::find_site_root, Command};
 
@@ -24,8 +25,26 @@ pub struct ServerOptions {
 
     #[clap(short, long, default_value_t = 4000)]
     port: u16,
+
+    /// Also build the site with unpublished posts and serve it under `/drafts/`
+    ///
+    /// The drafts build is kept in a temporary directory, so the output
+    /// directory only contains published posts.
+    #[arg(long)]
+    drafts: bool,
+
+    /// Don't reload pages in the browser when the site is rebuilt
+    #[arg(long)]
+    no_reload: bool,
 }
 
+/// Where pages listen for rebuilds
+const RELOAD_PATH: &str = "/_ebg/reload";
+
+/// Added to every HTML page so the browser reloads it after a rebuild
+const RELOAD_SCRIPT: &str =
+    r#"<script>new EventSource("/_ebg/reload").onmessage = () => location.reload();</script>"#;
+
 impl Command for ServerOptions {
     fn run(self) -> miette::Result<()> {
         let rt = Runtime::new().into_diagnostic()?;
@@ -56,9 +75,27 @@ pub(crate) async fn serve(options: ServerOptions) -> miette::Result<()> {
     let addr = SocketAddr::from(([127, 0, 0, 1], options.port));
 
     let args = options.build_opts.clone();
+
+    // Held until the server stops, so nothing else writes to the output
+    // between rebuilds
+    let lock_dir = args.destination.clone();
+    let mode = args.lock_mode();
+    let _lock = tokio::task::spawn_blocking(move || {
+        DirLock::acquire(&lock_dir, mode, |holder| {
+            info!(
+                "waiting for {holder} to finish with `{}`",
+                lock_dir.display()
+            )
+        })
+    })
+    .await
+    .into_diagnostic()??;
+
     let destination = std::fs::canonicalize(&args.destination).into_diagnostic()?;
 
     let (send, mut recv) = tokio::sync::mpsc::channel(1);
+    let (reload, _) = tokio::sync::broadcast::channel(1);
+    let reload_sender = reload.clone();
 
     let mut watcher = notify::recommended_watcher(move |result: Result<Event, _>| match result {
         Ok(event) => {
@@ -84,34 +121,33 @@ pub(crate) async fn serve(options: ServerOptions) -> miette::Result<()> {
         .watch(&path, RecursiveMode::Recursive)
         .into_diagnostic()?;
 
+    // Drafts are built into their own directory so they never end up in the
+    // real output
+    let drafts_dir = if options.drafts && !args.unpublished {
+        Some(tempfile::tempdir().into_diagnostic()?)
+    } else {
+        None
+    };
+    let drafts_args = drafts_dir.as_ref().map(|dir| Options {
+        destination: dir.path().to_path_buf(),
+        unpublished: true,
+        // Nothing else writes to this directory
+        force: true,
+        verify: false,
+        ..args.clone()
+    });
+
     // FIXME: Watch for file changes and rebuild the site if it changes.
     let generate = tokio::spawn(async move {
         loop {
             let start = Instant::now();
 
-            let site = match SiteIndex::from_directory(&path, options.build_opts.unpublished).await
-            {
-                Ok(site) => site,
-                Err(e) => {
-                    error!("failed to load site directory: {e}");
-                    continue;
-                }
-            };
-
-            let site = match site.render() {
-                Ok(site) => site,
-                Err(e) => {
-                    error!("failed to render site: {e}");
-                    continue;
-                }
-            };
-
-            // FIXME: share this with the build code
-            let gcx = GeneratorContext::new(&site, &args).unwrap();
-            if let Err(e) = gcx.generate_site(&site).await {
-                error!("failed to generate site: {e}");
-                continue;
+            build(&path, &args).await;
+            if let Some(drafts_args) = &drafts_args {
+                build(&path, drafts_args).await;
             }
+            // Nobody may be listening yet, which is fine
+            let _ = reload_sender.send(());
 
             info!(
                 "Generating site took {:.3} seconds",
@@ -127,15 +163,34 @@ pub(crate) async fn serve(options: ServerOptions) -> miette::Result<()> {
 
     // FIXME: we probably don't want to actually leak this...
     let serve_path = Box::leak(Box::new(options.build_opts.destination)).as_path();
+    let drafts_path = match &drafts_dir {
+        Some(dir) => Some(Box::leak(Box::new(dir.path().to_path_buf())).as_path()),
+        // With `--unpublished`, the main build already has the drafts
+        None if options.drafts => Some(serve_path),
+        None => None,
+    };
+
+    let live_reload = !options.no_reload;
+    let reload = &*Box::leak(Box::new(reload));
 
     println!("Listening on http://{addr}");
+    if drafts_path.is_some() {
+        println!("Drafts are at http://{addr}/drafts/");
+    }
     Server::bind(&addr)
         .serve(make_service_fn(
             |_conn: &hyper::server::conn::AddrStream| async move {
                 Ok::<_, Infallible>(service_fn(move |req| async move {
-                    match handle_request(req, serve_path).await {
+                    if live_reload && req.uri().path() == RELOAD_PATH {
+                        return Ok(reload_events(reload.subscribe()));
+                    }
+                    let uri_path = req.uri().path().to_string();
+                    match handle_request(req, serve_path, drafts_path, live_reload).await {
                         Ok(response) => Ok(response),
-                        Err(e) => generate_error_response(e).await,
+                        Err(e) => {
+                            let (root, prefix) = site_root(&uri_path, serve_path, drafts_path);
+                            generate_error_response(e, &uri_path, root, prefix, live_reload).await
+                        }
                     }
                 }))
             },
@@ -148,24 +203,89 @@ pub(crate) async fn serve(options: ServerOptions) -> miette::Result<()> {
     Ok(())
 }
 
-async fn handle_request(req: Request<Body>, site: &Path) -> Result<Response<Body>, ServerError> {
+/// Builds the site at `path` with `args`, logging any errors.
+async fn build(path: &Path, args: &Options) {
+    let site = match SiteIndex::from_directory(path, args.unpublished).await {
+        Ok(site) => site,
+        Err(e) => {
+            error!("failed to load site directory: {e}");
+            return;
+        }
+    };
+
+    let site = match site.render() {
+        Ok(site) => site,
+        Err(e) => {
+            error!("failed to render site: {e}");
+            return;
+        }
+    };
+
+    // FIXME: share this with the build code
+    let gcx = GeneratorContext::new(&site, args).unwrap();
+    match gcx.generate_site(&site).await {
+        Ok(summary) => {
+            for violation in &summary.budget_violations {
+                warn!("{violation}");
+            }
+            for (page, count) in summary.accessibility_by_page() {
+                warn!("{count} accessibility issue(s) in {}", page.display());
+            }
+        }
+        Err(e) => error!("failed to generate site: {e}"),
+    }
+}
+
+/// Streams an event to the browser each time the site is rebuilt.
+fn reload_events(mut rebuilds: tokio::sync::broadcast::Receiver<()>) -> Response<Body> {
+    let (mut sender, body) = Body::channel();
+    tokio::spawn(async move {
+        loop {
+            match rebuilds.recv().await {
+                Ok(()) | Err(RecvError::Lagged(_)) => (),
+                Err(RecvError::Closed) => break,
+            }
+            if sender.send_data("data: reload\n\n".into()).await.is_err() {
+                // The page was closed
+                break;
+            }
+        }
+    });
+    Response::builder()
+        .header("Content-Type", "text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .body(body)
+        .unwrap()
+}
+
+/// Serves a file from `site`, or from `drafts` for paths under `/drafts/`.
+///
+/// With `live_reload`, HTML pages get a script that reloads them when the
+/// site is rebuilt.
+async fn handle_request(
+    req: Request<Body>,
+    site: &Path,
+    drafts: Option<&Path>,
+    live_reload: bool,
+) -> Result<Response<Body>, ServerError> {
     debug!(?req);
 
     let response = if req.method() == Method::GET {
         // FIXME: check the URI and find the right file to serve.
-        let path = site.join(
-            Path::new(req.uri().path())
-                .strip_prefix("/")
-                .map_err(ServerError::StripPrefixError)?,
-        );
+        let (root, prefix) = site_root(req.uri().path(), site, drafts);
+        let uri_path = Path::new(req.uri().path())
+            .strip_prefix(prefix)
+            .and_then(|path| path.strip_prefix("/").or(Ok(path)))
+            .map_err(ServerError::StripPrefixError)?;
+        let path = root.join(uri_path);
         debug!("checking if `{}` exists", path.display());
         if path.is_file() {
-            serve_path(path.as_path()).await?
+            serve_path(path.as_path(), prefix, live_reload).await?
         } else {
             let path = path.join("index.html");
             if path.exists() {
                 debug!("attempting to serve index path `{}`", path.display());
-                serve_path(path.as_path()).await?
+                serve_path(path.as_path(), prefix, live_reload).await?
             } else {
                 debug!("`{}` not found, returning 404", path.display());
                 return Err(ServerError::PathNotFound(req.uri().clone()));
@@ -178,15 +298,27 @@ async fn handle_request(req: Request<Body>, site: &Path) -> Result<Response<Body
     Ok(response)
 }
 
-async fn serve_path(path: &Path) -> Result<Response<Body>, ServerError> {
+/// Serves the file at `path`, whose URL starts with `prefix`.
+async fn serve_path(
+    path: &Path,
+    prefix: &str,
+    live_reload: bool,
+) -> Result<Response<Body>, ServerError> {
     let mut response = Response::builder();
-    if let Some(mime) = guess_mime_type_from_path(path) {
+    let mime = guess_mime_type_from_path(path);
+    if let Some(mime) = mime {
         debug!("guessed mime type `{mime}`");
         response = response.header("Content-Type", mime);
     }
-    let data = tokio::fs::read(path)
+    let mut data = tokio::fs::read(path)
         .await
         .map_err(ServerError::ReadContents)?;
+    if !prefix.is_empty() && mime == Some("text/html") {
+        data = prefix_links(data, prefix);
+    }
+    if live_reload && mime == Some("text/html") {
+        data = inject_reload_script(data);
+    }
     debug!("writing {} bytes", data.len());
     response
         .header("Content-Length", data.len())
@@ -194,9 +326,58 @@ async fn serve_path(path: &Path) -> Result<Response<Body>, ServerError> {
         .map_err(ServerError::ResponseBodyError)
 }
 
+/// Adds the live reload script to the end of the page's body.
+fn inject_reload_script(html: Vec<u8>) -> Vec<u8> {
+    let mut html = match String::from_utf8(html) {
+        Ok(html) => html,
+        Err(e) => return e.into_bytes(),
+    };
+    match html.rfind("</body>") {
+        Some(end) => html.insert_str(end, RELOAD_SCRIPT),
+        None => html.push_str(RELOAD_SCRIPT),
+    }
+    html.into_bytes()
+}
+
+/// Adds `prefix` to the links in a page that start with `/`, so pages served
+/// under `/drafts/` link to other drafts pages rather than the published site.
+fn prefix_links(html: Vec<u8>, prefix: &str) -> Vec<u8> {
+    let html = match String::from_utf8(html) {
+        Ok(html) => html,
+        Err(e) => return e.into_bytes(),
+    };
+    let rewrite = |el: &mut lol_html::html_content::Element| {
+        for name in ["href", "src", "action", "poster"] {
+            let Some(url) = el.get_attribute(name) else {
+                continue;
+            };
+            let under_prefix = url
+                .strip_prefix(prefix)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(['/', '?', '#']));
+            if url.starts_with('/') && !url.starts_with("//") && !under_prefix {
+                el.set_attribute(name, &format!("{prefix}{url}"))?;
+            }
+        }
+        Ok(())
+    };
+    lol_html::rewrite_str(
+        &html,
+        lol_html::RewriteStrSettings {
+            element_content_handlers: vec![lol_html::element!(
+                "[href], [src], [action], [poster]",
+                rewrite
+            )],
+            ..lol_html::RewriteStrSettings::new()
+        },
+    )
+    .unwrap_or(html)
+    .into_bytes()
+}
+
 fn guess_mime_type_from_path(path: &Path) -> Option<&'static str> {
     match path.extension()?.to_str()? {
         "html" => Some("text/html"),
+        "json" => Some("application/json"),
         "png" => Some("image/png"),
         "svg" => Some("image/svg+xml"),
         "ttf" => Some("font/ttf"),
@@ -210,8 +391,30 @@ fn guess_mime_type_from_path(path: &Path) -> Option<&'static str> {
     }
 }
 
-async fn generate_error_response(e: ServerError) -> Result<Response<Body>, Infallible> {
-    let body = format!("{e}");
+/// Returns the directory that `uri_path` is served from, and the prefix of
+/// URLs that are served from it.
+fn site_root<'a>(
+    uri_path: &str,
+    site: &'a Path,
+    drafts: Option<&'a Path>,
+) -> (&'a Path, &'static str) {
+    match drafts {
+        Some(drafts) if Path::new(uri_path).starts_with("/drafts") => (drafts, "/drafts"),
+        _ => (site, ""),
+    }
+}
+
+/// Responds to a failed request with a page describing the error.
+///
+/// `root` is the directory the request was served from, whose pages are
+/// listed on the error page. URLs of pages in it start with `prefix`.
+async fn generate_error_response(
+    e: ServerError,
+    uri_path: &str,
+    root: &Path,
+    prefix: &str,
+    live_reload: bool,
+) -> Result<Response<Body>, Infallible> {
     let status = match e {
         ServerError::PathNotFound(_) => StatusCode::NOT_FOUND,
         ServerError::ResponseBodyError(_)
@@ -219,13 +422,150 @@ async fn generate_error_response(e: ServerError) -> Result<Response<Body>, Infal
         | ServerError::ReadContents(_) => StatusCode::INTERNAL_SERVER_ERROR,
         ServerError::UnsupportedMethod(_) => StatusCode::METHOD_NOT_ALLOWED,
     };
+    let mut body = error_page(status, &e.to_string(), uri_path, root, prefix).into_bytes();
+    if live_reload {
+        body = inject_reload_script(body);
+    }
     Ok(Response::builder()
         .status(status)
-        .header("Content-Type", "text/plain")
+        .header("Content-Type", "text/html")
         .body(body.into())
         .unwrap())
 }
 
+/// The page shown for a missing file or a server error
+///
+/// Links that go nowhere are usually close to a page that does exist, so the
+/// page suggests the nearest ones.
+const ERROR_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{{ status }}</title>
+<style>
+body { font-family: system-ui, sans-serif; max-width: 40em; margin: 2em auto; padding: 0 1em; line-height: 1.5; }
+code { background: #f2f2f2; padding: 0 .2em; }
+</style>
+</head>
+<body>
+<h1>{{ status }}</h1>
+<p>{{ message }}</p>
+<p>Requested <code>{{ path }}</code></p>
+{% if nearby %}<h2>Did you mean</h2>
+<ul>{% for url in nearby %}<li><a href="{{ url }}">{{ url }}</a></li>{% endfor %}</ul>
+{% endif %}{% if top_level %}<h2>Pages</h2>
+<ul>{% for url in top_level %}<li><a href="{{ url }}">{{ url }}</a></li>{% endfor %}</ul>
+{% endif %}</body>
+</html>
+"#;
+
+/// Renders [`ERROR_TEMPLATE`] for a request for `uri_path`.
+fn error_page(
+    status: StatusCode,
+    message: &str,
+    uri_path: &str,
+    root: &Path,
+    prefix: &str,
+) -> String {
+    let pages = page_urls(root, prefix);
+    let top_level = pages
+        .iter()
+        .filter(|url| {
+            url[prefix.len()..]
+                .trim_end_matches('/')
+                .matches('/')
+                .count()
+                <= 1
+        })
+        .collect::<Vec<_>>();
+    let mut context = tera::Context::new();
+    context.insert(
+        "status",
+        &format!(
+            "{} {}",
+            status.as_u16(),
+            status.canonical_reason().unwrap_or_default()
+        ),
+    );
+    context.insert("message", message);
+    context.insert("path", uri_path);
+    context.insert("nearby", &nearby_urls(uri_path, &pages));
+    context.insert("top_level", &top_level);
+    tera::Tera::one_off(ERROR_TEMPLATE, &context, true).unwrap_or_else(|e| {
+        // The template is fixed, so this would be a bug in it
+        error!("rendering error page: {e}");
+        message.to_string()
+    })
+}
+
+/// Lists the URL of every HTML page in `root`, with directories' index pages
+/// ending in `/`.
+fn page_urls(root: &Path, prefix: &str) -> Vec<String> {
+    let mut urls = walkdir::WalkDir::new(root)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "html"))
+        .filter_map(|entry| {
+            let path = entry
+                .path()
+                .strip_prefix(root)
+                .ok()?
+                .to_str()?
+                .replace('\\', "/");
+            let path = path.strip_suffix("index.html").unwrap_or(&path);
+            Some(format!("{prefix}/{path}"))
+        })
+        .collect::<Vec<_>>();
+    urls.sort();
+    urls
+}
+
+/// Returns the URLs in `pages` that look most like `uri_path`, best first.
+fn nearby_urls<'a>(uri_path: &str, pages: &'a [String]) -> Vec<&'a str> {
+    let wanted = uri_path.strip_suffix("index.html").unwrap_or(uri_path);
+    let mut nearby = pages
+        .iter()
+        .filter_map(|url| {
+            // A page that was moved often keeps its name
+            let distance =
+                if last_segment(url).is_some_and(|name| Some(name) == last_segment(wanted)) {
+                    1
+                } else {
+                    edit_distance(wanted, url)
+                };
+            (distance <= wanted.len().max(url.len()) / 3).then_some((distance, url.as_str()))
+        })
+        .collect::<Vec<_>>();
+    nearby.sort();
+    nearby.into_iter().take(5).map(|(_, url)| url).collect()
+}
+
+/// Returns the last part of `url`'s path, ignoring a trailing `/`.
+fn last_segment(url: &str) -> Option<&str> {
+    url.trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .filter(|name| !name.is_empty())
+}
+
+/// The number of characters that have to be inserted, removed, or replaced to
+/// turn `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let replace = diagonal + usize::from(a != *b);
+            diagonal = row[j + 1];
+            row[j + 1] = replace.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
 #[cfg(test)]
 mod test {
     use std::{
@@ -236,7 +576,10 @@ mod test {
     use hyper::{body::to_bytes, Request, StatusCode};
     use miette::IntoDiagnostic;
 
-    use crate::serve::{guess_mime_type_from_path, handle_request, ServerError};
+    use crate::serve::{
+        edit_distance, error_page, guess_mime_type_from_path, handle_request, nearby_urls,
+        prefix_links, ServerError, RELOAD_SCRIPT,
+    };
 
     #[test]
     fn test_mime_type() {
@@ -258,7 +601,9 @@ mod test {
             .body("".into())
             .into_diagnostic()?;
 
-        let res = handle_request(req, &site).await.into_diagnostic()?;
+        let res = handle_request(req, &site, None, false)
+            .await
+            .into_diagnostic()?;
 
         assert_eq!(res.status(), StatusCode::OK);
 
@@ -293,7 +638,9 @@ mod test {
             .body("".into())
             .into_diagnostic()?;
 
-        let res = handle_request(req, &site).await.into_diagnostic()?;
+        let res = handle_request(req, &site, None, false)
+            .await
+            .into_diagnostic()?;
 
         assert_eq!(res.status(), StatusCode::OK);
 
@@ -318,6 +665,61 @@ mod test {
         Ok(())
     }
 
+    /// Make sure pages get the live reload script
+    #[tokio::test]
+    async fn inject_reload() -> miette::Result<()> {
+        let site = test_site();
+
+        let req = Request::builder()
+            .uri("/")
+            .body("".into())
+            .into_diagnostic()?;
+        let res = handle_request(req, &site, None, true)
+            .await
+            .into_diagnostic()?;
+        let body = to_bytes(res.into_body()).await.into_diagnostic()?;
+        let body = String::from_utf8_lossy(&body);
+        assert!(body.contains(&format!("{RELOAD_SCRIPT}</body>")));
+
+        Ok(())
+    }
+
+    /// Make sure drafts are served from their own directory
+    #[tokio::test]
+    async fn get_draft() -> miette::Result<()> {
+        let drafts = test_site();
+        let site = Path::new("does-not-exist");
+
+        let req = Request::builder()
+            .uri("/drafts/index.html")
+            .body("".into())
+            .into_diagnostic()?;
+        let res = handle_request(req, site, Some(&drafts), false)
+            .await
+            .into_diagnostic()?;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = Request::builder()
+            .uri("/drafts/index.html")
+            .body("".into())
+            .into_diagnostic()?;
+        let res = handle_request(req, site, None, false).await;
+        assert!(matches!(res, Err(ServerError::PathNotFound(_))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn draft_links() {
+        let html = r#"<a href="/about/">About</a><a href="//example.com/">Elsewhere</a>
+<img src="/drafts/photo.png"><a href="../">Up</a><form action="/search"></form>"#;
+        assert_eq!(
+            String::from_utf8(prefix_links(html.into(), "/drafts")).unwrap(),
+            r#"<a href="/drafts/about/">About</a><a href="//example.com/">Elsewhere</a>
+<img src="/drafts/photo.png"><a href="../">Up</a><form action="/drafts/search"></form>"#
+        );
+    }
+
     /// Make sure we report an error if we ask for a nonexistent file
     #[tokio::test]
     async fn not_found() -> miette::Result<()> {
@@ -328,10 +730,67 @@ mod test {
             .body("".into())
             .into_diagnostic()?;
 
-        let res = handle_request(req, &site).await;
+        let res = handle_request(req, &site, None, false).await;
 
         assert!(matches!(res, Err(ServerError::PathNotFound(_))));
 
         Ok(())
     }
+
+    #[test]
+    fn edit_distances() {
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("/blog/", "/blog/"), 0);
+    }
+
+    #[test]
+    fn nearby() {
+        let pages = [
+            "/",
+            "/about/",
+            "/blog/2024/01/01/hello/",
+            "/blog/2024/01/02/goodbye/",
+        ]
+        .map(String::from);
+        assert_eq!(
+            nearby_urls("/blog/2024/1/1/hello/", &pages),
+            ["/blog/2024/01/01/hello/"]
+        );
+        assert_eq!(nearby_urls("/hello/", &pages), ["/blog/2024/01/01/hello/"]);
+        assert_eq!(nearby_urls("/abuot/index.html", &pages), ["/about/"]);
+        assert!(nearby_urls("/something-else/", &pages).is_empty());
+    }
+
+    /// Make sure missing pages get a page that points to the right place
+    #[test]
+    fn not_found_page() -> miette::Result<()> {
+        let site = tempfile::tempdir().into_diagnostic()?;
+        for page in [
+            "index.html",
+            "about/index.html",
+            "blog/2024/01/01/hello/index.html",
+        ] {
+            let path = site.path().join(page);
+            std::fs::create_dir_all(path.parent().unwrap()).into_diagnostic()?;
+            std::fs::write(path, "<p>Hello</p>").into_diagnostic()?;
+        }
+
+        let page = error_page(
+            StatusCode::NOT_FOUND,
+            "could not find file",
+            "/drafts/blog/2024/01/01/helo/",
+            site.path(),
+            "/drafts",
+        );
+        assert!(page.contains("<h1>404 Not Found</h1>"));
+        assert!(page.contains(
+            "<h2>Did you mean</h2>\n<ul><li><a href=\"&#x2F;drafts&#x2F;blog&#x2F;2024&#x2F;01&#x2F;01&#x2F;hello&#x2F;\">"
+        ));
+        assert!(page.contains(
+            "<h2>Pages</h2>\n<ul><li><a href=\"&#x2F;drafts&#x2F;\">&#x2F;drafts&#x2F;</a></li>\
+             <li><a href=\"&#x2F;drafts&#x2F;about&#x2F;\">&#x2F;drafts&#x2F;about&#x2F;</a></li></ul>"
+        ));
+        Ok(())
+    }
 }
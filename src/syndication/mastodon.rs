@@ -0,0 +1,79 @@
+//! Posting statuses to a Mastodon instance.
+
+use serde::Deserialize;
+
+use super::SyndicationError;
+use crate::http::HttpClient;
+
+pub const SERVICE: &str = "mastodon";
+
+/// The `[syndication.mastodon]` section of `Site.toml`
+#[derive(Deserialize, Debug)]
+pub struct MastodonConfig {
+    /// Base URL of the instance, e.g. `https://hachyderm.io`
+    pub instance: String,
+    /// Name of the environment variable holding the access token
+    #[serde(default = "default_token_env")]
+    pub token_env: String,
+    /// Visibility of new statuses (`public`, `unlisted`, `private`)
+    pub visibility: Option<String>,
+    /// Maximum length of a status
+    #[serde(default = "default_max_length")]
+    pub max_length: usize,
+}
+
+fn default_token_env() -> String {
+    "EBG_MASTODON_TOKEN".into()
+}
+
+fn default_max_length() -> usize {
+    500
+}
+
+#[derive(Deserialize)]
+struct Status {
+    url: String,
+}
+
+impl MastodonConfig {
+    pub fn token(&self) -> Result<String, SyndicationError> {
+        std::env::var(&self.token_env)
+            .map_err(|_| SyndicationError::MissingToken(self.token_env.clone()))
+    }
+
+    /// Posts a new status and returns its URL.
+    ///
+    /// The `idempotency_key` lets the server discard duplicate requests if we
+    /// retry after a failure.
+    pub async fn post_status(
+        &self,
+        client: &HttpClient,
+        token: &str,
+        status: &str,
+        idempotency_key: &str,
+    ) -> Result<String, SyndicationError> {
+        let mut form = vec![("status", status)];
+        if let Some(visibility) = &self.visibility {
+            form.push(("visibility", visibility));
+        }
+
+        let request = client
+            .client()
+            .post(format!(
+                "{}/api/v1/statuses",
+                self.instance.trim_end_matches('/')
+            ))
+            .bearer_auth(token)
+            .header("Idempotency-Key", idempotency_key)
+            .form(&form);
+        let response = client
+            .send(request)
+            .await
+            .map_err(|e| SyndicationError::Http(SERVICE, e))?;
+        let status: Status = response
+            .json()
+            .await
+            .map_err(|e| SyndicationError::Request(SERVICE, e))?;
+        Ok(status.url)
+    }
+}
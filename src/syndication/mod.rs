@@ -0,0 +1,304 @@
+//! Support for publishing on the site and syndicating elsewhere (POSSE).
+//!
+//! Posts are announced on other services with their title, excerpt, and
+//! canonical URL. The resulting remote URLs are recorded in a state file so
+//! each post is only syndicated once, and so themes can show where else a post
+//! was published.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use miette::Diagnostic;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::http::HttpError;
+
+pub mod mastodon;
+
+pub use mastodon::MastodonConfig;
+
+/// The `[syndication]` section of `Site.toml`
+#[derive(Deserialize, Debug)]
+pub struct SyndicationConfig {
+    /// Path to the state file, relative to the site root
+    #[serde(default = "default_state_file")]
+    pub state: PathBuf,
+    /// Only posts published on or after this date are syndicated. Defaults to
+    /// when `ebg syndicate` first ran, so an existing archive isn't announced.
+    pub since: Option<DateTime<Utc>>,
+    pub mastodon: Option<MastodonConfig>,
+}
+
+impl Default for SyndicationConfig {
+    fn default() -> Self {
+        Self {
+            state: default_state_file(),
+            since: None,
+            mastodon: None,
+        }
+    }
+}
+
+fn default_state_file() -> PathBuf {
+    "syndication.toml".into()
+}
+
+#[non_exhaustive]
+#[derive(Diagnostic, Error, Debug)]
+pub enum SyndicationError {
+    #[error("reading syndication state from `{}`", .0.display())]
+    ReadState(PathBuf, #[source] std::io::Error),
+    #[error("parsing syndication state from `{}`", .0.display())]
+    ParseState(PathBuf, #[source] toml::de::Error),
+    #[error("serializing syndication state")]
+    SerializeState(#[source] toml::ser::Error),
+    #[error("writing syndication state to `{}`", .0.display())]
+    WriteState(PathBuf, #[source] std::io::Error),
+    #[error("environment variable `{0}` is not set")]
+    #[diagnostic(help("set it to an access token for the account you want to post from"))]
+    MissingToken(String),
+    #[error("posting to {0}")]
+    Request(&'static str, #[source] reqwest::Error),
+    #[error("posting to {0}")]
+    Http(&'static str, #[source] HttpError),
+}
+
+/// A copy of a post that was published on another service.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SyndicatedCopy {
+    pub service: String,
+    pub url: String,
+    pub date: DateTime<Utc>,
+}
+
+/// Records which posts have been syndicated where.
+///
+/// Posts are keyed by their source path relative to the site root.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct SyndicationState {
+    /// When syndication first ran, which stands in for an unset `since`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    started: Option<DateTime<Utc>>,
+    #[serde(default)]
+    posts: BTreeMap<String, Vec<SyndicatedCopy>>,
+}
+
+impl SyndicationState {
+    /// Loads the state file, returning an empty state if it does not exist yet.
+    pub fn load(path: &Path) -> Result<Self, SyndicationError> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| SyndicationError::ReadState(path.into(), e))?;
+        toml::from_str(&contents).map_err(|e| SyndicationError::ParseState(path.into(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), SyndicationError> {
+        let contents = toml::to_string_pretty(self).map_err(SyndicationError::SerializeState)?;
+        std::fs::write(path, contents).map_err(|e| SyndicationError::WriteState(path.into(), e))
+    }
+
+    /// Returns all the places the post with the given source path was syndicated to.
+    pub fn copies(&self, source_path: &Path) -> &[SyndicatedCopy] {
+        self.posts
+            .get(&state_key(source_path))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    pub fn is_syndicated(&self, source_path: &Path, service: &str) -> bool {
+        self.copies(source_path)
+            .iter()
+            .any(|copy| copy.service == service)
+    }
+
+    /// Returns the date posts must be published on or after to be syndicated:
+    /// `configured` if it is set, and otherwise when syndication first ran,
+    /// which is now if it never has.
+    pub fn since(&mut self, configured: Option<DateTime<Utc>>) -> DateTime<Utc> {
+        configured.unwrap_or_else(|| *self.started.get_or_insert_with(Utc::now))
+    }
+
+    /// When syndication first ran, if it ever has without `since` set
+    pub fn started(&self) -> Option<DateTime<Utc>> {
+        self.started
+    }
+
+    pub fn record(&mut self, source_path: &Path, copy: SyndicatedCopy) {
+        self.posts
+            .entry(state_key(source_path))
+            .or_default()
+            .push(copy);
+    }
+
+    /// Moves the copies recorded for the post at `from` to `to`, for when the
+    /// post's source is renamed. Returns whether there were any.
+    pub fn rename(&mut self, from: &Path, to: &Path) -> bool {
+        let Some(copies) = self.posts.remove(&state_key(from)) else {
+            return false;
+        };
+        self.posts.entry(state_key(to)).or_default().extend(copies);
+        true
+    }
+}
+
+fn state_key(source_path: &Path) -> String {
+    source_path.to_string_lossy().replace('\\', "/")
+}
+
+/// Composes the text of an announcement for a post.
+///
+/// The summary is shortened on a word boundary so the whole message fits in
+/// `limit` characters. Following Mastodon, URLs are counted as 23 characters
+/// regardless of their actual length.
+pub fn compose_status(title: &str, summary: &str, url: &str, limit: usize) -> String {
+    const URL_LENGTH: usize = 23;
+    let fixed = title.chars().count() + URL_LENGTH + "\n\n\n\n".len();
+    let budget = limit.saturating_sub(fixed);
+
+    let summary = if summary.chars().count() <= budget {
+        summary.to_string()
+    } else {
+        let mut shortened = String::new();
+        for word in summary.split_whitespace() {
+            // leave room for a space and the ellipsis
+            if shortened.chars().count() + word.chars().count() + 2 > budget {
+                break;
+            }
+            if !shortened.is_empty() {
+                shortened.push(' ');
+            }
+            shortened += word;
+        }
+        shortened + "…"
+    };
+
+    if summary.is_empty() || budget == 0 {
+        format!("{title}\n\n{url}")
+    } else {
+        format!("{title}\n\n{summary}\n\n{url}")
+    }
+}
+
+/// Converts rendered HTML into plain text by dropping all tags and collapsing
+/// whitespace.
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            c if !in_tag => text.push(c),
+            _ => (),
+        }
+    }
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        // Braces that would otherwise be read as template tags
+        .replace("&#123;", "{")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        // Last, so escaped entities like `&amp;lt;` stay as written
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use chrono::{TimeZone, Utc};
+
+    use super::{compose_status, html_to_text, SyndicatedCopy, SyndicationState};
+
+    #[test]
+    fn short_status() {
+        assert_eq!(
+            compose_status("Hello", "A short post", "https://example.com/hello/", 500),
+            "Hello\n\nA short post\n\nhttps://example.com/hello/"
+        );
+    }
+
+    #[test]
+    fn long_status_is_shortened() {
+        let summary = "word ".repeat(200);
+        let status = compose_status("Hello", &summary, "https://example.com/hello/", 100);
+        assert!(status.contains("word…"));
+        assert!(status.chars().count() <= 100);
+    }
+
+    #[test]
+    fn strip_html() {
+        assert_eq!(
+            html_to_text("<p>this is <em>an excerpt</em> &amp; more</p>"),
+            "this is an excerpt & more"
+        );
+        assert_eq!(html_to_text("<code>&amp;lt;</code>"), "&lt;");
+    }
+
+    #[test]
+    fn round_trip_state() {
+        let mut state = SyndicationState::default();
+        let source = Path::new("_posts/2024-01-01-hello.md");
+        state.record(
+            source,
+            SyndicatedCopy {
+                service: "mastodon".into(),
+                url: "https://mastodon.example/@me/1".into(),
+                date: Utc::now(),
+            },
+        );
+
+        let state: SyndicationState =
+            toml::from_str(&toml::to_string_pretty(&state).unwrap()).unwrap();
+        assert!(state.is_syndicated(source, "mastodon"));
+        assert!(!state.is_syndicated(source, "bluesky"));
+        assert_eq!(state.copies(source).len(), 1);
+    }
+
+    #[test]
+    fn rename() {
+        let mut state = SyndicationState::default();
+        let from = Path::new("_posts/2024-01-01-hello.md");
+        let to = Path::new("_posts/2024-01-01-hello/index.md");
+        state.record(
+            from,
+            SyndicatedCopy {
+                service: "mastodon".into(),
+                url: "https://mastodon.example/@me/1".into(),
+                date: Utc::now(),
+            },
+        );
+
+        assert!(state.rename(from, to));
+        assert!(!state.is_syndicated(from, "mastodon"));
+        assert!(state.is_syndicated(to, "mastodon"));
+        assert!(!state.rename(from, to));
+    }
+
+    #[test]
+    fn since_defaults_to_first_run() {
+        let configured = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut state = SyndicationState::default();
+        assert_eq!(state.since(Some(configured)), configured);
+        assert_eq!(state.started(), None);
+
+        let before = Utc::now();
+        let started = state.since(None);
+        assert!(started >= before);
+
+        let state: SyndicationState =
+            toml::from_str(&toml::to_string_pretty(&state).unwrap()).unwrap();
+        assert_eq!(state.started(), Some(started));
+    }
+}